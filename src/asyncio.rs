@@ -0,0 +1,887 @@
+//! Async integration, for tokio and `futures::io`.
+//!
+//! `tokio::FioReadExt` and `tokio::FioWriteExt` extend tokio's
+//! `AsyncRead` and `AsyncWrite`; `futures::FioReadExt` and
+//! `futures::FioWriteExt` do the same for `futures::io`.  Both pairs have
+//! the same methods, and return the same futures:
+//!
+//! * `read_fio` and `write_fio` read and write `IO` values, in native byte
+//!   order (as `fio_read` and `fio_write` do).  A read takes
+//!   `size_of_val(value)` bytes off the reader (see `IO`).
+//! * `read_vlq` and `write_vlq` read and write `VLQ` values.  A VLQ which
+//!   runs longer than its type allows, or whose value overflows it, is
+//!   `InvalidData`.
+//! * `read_fio_string` and `write_fio_string` read and write strings with
+//!   an `IO` length prefix (as `FricganString` does), and
+//!   `read_vlq_string` and `write_vlq_string` do the same with a `VLQ`
+//!   prefix (as `VLQString` does).  Reads take a maximum length, as the
+//!   length comes off the wire, and fail with `InvalidData` if it is
+//!   exceeded or the string isn't UTF-8.
+//!
+//! Input which ends early is `UnexpectedEof`.  Every future resolves to
+//! the number of bytes read or written.
+//!
+//! With `tokio-codec` enabled, `tokio::FrameCodec` is a `tokio_util`
+//! `Decoder` and `Encoder` for frames (see `framing`).
+
+use core::marker::PhantomData;
+use core::mem::size_of_val;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use std::future::Future;
+use std::io;
+
+use IO;
+
+#[cfg(feature="vlq")]
+use VLQ;
+
+#[cfg(feature="vlq")]
+use {vlq_fits, vlq_max, MAX_VLQ_LEN};
+
+#[cfg(any(feature="io-string", feature="vlq-string"))]
+use {PrefixError, MAX_PREFIX_LEN};
+
+mod backend {
+    use core::task::{Context, Poll};
+    use std::io;
+
+    // Adapts the `poll_read` and `poll_write` of each async library, so
+    // the futures below can be shared.  The implementations are the
+    // marker types `tokio::Tokio` and `futures::Futures`.
+    pub trait ReadBackend<R: ?Sized> {
+        fn poll_read(reader: &mut R, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>>;
+    }
+
+    pub trait WriteBackend<W: ?Sized> {
+        fn poll_write(writer: &mut W, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>>;
+    }
+}
+
+use self::backend::{ReadBackend, WriteBackend};
+
+fn eof() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of input")
+}
+
+// Reads until `buffer[..*filled]` is the whole buffer.
+fn poll_fill<R: ?Sized, B: ReadBackend<R>>(
+    reader: &mut R,
+    cx: &mut Context,
+    buffer: &mut [u8],
+    filled: &mut usize,
+) -> Poll<io::Result<()>> {
+    while *filled < buffer.len() {
+        match B::poll_read(reader, cx, &mut buffer[*filled..]) {
+            Poll::Ready(Ok(0)) => return Poll::Ready(Err(eof())),
+            Poll::Ready(Ok(n)) => *filled += n,
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        }
+    }
+    Poll::Ready(Ok(()))
+}
+
+/// Future for `read_fio`.
+#[must_use = "futures do nothing unless polled"]
+pub struct ReadFio<'a, R: ?Sized + 'a, T: ?Sized + 'a, B> {
+    reader: &'a mut R,
+    value: &'a mut T,
+    buffer: Vec<u8>,
+    filled: usize,
+    backend: PhantomData<fn() -> B>,
+}
+
+impl<'a, R: ?Sized, T: IO + ?Sized, B> ReadFio<'a, R, T, B> {
+    fn new(reader: &'a mut R, value: &'a mut T) -> ReadFio<'a, R, T, B> {
+        let length = size_of_val(value);
+        ReadFio {
+            reader,
+            value,
+            buffer: vec![0u8; length],
+            filled: 0,
+            backend: PhantomData,
+        }
+    }
+}
+
+impl<'a, R: ?Sized, T: IO + ?Sized, B: ReadBackend<R>> Future for ReadFio<'a, R, T, B> {
+    type Output = io::Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        match poll_fill::<R, B>(this.reader, cx, &mut this.buffer, &mut this.filled) {
+            Poll::Ready(Ok(())) => {
+                this.value.fio_read(&this.buffer);
+                Poll::Ready(Ok(this.filled))
+            }
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Future for `read_vlq`.
+#[cfg(feature="vlq")]
+#[must_use = "futures do nothing unless polled"]
+pub struct ReadVlq<'a, R: ?Sized + 'a, V: 'a, B> {
+    reader: &'a mut R,
+    value: &'a mut V,
+    buffer: [u8; MAX_VLQ_LEN],
+    length: usize,
+    backend: PhantomData<fn() -> B>,
+}
+
+#[cfg(feature="vlq")]
+impl<'a, R: ?Sized, V: VLQ, B> ReadVlq<'a, R, V, B> {
+    fn new(reader: &'a mut R, value: &'a mut V) -> ReadVlq<'a, R, V, B> {
+        ReadVlq {
+            reader,
+            value,
+            buffer: [0u8; MAX_VLQ_LEN],
+            length: 0,
+            backend: PhantomData,
+        }
+    }
+}
+
+// Reads a VLQ one byte at a time, so nothing past its end is consumed.
+// Returns the length of the VLQ in `buffer`.
+#[cfg(feature="vlq")]
+fn poll_vlq<R: ?Sized, B: ReadBackend<R>>(
+    reader: &mut R,
+    cx: &mut Context,
+    buffer: &mut [u8],
+    length: &mut usize,
+    max: usize,
+) -> Poll<io::Result<usize>> {
+    loop {
+        if *length > 0 && (buffer[*length - 1] & 128) == 0 {
+            return Poll::Ready(Ok(*length));
+        }
+        if *length == max {
+            return Poll::Ready(Err(malformed_vlq()));
+        }
+        match B::poll_read(reader, cx, &mut buffer[*length..*length + 1]) {
+            Poll::Ready(Ok(0)) => return Poll::Ready(Err(eof())),
+            Poll::Ready(Ok(_)) => *length += 1,
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        }
+    }
+}
+
+#[cfg(feature="vlq")]
+fn malformed_vlq() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "malformed VLQ")
+}
+
+#[cfg(feature="vlq")]
+impl<'a, R: ?Sized, V: VLQ, B: ReadBackend<R>> Future for ReadVlq<'a, R, V, B> {
+    type Output = io::Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let max = vlq_max::<V>();
+        match poll_vlq::<R, B>(this.reader, cx, &mut this.buffer, &mut this.length, max) {
+            Poll::Ready(Ok(length)) if !vlq_fits::<V>(&this.buffer, length) => Poll::Ready(Err(malformed_vlq())),
+            Poll::Ready(Ok(length)) => Poll::Ready(Ok(this.value.vlq_read(&this.buffer[..length]))),
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+// Decodes a length prefix, returning the length and the size of the
+// prefix (`read_fio_prefix` or `read_vlq_prefix`).
+#[cfg(any(feature="io-string", feature="vlq-string"))]
+type Decode = fn(&[u8]) -> Result<(usize, usize), PrefixError>;
+
+// How a string's length prefix is read, and then decoded.
+#[cfg(any(feature="io-string", feature="vlq-string"))]
+#[derive(Clone, Copy)]
+enum Prefix {
+    // An `IO` value of the given size.
+    #[cfg(feature="io-string")]
+    Fixed(usize, Decode),
+    // A `VLQ` value of at most the given number of bytes.
+    #[cfg(feature="vlq-string")]
+    Vlq(usize, Decode),
+}
+
+/// Future for `read_fio_string` and `read_vlq_string`.
+#[cfg(any(feature="io-string", feature="vlq-string"))]
+#[must_use = "futures do nothing unless polled"]
+pub struct ReadString<'a, R: ?Sized + 'a, B> {
+    reader: &'a mut R,
+    string: &'a mut String,
+    max: usize,
+    prefix: Prefix,
+    header: [u8; MAX_PREFIX_LEN],
+    header_len: usize,
+    body: Option<Vec<u8>>,
+    filled: usize,
+    backend: PhantomData<fn() -> B>,
+}
+
+#[cfg(any(feature="io-string", feature="vlq-string"))]
+impl<'a, R: ?Sized, B> ReadString<'a, R, B> {
+    fn new(reader: &'a mut R, string: &'a mut String, max: usize, prefix: Prefix) -> ReadString<'a, R, B> {
+        ReadString {
+            reader,
+            string,
+            max,
+            prefix,
+            header: [0u8; MAX_PREFIX_LEN],
+            header_len: 0,
+            body: None,
+            filled: 0,
+            backend: PhantomData,
+        }
+    }
+}
+
+#[cfg(any(feature="io-string", feature="vlq-string"))]
+impl<'a, R: ?Sized, B: ReadBackend<R>> Future for ReadString<'a, R, B> {
+    type Output = io::Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        if this.body.is_none() {
+            let header = match this.prefix {
+                #[cfg(feature="io-string")]
+                Prefix::Fixed(size, decode) => {
+                    if size > this.header.len() {
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "length prefix too large",
+                        )));
+                    }
+                    match poll_fill::<R, B>(this.reader, cx, &mut this.header[..size], &mut this.header_len) {
+                        Poll::Ready(Ok(())) => decode(&this.header[..size]),
+                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+                #[cfg(feature="vlq-string")]
+                Prefix::Vlq(max, decode) => {
+                    match poll_vlq::<R, B>(this.reader, cx, &mut this.header, &mut this.header_len, max) {
+                        Poll::Ready(Ok(length)) => decode(&this.header[..length]),
+                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+            };
+            match header {
+                Ok((length, _)) if length <= this.max => this.body = Some(vec![0u8; length]),
+                #[cfg(feature="vlq-string")]
+                Err(PrefixError::MalformedVlq) => return Poll::Ready(Err(malformed_vlq())),
+                _ => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "string length exceeds the maximum",
+                    )))
+                }
+            }
+        }
+
+        let body = this.body.as_mut().unwrap();
+        match poll_fill::<R, B>(this.reader, cx, body, &mut this.filled) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        }
+        match String::from_utf8(::core::mem::take(body)) {
+            Ok(string) => {
+                *this.string = string;
+                Poll::Ready(Ok(this.header_len + this.filled))
+            }
+            Err(_) => Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, "invalid UTF-8"))),
+        }
+    }
+}
+
+/// Future for every write; the bytes are encoded up front and written
+/// out in full.
+#[must_use = "futures do nothing unless polled"]
+pub struct WriteAll<'a, W: ?Sized + 'a, B> {
+    writer: &'a mut W,
+    buffer: Vec<u8>,
+    written: usize,
+    error: Option<io::Error>,
+    backend: PhantomData<fn() -> B>,
+}
+
+impl<'a, W: ?Sized, B> WriteAll<'a, W, B> {
+    fn new(writer: &'a mut W, buffer: Vec<u8>) -> WriteAll<'a, W, B> {
+        WriteAll {
+            writer,
+            buffer,
+            written: 0,
+            error: None,
+            backend: PhantomData,
+        }
+    }
+
+    fn fio<T: IO + ?Sized>(writer: &'a mut W, value: &mut T) -> WriteAll<'a, W, B> {
+        let mut buffer = vec![0u8; size_of_val(value)];
+        let written = value.fio_write(&mut buffer[..]);
+        buffer.truncate(written);
+        WriteAll::new(writer, buffer)
+    }
+
+    #[cfg(feature="vlq")]
+    fn vlq<V: VLQ>(writer: &'a mut W, value: &V) -> WriteAll<'a, W, B> {
+        let mut buffer = [0u8; MAX_VLQ_LEN];
+        let written = value.vlq_write(&mut buffer[..]);
+        WriteAll::new(writer, buffer[..written].to_vec())
+    }
+
+    // Fails (when polled) with `InvalidInput` if the prefix can't hold
+    // the length of `value`.
+    #[cfg(any(feature="io-string", feature="vlq-string"))]
+    fn string(writer: &'a mut W, value: &str, prefix: Option<Vec<u8>>) -> WriteAll<'a, W, B> {
+        match prefix {
+            Some(mut buffer) => {
+                buffer.extend_from_slice(value.as_bytes());
+                WriteAll::new(writer, buffer)
+            }
+            None => {
+                let mut future = WriteAll::new(writer, Vec::new());
+                future.error = Some(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "string too long for its length prefix",
+                ));
+                future
+            }
+        }
+    }
+}
+
+// Encodes a length prefix with `write`, or returns None if it can't hold
+// `length`.
+#[cfg(any(feature="io-string", feature="vlq-string"))]
+fn prefix(length: usize, write: fn(usize, &mut [u8; MAX_PREFIX_LEN]) -> Option<usize>) -> Option<Vec<u8>> {
+    let mut buffer = [0u8; MAX_PREFIX_LEN];
+    write(length, &mut buffer).map(|size| buffer[..size].to_vec())
+}
+
+impl<'a, W: ?Sized, B: WriteBackend<W>> Future for WriteAll<'a, W, B> {
+    type Output = io::Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if let Some(e) = this.error.take() {
+            return Poll::Ready(Err(e));
+        }
+        while this.written < this.buffer.len() {
+            match B::poll_write(this.writer, cx, &this.buffer[this.written..]) {
+                Poll::Ready(Ok(0)) => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write whole value",
+                    )))
+                }
+                Poll::Ready(Ok(n)) => this.written += n,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(this.written))
+    }
+}
+
+/// Extension traits for tokio's `AsyncRead` and `AsyncWrite`.
+#[cfg(feature="tokio")]
+pub mod tokio {
+    use core::pin::Pin;
+    use core::task::{Context, Poll};
+
+    use std::io;
+
+    use ::tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    #[cfg(any(feature="io-string", feature="vlq-string"))]
+    use num_traits::{cast::{FromPrimitive, ToPrimitive}, sign::Unsigned};
+
+    use IO;
+
+    #[cfg(feature="vlq")]
+    use VLQ;
+
+    use super::backend::{ReadBackend, WriteBackend};
+    use super::{ReadFio, WriteAll};
+
+    #[cfg(feature="vlq")]
+    use super::ReadVlq;
+
+    #[cfg(any(feature="io-string", feature="vlq-string"))]
+    use super::{Prefix, ReadString};
+
+    /// Marks the futures returned by `FioReadExt` and `FioWriteExt` as
+    /// running on tokio.
+    #[derive(Debug, Clone, Copy)]
+    pub struct Tokio;
+
+    impl<R: AsyncRead + Unpin + ?Sized> ReadBackend<R> for Tokio {
+        fn poll_read(reader: &mut R, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+            let mut buf = ReadBuf::new(buf);
+            match Pin::new(reader).poll_read(cx, &mut buf) {
+                Poll::Ready(Ok(())) => Poll::Ready(Ok(buf.filled().len())),
+                Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+                Poll::Pending => Poll::Pending,
+            }
+        }
+    }
+
+    impl<W: AsyncWrite + Unpin + ?Sized> WriteBackend<W> for Tokio {
+        fn poll_write(writer: &mut W, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+            Pin::new(writer).poll_write(cx, buf)
+        }
+    }
+
+    /// Reads fricgan values from a tokio `AsyncRead`.
+    pub trait FioReadExt: AsyncRead + Unpin {
+        /// Reads an `IO` value.
+        fn read_fio<'a, T: IO + ?Sized>(&'a mut self, value: &'a mut T) -> ReadFio<'a, Self, T, Tokio> {
+            ReadFio::new(self, value)
+        }
+
+        /// Reads a `VLQ` value.
+        #[cfg(feature="vlq")]
+        fn read_vlq<'a, V: VLQ>(&'a mut self, value: &'a mut V) -> ReadVlq<'a, Self, V, Tokio> {
+            ReadVlq::new(self, value)
+        }
+
+        /// Reads a string prefixed with its length as an `IO` value `V`,
+        /// failing if the length exceeds `max`.
+        #[cfg(feature="io-string")]
+        fn read_fio_string<'a, V>(&'a mut self, string: &'a mut String, max: usize) -> ReadString<'a, Self, Tokio>
+        where V: ToPrimitive + FromPrimitive + Unsigned + IO {
+            let prefix = Prefix::Fixed(::core::mem::size_of::<V>(), ::read_fio_prefix::<V>);
+            ReadString::new(self, string, max, prefix)
+        }
+
+        /// Reads a string prefixed with its length as a `VLQ` value `V`,
+        /// failing if the length exceeds `max`.
+        #[cfg(feature="vlq-string")]
+        fn read_vlq_string<'a, V>(&'a mut self, string: &'a mut String, max: usize) -> ReadString<'a, Self, Tokio>
+        where V: ToPrimitive + FromPrimitive + Unsigned + VLQ {
+            ReadString::new(self, string, max, Prefix::Vlq(::vlq_max::<V>(), ::read_vlq_prefix::<V>))
+        }
+    }
+
+    impl<R: AsyncRead + Unpin + ?Sized> FioReadExt for R {}
+
+    /// Writes fricgan values to a tokio `AsyncWrite`.
+    pub trait FioWriteExt: AsyncWrite + Unpin {
+        /// Writes an `IO` value.
+        fn write_fio<T: IO + ?Sized>(&mut self, value: &mut T) -> WriteAll<'_, Self, Tokio> {
+            WriteAll::fio(self, value)
+        }
+
+        /// Writes a `VLQ` value.
+        #[cfg(feature="vlq")]
+        fn write_vlq<V: VLQ>(&mut self, value: &V) -> WriteAll<'_, Self, Tokio> {
+            WriteAll::vlq(self, value)
+        }
+
+        /// Writes `value` prefixed with its length as an `IO` value `V`.
+        #[cfg(feature="io-string")]
+        fn write_fio_string<V>(&mut self, value: &str) -> WriteAll<'_, Self, Tokio>
+        where V: ToPrimitive + FromPrimitive + Unsigned + IO {
+            WriteAll::string(self, value, super::prefix(value.len(), ::write_fio_prefix::<V>))
+        }
+
+        /// Writes `value` prefixed with its length as a `VLQ` value `V`.
+        #[cfg(feature="vlq-string")]
+        fn write_vlq_string<V>(&mut self, value: &str) -> WriteAll<'_, Self, Tokio>
+        where V: ToPrimitive + FromPrimitive + Unsigned + VLQ {
+            WriteAll::string(self, value, super::prefix(value.len(), ::write_vlq_prefix::<V>))
+        }
+    }
+
+    impl<W: AsyncWrite + Unpin + ?Sized> FioWriteExt for W {}
+
+    #[cfg(feature="tokio-codec")]
+    pub use self::codec::{CodecError, FrameCodec};
+
+    #[cfg(feature="tokio-codec")]
+    mod codec {
+        use std::io;
+
+        use bytes::{Buf, Bytes, BytesMut};
+        use tokio_util::codec::{Decoder, Encoder};
+
+        use framing::{FrameConfig, FrameError, FrameReader, FrameWriter};
+
+        /// Errors produced by `FrameCodec`.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum CodecError {
+            /// A frame could not be written or read.
+            Frame(FrameError),
+            /// The underlying stream failed.
+            Io(io::ErrorKind),
+        }
+
+        impl From<FrameError> for CodecError {
+            fn from(e: FrameError) -> CodecError {
+                CodecError::Frame(e)
+            }
+        }
+
+        impl From<io::Error> for CodecError {
+            fn from(e: io::Error) -> CodecError {
+                CodecError::Io(e.kind())
+            }
+        }
+
+        /// A `tokio_util` codec for frames; both ends need the same
+        /// `FrameConfig`.  Decoded items are the frame payloads.
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct FrameCodec {
+            reader: FrameReader,
+            writer: FrameWriter,
+        }
+
+        impl FrameCodec {
+            /// Creates a codec from `config`.
+            pub fn new(config: FrameConfig) -> FrameCodec {
+                FrameCodec {
+                    reader: FrameReader::new(config),
+                    writer: FrameWriter::new(config),
+                }
+            }
+
+            /// The configuration of the codec.
+            pub fn config(&self) -> &FrameConfig {
+                self.writer.config()
+            }
+        }
+
+        impl Decoder for FrameCodec {
+            type Item = BytesMut;
+            type Error = CodecError;
+
+            fn decode(&mut self, src: &mut BytesMut) -> Result<Option<BytesMut>, CodecError> {
+                let (start, length, used) = match self.reader.decode(&src[..])? {
+                    Some((payload, used)) => {
+                        let start = payload.as_ptr() as usize - src.as_ptr() as usize;
+                        (start, payload.len(), used)
+                    }
+                    None => return Ok(None),
+                };
+                let mut frame = src.split_to(used);
+                frame.advance(start);
+                frame.truncate(length);
+                Ok(Some(frame))
+            }
+        }
+
+        impl<'b> Encoder<&'b [u8]> for FrameCodec {
+            type Error = CodecError;
+
+            fn encode(&mut self, item: &'b [u8], dst: &mut BytesMut) -> Result<(), CodecError> {
+                let length = self.writer.config().frame_len(item.len());
+                let start = dst.len();
+                dst.resize(start + length, 0);
+                match self.writer.write(item, &mut dst[start..]) {
+                    Ok(_) => Ok(()),
+                    Err(e) => {
+                        dst.truncate(start);
+                        Err(e.into())
+                    }
+                }
+            }
+        }
+
+        impl Encoder<Bytes> for FrameCodec {
+            type Error = CodecError;
+
+            fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<(), CodecError> {
+                self.encode(&item[..], dst)
+            }
+        }
+    }
+}
+
+/// Extension traits for `futures::io`'s `AsyncRead` and `AsyncWrite`.
+#[cfg(feature="futures-io")]
+pub mod futures {
+    use core::pin::Pin;
+    use core::task::{Context, Poll};
+
+    use std::io;
+
+    use ::futures_io::{AsyncRead, AsyncWrite};
+
+    #[cfg(any(feature="io-string", feature="vlq-string"))]
+    use num_traits::{cast::{FromPrimitive, ToPrimitive}, sign::Unsigned};
+
+    use IO;
+
+    #[cfg(feature="vlq")]
+    use VLQ;
+
+    use super::backend::{ReadBackend, WriteBackend};
+    use super::{ReadFio, WriteAll};
+
+    #[cfg(feature="vlq")]
+    use super::ReadVlq;
+
+    #[cfg(any(feature="io-string", feature="vlq-string"))]
+    use super::{Prefix, ReadString};
+
+    /// Marks the futures returned by `FioReadExt` and `FioWriteExt` as
+    /// running on `futures::io`.
+    #[derive(Debug, Clone, Copy)]
+    pub struct Futures;
+
+    impl<R: AsyncRead + Unpin + ?Sized> ReadBackend<R> for Futures {
+        fn poll_read(reader: &mut R, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+            Pin::new(reader).poll_read(cx, buf)
+        }
+    }
+
+    impl<W: AsyncWrite + Unpin + ?Sized> WriteBackend<W> for Futures {
+        fn poll_write(writer: &mut W, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+            Pin::new(writer).poll_write(cx, buf)
+        }
+    }
+
+    /// Reads fricgan values from a `futures::io::AsyncRead`.
+    pub trait FioReadExt: AsyncRead + Unpin {
+        /// Reads an `IO` value.
+        fn read_fio<'a, T: IO + ?Sized>(&'a mut self, value: &'a mut T) -> ReadFio<'a, Self, T, Futures> {
+            ReadFio::new(self, value)
+        }
+
+        /// Reads a `VLQ` value.
+        #[cfg(feature="vlq")]
+        fn read_vlq<'a, V: VLQ>(&'a mut self, value: &'a mut V) -> ReadVlq<'a, Self, V, Futures> {
+            ReadVlq::new(self, value)
+        }
+
+        /// Reads a string prefixed with its length as an `IO` value `V`,
+        /// failing if the length exceeds `max`.
+        #[cfg(feature="io-string")]
+        fn read_fio_string<'a, V>(&'a mut self, string: &'a mut String, max: usize) -> ReadString<'a, Self, Futures>
+        where V: ToPrimitive + FromPrimitive + Unsigned + IO {
+            let prefix = Prefix::Fixed(::core::mem::size_of::<V>(), ::read_fio_prefix::<V>);
+            ReadString::new(self, string, max, prefix)
+        }
+
+        /// Reads a string prefixed with its length as a `VLQ` value `V`,
+        /// failing if the length exceeds `max`.
+        #[cfg(feature="vlq-string")]
+        fn read_vlq_string<'a, V>(&'a mut self, string: &'a mut String, max: usize) -> ReadString<'a, Self, Futures>
+        where V: ToPrimitive + FromPrimitive + Unsigned + VLQ {
+            ReadString::new(self, string, max, Prefix::Vlq(::vlq_max::<V>(), ::read_vlq_prefix::<V>))
+        }
+    }
+
+    impl<R: AsyncRead + Unpin + ?Sized> FioReadExt for R {}
+
+    /// Writes fricgan values to a `futures::io::AsyncWrite`.
+    pub trait FioWriteExt: AsyncWrite + Unpin {
+        /// Writes an `IO` value.
+        fn write_fio<T: IO + ?Sized>(&mut self, value: &mut T) -> WriteAll<'_, Self, Futures> {
+            WriteAll::fio(self, value)
+        }
+
+        /// Writes a `VLQ` value.
+        #[cfg(feature="vlq")]
+        fn write_vlq<V: VLQ>(&mut self, value: &V) -> WriteAll<'_, Self, Futures> {
+            WriteAll::vlq(self, value)
+        }
+
+        /// Writes `value` prefixed with its length as an `IO` value `V`.
+        #[cfg(feature="io-string")]
+        fn write_fio_string<V>(&mut self, value: &str) -> WriteAll<'_, Self, Futures>
+        where V: ToPrimitive + FromPrimitive + Unsigned + IO {
+            WriteAll::string(self, value, super::prefix(value.len(), ::write_fio_prefix::<V>))
+        }
+
+        /// Writes `value` prefixed with its length as a `VLQ` value `V`.
+        #[cfg(feature="vlq-string")]
+        fn write_vlq_string<V>(&mut self, value: &str) -> WriteAll<'_, Self, Futures>
+        where V: ToPrimitive + FromPrimitive + Unsigned + VLQ {
+            WriteAll::string(self, value, super::prefix(value.len(), ::write_vlq_prefix::<V>))
+        }
+    }
+
+    impl<W: AsyncWrite + Unpin + ?Sized> FioWriteExt for W {}
+}
+
+// A waker which does nothing, for `block_on`.
+#[cfg(test)]
+#[allow(dead_code)]
+fn noop_waker() -> ::std::task::Waker {
+    use std::task::{RawWaker, RawWakerVTable, Waker};
+
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(::core::ptr::null(), &VTABLE)
+    }
+    fn noop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+    unsafe { Waker::from_raw(RawWaker::new(::core::ptr::null(), &VTABLE)) }
+}
+
+// Polls `future` to completion with a no-op waker; the readers in the
+// tests never return `Pending` without being ready on the next poll.
+#[cfg(test)]
+#[allow(dead_code)]
+fn block_on<F: Future + Unpin>(mut future: F) -> F::Output {
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = Pin::new(&mut future).poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+#[cfg(all(feature="tokio", feature="io-u32", feature="vlq-32", feature="vlq-string"))]
+#[test]
+fn test_asyncio_tokio() {
+    use self::tokio::{FioReadExt, FioWriteExt};
+
+    let mut sink: Vec<u8> = Vec::new();
+    let mut value = 0x01020304u32.to_be();
+    assert_eq!(block_on(sink.write_fio(&mut value)).unwrap(), 4);
+    assert_eq!(block_on(sink.write_vlq(&300u32)).unwrap(), 2);
+    assert_eq!(block_on(sink.write_vlq_string::<u32>("hello")).unwrap(), 6);
+    assert_eq!(sink, b"\x01\x02\x03\x04\xAC\x02\x05hello");
+
+    let mut source = &sink[..];
+    let mut value = 0u32;
+    let mut vlq = 0u32;
+    let mut string = String::new();
+    assert_eq!(block_on(source.read_fio(&mut value)).unwrap(), 4);
+    assert_eq!(u32::from_be(value), 0x01020304);
+    assert_eq!(block_on(source.read_vlq(&mut vlq)).unwrap(), 2);
+    assert_eq!(vlq, 300);
+    assert_eq!(block_on(source.read_vlq_string::<u32>(&mut string, 16)).unwrap(), 6);
+    assert_eq!(string, "hello");
+    assert!(source.is_empty());
+
+    let error = block_on(source.read_fio(&mut value)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+// Hands out one byte per read, returning `Pending` in between.
+#[cfg(all(test, feature="futures-io", feature="io-u16", feature="vlq-64", feature="io-string"))]
+struct Trickle<'a> {
+    data: &'a [u8],
+    ready: bool,
+}
+
+#[cfg(all(test, feature="futures-io", feature="io-u16", feature="vlq-64", feature="io-string"))]
+impl<'a> ::futures_io::AsyncRead for Trickle<'a> {
+    fn poll_read(mut self: Pin<&mut Self>, _: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        if !self.ready {
+            self.ready = true;
+            return Poll::Pending;
+        }
+        self.ready = false;
+        if self.data.is_empty() || buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        buf[0] = self.data[0];
+        self.data = &self.data[1..];
+        Poll::Ready(Ok(1))
+    }
+}
+
+#[cfg(all(feature="futures-io", feature="io-u16", feature="vlq-64", feature="io-string"))]
+#[test]
+fn test_asyncio_futures() {
+    use self::futures::{FioReadExt, FioWriteExt};
+
+    let mut sink: Vec<u8> = Vec::new();
+    let mut value = 7u16.to_le();
+    assert_eq!(block_on(sink.write_fio(&mut value)).unwrap(), 2);
+    assert_eq!(block_on(sink.write_vlq(&0xFFFFFFFFFFu64)).unwrap(), 6);
+    assert_eq!(block_on(sink.write_fio_string::<u16>("héllo")).unwrap(), 8);
+
+    let mut source = Trickle { data: &sink[..], ready: false };
+    let mut value = 0u16;
+    let mut vlq = 0u64;
+    let mut string = String::new();
+    assert_eq!(block_on(source.read_fio(&mut value)).unwrap(), 2);
+    assert_eq!(u16::from_le(value), 7);
+    assert_eq!(block_on(source.read_vlq(&mut vlq)).unwrap(), 6);
+    assert_eq!(vlq, 0xFFFFFFFFFF);
+    assert_eq!(block_on(source.read_fio_string::<u16>(&mut string, 6)).unwrap(), 8);
+    assert_eq!(string, "héllo");
+
+    let error = block_on(sink.write_fio_string::<u16>(&"x".repeat(0x10000))).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[cfg(all(feature="futures-io", feature="vlq-32", feature="vlq-string"))]
+#[test]
+fn test_asyncio_errors() {
+    use self::futures::FioReadExt;
+
+    let mut vlq = 0u32;
+    let mut source = &[0xFFu8, 0xFF, 0xFF, 0xFF, 0xFF, 0x01][..];
+    let error = block_on(source.read_vlq(&mut vlq)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+    // Five bytes, but with bits above the 32 a `u32` holds.
+    let mut source = &[0xFFu8, 0xFF, 0xFF, 0xFF, 0x1F][..];
+    let error = block_on(source.read_vlq(&mut vlq)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+    let mut string = String::new();
+    let mut source = &b"\x05hello"[..];
+    let error = block_on(source.read_vlq_string::<u32>(&mut string, 4)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+    let mut source = &b"\x02\xC3\x28"[..];
+    let error = block_on(source.read_vlq_string::<u32>(&mut string, 4)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+    let mut source = &b"\x83\x80\x80\x80\x10abc"[..];
+    let error = block_on(source.read_vlq_string::<u32>(&mut string, 4)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+    let mut source = &b"\x05hel"[..];
+    let error = block_on(source.read_vlq_string::<u32>(&mut string, 8)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[cfg(feature="tokio-codec")]
+#[test]
+fn test_asyncio_codec() {
+    use bytes::BytesMut;
+    use tokio_util::codec::{Decoder, Encoder};
+    use framing::{FrameConfig, FrameError, LengthPrefix};
+    use self::tokio::{CodecError, FrameCodec};
+
+    let mut codec = FrameCodec::new(FrameConfig::new(LengthPrefix::U16Be).crc32(true));
+    let mut buffer = BytesMut::new();
+    codec.encode(&b"first"[..], &mut buffer).unwrap();
+    codec.encode(&b""[..], &mut buffer).unwrap();
+    assert_eq!(buffer.len(), 11 + 6);
+
+    let mut input = BytesMut::new();
+    input.extend_from_slice(&buffer[..4]);
+    assert_eq!(codec.decode(&mut input), Ok(None));
+    input.extend_from_slice(&buffer[4..]);
+    assert_eq!(codec.decode(&mut input).unwrap().unwrap(), &b"first"[..]);
+    assert_eq!(codec.decode(&mut input).unwrap().unwrap(), &b""[..]);
+    assert_eq!(codec.decode(&mut input), Ok(None));
+
+    let mut codec = FrameCodec::new(FrameConfig::new(LengthPrefix::U16Le).max_frame_size(4));
+    let mut buffer = BytesMut::new();
+    assert_eq!(
+        codec.encode(&b"hello"[..], &mut buffer),
+        Err(CodecError::Frame(FrameError::TooLarge { length: 5, max: 4 }))
+    );
+    assert!(buffer.is_empty());
+}
@@ -0,0 +1,426 @@
+//! Length-prefixed strings and vectors with a fixed capacity, for targets
+//! without an allocator.
+//!
+//! `BoundedString` is implemented for `heapless::String<N>` (with the
+//! `heapless` feature) and `arrayvec::ArrayString<N>` (with `arrayvec`),
+//! and `BoundedVec` for `heapless::Vec<T, N>` and `arrayvec::ArrayVec<T,
+//! N>`.  Their methods mirror `FricganString` and `VLQString`: the length
+//! prefix is an `IO` value `V` (in native byte order) for the `fio_`
+//! methods, and a `VLQ` value `V` for the `vlq_` methods.  A string's
+//! prefix counts bytes; a vector's counts elements, which are `IO`
+//! values.
+//!
+//! Where the input holds more than the container does, reading fails
+//! with `CapacityExceeded` (before anything is read) rather than
+//! panicking.  On any failure the container is left unchanged.
+
+use core::mem::size_of;
+
+use num_traits::{cast::{FromPrimitive, ToPrimitive}, sign::Unsigned};
+
+use IO;
+
+#[cfg(feature="vlq")]
+use VLQ;
+
+use {read_fio_prefix, write_fio_prefix, PrefixError, MAX_PREFIX_LEN};
+
+#[cfg(feature="vlq")]
+use {read_vlq_prefix, write_vlq_prefix};
+
+/// Errors produced reading or writing bounded strings and vectors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoundedError {
+    /// The sink is too small.
+    BufferTooSmall {
+        /// The number of bytes the value requires.
+        required: usize,
+    },
+    /// The input ended before the value being read.
+    Eof,
+    /// A VLQ length was longer than its type allows, or its value
+    /// overflowed it.
+    MalformedVlq,
+    /// The length is more than the container can hold.
+    CapacityExceeded {
+        /// The length read.
+        length: usize,
+        /// The capacity of the container.
+        capacity: usize,
+    },
+    /// The length doesn't fit the type of the prefix (or `usize`).
+    LengthOverflow,
+    /// A string was not valid UTF-8.
+    InvalidUtf8,
+}
+
+mod store {
+    // What the containers share, so the traits can be implemented once.
+    pub trait StrStore {
+        fn capacity(&self) -> usize;
+        fn as_str(&self) -> &str;
+        // Replaces the contents; `value` fits (the caller checks).
+        fn replace(&mut self, value: &str);
+    }
+
+    pub trait VecStore<T> {
+        fn capacity(&self) -> usize;
+        fn as_mut_slice(&mut self) -> &mut [T];
+        fn clear(&mut self);
+        // Appends `value`; there is room (the caller checks).
+        fn push(&mut self, value: T);
+    }
+}
+
+use self::store::{StrStore, VecStore};
+
+#[cfg(feature="heapless")]
+impl<const N: usize> StrStore for ::heapless::String<N> {
+    fn capacity(&self) -> usize {
+        N
+    }
+
+    fn as_str(&self) -> &str {
+        self.as_str()
+    }
+
+    fn replace(&mut self, value: &str) {
+        self.clear();
+        let _ = self.push_str(value);
+    }
+}
+
+#[cfg(feature="heapless")]
+impl<T, const N: usize> VecStore<T> for ::heapless::Vec<T, N> {
+    fn capacity(&self) -> usize {
+        N
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+
+    fn clear(&mut self) {
+        self.clear();
+    }
+
+    fn push(&mut self, value: T) {
+        let _ = self.push(value);
+    }
+}
+
+#[cfg(feature="arrayvec")]
+impl<const N: usize> StrStore for ::arrayvec::ArrayString<N> {
+    fn capacity(&self) -> usize {
+        N
+    }
+
+    fn as_str(&self) -> &str {
+        self.as_str()
+    }
+
+    fn replace(&mut self, value: &str) {
+        self.clear();
+        let _ = self.try_push_str(value);
+    }
+}
+
+#[cfg(feature="arrayvec")]
+impl<T, const N: usize> VecStore<T> for ::arrayvec::ArrayVec<T, N> {
+    fn capacity(&self) -> usize {
+        N
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+
+    fn clear(&mut self) {
+        self.clear();
+    }
+
+    fn push(&mut self, value: T) {
+        let _ = self.try_push(value);
+    }
+}
+
+// Maps an error reading a length prefix.
+fn prefix_error(e: PrefixError) -> BoundedError {
+    match e {
+        PrefixError::Eof => BoundedError::Eof,
+        #[cfg(feature="vlq")]
+        PrefixError::MalformedVlq => BoundedError::MalformedVlq,
+        PrefixError::Overflow => BoundedError::LengthOverflow,
+    }
+}
+
+// Copies an encoded length prefix to `sink`, provided `required` more
+// bytes fit after it.
+fn write_prefix(prefix: &[u8], required: usize, sink: &mut [u8]) -> Result<usize, BoundedError> {
+    let required = prefix.len() + required;
+    if sink.len() < required {
+        return Err(BoundedError::BufferTooSmall { required });
+    }
+    sink[..prefix.len()].copy_from_slice(prefix);
+    Ok(prefix.len())
+}
+
+fn read_str<S: StrStore + ?Sized>(string: &mut S, source: &[u8], length: usize, offset: usize) -> Result<usize, BoundedError> {
+    if length > string.capacity() {
+        return Err(BoundedError::CapacityExceeded { length, capacity: string.capacity() });
+    }
+    if source.len() - offset < length {
+        return Err(BoundedError::Eof);
+    }
+    let value = ::core::str::from_utf8(&source[offset..offset + length])
+        .map_err(|_| BoundedError::InvalidUtf8)?;
+    string.replace(value);
+    Ok(offset + length)
+}
+
+fn read_vec<T: IO + Default, S: VecStore<T> + ?Sized>(vec: &mut S, source: &[u8], length: usize, offset: usize) -> Result<usize, BoundedError> {
+    if length > vec.capacity() {
+        return Err(BoundedError::CapacityExceeded { length, capacity: vec.capacity() });
+    }
+    // Each element takes `size_of::<T>()` bytes (see `IO`), which may
+    // be none at all.
+    match length.checked_mul(size_of::<T>()) {
+        Some(required) if required <= source.len() - offset => {}
+        _ => return Err(BoundedError::Eof),
+    }
+    vec.clear();
+    let mut read = offset;
+    for _ in 0..length {
+        let mut value = T::default();
+        read += value.fio_read(&source[read..]);
+        vec.push(value);
+    }
+    Ok(read)
+}
+
+fn write_vec<T: IO>(values: &mut [T], sink: &mut [u8], offset: usize) -> usize {
+    let mut written = offset;
+    for value in values.iter_mut() {
+        written += value.fio_write(&mut sink[written..]);
+    }
+    written
+}
+
+/// Length-prefixed reading and writing for fixed-capacity strings.
+pub trait BoundedString: StrStore {
+    /// Reads a string prefixed with its length as an `IO` value `V`.
+    ///
+    /// The return value is the number of bytes read.
+    fn fio_string_read<V>(&mut self, source: &[u8]) -> Result<usize, BoundedError>
+    where V: ToPrimitive + FromPrimitive + Unsigned + IO {
+        let (length, offset) = read_fio_prefix::<V>(source).map_err(prefix_error)?;
+        read_str(self, source, length, offset)
+    }
+
+    /// Writes the string prefixed with its length as an `IO` value `V`.
+    ///
+    /// The return value is the number of bytes written.
+    fn fio_string_write<V>(&self, sink: &mut [u8]) -> Result<usize, BoundedError>
+    where V: ToPrimitive + FromPrimitive + Unsigned + IO {
+        let value = self.as_str().as_bytes();
+        let mut prefix = [0u8; MAX_PREFIX_LEN];
+        let size = write_fio_prefix::<V>(value.len(), &mut prefix).ok_or(BoundedError::LengthOverflow)?;
+        let offset = write_prefix(&prefix[..size], value.len(), sink)?;
+        sink[offset..offset + value.len()].copy_from_slice(value);
+        Ok(offset + value.len())
+    }
+
+    /// Reads a string prefixed with its length as a `VLQ` value `V`.
+    ///
+    /// The return value is the number of bytes read.
+    #[cfg(feature="vlq")]
+    fn vlq_string_read<V>(&mut self, source: &[u8]) -> Result<usize, BoundedError>
+    where V: ToPrimitive + FromPrimitive + Unsigned + VLQ {
+        let (length, offset) = read_vlq_prefix::<V>(source).map_err(prefix_error)?;
+        read_str(self, source, length, offset)
+    }
+
+    /// Writes the string prefixed with its length as a `VLQ` value `V`.
+    ///
+    /// The return value is the number of bytes written.
+    #[cfg(feature="vlq")]
+    fn vlq_string_write<V>(&self, sink: &mut [u8]) -> Result<usize, BoundedError>
+    where V: ToPrimitive + FromPrimitive + Unsigned + VLQ {
+        let value = self.as_str().as_bytes();
+        let mut prefix = [0u8; MAX_PREFIX_LEN];
+        let size = write_vlq_prefix::<V>(value.len(), &mut prefix).ok_or(BoundedError::LengthOverflow)?;
+        let offset = write_prefix(&prefix[..size], value.len(), sink)?;
+        sink[offset..offset + value.len()].copy_from_slice(value);
+        Ok(offset + value.len())
+    }
+}
+
+impl<S: StrStore + ?Sized> BoundedString for S {}
+
+/// Length-prefixed reading and writing for fixed-capacity vectors of `IO`
+/// values.
+pub trait BoundedVec<T: IO + Default>: VecStore<T> {
+    /// Reads elements prefixed with their count as an `IO` value `V`.
+    ///
+    /// The return value is the number of bytes read.
+    fn fio_vec_read<V>(&mut self, source: &[u8]) -> Result<usize, BoundedError>
+    where V: ToPrimitive + FromPrimitive + Unsigned + IO {
+        let (length, offset) = read_fio_prefix::<V>(source).map_err(prefix_error)?;
+        read_vec(self, source, length, offset)
+    }
+
+    /// Writes the elements prefixed with their count as an `IO` value
+    /// `V`.
+    ///
+    /// The return value is the number of bytes written.
+    fn fio_vec_write<V>(&mut self, sink: &mut [u8]) -> Result<usize, BoundedError>
+    where V: ToPrimitive + FromPrimitive + Unsigned + IO {
+        let values = self.as_mut_slice();
+        let mut prefix = [0u8; MAX_PREFIX_LEN];
+        let size = write_fio_prefix::<V>(values.len(), &mut prefix).ok_or(BoundedError::LengthOverflow)?;
+        let offset = write_prefix(&prefix[..size], ::core::mem::size_of_val(values), sink)?;
+        Ok(write_vec(values, sink, offset))
+    }
+
+    /// Reads elements prefixed with their count as a `VLQ` value `V`.
+    ///
+    /// The return value is the number of bytes read.
+    #[cfg(feature="vlq")]
+    fn vlq_vec_read<V>(&mut self, source: &[u8]) -> Result<usize, BoundedError>
+    where V: ToPrimitive + FromPrimitive + Unsigned + VLQ {
+        let (length, offset) = read_vlq_prefix::<V>(source).map_err(prefix_error)?;
+        read_vec(self, source, length, offset)
+    }
+
+    /// Writes the elements prefixed with their count as a `VLQ` value
+    /// `V`.
+    ///
+    /// The return value is the number of bytes written.
+    #[cfg(feature="vlq")]
+    fn vlq_vec_write<V>(&mut self, sink: &mut [u8]) -> Result<usize, BoundedError>
+    where V: ToPrimitive + FromPrimitive + Unsigned + VLQ {
+        let values = self.as_mut_slice();
+        let mut prefix = [0u8; MAX_PREFIX_LEN];
+        let size = write_vlq_prefix::<V>(values.len(), &mut prefix).ok_or(BoundedError::LengthOverflow)?;
+        let offset = write_prefix(&prefix[..size], ::core::mem::size_of_val(values), sink)?;
+        Ok(write_vec(values, sink, offset))
+    }
+}
+
+impl<T: IO + Default, S: VecStore<T> + ?Sized> BoundedVec<T> for S {}
+
+#[cfg(all(feature="heapless", feature="io-u16", feature="vlq-32"))]
+#[test]
+fn test_bounded_heapless_string() {
+    use core::str::FromStr;
+
+    let mut data = [0u8; 16];
+    let a: ::heapless::String<8> = ::heapless::String::from_str("héllo").unwrap();
+    let mut b: ::heapless::String<8> = ::heapless::String::new();
+
+    assert_eq!(a.fio_string_write::<u16>(&mut data[..]), Ok(8));
+    assert_eq!(u16::from_ne_bytes([data[0], data[1]]), 6);
+    assert_eq!(b.fio_string_read::<u16>(&data[..]), Ok(8));
+    assert_eq!(b, a);
+
+    assert_eq!(a.vlq_string_write::<u32>(&mut data[..]), Ok(7));
+    assert_eq!(&data[..7], b"\x06h\xC3\xA9llo");
+    b.clear();
+    assert_eq!(b.vlq_string_read::<u32>(&data[..]), Ok(7));
+    assert_eq!(b, a);
+}
+
+#[cfg(all(feature="heapless", feature="io-u16", feature="vlq-32"))]
+#[test]
+fn test_bounded_heapless_vec() {
+    let mut data = [0u8; 16];
+    let mut a: ::heapless::Vec<u16, 4> = ::heapless::Vec::from_slice(&[1, 2, 300]).unwrap();
+    let mut b: ::heapless::Vec<u16, 4> = ::heapless::Vec::new();
+
+    assert_eq!(a.vlq_vec_write::<u32>(&mut data[..]), Ok(7));
+    assert_eq!(data[0], 3);
+    assert_eq!(b.vlq_vec_read::<u32>(&data[..]), Ok(7));
+    assert_eq!(b, a);
+
+    assert_eq!(a.fio_vec_write::<u16>(&mut data[..]), Ok(8));
+    assert_eq!(b.fio_vec_read::<u16>(&data[..]), Ok(8));
+    assert_eq!(b, a);
+
+    // Five elements don't fit in two.
+    let mut small: ::heapless::Vec<u16, 2> = ::heapless::Vec::new();
+    assert_eq!(
+        small.vlq_vec_read::<u32>(&[5, 0, 0]),
+        Err(BoundedError::CapacityExceeded { length: 5, capacity: 2 })
+    );
+}
+
+#[cfg(all(feature="arrayvec", feature="io-u32", feature="vlq-32"))]
+#[test]
+fn test_bounded_arrayvec() {
+    use arrayvec::{ArrayString, ArrayVec};
+
+    let mut data = [0u8; 16];
+    let a = ArrayString::<8>::from("fricgan").unwrap();
+    let mut b = ArrayString::<8>::new();
+    assert_eq!(a.vlq_string_write::<u32>(&mut data[..]), Ok(8));
+    assert_eq!(b.vlq_string_read::<u32>(&data[..]), Ok(8));
+    assert_eq!(b, a);
+
+    let mut c: ArrayVec<u32, 2> = ArrayVec::new();
+    c.push(0xDEADBEEF);
+    let mut d: ArrayVec<u32, 2> = ArrayVec::new();
+    assert_eq!(c.fio_vec_write::<u32>(&mut data[..]), Ok(8));
+    assert_eq!(d.fio_vec_read::<u32>(&data[..]), Ok(8));
+    assert_eq!(d, c);
+}
+
+#[cfg(all(feature="heapless", feature="io-u16", feature="vlq-32"))]
+#[test]
+fn test_bounded_errors() {
+    use core::str::FromStr;
+
+    let mut data = [0u8; 4];
+    let a: ::heapless::String<8> = ::heapless::String::from_str("hello").unwrap();
+    let mut b: ::heapless::String<4> = ::heapless::String::from_str("keep").unwrap();
+
+    assert_eq!(
+        a.vlq_string_write::<u32>(&mut data[..]),
+        Err(BoundedError::BufferTooSmall { required: 6 })
+    );
+    assert_eq!(
+        b.vlq_string_read::<u32>(b"\x05hello"),
+        Err(BoundedError::CapacityExceeded { length: 5, capacity: 4 })
+    );
+    assert_eq!(b.vlq_string_read::<u32>(b"\x03ab"), Err(BoundedError::Eof));
+    assert_eq!(b.vlq_string_read::<u32>(b"\x02\xC3\x28"), Err(BoundedError::InvalidUtf8));
+    assert_eq!(b.vlq_string_read::<u32>(&[0xFF; 5]), Err(BoundedError::MalformedVlq));
+    // 2^32 + 3 doesn't fit a `u32`, rather than reading as 3.
+    assert_eq!(
+        b.vlq_string_read::<u32>(&[0x83, 0x80, 0x80, 0x80, 0x10, b'a', b'b', b'c']),
+        Err(BoundedError::MalformedVlq)
+    );
+    assert_eq!(b, "keep");
+
+    let mut v: ::heapless::Vec<u16, 4> = ::heapless::Vec::new();
+    assert_eq!(v.fio_vec_read::<u16>(&[1]), Err(BoundedError::Eof));
+    assert_eq!(v.vlq_vec_read::<u32>(&[2, 0, 0, 0]), Err(BoundedError::Eof));
+
+    // Zero sized elements take no bytes.
+    #[derive(Debug, Default, PartialEq)]
+    struct Unit;
+
+    impl IO for Unit {
+        fn fio_write(&mut self, _: &mut [u8]) -> usize {
+            0
+        }
+
+        fn fio_read(&mut self, _: &[u8]) -> usize {
+            0
+        }
+    }
+
+    let mut units: ::heapless::Vec<Unit, 4> = ::heapless::Vec::new();
+    assert_eq!(units.vlq_vec_read::<u32>(&[3]), Ok(1));
+    assert_eq!(units.len(), 3);
+    assert_eq!(units.vlq_vec_read::<u32>(&[5]), Err(BoundedError::CapacityExceeded { length: 5, capacity: 4 }));
+}
@@ -0,0 +1,379 @@
+//! `bytes::Buf` and `bytes::BufMut` integration.
+//!
+//! `FioBuf` reads `IO` and `VLQ` values from any `Buf`, consuming them as
+//! it goes, and `FioBufMut` writes them to any `BufMut`.  Values spanning
+//! more than one chunk (as in a `Chain`) are handled; reads are taken
+//! straight from the chunk where they fit in it.  `BytesMut` and `Vec<u8>`
+//! grow as they are written to; anything else fails with
+//! `BufferTooSmall` once it is full, rather than panicking.
+//!
+//! With `io-string` or `vlq-string` enabled there are also
+//! length-prefixed strings, as `FricganString` and `VLQString` write them.
+//!
+//! `IO` values are only consumed if the whole value is present.  A VLQ
+//! spanning chunks is consumed as it is read, as is the prefix of a
+//! string, so if either turns out to be truncated or malformed the bytes
+//! it covered are gone; the buffer is unlikely to be usable after that in
+//! any case.
+
+use core::mem::size_of_val;
+
+#[cfg(any(feature="io-string", feature="vlq-string"))]
+use core::mem::size_of;
+
+use bytes::{Buf, BufMut};
+
+#[cfg(any(feature="io-string", feature="vlq-string"))]
+use num_traits::{cast::{FromPrimitive, ToPrimitive}, sign::Unsigned};
+
+use IO;
+
+#[cfg(feature="vlq")]
+use VLQ;
+
+#[cfg(feature="vlq")]
+use {vlq_fits, vlq_len, vlq_max, MAX_VLQ_LEN};
+
+#[cfg(any(feature="io-string", feature="vlq-string"))]
+use {PrefixError, MAX_PREFIX_LEN};
+
+#[cfg(feature="io-string")]
+use {read_fio_prefix, write_fio_prefix};
+
+#[cfg(feature="vlq-string")]
+use {read_vlq_prefix, write_vlq_prefix};
+
+// Values up to this size are staged on the stack where they need to be
+// copied.
+const STACK_LEN: usize = 16;
+
+/// Errors produced reading from a `Buf` or writing to a `BufMut`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufError {
+    /// The `BufMut` is full.
+    BufferTooSmall {
+        /// The number of bytes the value requires.
+        required: usize,
+    },
+    /// The `Buf` ended before the value being read.
+    Eof,
+    /// A VLQ was longer than its type allows, or its value overflowed it.
+    MalformedVlq,
+    /// A string length exceeded the maximum, or couldn't be represented
+    /// by its prefix.
+    LengthTooLarge {
+        /// The length.
+        length: usize,
+        /// The maximum.
+        max: usize,
+    },
+    /// A string was not valid UTF-8.
+    InvalidUtf8,
+}
+
+/// Reads fricgan values from a `bytes::Buf`.
+pub trait FioBuf: Buf {
+    /// Reads an `IO` value, returning the number of bytes read.
+    ///
+    /// The value takes `size_of_val(value)` bytes (see `IO`), which are
+    /// consumed from the `Buf` whatever `fio_read` reports.
+    fn get_fio<T: IO + ?Sized>(&mut self, value: &mut T) -> Result<usize, BufError> {
+        let length = size_of_val(value);
+        if self.remaining() < length {
+            return Err(BufError::Eof);
+        }
+        if self.chunk().len() >= length {
+            value.fio_read(&self.chunk()[..length]);
+            self.advance(length);
+        } else if length <= STACK_LEN {
+            let mut buffer = [0u8; STACK_LEN];
+            self.copy_to_slice(&mut buffer[..length]);
+            value.fio_read(&buffer[..length]);
+        } else {
+            let mut buffer = vec![0u8; length];
+            self.copy_to_slice(&mut buffer[..]);
+            value.fio_read(&buffer[..]);
+        }
+        Ok(length)
+    }
+
+    /// Reads a `VLQ` value, which must end within `size_of::<V>()`
+    /// rounded up to whole 7-bit groups.  Returns the number of bytes
+    /// read.
+    #[cfg(feature="vlq")]
+    fn get_vlq<V: VLQ>(&mut self, value: &mut V) -> Result<usize, BufError> {
+        let max = vlq_max::<V>();
+
+        if let Some(read) = vlq_len(self.chunk(), max) {
+            if !vlq_fits::<V>(self.chunk(), read) {
+                return Err(BufError::MalformedVlq);
+            }
+            value.vlq_read(self.chunk());
+            self.advance(read);
+            return Ok(read);
+        }
+
+        let mut buffer = [0u8; MAX_VLQ_LEN];
+        let length = take_vlq(self, &mut buffer[..max])?;
+        if !vlq_fits::<V>(&buffer[..], length) {
+            return Err(BufError::MalformedVlq);
+        }
+        Ok(value.vlq_read(&buffer[..length]))
+    }
+
+    /// Reads a string prefixed with its length as an `IO` value `V`,
+    /// failing if the length exceeds `max`.  Returns the number of bytes
+    /// read.
+    #[cfg(feature="io-string")]
+    fn get_fio_string<V>(&mut self, string: &mut String, max: usize) -> Result<usize, BufError>
+    where V: ToPrimitive + FromPrimitive + Unsigned + IO {
+        let mut buffer = [0u8; MAX_PREFIX_LEN];
+        let size = size_of::<V>().min(MAX_PREFIX_LEN);
+        if self.remaining() < size {
+            return Err(BufError::Eof);
+        }
+        self.copy_to_slice(&mut buffer[..size]);
+        let (length, prefix) = read_fio_prefix::<V>(&buffer[..size]).map_err(|e| prefix_error(e, max))?;
+        get_string(self, string, length, max).map(|read| prefix + read)
+    }
+
+    /// Reads a string prefixed with its length as a `VLQ` value `V`,
+    /// failing if the length exceeds `max`.  Returns the number of bytes
+    /// read.
+    #[cfg(feature="vlq-string")]
+    fn get_vlq_string<V>(&mut self, string: &mut String, max: usize) -> Result<usize, BufError>
+    where V: ToPrimitive + FromPrimitive + Unsigned + VLQ {
+        let mut buffer = [0u8; MAX_PREFIX_LEN];
+        let size = take_vlq(self, &mut buffer[..vlq_max::<V>()])?;
+        let (length, prefix) = read_vlq_prefix::<V>(&buffer[..size]).map_err(|e| prefix_error(e, max))?;
+        get_string(self, string, length, max).map(|read| prefix + read)
+    }
+}
+
+impl<B: Buf + ?Sized> FioBuf for B {}
+
+// Takes a VLQ off `source` a byte at a time, up to and including its last
+// byte, into `buffer`.  Returns its length, or fails if it doesn't end
+// within `buffer`.
+#[cfg(feature="vlq")]
+fn take_vlq<B: Buf + ?Sized>(source: &mut B, buffer: &mut [u8]) -> Result<usize, BufError> {
+    for (length, byte) in buffer.iter_mut().enumerate() {
+        if !source.has_remaining() {
+            return Err(BufError::Eof);
+        }
+        *byte = source.get_u8();
+        if (*byte & 128) == 0 {
+            return Ok(length + 1);
+        }
+    }
+    Err(BufError::MalformedVlq)
+}
+
+// Maps an error reading the length prefix of a string of at most `max`
+// bytes.
+#[cfg(any(feature="io-string", feature="vlq-string"))]
+fn prefix_error(e: PrefixError, max: usize) -> BufError {
+    match e {
+        PrefixError::Eof => BufError::Eof,
+        #[cfg(feature="vlq")]
+        PrefixError::MalformedVlq => BufError::MalformedVlq,
+        PrefixError::Overflow => BufError::LengthTooLarge { length: usize::MAX, max },
+    }
+}
+
+// Reads the body of a length-prefixed string.
+#[cfg(any(feature="io-string", feature="vlq-string"))]
+fn get_string<B: Buf + ?Sized>(source: &mut B, string: &mut String, length: usize, max: usize) -> Result<usize, BufError> {
+    if length > max {
+        return Err(BufError::LengthTooLarge { length, max });
+    }
+    if source.remaining() < length {
+        return Err(BufError::Eof);
+    }
+    let mut body = vec![0u8; length];
+    source.copy_to_slice(&mut body[..]);
+    *string = String::from_utf8(body).map_err(|_| BufError::InvalidUtf8)?;
+    Ok(length)
+}
+
+/// Writes fricgan values to a `bytes::BufMut`.
+pub trait FioBufMut: BufMut {
+    /// Writes an `IO` value, returning the number of bytes written.
+    fn put_fio<T: IO + ?Sized>(&mut self, value: &mut T) -> Result<usize, BufError> {
+        let length = size_of_val(value);
+        reserve(self, length)?;
+        if length <= STACK_LEN {
+            let mut buffer = [0u8; STACK_LEN];
+            let written = value.fio_write(&mut buffer[..length]);
+            self.put_slice(&buffer[..written]);
+            Ok(written)
+        } else {
+            let mut buffer = vec![0u8; length];
+            let written = value.fio_write(&mut buffer[..]);
+            self.put_slice(&buffer[..written]);
+            Ok(written)
+        }
+    }
+
+    /// Writes a `VLQ` value, returning the number of bytes written.
+    #[cfg(feature="vlq")]
+    fn put_vlq<V: VLQ>(&mut self, value: &V) -> Result<usize, BufError> {
+        let mut buffer = [0u8; MAX_VLQ_LEN];
+        let written = value.vlq_write(&mut buffer[..]);
+        reserve(self, written)?;
+        self.put_slice(&buffer[..written]);
+        Ok(written)
+    }
+
+    /// Writes `value` prefixed with its length as an `IO` value `V`,
+    /// returning the number of bytes written.
+    #[cfg(feature="io-string")]
+    fn put_fio_string<V>(&mut self, value: &str) -> Result<usize, BufError>
+    where V: ToPrimitive + FromPrimitive + Unsigned + IO {
+        let mut prefix = [0u8; MAX_PREFIX_LEN];
+        let size = write_fio_prefix::<V>(value.len(), &mut prefix).ok_or(BufError::LengthTooLarge {
+            length: value.len(),
+            max: prefix_max::<V>(),
+        })?;
+        put_string(self, &prefix[..size], value)
+    }
+
+    /// Writes `value` prefixed with its length as a `VLQ` value `V`,
+    /// returning the number of bytes written.
+    #[cfg(feature="vlq-string")]
+    fn put_vlq_string<V>(&mut self, value: &str) -> Result<usize, BufError>
+    where V: ToPrimitive + FromPrimitive + Unsigned + VLQ {
+        let mut prefix = [0u8; MAX_PREFIX_LEN];
+        let size = write_vlq_prefix::<V>(value.len(), &mut prefix).ok_or(BufError::LengthTooLarge {
+            length: value.len(),
+            max: prefix_max::<V>(),
+        })?;
+        put_string(self, &prefix[..size], value)
+    }
+}
+
+impl<B: BufMut + ?Sized> FioBufMut for B {}
+
+// Writes `value` after its encoded length prefix, provided both fit.
+#[cfg(any(feature="io-string", feature="vlq-string"))]
+fn put_string<B: BufMut + ?Sized>(sink: &mut B, prefix: &[u8], value: &str) -> Result<usize, BufError> {
+    reserve(sink, prefix.len() + value.len())?;
+    sink.put_slice(prefix);
+    sink.put_slice(value.as_bytes());
+    Ok(prefix.len() + value.len())
+}
+
+// The largest length a prefix of type `V` holds.
+#[cfg(any(feature="io-string", feature="vlq-string"))]
+fn prefix_max<V>() -> usize {
+    let bits = size_of::<V>() * 8;
+    if bits >= usize::BITS as usize {
+        usize::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+fn reserve<B: BufMut + ?Sized>(sink: &B, length: usize) -> Result<(), BufError> {
+    if sink.remaining_mut() < length {
+        Err(BufError::BufferTooSmall { required: length })
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(all(feature="io-u32", feature="vlq-32"))]
+#[test]
+fn test_buf_values() {
+    use bytes::BytesMut;
+
+    let mut sink = BytesMut::new();
+    let mut value = 0x01020304u32.to_be();
+    assert_eq!(sink.put_fio(&mut value), Ok(4));
+    assert_eq!(sink.put_vlq(&300u32), Ok(2));
+    assert_eq!(&sink[..], b"\x01\x02\x03\x04\xAC\x02");
+
+    let mut source = sink.freeze();
+    let mut value = 0u32;
+    let mut vlq = 0u32;
+    assert_eq!(source.get_fio(&mut value), Ok(4));
+    assert_eq!(u32::from_be(value), 0x01020304);
+    assert_eq!(source.get_vlq(&mut vlq), Ok(2));
+    assert_eq!(vlq, 300);
+    assert_eq!(source.get_fio(&mut value), Err(BufError::Eof));
+}
+
+#[cfg(all(feature="io-u32", feature="vlq-32"))]
+#[test]
+fn test_buf_chain() {
+    // Values split across the two halves of a chain.
+    let mut source = (&[0x01u8, 0x02, 0x03][..]).chain(&[0x04u8, 0xAC, 0x02][..]);
+    let mut value = 0u32;
+    let mut vlq = 0u32;
+    assert_eq!(source.get_fio(&mut value), Ok(4));
+    assert_eq!(value, u32::from_ne_bytes([0x01, 0x02, 0x03, 0x04]));
+    assert_eq!(source.get_vlq(&mut vlq), Ok(2));
+    assert_eq!(vlq, 300);
+
+    let mut source = (&[0xACu8][..]).chain(&[0x02u8][..]);
+    assert_eq!(source.get_vlq(&mut vlq), Ok(2));
+    assert_eq!(vlq, 300);
+
+    let mut source = (&[0xACu8][..]).chain(&[0x80u8][..]);
+    assert_eq!(source.get_vlq(&mut vlq), Err(BufError::Eof));
+
+    let mut source = &[0xFFu8, 0xFF, 0xFF, 0xFF, 0xFF, 0x01][..];
+    assert_eq!(source.get_vlq(&mut vlq), Err(BufError::MalformedVlq));
+
+    // 0xFFFFFFFF plus a bit above 32 overflows a `u32`, in one chunk or two.
+    let mut source = &[0xFFu8, 0xFF, 0xFF, 0xFF, 0x1F][..];
+    assert_eq!(source.get_vlq(&mut vlq), Err(BufError::MalformedVlq));
+    let mut source = (&[0xFFu8, 0xFF][..]).chain(&[0xFFu8, 0xFF, 0x1F][..]);
+    assert_eq!(source.get_vlq(&mut vlq), Err(BufError::MalformedVlq));
+
+    // A value which isn't all there is not consumed.
+    let mut source = (&[0x01u8, 0x02][..]).chain(&[0x03u8][..]);
+    assert_eq!(source.get_fio(&mut value), Err(BufError::Eof));
+    assert_eq!(source.remaining(), 3);
+}
+
+#[cfg(all(feature="io-u32", feature="vlq-32"))]
+#[test]
+fn test_buf_full() {
+    let mut data = [0u8; 5];
+    let mut sink = &mut data[..];
+    let mut value = 1u32;
+    assert_eq!(sink.put_fio(&mut value), Ok(4));
+    assert_eq!(sink.put_fio(&mut value), Err(BufError::BufferTooSmall { required: 4 }));
+    assert_eq!(sink.put_vlq(&300u32), Err(BufError::BufferTooSmall { required: 2 }));
+    assert_eq!(sink.put_vlq(&3u32), Ok(1));
+}
+
+#[cfg(all(feature="io-string", feature="vlq-string", feature="io-u16", feature="vlq-32"))]
+#[test]
+fn test_buf_strings() {
+    let mut sink: Vec<u8> = Vec::new();
+    assert_eq!(sink.put_fio_string::<u16>("hello"), Ok(7));
+    assert_eq!(sink.put_vlq_string::<u32>("héllo"), Ok(7));
+    assert_eq!(
+        sink.put_fio_string::<u16>(&"x".repeat(0x10000)),
+        Err(BufError::LengthTooLarge { length: 0x10000, max: 0xFFFF })
+    );
+
+    let mut source = &sink[..];
+    let mut string = String::new();
+    assert_eq!(source.get_fio_string::<u16>(&mut string, 16), Ok(7));
+    assert_eq!(string, "hello");
+    assert_eq!(source.get_vlq_string::<u32>(&mut string, 16), Ok(7));
+    assert_eq!(string, "héllo");
+
+    let mut source = &b"\x05hello"[..];
+    assert_eq!(
+        source.get_vlq_string::<u32>(&mut string, 4),
+        Err(BufError::LengthTooLarge { length: 5, max: 4 })
+    );
+    let mut source = &b"\x02\xC3\x28"[..];
+    assert_eq!(source.get_vlq_string::<u32>(&mut string, 4), Err(BufError::InvalidUtf8));
+    let mut source = &b"\x83\x80\x80\x80\x10abc"[..];
+    assert_eq!(source.get_vlq_string::<u32>(&mut string, 4), Err(BufError::MalformedVlq));
+}
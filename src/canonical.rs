@@ -0,0 +1,205 @@
+//! Canonical floats, and raw float bits.
+//!
+//! The `io-f32` and `io-f64` impls copy a float's bits untouched, NaN
+//! payloads and all.  That's usually what's wanted, but content addressed
+//! storage needs equal values to encode to equal bytes.  `Canonical`
+//! rewrites floats before writing (or after reading): every NaN becomes
+//! the single quiet NaN (`0x7FC00000`, or `0x7FF8000000000000`), and
+//! optionally -0.0 becomes 0.0 and subnormals become zero.
+//!
+//! The `*_bits` functions read and write floats as their `u32` and `u64`
+//! bit patterns (in native byte order, as `IO` does), so a value never
+//! passes through a float register on the way.  On some targets (x87)
+//! that alone can quiet a signalling NaN; with bits, round trips can be
+//! checked exactly.
+
+use IO;
+
+/// What to do with subnormal values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subnormals {
+    /// Leave them alone.
+    Keep,
+    /// Replace them with zero (of the same sign, unless -0.0 is
+    /// normalised too).
+    Flush,
+}
+
+/// The canonical quiet NaN, as `f32` bits.
+pub const NAN_F32: u32 = 0x7FC0_0000;
+
+/// The canonical quiet NaN, as `f64` bits.
+pub const NAN_F64: u64 = 0x7FF8_0000_0000_0000;
+
+/// A float canonicalisation policy.
+///
+/// NaNs are always canonicalised; -0.0 and subnormals are kept unless
+/// asked otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Canonical {
+    negative_zero: bool,
+    subnormals: Subnormals,
+}
+
+impl Default for Canonical {
+    fn default() -> Canonical {
+        Canonical::new()
+    }
+}
+
+macro_rules! canonical {
+    ($float:ident, $bits:ident, $canonical:ident, $canonical_bits:ident, $read:ident, $write:ident, $read_bits:ident, $write_bits:ident) => {
+        impl Canonical {
+            #[doc = concat!("Returns the canonical form of an `", stringify!($float), "`.")]
+            pub fn $canonical(&self, value: $float) -> $float {
+                $float::from_bits(self.$canonical_bits(value.to_bits()))
+            }
+
+            #[doc = concat!("Reads an `", stringify!($float), "` at offset zero of `source`, canonicalised.")]
+            ///
+            /// The return value is the number of bytes read.
+            pub fn $read(&self, value: &mut $float, source: &[u8]) -> usize {
+                let mut bits = 0;
+                let read = $read_bits(&mut bits, source);
+                *value = $float::from_bits(self.$canonical_bits(bits));
+                read
+            }
+
+            #[doc = concat!("Writes the canonical form of an `", stringify!($float), "` to offset zero of `sink`.")]
+            ///
+            /// The return value is the number of bytes written.
+            pub fn $write(&self, value: $float, sink: &mut [u8]) -> usize {
+                $write_bits(self.$canonical_bits(value.to_bits()), sink)
+            }
+        }
+
+        #[doc = concat!("Reads an `", stringify!($float), "` at offset zero of `source` as its bits.")]
+        ///
+        /// The return value is the number of bytes read.
+        pub fn $read_bits(value: &mut $bits, source: &[u8]) -> usize {
+            let mut bytes = [0u8; ::core::mem::size_of::<$bits>()];
+            let read = bytes[..].fio_read(source);
+            *value = $bits::from_ne_bytes(bytes);
+            read
+        }
+
+        #[doc = concat!("Writes `", stringify!($float), "` bits to offset zero of `sink`.")]
+        ///
+        /// The return value is the number of bytes written.
+        pub fn $write_bits(value: $bits, sink: &mut [u8]) -> usize {
+            value.to_ne_bytes()[..].fio_write(sink)
+        }
+    };
+}
+
+impl Canonical {
+    /// Creates the default policy.
+    pub fn new() -> Canonical {
+        Canonical {
+            negative_zero: false,
+            subnormals: Subnormals::Keep,
+        }
+    }
+
+    /// Sets whether -0.0 becomes 0.0.
+    pub fn negative_zero(mut self, normalise: bool) -> Canonical {
+        self.negative_zero = normalise;
+        self
+    }
+
+    /// Sets the subnormal policy.
+    pub fn subnormals(mut self, subnormals: Subnormals) -> Canonical {
+        self.subnormals = subnormals;
+        self
+    }
+
+    fn f32_bits(&self, bits: u32) -> u32 {
+        self.apply(bits as u64, 32, 8, NAN_F32 as u64) as u32
+    }
+
+    fn f64_bits(&self, bits: u64) -> u64 {
+        self.apply(bits, 64, 11, NAN_F64)
+    }
+
+    // Canonicalises the bits of a float `width` bits wide with an
+    // `exponent` bit exponent.
+    fn apply(&self, bits: u64, width: u32, exponent: u32, nan: u64) -> u64 {
+        let mantissa_bits = width - 1 - exponent;
+        let sign = 1 << (width - 1);
+        let exponent_mask = ((1 << exponent) - 1) << mantissa_bits;
+        let mantissa_mask = (1 << mantissa_bits) - 1;
+
+        if bits & exponent_mask == exponent_mask && bits & mantissa_mask != 0 {
+            return nan;
+        }
+        let bits = if bits & exponent_mask == 0 && self.subnormals == Subnormals::Flush {
+            bits & sign
+        } else {
+            bits
+        };
+        if bits == sign && self.negative_zero { 0 } else { bits }
+    }
+}
+
+canonical!(f32, u32, f32, f32_bits, read_f32, write_f32, read_f32_bits, write_f32_bits);
+canonical!(f64, u64, f64, f64_bits, read_f64, write_f64, read_f64_bits, write_f64_bits);
+
+#[test]
+fn test_canonical_nan() {
+    let canonical = Canonical::new();
+    for &bits in [0x7FC0_0000u32, 0xFFC0_0000, 0x7F80_0001, 0x7FFF_FFFF, 0xFFA0_0000].iter() {
+        assert_eq!(canonical.f32(f32::from_bits(bits)).to_bits(), NAN_F32);
+    }
+    for &bits in [0x7FF8_0000_0000_0001u64, 0xFFF0_0000_0000_0001, 0xFFFF_FFFF_FFFF_FFFF].iter() {
+        assert_eq!(canonical.f64(f64::from_bits(bits)).to_bits(), NAN_F64);
+    }
+    // Everything else is left alone by default.
+    for &value in [1.5f64, -0.0, f64::INFINITY, f64::NEG_INFINITY, 5e-324].iter() {
+        assert_eq!(canonical.f64(value).to_bits(), value.to_bits());
+    }
+}
+
+#[test]
+fn test_canonical_zero() {
+    let canonical = Canonical::new().negative_zero(true);
+    assert_eq!(canonical.f32(-0.0).to_bits(), 0);
+    assert_eq!(canonical.f64(-0.0).to_bits(), 0);
+    assert_eq!(canonical.f64(-1.0), -1.0);
+
+    let flush = Canonical::new().subnormals(Subnormals::Flush);
+    assert_eq!(flush.f64(5e-324).to_bits(), 0);
+    assert_eq!(flush.f64(-5e-324).to_bits(), (-0.0f64).to_bits());
+    assert_eq!(flush.f32(-1e-40).to_bits(), (-0.0f32).to_bits());
+    assert_eq!(flush.f32(f32::MIN_POSITIVE), f32::MIN_POSITIVE);
+    assert_eq!(flush.negative_zero(true).f32(-1e-40).to_bits(), 0);
+}
+
+#[test]
+fn test_canonical_io() {
+    let mut data = [0u8; 8];
+    let canonical = Canonical::new().negative_zero(true);
+    assert_eq!(canonical.write_f32(f32::from_bits(0x7F80_0001), &mut data[..]), 4);
+    assert_eq!(u32::from_ne_bytes([data[0], data[1], data[2], data[3]]), NAN_F32);
+    assert_eq!(canonical.write_f64(-0.0, &mut data[..]), 8);
+    assert_eq!(data, [0; 8]);
+
+    let mut value = 1.0f64;
+    assert_eq!(write_f64_bits(0x8000_0000_0000_0000, &mut data[..]), 8);
+    assert_eq!(canonical.read_f64(&mut value, &data[..]), 8);
+    assert_eq!(value.to_bits(), 0);
+}
+
+#[test]
+fn test_canonical_bits() {
+    // A signalling NaN survives a round trip through the bit functions.
+    let mut data = [0u8; 8];
+    let mut bits = 0u32;
+    assert_eq!(write_f32_bits(0x7F80_0001, &mut data[..]), 4);
+    assert_eq!(read_f32_bits(&mut bits, &data[..]), 4);
+    assert_eq!(bits, 0x7F80_0001);
+
+    let mut bits = 0u64;
+    assert_eq!(write_f64_bits(0xFFF0_0000_0000_0001, &mut data[..]), 8);
+    assert_eq!(read_f64_bits(&mut bits, &data[..]), 8);
+    assert_eq!(bits, 0xFFF0_0000_0000_0001);
+}
@@ -0,0 +1,903 @@
+//! CBOR (RFC 8949) encoding and decoding.
+//!
+//! `Encoder` writes items to a slice, and `Decoder` reads them back one
+//! token at a time; neither allocates, so both work without std.  Arrays,
+//! maps and tags are written as a header followed by their contents, and
+//! read back as a header token followed by the contents' tokens.
+//!
+//! In canonical mode (RFC 8949 section 4.2.1, "core deterministic
+//! encoding") the encoder rejects indefinite-length items, writes floats
+//! in the shortest form which preserves their value, and sorts map
+//! entries written through `Encoder::map_with` by their encoded keys.  A
+//! canonical `Decoder` rejects input which doesn't use the shortest form
+//! of each argument or float, or uses indefinite lengths; it does not
+//! check the order of map keys.
+//!
+//! Multi-byte arguments and floats are big endian, written through `IO`.
+
+use core::str;
+
+use IO;
+
+/// Errors produced while encoding or decoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CborError {
+    /// The output buffer is too small.
+    BufferTooSmall {
+        /// The number of bytes the output buffer requires.
+        required: usize,
+    },
+    /// The input ended partway through an item.
+    Eof,
+    /// The initial byte uses a reserved or invalid additional information
+    /// value.
+    InvalidAdditionalInfo(u8),
+    /// A simple value was reserved (24 to 31), or encoded in two bytes
+    /// when it fits in one.
+    InvalidSimple(u8),
+    /// A text string was not valid UTF-8.
+    InvalidUtf8,
+    /// A chunk of an indefinite-length string wasn't a definite-length
+    /// string of the same type.
+    InvalidChunk,
+    /// A break was found outside an indefinite-length item, or partway
+    /// through a map entry.
+    UnexpectedBreak,
+    /// The input (or a requested item) is not canonical.
+    NotCanonical,
+    /// A canonical map has two entries with the same key.
+    DuplicateKey,
+    /// A canonical map didn't contain the number of entries given.
+    CountMismatch,
+    /// Items were nested too deeply to skip.
+    DepthLimitExceeded,
+}
+
+// Major types.
+const UNSIGNED: u8 = 0;
+const NEGATIVE: u8 = 1;
+const BYTES: u8 = 2;
+const TEXT: u8 = 3;
+const ARRAY: u8 = 4;
+const MAP: u8 = 5;
+const TAG: u8 = 6;
+const SIMPLE: u8 = 7;
+
+// Additional information meaning "indefinite length", or break.
+const INDEFINITE: u8 = 31;
+
+/// The break stop code, ending an indefinite-length item.
+pub const BREAK: u8 = 0xFF;
+
+/// The canonical half precision NaN.
+pub const CANONICAL_NAN: u16 = 0x7E00;
+
+// Nesting limit when skipping items, so hostile input can't exhaust the
+// stack.
+const MAX_DEPTH: usize = 256;
+
+// ----------------------------------------------------------------------
+// Half precision floats
+// ----------------------------------------------------------------------
+
+/// Converts half precision float bits to an `f64` (which is always exact).
+pub fn f16_to_f64(bits: u16) -> f64 {
+    let sign = ((bits >> 15) as u64) << 63;
+    let exponent = ((bits >> 10) & 0x1F) as i32;
+    let mut mantissa = (bits & 0x3FF) as u64;
+
+    let bits = match exponent {
+        0 if mantissa == 0 => sign,
+        0 => {
+            // Subnormal; normalise it.
+            let mut exponent = -14;
+            while mantissa & 0x400 == 0 {
+                mantissa <<= 1;
+                exponent -= 1;
+            }
+            sign | (((exponent + 1023) as u64) << 52) | ((mantissa & 0x3FF) << 42)
+        }
+        31 => sign | (0x7FF << 52) | (mantissa << 42),
+        _ => sign | (((exponent - 15 + 1023) as u64) << 52) | (mantissa << 42),
+    };
+    f64::from_bits(bits)
+}
+
+/// Converts an `f64` to half precision float bits, if that can be done
+/// without losing precision.  NaNs convert if their payload fits.
+pub fn f64_to_f16(value: f64) -> Option<u16> {
+    let bits = value.to_bits();
+    let sign = ((bits >> 63) as u16) << 15;
+    let exponent = ((bits >> 52) & 0x7FF) as i32;
+    let mantissa = bits & 0xF_FFFF_FFFF_FFFF;
+
+    if exponent == 0x7FF {
+        return if mantissa & 0x3FF_FFFF_FFFF == 0 {
+            Some(sign | 0x7C00 | (mantissa >> 42) as u16)
+        } else {
+            None
+        };
+    }
+    if exponent == 0 {
+        // Zero; f64 subnormals are far too small for half precision.
+        return if mantissa == 0 { Some(sign) } else { None };
+    }
+
+    let exponent = exponent - 1023;
+    if (-14..=15).contains(&exponent) {
+        if mantissa & 0x3FF_FFFF_FFFF == 0 {
+            return Some(sign | (((exponent + 15) as u16) << 10) | (mantissa >> 42) as u16);
+        }
+    } else if (-24..-14).contains(&exponent) {
+        let significand = mantissa | (1 << 52);
+        let shift = 42 + (-14 - exponent) as u32;
+        if significand & ((1 << shift) - 1) == 0 {
+            return Some(sign | (significand >> shift) as u16);
+        }
+    }
+    None
+}
+
+// The float width (2, 4 or 8 bytes) the value needs to preserve it.
+fn float_width(value: f64) -> usize {
+    if value.is_nan() || f64_to_f16(value).is_some() {
+        2
+    } else if (value as f32) as f64 == value {
+        4
+    } else {
+        8
+    }
+}
+
+// ----------------------------------------------------------------------
+// Encoding
+// ----------------------------------------------------------------------
+
+/// Writes CBOR items to a slice.
+///
+/// Each method returns the number of bytes written.  If a method fails,
+/// nothing it wrote is kept.
+pub struct Encoder<'a> {
+    sink: &'a mut [u8],
+    position: usize,
+    canonical: bool,
+}
+
+impl<'a> Encoder<'a> {
+    /// Creates an encoder writing to offset zero of `sink`.
+    pub fn new(sink: &'a mut [u8]) -> Encoder<'a> {
+        Encoder { sink, position: 0, canonical: false }
+    }
+
+    /// Sets whether the encoder produces canonical output.
+    pub fn canonical(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
+    }
+
+    /// The number of bytes written so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Finishes encoding, returning the number of bytes written.
+    pub fn finish(self) -> usize {
+        self.position
+    }
+
+    // Runs `f`, rolling back anything it wrote if it fails.
+    fn item<F>(&mut self, f: F) -> Result<usize, CborError>
+    where F: FnOnce(&mut Encoder<'a>) -> Result<(), CborError> {
+        let start = self.position;
+        match f(self) {
+            Ok(()) => Ok(self.position - start),
+            Err(e) => {
+                self.position = start;
+                Err(e)
+            }
+        }
+    }
+
+    fn raw(&mut self, data: &[u8]) -> Result<(), CborError> {
+        let required = self.position + data.len();
+        if self.sink.len() < required {
+            return Err(CborError::BufferTooSmall { required });
+        }
+        self.sink[self.position..required].copy_from_slice(data);
+        self.position = required;
+        Ok(())
+    }
+
+    // Writes an initial byte and a big endian value of the given width.
+    fn raw_head(&mut self, initial: u8, value: u64, width: usize) -> Result<(), CborError> {
+        let mut head = [initial, 0, 0, 0, 0, 0, 0, 0, 0];
+        match width {
+            0 => 0,
+            1 => {
+                head[1] = value as u8;
+                1
+            }
+            2 => (value as u16).to_be().fio_write(&mut head[1..]),
+            4 => (value as u32).to_be().fio_write(&mut head[1..]),
+            _ => value.to_be().fio_write(&mut head[1..]),
+        };
+        self.raw(&head[..1 + width])
+    }
+
+    // Writes a major type and argument, in the shortest form.
+    fn head(&mut self, major: u8, value: u64) -> Result<(), CborError> {
+        let major = major << 5;
+        if value < 24 {
+            self.raw_head(major | value as u8, 0, 0)
+        } else if value <= 0xFF {
+            self.raw_head(major | 24, value, 1)
+        } else if value <= 0xFFFF {
+            self.raw_head(major | 25, value, 2)
+        } else if value <= 0xFFFFFFFF {
+            self.raw_head(major | 26, value, 4)
+        } else {
+            self.raw_head(major | 27, value, 8)
+        }
+    }
+
+    fn indefinite(&mut self, major: u8) -> Result<usize, CborError> {
+        if self.canonical {
+            return Err(CborError::NotCanonical);
+        }
+        self.item(|e| e.raw(&[(major << 5) | INDEFINITE]))
+    }
+
+    /// Writes an unsigned integer (major type 0).
+    pub fn unsigned(&mut self, value: u64) -> Result<usize, CborError> {
+        self.item(|e| e.head(UNSIGNED, value))
+    }
+
+    /// Writes a negative integer (major type 1) with the value `-1 - n`.
+    pub fn negative(&mut self, n: u64) -> Result<usize, CborError> {
+        self.item(|e| e.head(NEGATIVE, n))
+    }
+
+    /// Writes a signed integer, as major type 0 or 1.
+    pub fn int(&mut self, value: i64) -> Result<usize, CborError> {
+        if value >= 0 {
+            self.unsigned(value as u64)
+        } else {
+            self.negative(!value as u64)
+        }
+    }
+
+    /// Writes a byte string.
+    pub fn bytes(&mut self, value: &[u8]) -> Result<usize, CborError> {
+        self.item(|e| {
+            e.head(BYTES, value.len() as u64)?;
+            e.raw(value)
+        })
+    }
+
+    /// Writes a text string.
+    pub fn text(&mut self, value: &str) -> Result<usize, CborError> {
+        self.item(|e| {
+            e.head(TEXT, value.len() as u64)?;
+            e.raw(value.as_bytes())
+        })
+    }
+
+    /// Writes an array header; the `length` elements follow it.
+    pub fn array(&mut self, length: u64) -> Result<usize, CborError> {
+        self.item(|e| e.head(ARRAY, length))
+    }
+
+    /// Writes a map header; the `length` key and value pairs follow it.
+    ///
+    /// Entries written this way are not sorted in canonical mode; use
+    /// `map_with` for that.
+    pub fn map(&mut self, length: u64) -> Result<usize, CborError> {
+        self.item(|e| e.head(MAP, length))
+    }
+
+    /// Writes a map of `length` entries, written to the encoder by `f`.
+    ///
+    /// In canonical mode the entries are then sorted by their encoded
+    /// keys, and duplicate keys or the wrong number of entries are
+    /// errors.
+    pub fn map_with<F>(&mut self, length: u64, f: F) -> Result<usize, CborError>
+    where F: FnOnce(&mut Encoder<'a>) -> Result<(), CborError> {
+        self.item(|e| {
+            e.head(MAP, length)?;
+            let start = e.position;
+            f(e)?;
+            if e.canonical {
+                e.sort_entries(start, length)?;
+            }
+            Ok(())
+        })
+    }
+
+    // The length of the item at `from`, which must lie within what has
+    // been written.
+    fn item_len(&self, from: usize) -> Result<usize, CborError> {
+        let mut decoder = Decoder::new(&self.sink[from..self.position]);
+        decoder.skip()?;
+        Ok(decoder.position())
+    }
+
+    // Insertion sorts the `count` map entries starting at `start`, moving
+    // each entry into place with a rotation.
+    fn sort_entries(&mut self, start: usize, count: u64) -> Result<(), CborError> {
+        let mut sorted = start;
+        for _ in 0..count {
+            if sorted == self.position {
+                return Err(CborError::CountMismatch);
+            }
+            let key = self.item_len(sorted).map_err(|_| CborError::CountMismatch)?;
+            let value = self.item_len(sorted + key).map_err(|_| CborError::CountMismatch)?;
+            let entry = key + value;
+
+            let mut at = start;
+            while at < sorted {
+                let other = self.item_len(at)?;
+                let ordering = self.sink[at..at + other].cmp(&self.sink[sorted..sorted + key]);
+                if ordering == core::cmp::Ordering::Equal {
+                    return Err(CborError::DuplicateKey);
+                }
+                if ordering == core::cmp::Ordering::Greater {
+                    break;
+                }
+                at += other + self.item_len(at + other)?;
+            }
+            self.sink[at..sorted + entry].rotate_right(entry);
+            sorted += entry;
+        }
+        if sorted != self.position {
+            return Err(CborError::CountMismatch);
+        }
+        Ok(())
+    }
+
+    /// Writes a tag; the tagged item follows it.
+    pub fn tag(&mut self, tag: u64) -> Result<usize, CborError> {
+        self.item(|e| e.head(TAG, tag))
+    }
+
+    /// Writes `false` or `true`.
+    pub fn bool(&mut self, value: bool) -> Result<usize, CborError> {
+        self.item(|e| e.raw(&[if value { 0xF5 } else { 0xF4 }]))
+    }
+
+    /// Writes `null`.
+    pub fn null(&mut self) -> Result<usize, CborError> {
+        self.item(|e| e.raw(&[0xF6]))
+    }
+
+    /// Writes `undefined`.
+    pub fn undefined(&mut self) -> Result<usize, CborError> {
+        self.item(|e| e.raw(&[0xF7]))
+    }
+
+    /// Writes a simple value.  Values 24 to 31 are reserved.
+    pub fn simple(&mut self, value: u8) -> Result<usize, CborError> {
+        if (24..32).contains(&value) {
+            return Err(CborError::InvalidSimple(value));
+        }
+        self.item(|e| e.head(SIMPLE, value as u64))
+    }
+
+    /// Writes a half precision float, given its bits.  In canonical mode
+    /// NaNs are written as `CANONICAL_NAN`.
+    pub fn f16(&mut self, bits: u16) -> Result<usize, CborError> {
+        if self.canonical {
+            return self.f64(f16_to_f64(bits));
+        }
+        self.item(|e| e.raw_head(0xF9, bits as u64, 2))
+    }
+
+    /// Writes a single precision float.  In canonical mode the shortest
+    /// form which preserves the value is used instead.
+    pub fn f32(&mut self, value: f32) -> Result<usize, CborError> {
+        if self.canonical {
+            return self.f64(value as f64);
+        }
+        self.item(|e| e.raw_head(0xFA, value.to_bits() as u64, 4))
+    }
+
+    /// Writes a double precision float.  In canonical mode the shortest
+    /// form which preserves the value is used instead.
+    pub fn f64(&mut self, value: f64) -> Result<usize, CborError> {
+        if !self.canonical {
+            return self.item(|e| e.raw_head(0xFB, value.to_bits(), 8));
+        }
+        self.item(|e| match float_width(value) {
+            2 if value.is_nan() => e.raw_head(0xF9, CANONICAL_NAN as u64, 2),
+            2 => e.raw_head(0xF9, f64_to_f16(value).unwrap_or(CANONICAL_NAN) as u64, 2),
+            4 => e.raw_head(0xFA, (value as f32).to_bits() as u64, 4),
+            _ => e.raw_head(0xFB, value.to_bits(), 8),
+        })
+    }
+
+    /// Begins an indefinite-length byte string; definite-length byte
+    /// strings (the chunks) follow it, then `end`.
+    pub fn begin_bytes(&mut self) -> Result<usize, CborError> {
+        self.indefinite(BYTES)
+    }
+
+    /// Begins an indefinite-length text string; definite-length text
+    /// strings (the chunks) follow it, then `end`.
+    pub fn begin_text(&mut self) -> Result<usize, CborError> {
+        self.indefinite(TEXT)
+    }
+
+    /// Begins an indefinite-length array; the elements follow it, then
+    /// `end`.
+    pub fn begin_array(&mut self) -> Result<usize, CborError> {
+        self.indefinite(ARRAY)
+    }
+
+    /// Begins an indefinite-length map; the key and value pairs follow
+    /// it, then `end`.
+    pub fn begin_map(&mut self) -> Result<usize, CborError> {
+        self.indefinite(MAP)
+    }
+
+    /// Ends an indefinite-length item.
+    pub fn end(&mut self) -> Result<usize, CborError> {
+        if self.canonical {
+            return Err(CborError::NotCanonical);
+        }
+        self.item(|e| e.raw(&[BREAK]))
+    }
+}
+
+// ----------------------------------------------------------------------
+// Decoding
+// ----------------------------------------------------------------------
+
+/// A single token read by `Decoder`.
+///
+/// Strings are borrowed from the input.  Arrays, maps and tags are just
+/// their headers; their contents follow as further tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Token<'a> {
+    /// An unsigned integer (major type 0).
+    Unsigned(u64),
+    /// A negative integer (major type 1) with the value `-1 - n`.
+    Negative(u64),
+    /// A byte string, or a chunk of an indefinite-length byte string.
+    Bytes(&'a [u8]),
+    /// A text string, or a chunk of an indefinite-length text string.
+    Text(&'a str),
+    /// The start of an indefinite-length byte string.
+    BytesStart,
+    /// The start of an indefinite-length text string.
+    TextStart,
+    /// An array header, holding the number of elements.
+    Array(u64),
+    /// The start of an indefinite-length array.
+    ArrayStart,
+    /// A map header, holding the number of key and value pairs.
+    Map(u64),
+    /// The start of an indefinite-length map.
+    MapStart,
+    /// A tag, holding the tag number.
+    Tag(u64),
+    /// `false` or `true`.
+    Bool(bool),
+    /// `null`.
+    Null,
+    /// `undefined`.
+    Undefined,
+    /// Any other simple value.
+    Simple(u8),
+    /// A half, single or double precision float.
+    Float(f64),
+    /// The end of an indefinite-length item.
+    Break,
+}
+
+impl<'a> Token<'a> {
+    /// The value of an integer token, if it fits in an `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Token::Unsigned(value) if value <= i64::MAX as u64 => Some(value as i64),
+            Token::Negative(n) if n <= i64::MAX as u64 => Some(!(n as i64)),
+            _ => None,
+        }
+    }
+}
+
+/// Reads CBOR tokens from a slice.
+pub struct Decoder<'a> {
+    source: &'a [u8],
+    position: usize,
+    canonical: bool,
+    // The major type of the indefinite-length string being read, if any.
+    chunks: Option<u8>,
+    // The number of indefinite-length arrays and maps left open by `read`.
+    open: usize,
+}
+
+impl<'a> Decoder<'a> {
+    /// Creates a decoder reading from offset zero of `source`.
+    pub fn new(source: &'a [u8]) -> Decoder<'a> {
+        Decoder { source, position: 0, canonical: false, chunks: None, open: 0 }
+    }
+
+    /// Sets whether the decoder rejects input which isn't canonical.
+    pub fn canonical(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
+    }
+
+    /// The number of bytes read so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Whether all of the input has been read.
+    pub fn is_empty(&self) -> bool {
+        self.position == self.source.len()
+    }
+
+    fn take(&mut self, length: u64) -> Result<&'a [u8], CborError> {
+        let remaining = self.source.len() - self.position;
+        if length > remaining as u64 {
+            return Err(CborError::Eof);
+        }
+        let data = &self.source[self.position..self.position + length as usize];
+        self.position += length as usize;
+        Ok(data)
+    }
+
+    fn read_be(&mut self, width: usize) -> Result<u64, CborError> {
+        let data = self.take(width as u64)?;
+        Ok(match width {
+            1 => data[0] as u64,
+            2 => {
+                let mut value: u16 = 0;
+                value.fio_read(data);
+                u16::from_be(value) as u64
+            }
+            4 => {
+                let mut value: u32 = 0;
+                value.fio_read(data);
+                u32::from_be(value) as u64
+            }
+            _ => {
+                let mut value: u64 = 0;
+                value.fio_read(data);
+                u64::from_be(value)
+            }
+        })
+    }
+
+    // Reads the argument following an initial byte with additional
+    // information 0 to 27.
+    fn argument(&mut self, initial: u8) -> Result<u64, CborError> {
+        let info = initial & 0x1F;
+        let (value, minimum) = match info {
+            0..=23 => return Ok(info as u64),
+            24 => (self.read_be(1)?, 24),
+            25 => (self.read_be(2)?, 0x100),
+            26 => (self.read_be(4)?, 0x10000),
+            27 => (self.read_be(8)?, 0x100000000),
+            _ => return Err(CborError::InvalidAdditionalInfo(initial)),
+        };
+        if self.canonical && value < minimum {
+            return Err(CborError::NotCanonical);
+        }
+        Ok(value)
+    }
+
+    /// Reads the next token.
+    pub fn read(&mut self) -> Result<Token<'a>, CborError> {
+        let (start, chunks, open) = (self.position, self.chunks, self.open);
+        let token = self.next_token().and_then(|token| self.track(token, chunks.is_some()));
+        if token.is_err() {
+            self.position = start;
+            self.chunks = chunks;
+            self.open = open;
+        }
+        token
+    }
+
+    // Counts the indefinite-length arrays and maps opened and closed, so that
+    // a break outside of any of them (or of a chunked string) is an error.
+    fn track(&mut self, token: Token<'a>, chunked: bool) -> Result<Token<'a>, CborError> {
+        match token {
+            Token::ArrayStart | Token::MapStart => self.open += 1,
+            Token::Break if !chunked => {
+                if self.open == 0 {
+                    return Err(CborError::UnexpectedBreak);
+                }
+                self.open -= 1;
+            }
+            _ => {}
+        }
+        Ok(token)
+    }
+
+    fn next_token(&mut self) -> Result<Token<'a>, CborError> {
+        let initial = self.read_be(1)? as u8;
+        let major = initial >> 5;
+        let info = initial & 0x1F;
+
+        if let Some(chunks) = self.chunks {
+            if initial == BREAK {
+                self.chunks = None;
+                return Ok(Token::Break);
+            }
+            if major != chunks || info == INDEFINITE {
+                return Err(CborError::InvalidChunk);
+            }
+        }
+
+        if info == INDEFINITE {
+            if self.canonical {
+                return Err(CborError::NotCanonical);
+            }
+            return match major {
+                BYTES => {
+                    self.chunks = Some(BYTES);
+                    Ok(Token::BytesStart)
+                }
+                TEXT => {
+                    self.chunks = Some(TEXT);
+                    Ok(Token::TextStart)
+                }
+                ARRAY => Ok(Token::ArrayStart),
+                MAP => Ok(Token::MapStart),
+                SIMPLE => Ok(Token::Break),
+                _ => Err(CborError::InvalidAdditionalInfo(initial)),
+            };
+        }
+
+        if major == SIMPLE {
+            return self.simple(initial);
+        }
+
+        let argument = self.argument(initial)?;
+        Ok(match major {
+            UNSIGNED => Token::Unsigned(argument),
+            NEGATIVE => Token::Negative(argument),
+            BYTES => Token::Bytes(self.take(argument)?),
+            TEXT => {
+                let data = self.take(argument)?;
+                Token::Text(str::from_utf8(data).map_err(|_| CborError::InvalidUtf8)?)
+            }
+            ARRAY => Token::Array(argument),
+            MAP => Token::Map(argument),
+            _ => Token::Tag(argument),
+        })
+    }
+
+    fn simple(&mut self, initial: u8) -> Result<Token<'a>, CborError> {
+        let (value, width) = match initial & 0x1F {
+            20 => return Ok(Token::Bool(false)),
+            21 => return Ok(Token::Bool(true)),
+            22 => return Ok(Token::Null),
+            23 => return Ok(Token::Undefined),
+            24 => {
+                let value = self.read_be(1)? as u8;
+                if value < 32 {
+                    return Err(CborError::InvalidSimple(value));
+                }
+                return Ok(Token::Simple(value));
+            }
+            25 => (f16_to_f64(self.read_be(2)? as u16), 2),
+            26 => (f32::from_bits(self.read_be(4)? as u32) as f64, 4),
+            27 => (f64::from_bits(self.read_be(8)?), 8),
+            info @ 0..=19 => return Ok(Token::Simple(info)),
+            _ => return Err(CborError::InvalidAdditionalInfo(initial)),
+        };
+        if self.canonical {
+            let nan = value.is_nan() && (width != 2 || self.source[self.position - 2..self.position] != [0x7E, 0x00]);
+            if nan || float_width(value) != width {
+                return Err(CborError::NotCanonical);
+            }
+        }
+        Ok(Token::Float(value))
+    }
+
+    /// Skips the next item, including the contents of arrays, maps,
+    /// tags and indefinite-length strings.
+    pub fn skip(&mut self) -> Result<(), CborError> {
+        let (start, chunks) = (self.position, self.chunks);
+        let result = self.skip_item(0);
+        if result.is_err() {
+            self.position = start;
+            self.chunks = chunks;
+        }
+        result
+    }
+
+    fn at_break(&self) -> bool {
+        self.source.get(self.position) == Some(&BREAK)
+    }
+
+    fn skip_item(&mut self, depth: usize) -> Result<(), CborError> {
+        if depth > MAX_DEPTH {
+            return Err(CborError::DepthLimitExceeded);
+        }
+        match self.next_token()? {
+            Token::Array(length) => {
+                for _ in 0..length {
+                    self.skip_item(depth + 1)?;
+                }
+            }
+            Token::Map(length) => {
+                for _ in 0..length {
+                    self.skip_item(depth + 1)?;
+                    self.skip_item(depth + 1)?;
+                }
+            }
+            Token::ArrayStart => {
+                while !self.at_break() {
+                    self.skip_item(depth + 1)?;
+                }
+                self.position += 1;
+            }
+            Token::MapStart => {
+                while !self.at_break() {
+                    self.skip_item(depth + 1)?;
+                    self.skip_item(depth + 1)?;
+                }
+                self.position += 1;
+            }
+            Token::BytesStart | Token::TextStart => while self.next_token()? != Token::Break {},
+            Token::Tag(_) => self.skip_item(depth + 1)?,
+            Token::Break => return Err(CborError::UnexpectedBreak),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_cbor_half() {
+    let cases: [(u16, f64); 8] = [
+        (0x0000, 0.0),
+        (0x8000, -0.0),
+        (0x3C00, 1.0),
+        (0xC000, -2.0),
+        (0x7BFF, 65504.0),
+        (0x0001, 5.960464477539063e-8),
+        (0x0400, 0.00006103515625),
+        (0x7C00, f64::INFINITY),
+    ];
+    for &(bits, value) in cases.iter() {
+        assert_eq!(f16_to_f64(bits), value);
+        assert_eq!(f64_to_f16(value), Some(bits));
+    }
+    assert!(f16_to_f64(0x7E00).is_nan());
+    assert_eq!(f64_to_f16(f64::NAN), Some(0x7E00));
+    assert_eq!(f64_to_f16(0.1), None);
+    assert_eq!(f64_to_f16(65536.0), None);
+    assert_eq!(f64_to_f16(2.9802322387695312e-8), None);
+}
+
+#[test]
+fn test_cbor_encode() {
+    let mut sink = [0u8; 64];
+    let mut e = Encoder::new(&mut sink[..]);
+    assert_eq!(e.unsigned(23), Ok(1));
+    assert_eq!(e.unsigned(24), Ok(2));
+    assert_eq!(e.unsigned(1000), Ok(3));
+    assert_eq!(e.int(-1), Ok(1));
+    assert_eq!(e.int(i64::MIN), Ok(9));
+    assert_eq!(e.text("IETF"), Ok(5));
+    assert_eq!(e.bytes(&[1, 2]), Ok(3));
+    assert_eq!(e.tag(1), Ok(1));
+    assert_eq!(e.bool(true), Ok(1));
+    assert_eq!(e.simple(255), Ok(2));
+    assert_eq!(e.f32(100000.0), Ok(5));
+    assert_eq!(e.begin_array(), Ok(1));
+    assert_eq!(e.end(), Ok(1));
+    assert_eq!(e.simple(24), Err(CborError::InvalidSimple(24)));
+    let n = e.finish();
+    assert_eq!(
+        &sink[..n],
+        &[
+            0x17, 0x18, 0x18, 0x19, 0x03, 0xE8, 0x20, 0x3B, 0x7F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+            0x64, b'I', b'E', b'T', b'F', 0x42, 1, 2, 0xC1, 0xF5, 0xF8, 0xFF, 0xFA, 0x47, 0xC3, 0x50, 0x00,
+            0x9F, 0xFF,
+        ][..]
+    );
+
+    // Failed writes leave nothing behind.
+    let mut sink = [0u8; 4];
+    let mut e = Encoder::new(&mut sink[..]);
+    assert_eq!(e.text("IETF"), Err(CborError::BufferTooSmall { required: 5 }));
+    assert_eq!(e.position(), 0);
+}
+
+#[test]
+fn test_cbor_canonical() {
+    let mut sink = [0u8; 64];
+    let mut e = Encoder::new(&mut sink[..]).canonical(true);
+    assert_eq!(e.f64(1.5), Ok(3));
+    assert_eq!(e.f64(100000.0), Ok(5));
+    assert_eq!(e.f64(1.1), Ok(9));
+    assert_eq!(e.f32(f32::NAN), Ok(3));
+    assert_eq!(e.begin_array(), Err(CborError::NotCanonical));
+    let written = e.map_with(3, |e| {
+        e.text("b")?;
+        e.unsigned(1)?;
+        e.int(-1)?;
+        e.unsigned(2)?;
+        e.unsigned(10)?;
+        e.unsigned(3)?;
+        Ok(())
+    });
+    assert_eq!(written, Ok(8));
+    assert_eq!(e.map_with(2, |e| e.unsigned(1).map(|_| ())), Err(CborError::CountMismatch));
+    assert_eq!(
+        e.map_with(2, |e| {
+            for _ in 0..2 {
+                e.unsigned(1)?;
+                e.null()?;
+            }
+            Ok(())
+        }),
+        Err(CborError::DuplicateKey)
+    );
+    let n = e.finish();
+    assert_eq!(&sink[..20], &[0xF9, 0x3E, 0x00, 0xFA, 0x47, 0xC3, 0x50, 0x00, 0xFB, 0x3F, 0xF1, 0x99, 0x99, 0x99, 0x99, 0x99, 0x9A, 0xF9, 0x7E, 0x00][..]);
+    assert_eq!(&sink[20..n], &[0xA3, 0x0A, 0x03, 0x20, 0x02, 0x61, b'b', 0x01][..]);
+
+    let mut d = Decoder::new(&sink[..n]).canonical(true);
+    while !d.is_empty() {
+        d.skip().unwrap();
+    }
+    assert_eq!(Decoder::new(&[0x18, 0x17]).canonical(true).read(), Err(CborError::NotCanonical));
+    assert_eq!(Decoder::new(&[0xFA, 0x3F, 0xC0, 0x00, 0x00]).canonical(true).read(), Err(CborError::NotCanonical));
+    assert_eq!(Decoder::new(&[0x9F, 0xFF]).canonical(true).read(), Err(CborError::NotCanonical));
+    assert_eq!(Decoder::new(&[0x18, 0x17]).read(), Ok(Token::Unsigned(23)));
+}
+
+#[test]
+fn test_cbor_decode() {
+    // {"a": [1, -2, 1.5], "b": (_ h'01', h'0203'), "c": [_ "x", 0("t")], "d": null}
+    let source = [
+        0xA4, 0x61, b'a', 0x83, 0x01, 0x21, 0xF9, 0x3E, 0x00, 0x61, b'b', 0x5F, 0x41, 0x01, 0x42, 0x02, 0x03, 0xFF,
+        0x61, b'c', 0x9F, 0x61, b'x', 0xC0, 0x61, b't', 0xFF, 0x61, b'd', 0xF6,
+    ];
+    let mut d = Decoder::new(&source[..]);
+    let expected = [
+        Token::Map(4), Token::Text("a"), Token::Array(3), Token::Unsigned(1), Token::Negative(1), Token::Float(1.5),
+        Token::Text("b"), Token::BytesStart, Token::Bytes(&[1]), Token::Bytes(&[2, 3]), Token::Break,
+        Token::Text("c"), Token::ArrayStart, Token::Text("x"), Token::Tag(0), Token::Text("t"), Token::Break,
+        Token::Text("d"), Token::Null,
+    ];
+    for token in expected.iter() {
+        assert_eq!(d.read().as_ref(), Ok(token));
+    }
+    assert!(d.is_empty());
+    assert_eq!(Token::Negative(1).as_i64(), Some(-2));
+
+    let mut d = Decoder::new(&source[..]);
+    assert_eq!(d.skip(), Ok(()));
+    assert_eq!(d.position(), source.len());
+
+    assert_eq!(Decoder::new(&[0x5F, 0x61, b'x', 0xFF]).skip(), Err(CborError::InvalidChunk));
+    assert_eq!(Decoder::new(&[0x82, 0x01, 0xFF]).skip(), Err(CborError::UnexpectedBreak));
+    assert_eq!(Decoder::new(&[0x1C]).read(), Err(CborError::InvalidAdditionalInfo(0x1C)));
+    assert_eq!(Decoder::new(&[0xF8, 0x10]).read(), Err(CborError::InvalidSimple(0x10)));
+    assert_eq!(Decoder::new(&[0x62, b'x']).read(), Err(CborError::Eof));
+    assert_eq!(Decoder::new(&[0x61, 0xFF]).read(), Err(CborError::InvalidUtf8));
+    assert_eq!(Decoder::new(&[0x81; 300]).skip(), Err(CborError::DepthLimitExceeded));
+
+    // A break outside any indefinite-length item is an error, and leaves the decoder where it was.
+    let mut d = Decoder::new(&[0x9F, 0xFF, 0xFF]);
+    assert_eq!(d.read(), Ok(Token::ArrayStart));
+    assert_eq!(d.read(), Ok(Token::Break));
+    assert_eq!(d.read(), Err(CborError::UnexpectedBreak));
+    assert_eq!(d.position(), 2);
+    assert_eq!(Decoder::new(&[0xFF]).read(), Err(CborError::UnexpectedBreak));
+
+    // A failed skip keeps the chunked string open, so the bad chunk is still refused.
+    let mut d = Decoder::new(&[0x5F, 0x41, 0x01, 0x61, b'x', 0xFF]);
+    assert_eq!(d.read(), Ok(Token::BytesStart));
+    assert_eq!(d.read(), Ok(Token::Bytes(&[1])));
+    assert_eq!(d.skip(), Err(CborError::InvalidChunk));
+    assert_eq!(d.position(), 3);
+    assert_eq!(d.read(), Err(CborError::InvalidChunk));
+}
@@ -0,0 +1,228 @@
+//! Legacy single-byte character sets: ISO-8859-1 (Latin-1),
+//! Windows-1252 and code page 437.
+//!
+//! Every byte decodes to a character, so decoding can't fail: `chars`
+//! iterates over the characters of a byte slice, and with `std` enabled
+//! `decode` collects them into a `String`.  `encode` goes the other way,
+//! failing with `Unmappable` on the first character the set lacks.
+//!
+//! The five bytes Windows-1252 leaves undefined (0x81, 0x8D, 0x8F, 0x90
+//! and 0x9D) decode to the C1 control characters of the same value, as
+//! web browsers do, so they survive a round trip.  Code page 437 follows
+//! the Unicode mapping, in which 0x00 to 0x7F are ASCII (rather than the
+//! smiley faces and arrows a PC displays for the control characters).
+
+#[cfg(feature="std")]
+use core::iter::FromIterator;
+
+/// A single-byte character set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    /// ISO-8859-1: bytes are the first 256 code points.
+    Latin1,
+    /// Windows-1252: Latin-1 with printable characters at 0x80 to 0x9F.
+    Windows1252,
+    /// Code page 437, the original IBM PC character set.
+    Cp437,
+}
+
+/// Errors produced encoding text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharsetError {
+    /// The sink is too small.
+    BufferTooSmall {
+        /// The number of bytes required.
+        required: usize,
+    },
+    /// A character has no encoding in the character set.
+    Unmappable {
+        /// The character.
+        ch: char,
+        /// Its byte offset in the string.
+        position: usize,
+    },
+}
+
+// Windows-1252 from 0x80 to 0x9F.  Zero marks the undefined bytes.
+const WINDOWS_1252: [u16; 32] = [
+    0x20AC, 0, 0x201A, 0x0192, 0x201E, 0x2026, 0x2020, 0x2021,
+    0x02C6, 0x2030, 0x0160, 0x2039, 0x0152, 0, 0x017D, 0,
+    0, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014,
+    0x02DC, 0x2122, 0x0161, 0x203A, 0x0153, 0, 0x017E, 0x0178,
+];
+
+// Code page 437 from 0x80 to 0xFF.
+const CP437: [u16; 128] = [
+    0x00C7, 0x00FC, 0x00E9, 0x00E2, 0x00E4, 0x00E0, 0x00E5, 0x00E7,
+    0x00EA, 0x00EB, 0x00E8, 0x00EF, 0x00EE, 0x00EC, 0x00C4, 0x00C5,
+    0x00C9, 0x00E6, 0x00C6, 0x00F4, 0x00F6, 0x00F2, 0x00FB, 0x00F9,
+    0x00FF, 0x00D6, 0x00DC, 0x00A2, 0x00A3, 0x00A5, 0x20A7, 0x0192,
+    0x00E1, 0x00ED, 0x00F3, 0x00FA, 0x00F1, 0x00D1, 0x00AA, 0x00BA,
+    0x00BF, 0x2310, 0x00AC, 0x00BD, 0x00BC, 0x00A1, 0x00AB, 0x00BB,
+    0x2591, 0x2592, 0x2593, 0x2502, 0x2524, 0x2561, 0x2562, 0x2556,
+    0x2555, 0x2563, 0x2551, 0x2557, 0x255D, 0x255C, 0x255B, 0x2510,
+    0x2514, 0x2534, 0x252C, 0x251C, 0x2500, 0x253C, 0x255E, 0x255F,
+    0x255A, 0x2554, 0x2569, 0x2566, 0x2560, 0x2550, 0x256C, 0x2567,
+    0x2568, 0x2564, 0x2565, 0x2559, 0x2558, 0x2552, 0x2553, 0x256B,
+    0x256A, 0x2518, 0x250C, 0x2588, 0x2584, 0x258C, 0x2590, 0x2580,
+    0x03B1, 0x00DF, 0x0393, 0x03C0, 0x03A3, 0x03C3, 0x00B5, 0x03C4,
+    0x03A6, 0x0398, 0x03A9, 0x03B4, 0x221E, 0x03C6, 0x03B5, 0x2229,
+    0x2261, 0x00B1, 0x2265, 0x2264, 0x2320, 0x2321, 0x00F7, 0x2248,
+    0x00B0, 0x2219, 0x00B7, 0x221A, 0x207F, 0x00B2, 0x25A0, 0x00A0,
+];
+
+impl Charset {
+    /// The character `byte` decodes to.
+    pub fn decode_byte(&self, byte: u8) -> char {
+        let code = match *self {
+            Charset::Latin1 => byte as u16,
+            Charset::Windows1252 => match byte {
+                0x80..=0x9F => match WINDOWS_1252[byte as usize - 0x80] {
+                    0 => byte as u16,
+                    code => code,
+                },
+                _ => byte as u16,
+            },
+            Charset::Cp437 => match byte {
+                0x80..=0xFF => CP437[byte as usize - 0x80],
+                _ => byte as u16,
+            },
+        };
+        // Every table entry is a valid (BMP, non-surrogate) character.
+        char::from_u32(code as u32).unwrap()
+    }
+
+    /// The byte `ch` encodes to, if the character set has it.
+    pub fn encode_char(&self, ch: char) -> Option<u8> {
+        let code = ch as u32;
+        match *self {
+            Charset::Latin1 => if code < 0x100 { Some(code as u8) } else { None },
+            Charset::Windows1252 => {
+                if code < 0x80 || (0xA0..0x100).contains(&code) {
+                    return Some(code as u8);
+                }
+                if let Some(i) = WINDOWS_1252.iter().position(|c| *c != 0 && *c as u32 == code) {
+                    return Some(0x80 + i as u8);
+                }
+                // The undefined bytes, which decode to themselves.
+                match code {
+                    0x81 | 0x8D | 0x8F | 0x90 | 0x9D => Some(code as u8),
+                    _ => None,
+                }
+            }
+            Charset::Cp437 => {
+                if code < 0x80 {
+                    return Some(code as u8);
+                }
+                CP437.iter().position(|c| *c as u32 == code).map(|i| 0x80 + i as u8)
+            }
+        }
+    }
+}
+
+/// Encodes `value` to offset zero of `sink`.
+///
+/// The return value is the number of bytes written (one per character).
+/// Nothing is written if any character is unmappable.
+pub fn encode(charset: Charset, value: &str, sink: &mut [u8]) -> Result<usize, CharsetError> {
+    let required = value.chars().count();
+    if sink.len() < required {
+        return Err(CharsetError::BufferTooSmall { required });
+    }
+    for (position, ch) in value.char_indices() {
+        if charset.encode_char(ch).is_none() {
+            return Err(CharsetError::Unmappable { ch, position });
+        }
+    }
+    for (b, ch) in sink.iter_mut().zip(value.chars()) {
+        *b = charset.encode_char(ch).unwrap();
+    }
+    Ok(required)
+}
+
+/// Returns an iterator over the characters `source` decodes to.
+pub fn chars(charset: Charset, source: &[u8]) -> Chars<'_> {
+    Chars {
+        charset,
+        source: source.iter(),
+    }
+}
+
+/// Decodes `source` to a `String`.
+#[cfg(feature="std")]
+pub fn decode(charset: Charset, source: &[u8]) -> String {
+    String::from_iter(chars(charset, source))
+}
+
+/// An iterator over the characters of a byte slice.
+#[derive(Debug, Clone)]
+pub struct Chars<'a> {
+    charset: Charset,
+    source: ::core::slice::Iter<'a, u8>,
+}
+
+impl<'a> Iterator for Chars<'a> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        self.source.next().map(|b| self.charset.decode_byte(*b))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.source.size_hint()
+    }
+}
+
+#[test]
+fn test_charset_roundtrip() {
+    // Every byte of every character set survives a round trip.
+    for charset in [Charset::Latin1, Charset::Windows1252, Charset::Cp437].iter() {
+        for b in 0..=255u8 {
+            assert_eq!(charset.encode_char(charset.decode_byte(b)), Some(b), "{:?} {:#x}", charset, b);
+        }
+    }
+}
+
+#[test]
+fn test_charset_tables() {
+    assert_eq!(Charset::Latin1.decode_byte(0x80), '\u{80}');
+    assert_eq!(Charset::Windows1252.decode_byte(0x80), '€');
+    assert_eq!(Charset::Windows1252.decode_byte(0x81), '\u{81}');
+    assert_eq!(Charset::Windows1252.decode_byte(0x9F), 'Ÿ');
+    assert_eq!(Charset::Cp437.decode_byte(0x82), 'é');
+    assert_eq!(Charset::Cp437.decode_byte(0xC9), '╔');
+    assert_eq!(Charset::Cp437.decode_byte(0xFF), '\u{A0}');
+
+    let mut decoded = [' '; 4];
+    for (c, d) in chars(Charset::Cp437, b"\xDA\xC4\xBFA").zip(decoded.iter_mut()) {
+        *d = c;
+    }
+    assert_eq!(decoded, ['┌', '─', '┐', 'A']);
+}
+
+#[test]
+fn test_charset_encode() {
+    let mut data = [0u8; 8];
+    assert_eq!(encode(Charset::Windows1252, "€5 café", &mut data[..]), Ok(7));
+    assert_eq!(&data[..7], b"\x805 caf\xE9");
+    assert_eq!(
+        encode(Charset::Latin1, "€5", &mut data[..]),
+        Err(CharsetError::Unmappable { ch: '€', position: 0 })
+    );
+    assert_eq!(
+        encode(Charset::Cp437, "a→", &mut data[..]),
+        Err(CharsetError::Unmappable { ch: '→', position: 1 })
+    );
+    assert_eq!(
+        encode(Charset::Cp437, "ÇüéâäàåçÇ", &mut data[..]),
+        Err(CharsetError::BufferTooSmall { required: 9 })
+    );
+}
+
+#[cfg(feature="std")]
+#[test]
+fn test_charset_decode() {
+    assert_eq!(decode(Charset::Windows1252, b"\x93hi\x94"), "\u{201C}hi\u{201D}");
+    assert_eq!(decode(Charset::Latin1, b"\x93hi\x94"), "\u{93}hi\u{94}");
+    assert_eq!(decode(Charset::Cp437, b"\xE0\xE1"), "αß");
+}
@@ -0,0 +1,532 @@
+//! Table driven checksums: CRC-32 (IEEE and Castagnoli), CRC-16/CCITT
+//! and Adler-32.  None of these require std.
+//!
+//! `ChecksumCursor` wraps a cursor writer (`WriteCursor`, or `VecWriter`
+//! and `SeekWriter` with `std`) and updates a checksum as values are
+//! written through `IO` (and `VLQ`), so the checksum is available as soon
+//! as the last value is written rather than requiring a second pass.
+//! With `std` enabled, `ChecksumWriter` and `ChecksumReader` do the same
+//! for `std::io::Write` and `std::io::Read`.
+
+use IO;
+use with_scratch;
+use cursor::{CursorError, CursorWrite};
+
+#[cfg(feature="vlq")]
+use VLQ;
+
+#[cfg(feature="vlq")]
+use MAX_VLQ_LEN;
+
+#[cfg(feature="std")]
+use std::io;
+
+/// A running checksum.
+pub trait Checksum {
+    /// The type of the final checksum value.
+    type Output;
+
+    /// Feeds `data` into the checksum.
+    fn update(&mut self, data: &[u8]);
+
+    /// The checksum of everything fed in so far.  This does not consume
+    /// or alter the state, so more data can be fed in afterwards.
+    fn value(&self) -> Self::Output;
+
+    /// Returns the checksum to its initial state.
+    fn reset(&mut self);
+}
+
+// Tables for the reflected (least significant bit first) CRC-32 variants.
+const fn crc32_table(polynomial: u32) -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if (crc & 1) != 0 {
+                (crc >> 1) ^ polynomial
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+// Table for the non-reflected (most significant bit first) CRC-16.
+const fn crc16_table(polynomial: u16) -> [u16; 256] {
+    let mut table = [0u16; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if (crc & 0x8000) != 0 {
+                (crc << 1) ^ polynomial
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC32_IEEE: [u32; 256] = crc32_table(0xEDB88320);
+static CRC32_CASTAGNOLI: [u32; 256] = crc32_table(0x82F63B78);
+static CRC16_CCITT: [u16; 256] = crc16_table(0x1021);
+
+/// CRC-32, in either the IEEE (zlib, Ethernet, PNG) or Castagnoli
+/// (iSCSI, ext4, SSE 4.2) variants.
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32 {
+    table: &'static [u32; 256],
+    state: u32,
+}
+
+impl Crc32 {
+    /// CRC-32 using the IEEE 802.3 polynomial.
+    pub fn ieee() -> Crc32 {
+        Crc32 {
+            table: &CRC32_IEEE,
+            state: 0xFFFFFFFF,
+        }
+    }
+
+    /// CRC-32C using the Castagnoli polynomial.
+    pub fn castagnoli() -> Crc32 {
+        Crc32 {
+            table: &CRC32_CASTAGNOLI,
+            state: 0xFFFFFFFF,
+        }
+    }
+}
+
+impl Checksum for Crc32 {
+    type Output = u32;
+
+    fn update(&mut self, data: &[u8]) {
+        let mut crc = self.state;
+        for byte in data {
+            crc = self.table[((crc as u8) ^ *byte) as usize] ^ (crc >> 8);
+        }
+        self.state = crc;
+    }
+
+    fn value(&self) -> u32 {
+        !self.state
+    }
+
+    fn reset(&mut self) {
+        self.state = 0xFFFFFFFF;
+    }
+}
+
+/// CRC-16 using the CCITT polynomial (`0x1021`), most significant bit
+/// first and with no final XOR.
+///
+/// The initial value differs between the common variants, so both are
+/// provided: `ccitt_false` (initial `0xFFFF`) and `xmodem` (initial `0`).
+#[derive(Debug, Clone, Copy)]
+pub struct Crc16 {
+    initial: u16,
+    state: u16,
+}
+
+impl Crc16 {
+    /// CRC-16/CCITT-FALSE (initial value `0xFFFF`).
+    pub fn ccitt_false() -> Crc16 {
+        Crc16 {
+            initial: 0xFFFF,
+            state: 0xFFFF,
+        }
+    }
+
+    /// CRC-16/XMODEM (initial value `0`).
+    pub fn xmodem() -> Crc16 {
+        Crc16 {
+            initial: 0,
+            state: 0,
+        }
+    }
+}
+
+impl Checksum for Crc16 {
+    type Output = u16;
+
+    fn update(&mut self, data: &[u8]) {
+        let mut crc = self.state;
+        for byte in data {
+            crc = CRC16_CCITT[((crc >> 8) as u8 ^ *byte) as usize] ^ (crc << 8);
+        }
+        self.state = crc;
+    }
+
+    fn value(&self) -> u16 {
+        self.state
+    }
+
+    fn reset(&mut self) {
+        self.state = self.initial;
+    }
+}
+
+/// Adler-32, as used by zlib.
+#[derive(Debug, Clone, Copy)]
+pub struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+// Largest prime below 2^16.
+const ADLER_MOD: u32 = 65521;
+
+// The most bytes that can be summed before `b` may overflow a `u32`.
+const ADLER_NMAX: usize = 5552;
+
+impl Adler32 {
+    /// Creates a new Adler-32 checksum.
+    pub fn new() -> Adler32 {
+        Adler32 { a: 1, b: 0 }
+    }
+}
+
+impl Default for Adler32 {
+    fn default() -> Adler32 {
+        Adler32::new()
+    }
+}
+
+impl Checksum for Adler32 {
+    type Output = u32;
+
+    fn update(&mut self, data: &[u8]) {
+        for chunk in data.chunks(ADLER_NMAX) {
+            for byte in chunk {
+                self.a += *byte as u32;
+                self.b += self.a;
+            }
+            self.a %= ADLER_MOD;
+            self.b %= ADLER_MOD;
+        }
+    }
+
+    fn value(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+
+    fn reset(&mut self) {
+        self.a = 1;
+        self.b = 0;
+    }
+}
+
+/// IEEE CRC-32 of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::ieee();
+    crc.update(data);
+    crc.value()
+}
+
+/// Castagnoli CRC-32C of `data`.
+pub fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = Crc32::castagnoli();
+    crc.update(data);
+    crc.value()
+}
+
+/// CRC-16/CCITT-FALSE of `data`.
+pub fn crc16_ccitt(data: &[u8]) -> u16 {
+    let mut crc = Crc16::ccitt_false();
+    crc.update(data);
+    crc.value()
+}
+
+/// Adler-32 of `data`.
+pub fn adler32(data: &[u8]) -> u32 {
+    let mut adler = Adler32::new();
+    adler.update(data);
+    adler.value()
+}
+
+#[test]
+fn test_checksum_check_values() {
+    // The standard "123456789" check values.
+    let data = b"123456789";
+    assert_eq!(crc32(data), 0xCBF43926);
+    assert_eq!(crc32c(data), 0xE3069283);
+    assert_eq!(crc16_ccitt(data), 0x29B1);
+
+    let mut xmodem = Crc16::xmodem();
+    xmodem.update(data);
+    assert_eq!(xmodem.value(), 0x31C3);
+
+    assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+    assert_eq!(adler32(b""), 1);
+    assert_eq!(crc32(b""), 0);
+}
+
+#[test]
+fn test_checksum_incremental() {
+    let data = [0xA5u8; 20000];
+    let mut crc = Crc32::ieee();
+    let mut adler = Adler32::new();
+    for chunk in data.chunks(7) {
+        crc.update(chunk);
+        adler.update(chunk);
+    }
+    assert_eq!(crc.value(), crc32(&data[..]));
+    assert_eq!(adler.value(), adler32(&data[..]));
+
+    crc.reset();
+    adler.reset();
+    assert_eq!(crc.value(), 0);
+    assert_eq!(adler.value(), 1);
+}
+
+// ----------------------------------------------------------------------
+// Writers
+// ----------------------------------------------------------------------
+
+/// Writes values through a cursor writer, updating a checksum with every
+/// byte written.
+///
+/// The checksum only covers what is written after the writer is wrapped.
+/// Placeholders aren't available through it, as the checksum would
+/// already cover their zero bytes by the time they're patched.
+#[derive(Debug)]
+pub struct ChecksumCursor<W: CursorWrite, C: Checksum> {
+    inner: W,
+    checksum: C,
+}
+
+impl<W: CursorWrite, C: Checksum> ChecksumCursor<W, C> {
+    /// Wraps `inner`.
+    pub fn new(inner: W, checksum: C) -> ChecksumCursor<W, C> {
+        ChecksumCursor { inner, checksum }
+    }
+
+    /// Writes an `IO` value at the current position.
+    ///
+    /// The return value is the number of bytes written.
+    pub fn write<T: IO>(&mut self, value: &mut T) -> Result<usize, CursorError> {
+        with_scratch::<T, _, _>(|buffer| {
+            let written = value.fio_write(buffer);
+            self.write_bytes(&buffer[..written])
+        })
+    }
+
+    /// Writes a `VLQ` value at the current position.
+    ///
+    /// The return value is the number of bytes written.
+    #[cfg(feature="vlq")]
+    pub fn write_vlq<T: VLQ>(&mut self, value: &T) -> Result<usize, CursorError> {
+        let mut buffer = [0u8; MAX_VLQ_LEN];
+        let written = value.vlq_write(&mut buffer[..]);
+        self.write_bytes(&buffer[..written])
+    }
+
+    /// Writes raw bytes at the current position.  The checksum is only
+    /// updated once they have been written.
+    pub fn write_bytes(&mut self, data: &[u8]) -> Result<usize, CursorError> {
+        let written = self.inner.write_bytes(data)?;
+        self.checksum.update(data);
+        Ok(written)
+    }
+
+    /// The checksum of everything written so far.
+    pub fn value(&self) -> C::Output {
+        self.checksum.value()
+    }
+
+    /// The underlying checksum.
+    pub fn checksum(&self) -> &C {
+        &self.checksum
+    }
+
+    /// The wrapped writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns the wrapped writer and the checksum.
+    pub fn into_inner(self) -> (W, C) {
+        (self.inner, self.checksum)
+    }
+}
+
+/// Wraps a `std::io::Write`, updating a checksum with every byte written.
+///
+/// Values written through `fricgan::fio_write` (or `write_vlq`) update the
+/// checksum as they go.
+#[cfg(feature="std")]
+pub struct ChecksumWriter<W: io::Write, C: Checksum> {
+    inner: W,
+    checksum: C,
+}
+
+#[cfg(feature="std")]
+impl<W: io::Write, C: Checksum> ChecksumWriter<W, C> {
+    /// Wraps `inner`.
+    pub fn new(inner: W, checksum: C) -> ChecksumWriter<W, C> {
+        ChecksumWriter { inner, checksum }
+    }
+
+    /// Writes a `VLQ` value, returning the number of bytes written.
+    #[cfg(feature="vlq")]
+    pub fn write_vlq<T: VLQ>(&mut self, value: &T) -> io::Result<usize> {
+        let mut buffer = [0u8; MAX_VLQ_LEN];
+        let written = value.vlq_write(&mut buffer[..]);
+        io::Write::write_all(self, &buffer[..written])?;
+        Ok(written)
+    }
+
+    /// The checksum of everything written so far.
+    pub fn value(&self) -> C::Output {
+        self.checksum.value()
+    }
+
+    /// The underlying checksum.
+    pub fn checksum(&self) -> &C {
+        &self.checksum
+    }
+
+    /// Returns the wrapped writer and the checksum.
+    pub fn into_inner(self) -> (W, C) {
+        (self.inner, self.checksum)
+    }
+}
+
+#[cfg(feature="std")]
+impl<W: io::Write, C: Checksum> io::Write for ChecksumWriter<W, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.checksum.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Wraps a `std::io::Read`, updating a checksum with every byte read.
+#[cfg(feature="std")]
+pub struct ChecksumReader<R: io::Read, C: Checksum> {
+    inner: R,
+    checksum: C,
+}
+
+#[cfg(feature="std")]
+impl<R: io::Read, C: Checksum> ChecksumReader<R, C> {
+    /// Wraps `inner`.
+    pub fn new(inner: R, checksum: C) -> ChecksumReader<R, C> {
+        ChecksumReader { inner, checksum }
+    }
+
+    /// The checksum of everything read so far.
+    pub fn value(&self) -> C::Output {
+        self.checksum.value()
+    }
+
+    /// The underlying checksum.
+    pub fn checksum(&self) -> &C {
+        &self.checksum
+    }
+
+    /// Returns the wrapped reader and the checksum.
+    pub fn into_inner(self) -> (R, C) {
+        (self.inner, self.checksum)
+    }
+}
+
+#[cfg(feature="std")]
+impl<R: io::Read, C: Checksum> io::Read for ChecksumReader<R, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.checksum.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+#[cfg(all(feature="io-u16", feature="io-u32", feature="vlq-32"))]
+#[test]
+fn test_checksum_cursor() {
+    use cursor::WriteCursor;
+
+    let mut data = [0u8; 32];
+    let written = {
+        let mut cursor = ChecksumCursor::new(WriteCursor::new(&mut data[..]), Crc32::ieee());
+        assert_eq!(cursor.write(&mut 0x1234u16.to_be()), Ok(2));
+        assert_eq!(cursor.write_vlq(&300u32), Ok(2));
+        assert_eq!(cursor.write_bytes(b"fricgan"), Ok(7));
+
+        // Append the checksum itself.
+        let mut crc = cursor.value().to_le();
+        assert_eq!(crc32(cursor.get_ref().written()), u32::from_le(crc));
+        assert_eq!(cursor.write(&mut crc), Ok(4));
+        cursor.into_inner().0.finish().unwrap()
+    };
+    assert_eq!(written, 2 + 2 + 7 + 4);
+    assert_eq!(&data[..11], b"\x12\x34\xAC\x02fricgan");
+
+    // The CRC of data followed by its little endian CRC is a constant.
+    assert_eq!(crc32(&data[..written]), 0x2144DF1C);
+
+    // A write which doesn't fit leaves the checksum alone.
+    let mut cursor = ChecksumCursor::new(WriteCursor::new(&mut data[..3]), Crc32::ieee());
+    assert_eq!(cursor.write_bytes(b"ab"), Ok(2));
+    assert_eq!(cursor.write(&mut 1u16), Err(CursorError::BufferTooSmall { required: 4 }));
+    assert_eq!(cursor.write_vlq(&300u32), Err(CursorError::BufferTooSmall { required: 4 }));
+    assert_eq!(cursor.value(), crc32(b"ab"));
+}
+
+#[cfg(all(feature="std", feature="io-u32"))]
+#[test]
+fn test_checksum_cursor_writers() {
+    use cursor::{SeekWriter, VecWriter};
+
+    let mut cursor = ChecksumCursor::new(VecWriter::new(), Crc16::xmodem());
+    assert_eq!(cursor.write(&mut 0xDEADBEEFu32.to_be()), Ok(4));
+    let (writer, crc) = cursor.into_inner();
+    assert_eq!(writer.written(), b"\xDE\xAD\xBE\xEF");
+    assert_eq!(crc.value(), {
+        let mut xmodem = Crc16::xmodem();
+        xmodem.update(b"\xDE\xAD\xBE\xEF");
+        xmodem.value()
+    });
+
+    let mut cursor = ChecksumCursor::new(SeekWriter::new(io::Cursor::new(Vec::new())), Adler32::new());
+    assert_eq!(cursor.write_bytes(b"Wikipedia"), Ok(9));
+    assert_eq!(cursor.value(), 0x11E60398);
+    assert_eq!(cursor.into_inner().0.finish().unwrap().into_inner(), b"Wikipedia".to_vec());
+}
+
+#[cfg(all(feature="std", feature="io-u32", feature="vlq-32"))]
+#[test]
+fn test_checksum_writer() {
+    use std::io::Read;
+    use fio_read;
+    use fio_write;
+
+    let mut writer = ChecksumWriter::new(Vec::new(), Adler32::new());
+    assert_eq!(fio_write(&mut 0xDEADBEEFu32, &mut writer), 4);
+    assert_eq!(writer.write_vlq(&0xFFFFu32).unwrap(), 3);
+    let (data, adler) = writer.into_inner();
+    assert_eq!(adler.value(), adler32(&data[..]));
+
+    let mut reader = ChecksumReader::new(&data[..], Adler32::new());
+    let mut value: u32 = 0;
+    assert_eq!(fio_read(&mut value, &mut reader), 4);
+    assert_eq!(value, 0xDEADBEEF);
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    assert_eq!(reader.value(), adler.value());
+}
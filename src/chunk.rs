@@ -0,0 +1,421 @@
+//! RIFF and IFF chunks.
+//!
+//! A chunk is a four character code, a 32-bit size (little endian for
+//! RIFF, big endian for IFF), and `size` bytes of data.  Chunks start on
+//! even offsets, so a chunk with an odd size is followed by a pad byte,
+//! which the size doesn't count.
+//!
+//! Container chunks (`RIFF`, `LIST`, `FORM`, `CAT `, `PROP`) begin their
+//! data with a form type, followed by further chunks.  `chunks` iterates
+//! over the chunks in a slice, and `Chunk::children` over those within a
+//! container.  `ChunkWriter` writes chunks, filling in the sizes of
+//! nested chunks once their contents are written.
+
+use core::fmt;
+use core::str;
+
+use IO;
+
+/// A four character code, identifying a chunk or a form type.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FourCC(pub [u8; 4]);
+
+impl FourCC {
+    /// `RIFF`, the outermost chunk of a RIFF file.
+    pub const RIFF: FourCC = FourCC(*b"RIFF");
+    /// `LIST`, a RIFF (or IFF) list of chunks.
+    pub const LIST: FourCC = FourCC(*b"LIST");
+    /// `FORM`, the outermost chunk of an IFF file.
+    pub const FORM: FourCC = FourCC(*b"FORM");
+    /// `CAT `, an IFF concatenation of chunks.
+    pub const CAT: FourCC = FourCC(*b"CAT ");
+    /// `PROP`, IFF shared properties.
+    pub const PROP: FourCC = FourCC(*b"PROP");
+
+    /// The code's bytes.
+    pub fn as_bytes(&self) -> &[u8; 4] {
+        &self.0
+    }
+
+    /// The code as a string, if it is ASCII.
+    pub fn as_str(&self) -> Option<&str> {
+        if self.0.is_ascii() {
+            str::from_utf8(&self.0).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Whether chunks with this code hold a form type and further chunks.
+    pub fn is_container(&self) -> bool {
+        matches!(*self, FourCC::RIFF | FourCC::LIST | FourCC::FORM | FourCC::CAT | FourCC::PROP)
+    }
+}
+
+impl<'a> From<&'a [u8; 4]> for FourCC {
+    fn from(code: &'a [u8; 4]) -> FourCC {
+        FourCC(*code)
+    }
+}
+
+impl fmt::Debug for FourCC {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.as_str() {
+            Some(code) => write!(f, "FourCC({:?})", code),
+            None => write!(f, "FourCC({:?})", self.0),
+        }
+    }
+}
+
+/// The byte order of chunk sizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkFormat {
+    /// RIFF: little endian sizes.
+    Riff,
+    /// IFF: big endian sizes.
+    Iff,
+}
+
+impl ChunkFormat {
+    fn write_size(&self, size: u32, sink: &mut [u8]) -> usize {
+        match *self {
+            ChunkFormat::Riff => size.to_le().fio_write(sink),
+            ChunkFormat::Iff => size.to_be().fio_write(sink),
+        }
+    }
+
+    fn read_size(&self, source: &[u8]) -> u32 {
+        let mut size: u32 = 0;
+        size.fio_read(source);
+        match *self {
+            ChunkFormat::Riff => u32::from_le(size),
+            ChunkFormat::Iff => u32::from_be(size),
+        }
+    }
+}
+
+/// Errors produced while writing or reading chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkError {
+    /// The sink is too small to hold the chunk.
+    BufferTooSmall {
+        /// The number of bytes the sink requires.
+        required: usize,
+    },
+    /// The input ended partway through a chunk header, or a container
+    /// had no form type.
+    Truncated,
+    /// A chunk's size runs past the end of the input.
+    Overrun {
+        /// The chunk's size.
+        size: usize,
+        /// The number of bytes left in the input.
+        available: usize,
+    },
+    /// The chunk's data is too large for a 32-bit size.
+    TooLarge,
+}
+
+// The size of a chunk header (code and size).
+const HEADER_LEN: usize = 8;
+
+/// A single chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunk<'a> {
+    /// The chunk's code.
+    pub id: FourCC,
+    /// The chunk's data, without any pad byte.
+    pub data: &'a [u8],
+    format: ChunkFormat,
+}
+
+impl<'a> Chunk<'a> {
+    /// The form type of a container chunk (the first four bytes of its
+    /// data).
+    pub fn form_type(&self) -> Result<FourCC, ChunkError> {
+        if self.data.len() < 4 {
+            return Err(ChunkError::Truncated);
+        }
+        Ok(FourCC([self.data[0], self.data[1], self.data[2], self.data[3]]))
+    }
+
+    /// An iterator over the chunks within a container chunk, after its
+    /// form type.
+    pub fn children(&self) -> Result<Chunks<'a>, ChunkError> {
+        self.form_type()?;
+        Ok(chunks(self.format, &self.data[4..]))
+    }
+}
+
+/// Reads a single chunk from offset zero of `source`, returning it and
+/// the number of bytes read (including any pad byte).
+///
+/// A missing pad byte after the final chunk is tolerated, as many
+/// writers leave it out.
+pub fn read_chunk(format: ChunkFormat, source: &[u8]) -> Result<(Chunk<'_>, usize), ChunkError> {
+    if source.len() < HEADER_LEN {
+        return Err(ChunkError::Truncated);
+    }
+    let id = FourCC([source[0], source[1], source[2], source[3]]);
+    let size = format.read_size(&source[4..]) as usize;
+    let available = source.len() - HEADER_LEN;
+    if size > available {
+        return Err(ChunkError::Overrun { size, available });
+    }
+    let data = &source[HEADER_LEN..HEADER_LEN + size];
+    let read = (HEADER_LEN + size + (size & 1)).min(source.len());
+    Ok((Chunk { id, data, format }, read))
+}
+
+/// Returns an iterator over the chunks in `source`.
+pub fn chunks(format: ChunkFormat, source: &[u8]) -> Chunks<'_> {
+    Chunks {
+        format,
+        source,
+        failed: false,
+    }
+}
+
+/// An iterator over the chunks in a slice.  Iteration stops after the
+/// first error.
+#[derive(Debug, Clone)]
+pub struct Chunks<'a> {
+    format: ChunkFormat,
+    source: &'a [u8],
+    failed: bool,
+}
+
+impl<'a> Chunks<'a> {
+    /// The input not yet consumed.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.source
+    }
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Result<Chunk<'a>, ChunkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.source.is_empty() {
+            return None;
+        }
+        match read_chunk(self.format, self.source) {
+            Ok((chunk, read)) => {
+                self.source = &self.source[read..];
+                Some(Ok(chunk))
+            }
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Writes chunks to a byte buffer.
+///
+/// Each method writes a single chunk, including its pad byte, and
+/// returns the number of bytes written.  If a method fails, nothing it
+/// wrote is kept.
+pub struct ChunkWriter<'a> {
+    format: ChunkFormat,
+    sink: &'a mut [u8],
+    position: usize,
+}
+
+impl<'a> ChunkWriter<'a> {
+    /// Creates a writer writing to offset zero of `sink`.
+    pub fn new(format: ChunkFormat, sink: &'a mut [u8]) -> ChunkWriter<'a> {
+        ChunkWriter { format, sink, position: 0 }
+    }
+
+    /// The number of bytes written so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Finishes writing, returning the number of bytes written.
+    pub fn finish(self) -> usize {
+        self.position
+    }
+
+    fn reserve(&self, length: usize) -> Result<(), ChunkError> {
+        let required = self.position + length;
+        if self.sink.len() < required {
+            Err(ChunkError::BufferTooSmall { required })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Writes a chunk with the given code and data.
+    pub fn chunk(&mut self, id: FourCC, data: &[u8]) -> Result<usize, ChunkError> {
+        if data.len() as u64 > u32::MAX as u64 {
+            return Err(ChunkError::TooLarge);
+        }
+        let pad = data.len() & 1;
+        self.reserve(HEADER_LEN + data.len() + pad)?;
+
+        let start = self.position;
+        self.sink[start..start + 4].copy_from_slice(&id.0);
+        self.format.write_size(data.len() as u32, &mut self.sink[start + 4..]);
+        let end = start + HEADER_LEN + data.len();
+        self.sink[start + HEADER_LEN..end].copy_from_slice(data);
+        if pad != 0 {
+            self.sink[end] = 0;
+        }
+        self.position = end + pad;
+        Ok(self.position - start)
+    }
+
+    /// Writes a chunk whose data is written by `f`.  The size is filled in
+    /// afterwards, and a pad byte added if needed.
+    pub fn nested<F>(&mut self, id: FourCC, f: F) -> Result<usize, ChunkError>
+    where F: FnOnce(&mut ChunkWriter) -> Result<(), ChunkError> {
+        self.with_header(id, None, f)
+    }
+
+    /// Writes a container chunk (such as `RIFF`, `LIST` or `FORM`): the
+    /// form type, then chunks written by `f`.  The size is filled in
+    /// afterwards.
+    pub fn container<F>(&mut self, id: FourCC, form_type: FourCC, f: F) -> Result<usize, ChunkError>
+    where F: FnOnce(&mut ChunkWriter) -> Result<(), ChunkError> {
+        self.with_header(id, Some(form_type), f)
+    }
+
+    /// Writes raw data within a chunk being written by `nested`.
+    pub fn write_bytes(&mut self, data: &[u8]) -> Result<usize, ChunkError> {
+        self.reserve(data.len())?;
+        self.sink[self.position..self.position + data.len()].copy_from_slice(data);
+        self.position += data.len();
+        Ok(data.len())
+    }
+
+    /// Writes a value within a chunk being written by `nested`, using
+    /// `IO` (so in native byte order).
+    pub fn write<T: IO + ?Sized>(&mut self, value: &mut T) -> Result<usize, ChunkError> {
+        let length = ::core::mem::size_of_val(value);
+        self.reserve(length)?;
+        let written = value.fio_write(&mut self.sink[self.position..]);
+        self.position += written;
+        Ok(written)
+    }
+
+    fn with_header<F>(&mut self, id: FourCC, form_type: Option<FourCC>, f: F) -> Result<usize, ChunkError>
+    where F: FnOnce(&mut ChunkWriter) -> Result<(), ChunkError> {
+        let start = self.position;
+        let result = self.with_header_inner(id, form_type, f);
+        match result {
+            Ok(()) => Ok(self.position - start),
+            Err(e) => {
+                self.position = start;
+                Err(e)
+            }
+        }
+    }
+
+    fn with_header_inner<F>(&mut self, id: FourCC, form_type: Option<FourCC>, f: F) -> Result<(), ChunkError>
+    where F: FnOnce(&mut ChunkWriter) -> Result<(), ChunkError> {
+        let start = self.position;
+        self.reserve(HEADER_LEN)?;
+        self.sink[start..start + 4].copy_from_slice(&id.0);
+        self.position += HEADER_LEN;
+        if let Some(form_type) = form_type {
+            self.write_bytes(&form_type.0)?;
+        }
+
+        let data = self.position;
+        let length = {
+            let mut inner = ChunkWriter::new(self.format, &mut self.sink[data..]);
+            f(&mut inner).map_err(|e| match e {
+                ChunkError::BufferTooSmall { required } => ChunkError::BufferTooSmall { required: data + required },
+                e => e,
+            })?;
+            inner.finish()
+        };
+        self.position += length;
+
+        let size = self.position - start - HEADER_LEN;
+        if size as u64 > u32::MAX as u64 {
+            return Err(ChunkError::TooLarge);
+        }
+        if size & 1 != 0 {
+            self.write_bytes(&[0])?;
+        }
+        self.format.write_size(size as u32, &mut self.sink[start + 4..]);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_chunk_fourcc() {
+    assert_eq!(FourCC::from(b"WAVE").as_str(), Some("WAVE"));
+    assert_eq!(FourCC([0xFF, 0, 0, 0]).as_str(), None);
+    assert!(FourCC::RIFF.is_container());
+    assert!(!FourCC(*b"fmt ").is_container());
+}
+
+#[test]
+fn test_chunk_riff() {
+    let mut sink = [0u8; 64];
+    let mut w = ChunkWriter::new(ChunkFormat::Riff, &mut sink[..]);
+    let written = w.container(FourCC::RIFF, FourCC(*b"WAVE"), |w| {
+        w.nested(FourCC(*b"fmt "), |w| w.write(&mut 0x0002_0001u32.to_le()).map(|_| ()))?;
+        w.chunk(FourCC(*b"data"), &[1, 2, 3])?;
+        Ok(())
+    });
+    let n = w.finish();
+    assert_eq!(written, Ok(n));
+    assert_eq!(
+        &sink[..n],
+        &[
+            b'R', b'I', b'F', b'F', 28, 0, 0, 0, b'W', b'A', b'V', b'E',
+            b'f', b'm', b't', b' ', 4, 0, 0, 0, 1, 0, 2, 0,
+            b'd', b'a', b't', b'a', 3, 0, 0, 0, 1, 2, 3, 0,
+        ][..]
+    );
+
+    let mut outer = chunks(ChunkFormat::Riff, &sink[..n]);
+    let riff = outer.next().unwrap().unwrap();
+    assert_eq!(outer.next(), None);
+    assert_eq!((riff.id, riff.form_type()), (FourCC::RIFF, Ok(FourCC(*b"WAVE"))));
+    let mut children = riff.children().unwrap();
+    let fmt = children.next().unwrap().unwrap();
+    assert_eq!((fmt.id, fmt.data), (FourCC(*b"fmt "), &[1, 0, 2, 0][..]));
+    let data = children.next().unwrap().unwrap();
+    assert_eq!((data.id, data.data), (FourCC(*b"data"), &[1, 2, 3][..]));
+    assert_eq!(children.next(), None);
+}
+
+#[test]
+fn test_chunk_iff() {
+    let mut sink = [0u8; 32];
+    let mut w = ChunkWriter::new(ChunkFormat::Iff, &mut sink[..]);
+    let written = w.nested(FourCC(*b"NAME"), |w| w.write_bytes(b"abc").map(|_| ()));
+    assert_eq!(written, Ok(12));
+    assert_eq!(&sink[..12], &[b'N', b'A', b'M', b'E', 0, 0, 0, 3, b'a', b'b', b'c', 0][..]);
+
+    // The final pad byte may be missing.
+    let (chunk, read) = read_chunk(ChunkFormat::Iff, &sink[..11]).unwrap();
+    assert_eq!((chunk.data, read), (&b"abc"[..], 11));
+}
+
+#[test]
+fn test_chunk_errors() {
+    assert_eq!(read_chunk(ChunkFormat::Riff, b"RIFF\x04\0\0"), Err(ChunkError::Truncated));
+    assert_eq!(
+        read_chunk(ChunkFormat::Riff, b"data\x04\0\0\0ab"),
+        Err(ChunkError::Overrun { size: 4, available: 2 })
+    );
+    let (list, _) = read_chunk(ChunkFormat::Riff, b"LIST\x02\0\0\0ab").unwrap();
+    assert_eq!(list.children().map(|_| ()), Err(ChunkError::Truncated));
+
+    let mut sink = [0u8; 12];
+    let mut w = ChunkWriter::new(ChunkFormat::Riff, &mut sink[..]);
+    assert_eq!(w.chunk(FourCC(*b"data"), &[0; 5]), Err(ChunkError::BufferTooSmall { required: 14 }));
+    assert_eq!(
+        w.nested(FourCC(*b"LIST"), |w| w.write_bytes(&[0; 5]).map(|_| ())),
+        Err(ChunkError::BufferTooSmall { required: 13 })
+    );
+    assert_eq!(w.position(), 0);
+}
@@ -0,0 +1,444 @@
+//! Consistent Overhead Byte Stuffing (COBS), and the reduced COBS/R
+//! variant.
+//!
+//! COBS removes every zero byte from a packet, so a zero can be used to
+//! delimit packets on a serial link.  The overhead is at most one byte in
+//! every 254 (plus one).  COBS/R is identical, save that the last byte of
+//! the packet may replace the final length code, which often avoids the
+//! overhead entirely for short packets.
+//!
+//! The encoded output does not include the delimiter; append a zero when
+//! sending it.  `CobsDecoder` decodes a byte stream containing delimited
+//! packets one byte at a time.
+
+/// Errors produced while encoding or decoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CobsError {
+    /// The output buffer is too small.
+    BufferTooSmall {
+        /// The number of bytes the output buffer requires.
+        required: usize,
+    },
+    /// A zero byte was found within encoded data.
+    UnexpectedZero,
+    /// The encoded data ended partway through a block.
+    Truncated,
+}
+
+/// The largest possible encoded size for `length` bytes of input, not
+/// counting the delimiter.
+pub fn max_encoded_len(length: usize) -> usize {
+    length + length / 254 + 1
+}
+
+// Encodes `buf[start..start + length]` to the front of `buf`.  The
+// output never overtakes the input provided `start` is at least the
+// worst case overhead (`max_encoded_len(length) - length`), which is what
+// makes encoding in place possible.
+fn encode_within(buf: &mut [u8], start: usize, length: usize, reduced: bool) -> usize {
+    let mut code_index = 0;
+    let mut out = 1;
+    let mut code: u8 = 1;
+
+    for read in start..start + length {
+        let byte = buf[read];
+        if byte == 0 {
+            buf[code_index] = code;
+            code_index = out;
+            out += 1;
+            code = 1;
+        } else {
+            buf[out] = byte;
+            out += 1;
+            code += 1;
+            if code == 0xFF {
+                buf[code_index] = code;
+                code_index = out;
+                out += 1;
+                code = 1;
+            }
+        }
+    }
+
+    // COBS/R: if the final byte is larger than the final length code it
+    // can take the place of the code (the decoder spots this because the
+    // code points beyond the end of the data).
+    if reduced && code > 1 && buf[out - 1] > code {
+        buf[code_index] = buf[out - 1];
+        out -= 1;
+    } else {
+        buf[code_index] = code;
+    }
+
+    out
+}
+
+// Decodes `buf[..length]` in place; the output never overtakes the input.
+fn decode_within(buf: &mut [u8], length: usize, reduced: bool) -> Result<usize, CobsError> {
+    let mut read = 0;
+    let mut out = 0;
+
+    while read < length {
+        let code = buf[read];
+        if code == 0 {
+            return Err(CobsError::UnexpectedZero);
+        }
+        read += 1;
+
+        let block_end = read + code as usize - 1;
+        let truncated = block_end > length;
+        if truncated && !reduced {
+            return Err(CobsError::Truncated);
+        }
+
+        let end = if truncated { length } else { block_end };
+        while read < end {
+            let byte = buf[read];
+            if byte == 0 {
+                return Err(CobsError::UnexpectedZero);
+            }
+            buf[out] = byte;
+            out += 1;
+            read += 1;
+        }
+
+        if truncated {
+            // COBS/R: the length code was the final byte.
+            buf[out] = code;
+            return Ok(out + 1);
+        }
+
+        if code != 0xFF && read < length {
+            buf[out] = 0;
+            out += 1;
+        }
+    }
+
+    Ok(out)
+}
+
+fn encode_into(source: &[u8], sink: &mut [u8], reduced: bool) -> Result<usize, CobsError> {
+    let required = max_encoded_len(source.len());
+    if sink.len() < required {
+        return Err(CobsError::BufferTooSmall { required });
+    }
+    let start = sink.len() - source.len();
+    sink[start..].copy_from_slice(source);
+    Ok(encode_within(sink, start, source.len(), reduced))
+}
+
+fn encode_inplace(buf: &mut [u8], length: usize, reduced: bool) -> Result<usize, CobsError> {
+    let required = max_encoded_len(length);
+    if buf.len() < required {
+        return Err(CobsError::BufferTooSmall { required });
+    }
+    let start = buf.len() - length;
+    buf.copy_within(0..length, start);
+    Ok(encode_within(buf, start, length, reduced))
+}
+
+fn decode_into(source: &[u8], sink: &mut [u8], reduced: bool) -> Result<usize, CobsError> {
+    if sink.len() < source.len() {
+        return Err(CobsError::BufferTooSmall { required: source.len() });
+    }
+    sink[..source.len()].copy_from_slice(source);
+    decode_within(sink, source.len(), reduced)
+}
+
+/// COBS encodes `source` to offset zero of `sink`.
+///
+/// `sink` must be at least `max_encoded_len(source.len())` bytes.  The
+/// return value is the number of bytes written.
+pub fn encode(source: &[u8], sink: &mut [u8]) -> Result<usize, CobsError> {
+    encode_into(source, sink, false)
+}
+
+/// COBS encodes the first `length` bytes of `buf` in place.
+///
+/// `buf` must be at least `max_encoded_len(length)` bytes.  The return
+/// value is the encoded length.
+pub fn encode_in_place(buf: &mut [u8], length: usize) -> Result<usize, CobsError> {
+    encode_inplace(buf, length, false)
+}
+
+/// Decodes COBS encoded `source` (without the delimiter) to offset zero
+/// of `sink`.
+///
+/// `sink` must be at least `source.len()` bytes.  The return value is the
+/// number of bytes written.
+pub fn decode(source: &[u8], sink: &mut [u8]) -> Result<usize, CobsError> {
+    decode_into(source, sink, false)
+}
+
+/// Decodes COBS encoded `buf` (without the delimiter) in place, returning
+/// the decoded length.
+pub fn decode_in_place(buf: &mut [u8]) -> Result<usize, CobsError> {
+    let length = buf.len();
+    decode_within(buf, length, false)
+}
+
+/// COBS/R encodes `source` to offset zero of `sink`.
+///
+/// `sink` must be at least `max_encoded_len(source.len())` bytes.  The
+/// return value is the number of bytes written.
+pub fn encode_reduced(source: &[u8], sink: &mut [u8]) -> Result<usize, CobsError> {
+    encode_into(source, sink, true)
+}
+
+/// COBS/R encodes the first `length` bytes of `buf` in place.
+///
+/// `buf` must be at least `max_encoded_len(length)` bytes.  The return
+/// value is the encoded length.
+pub fn encode_reduced_in_place(buf: &mut [u8], length: usize) -> Result<usize, CobsError> {
+    encode_inplace(buf, length, true)
+}
+
+/// Decodes COBS/R encoded `source` (without the delimiter) to offset zero
+/// of `sink`.
+///
+/// `sink` must be at least `source.len()` bytes.  The return value is the
+/// number of bytes written.
+pub fn decode_reduced(source: &[u8], sink: &mut [u8]) -> Result<usize, CobsError> {
+    decode_into(source, sink, true)
+}
+
+/// Decodes COBS/R encoded `buf` (without the delimiter) in place,
+/// returning the decoded length.
+pub fn decode_reduced_in_place(buf: &mut [u8]) -> Result<usize, CobsError> {
+    let length = buf.len();
+    decode_within(buf, length, true)
+}
+
+/// Decodes a stream of zero delimited COBS (or COBS/R) packets, one byte
+/// at a time, into a caller supplied buffer.
+///
+/// Empty packets (consecutive delimiters) are skipped.  After an error
+/// the rest of the packet is discarded, and decoding resumes after the
+/// next delimiter.
+pub struct CobsDecoder<'a> {
+    buffer: &'a mut [u8],
+    length: usize,
+    code: u8,
+    remaining: u8,
+    started: bool,
+    discarding: bool,
+    complete: bool,
+    reduced: bool,
+}
+
+impl<'a> CobsDecoder<'a> {
+    /// Creates a COBS decoder writing packets into `buffer`.
+    pub fn new(buffer: &'a mut [u8]) -> CobsDecoder<'a> {
+        CobsDecoder {
+            buffer,
+            length: 0,
+            code: 0,
+            remaining: 0,
+            started: false,
+            discarding: false,
+            complete: false,
+            reduced: false,
+        }
+    }
+
+    /// Creates a COBS/R decoder writing packets into `buffer`.
+    pub fn reduced(buffer: &'a mut [u8]) -> CobsDecoder<'a> {
+        let mut decoder = CobsDecoder::new(buffer);
+        decoder.reduced = true;
+        decoder
+    }
+
+    /// Discards any partially decoded packet.
+    pub fn reset(&mut self) {
+        self.length = 0;
+        self.code = 0;
+        self.remaining = 0;
+        self.started = false;
+        self.discarding = false;
+        self.complete = false;
+    }
+
+    fn push(&mut self, byte: u8) -> Result<(), CobsError> {
+        if self.length == self.buffer.len() {
+            self.discarding = true;
+            return Err(CobsError::BufferTooSmall { required: self.length + 1 });
+        }
+        self.buffer[self.length] = byte;
+        self.length += 1;
+        Ok(())
+    }
+
+    /// Feeds a single byte to the decoder.
+    ///
+    /// Returns the decoded packet once its delimiter is received, and
+    /// `Ok(None)` otherwise.
+    pub fn feed(&mut self, byte: u8) -> Result<Option<&[u8]>, CobsError> {
+        if self.complete {
+            self.reset();
+        }
+
+        if byte == 0 {
+            if self.discarding || !self.started {
+                self.reset();
+                return Ok(None);
+            }
+            if self.remaining != 0 {
+                if !self.reduced {
+                    self.reset();
+                    return Err(CobsError::Truncated);
+                }
+                // COBS/R: the length code was the final byte.
+                let code = self.code;
+                if let Err(e) = self.push(code) {
+                    self.reset();
+                    return Err(e);
+                }
+            }
+            self.complete = true;
+            return Ok(Some(&self.buffer[..self.length]));
+        }
+
+        if self.discarding {
+            return Ok(None);
+        }
+
+        if self.remaining == 0 {
+            // A new block; every block but the first (and those following
+            // a maximum length block) is preceded by an implied zero.
+            if self.started && self.code != 0xFF {
+                self.push(0)?;
+            }
+            self.started = true;
+            self.code = byte;
+            self.remaining = byte - 1;
+        } else {
+            self.push(byte)?;
+            self.remaining -= 1;
+        }
+
+        Ok(None)
+    }
+}
+
+#[test]
+fn test_cobs_vectors() {
+    // (decoded, COBS encoded)
+    let vectors: [(&[u8], &[u8]); 6] = [
+        (b"", b"\x01"),
+        (b"\x00", b"\x01\x01"),
+        (b"\x00\x00", b"\x01\x01\x01"),
+        (b"\x11\x22\x00\x33", b"\x03\x11\x22\x02\x33"),
+        (b"\x11\x22\x33\x44", b"\x05\x11\x22\x33\x44"),
+        (b"\x11\x00\x00\x00", b"\x02\x11\x01\x01\x01"),
+    ];
+
+    let mut sink = [0u8; 16];
+    for &(decoded, encoded) in vectors.iter() {
+        let n = encode(decoded, &mut sink[..]).unwrap();
+        assert_eq!(&sink[..n], encoded);
+        let n = decode(encoded, &mut sink[..]).unwrap();
+        assert_eq!(&sink[..n], decoded);
+    }
+
+    assert_eq!(decode(b"\x03\x11", &mut sink[..]), Err(CobsError::Truncated));
+    assert_eq!(decode(b"\x03\x11\x00", &mut sink[..]), Err(CobsError::UnexpectedZero));
+    assert_eq!(
+        encode(b"\x11\x22", &mut sink[..2]),
+        Err(CobsError::BufferTooSmall { required: 3 })
+    );
+}
+
+#[test]
+fn test_cobs_long_runs() {
+    let mut data = [0u8; 600];
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte = (i % 255) as u8 + 1;
+    }
+    data[300] = 0;
+
+    let mut encoded = [0u8; 604];
+    let mut decoded = [0u8; 604];
+    for length in [0, 1, 253, 254, 255, 300, 301, 508, 600].iter() {
+        let n = encode(&data[..*length], &mut encoded[..]).unwrap();
+        assert!(n <= max_encoded_len(*length));
+        assert!(encoded[..n].iter().all(|b| *b != 0));
+        let m = decode(&encoded[..n], &mut decoded[..]).unwrap();
+        assert_eq!(&decoded[..m], &data[..*length]);
+
+        // And again, in place.
+        let mut buf = [0u8; 604];
+        buf[..*length].copy_from_slice(&data[..*length]);
+        let n = encode_in_place(&mut buf[..max_encoded_len(*length)], *length).unwrap();
+        assert_eq!(&buf[..n], &encoded[..n]);
+        let m = decode_in_place(&mut buf[..n]).unwrap();
+        assert_eq!(&buf[..m], &data[..*length]);
+    }
+}
+
+#[test]
+fn test_cobs_reduced() {
+    // (decoded, COBS/R encoded)
+    let vectors: [(&[u8], &[u8]); 5] = [
+        (b"", b"\x01"),
+        (b"\x05", b"\x05"),
+        (b"\x01", b"\x02\x01"),
+        (b"\x31\x32\x33", b"\x33\x31\x32"),
+        (b"\x31\x00\x02", b"\x02\x31\x02\x02"),
+    ];
+
+    let mut sink = [0u8; 16];
+    for &(decoded, encoded) in vectors.iter() {
+        let n = encode_reduced(decoded, &mut sink[..]).unwrap();
+        assert_eq!(&sink[..n], encoded);
+        let n = decode_reduced(encoded, &mut sink[..]).unwrap();
+        assert_eq!(&sink[..n], decoded);
+
+        let mut buf = [0u8; 16];
+        buf[..decoded.len()].copy_from_slice(decoded);
+        let n = encode_reduced_in_place(&mut buf[..], decoded.len()).unwrap();
+        assert_eq!(&buf[..n], encoded);
+        let n = decode_reduced_in_place(&mut buf[..n]).unwrap();
+        assert_eq!(&buf[..n], decoded);
+    }
+}
+
+#[test]
+fn test_cobs_decoder() {
+    let stream = b"\x00\x03\x11\x22\x02\x33\x00\x00\x03\x11\x00\x01\x01\x00";
+    let mut buffer = [0u8; 8];
+    let mut decoder = CobsDecoder::new(&mut buffer[..]);
+
+    let mut results = [None, None, None];
+    let mut count = 0;
+    for byte in stream.iter() {
+        match decoder.feed(*byte) {
+            Ok(Some(packet)) => {
+                let mut copy = [0u8; 8];
+                copy[..packet.len()].copy_from_slice(packet);
+                results[count] = Some(Ok((copy, packet.len())));
+                count += 1;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                results[count] = Some(Err(e));
+                count += 1;
+            }
+        }
+    }
+
+    assert_eq!(count, 3);
+    assert_eq!(results[0], Some(Ok(([0x11, 0x22, 0x00, 0x33, 0, 0, 0, 0], 4))));
+    assert_eq!(results[1], Some(Err(CobsError::Truncated)));
+    assert_eq!(results[2], Some(Ok(([0; 8], 1))));
+
+    let mut buffer = [0u8; 8];
+    let mut decoder = CobsDecoder::reduced(&mut buffer[..]);
+    let mut last = None;
+    for byte in b"\x33\x31\x32\x00".iter() {
+        if let Some(packet) = decoder.feed(*byte).unwrap() {
+            last = Some(packet.len());
+            assert_eq!(packet, b"\x31\x32\x33");
+        }
+    }
+    assert_eq!(last, Some(3));
+}
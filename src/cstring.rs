@@ -0,0 +1,198 @@
+//! NUL-terminated (C) strings.
+//!
+//! `read_cstr` borrows a `&CStr` straight out of the input, scanning at
+//! most `max` bytes (NUL included) for the terminator, and `write_cstr`
+//! writes one back out, NUL and all.  With `std` enabled `read_cstring`
+//! copies the string into a `CString` instead.  `write_str` writes a
+//! `&str` with a terminator added, rejecting strings containing a NUL.
+//!
+//! `read_field` and `write_field` handle fixed width fields, as in a C
+//! `char name[32]`: the string is followed by NUL padding up to `width`
+//! bytes, and the whole field is always consumed.  A string which fills
+//! the field exactly has no terminator (as `strncpy` leaves it);
+//! `read_field` accepts that unless `require_nul` is set.
+
+use core::ffi::CStr;
+
+#[cfg(feature="std")]
+use std::ffi::CString;
+
+/// Errors produced reading and writing C strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CStrError {
+    /// The sink is too small.
+    BufferTooSmall {
+        /// The number of bytes required.
+        required: usize,
+    },
+    /// The input ended before the terminator (or the field).
+    Eof,
+    /// No terminator was found within the maximum scan length (or the
+    /// field, where one is required).
+    Unterminated {
+        /// The number of bytes scanned.
+        max: usize,
+    },
+    /// The string contains a NUL byte.
+    InteriorNul {
+        /// The offset of the NUL.
+        position: usize,
+    },
+    /// The string doesn't fit the field.
+    TooLong {
+        /// The length of the string.
+        length: usize,
+        /// The width of the field.
+        width: usize,
+    },
+}
+
+/// Reads the NUL-terminated string at offset zero of `source`, which must
+/// be terminated within `max` bytes (counting the NUL).
+///
+/// Returns the string, and the number of bytes read (including the NUL).
+pub fn read_cstr(source: &[u8], max: usize) -> Result<(&CStr, usize), CStrError> {
+    let scan = if source.len() < max { source.len() } else { max };
+    match source[..scan].iter().position(|b| *b == 0) {
+        Some(position) => {
+            let value = CStr::from_bytes_with_nul(&source[..position + 1])
+                .map_err(|_| CStrError::InteriorNul { position })?;
+            Ok((value, position + 1))
+        }
+        None if scan < max => Err(CStrError::Eof),
+        None => Err(CStrError::Unterminated { max }),
+    }
+}
+
+/// Reads the NUL-terminated string at offset zero of `source` into a
+/// `CString`, as `read_cstr` does.
+#[cfg(feature="std")]
+pub fn read_cstring(source: &[u8], max: usize) -> Result<(CString, usize), CStrError> {
+    let (value, read) = read_cstr(source, max)?;
+    Ok((value.to_owned(), read))
+}
+
+/// Writes `value`, and its NUL, to offset zero of `sink`.
+///
+/// The return value is the number of bytes written (including the NUL).
+pub fn write_cstr(value: &CStr, sink: &mut [u8]) -> Result<usize, CStrError> {
+    let bytes = value.to_bytes_with_nul();
+    if sink.len() < bytes.len() {
+        return Err(CStrError::BufferTooSmall { required: bytes.len() });
+    }
+    sink[..bytes.len()].copy_from_slice(bytes);
+    Ok(bytes.len())
+}
+
+/// Writes `value` followed by a NUL to offset zero of `sink`.
+///
+/// The return value is the number of bytes written (including the NUL).
+pub fn write_str(value: &str, sink: &mut [u8]) -> Result<usize, CStrError> {
+    let bytes = value.as_bytes();
+    check_nul(bytes)?;
+    let required = bytes.len() + 1;
+    if sink.len() < required {
+        return Err(CStrError::BufferTooSmall { required });
+    }
+    sink[..bytes.len()].copy_from_slice(bytes);
+    sink[bytes.len()] = 0;
+    Ok(required)
+}
+
+fn check_nul(value: &[u8]) -> Result<(), CStrError> {
+    match value.iter().position(|b| *b == 0) {
+        Some(position) => Err(CStrError::InteriorNul { position }),
+        None => Ok(()),
+    }
+}
+
+/// Reads a `width` byte NUL-padded field at offset zero of `source`,
+/// returning the string (without its terminator or padding).  The whole
+/// field is consumed, so the number of bytes read is always `width`.
+///
+/// If `require_nul` is set, a field without a terminator is
+/// `Unterminated`.
+pub fn read_field(source: &[u8], width: usize, require_nul: bool) -> Result<&[u8], CStrError> {
+    if source.len() < width {
+        return Err(CStrError::Eof);
+    }
+    let field = &source[..width];
+    match field.iter().position(|b| *b == 0) {
+        Some(position) => Ok(&field[..position]),
+        None if require_nul => Err(CStrError::Unterminated { max: width }),
+        None => Ok(field),
+    }
+}
+
+/// Writes `value` to offset zero of `sink` as a `width` byte field,
+/// padded with NULs.  `value` may fill the field, leaving no terminator.
+///
+/// The return value is the number of bytes written, which is always
+/// `width`.
+pub fn write_field(value: &[u8], sink: &mut [u8], width: usize) -> Result<usize, CStrError> {
+    check_nul(value)?;
+    if value.len() > width {
+        return Err(CStrError::TooLong { length: value.len(), width });
+    }
+    if sink.len() < width {
+        return Err(CStrError::BufferTooSmall { required: width });
+    }
+    sink[..value.len()].copy_from_slice(value);
+    for b in sink[value.len()..width].iter_mut() {
+        *b = 0;
+    }
+    Ok(width)
+}
+
+#[test]
+fn test_cstring_cstr() {
+    let source = b"hello\0world\0";
+    let (value, read) = read_cstr(&source[..], 64).unwrap();
+    assert_eq!(value.to_bytes(), b"hello");
+    assert_eq!(read, 6);
+    let (value, read) = read_cstr(&source[read..], 6).unwrap();
+    assert_eq!(value.to_bytes(), b"world");
+    assert_eq!(read, 6);
+
+    let mut data = [0xFFu8; 8];
+    assert_eq!(write_cstr(value, &mut data[..]), Ok(6));
+    assert_eq!(&data[..6], b"world\0");
+    assert_eq!(write_str("abc", &mut data[..]), Ok(4));
+    assert_eq!(&data[..4], b"abc\0");
+}
+
+#[test]
+fn test_cstring_errors() {
+    assert_eq!(read_cstr(b"hello\0", 5), Err(CStrError::Unterminated { max: 5 }));
+    assert_eq!(read_cstr(b"hel", 5), Err(CStrError::Eof));
+
+    let mut data = [0u8; 4];
+    assert_eq!(write_str("a\0b", &mut data[..]), Err(CStrError::InteriorNul { position: 1 }));
+    assert_eq!(write_str("abcd", &mut data[..]), Err(CStrError::BufferTooSmall { required: 5 }));
+}
+
+#[test]
+fn test_cstring_field() {
+    let mut data = [0xFFu8; 10];
+    assert_eq!(write_field(b"abc", &mut data[..], 8), Ok(8));
+    assert_eq!(&data[..], b"abc\0\0\0\0\0\xFF\xFF");
+    assert_eq!(read_field(&data[..], 8, true), Ok(&b"abc"[..]));
+
+    // A full field has no terminator.
+    assert_eq!(write_field(b"abcdefgh", &mut data[..], 8), Ok(8));
+    assert_eq!(read_field(&data[..], 8, false), Ok(&b"abcdefgh"[..]));
+    assert_eq!(read_field(&data[..], 8, true), Err(CStrError::Unterminated { max: 8 }));
+
+    assert_eq!(write_field(b"abcdefghi", &mut data[..], 8), Err(CStrError::TooLong { length: 9, width: 8 }));
+    assert_eq!(write_field(b"abc", &mut data[..4], 8), Err(CStrError::BufferTooSmall { required: 8 }));
+    assert_eq!(read_field(&data[..4], 8, false), Err(CStrError::Eof));
+}
+
+#[cfg(feature="std")]
+#[test]
+fn test_cstring_owned() {
+    let mut data = [0u8; 16];
+    let value = CString::new("fricgan").unwrap();
+    assert_eq!(write_cstr(&value, &mut data[..]), Ok(8));
+    assert_eq!(read_cstring(&data[..], 16), Ok((value, 8)));
+}
@@ -0,0 +1,781 @@
+//! Cursors, with backpatched placeholders and alignment.
+//!
+//! `WriteCursor` writes `IO` (and `VLQ`) values to a slice, tracking its
+//! position, and `ReadCursor` reads them back.  With `std` enabled,
+//! `VecWriter` writes by appending to a `Vec<u8>`, and `SeekWriter` wraps
+//! any `std::io::Write + Seek`.
+//!
+//! All three hand out `Placeholder`s: `placeholder::<T>()` reserves
+//! `size_of::<T>()` zero bytes at the current position, and `patch` later
+//! writes the value there, once it is known (a length or an offset,
+//! say).  Values are written with `IO`, in native byte order, so convert
+//! them with `to_le` or `to_be` first.
+//!
+//! Each placeholder records the writer that handed it out, and patching
+//! it through any other writer fails with `InvalidPlaceholder`.  (On
+//! targets without atomic compare-and-swap, such as `thumbv6m`, writers
+//! can't be told apart, so only that the placeholder lies within what
+//! has been written is checked.)  Each writer also counts the
+//! placeholders it has handed out which haven't been patched; `finish`
+//! fails with `UnfilledPlaceholders` if any were dropped unfilled.
+//!
+//! All three implement `CursorWrite`, so adapters such as
+//! `checksum::ChecksumCursor` can write through any of them.
+//!
+//! Writers also have `align_to(n)`, which writes zero bytes up to the
+//! next multiple of `n` bytes, and `pad(n, byte)`, which does the same
+//! with another byte.  `ReadCursor::skip_padding(n, verify)` skips to the
+//! next multiple of `n`, and if `verify` is set, checks the padding is
+//! zero.  `WritePadding` and `ReadPadding` add the same to `std::io`
+//! writers and readers.
+
+use core::marker::PhantomData;
+use core::mem::size_of;
+#[cfg(target_has_atomic="ptr")]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature="std")]
+use std::io;
+
+#[cfg(feature="std")]
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use IO;
+
+#[cfg(feature="vlq")]
+use VLQ;
+
+#[cfg(feature="vlq")]
+use {vlq_fits, vlq_len, vlq_max, MAX_VLQ_LEN};
+
+/// Errors produced by cursors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorError {
+    /// The buffer is too small.
+    BufferTooSmall {
+        /// The number of bytes the buffer requires.
+        required: usize,
+    },
+    /// Placeholders were dropped without being patched.
+    UnfilledPlaceholders {
+        /// The number of placeholders not patched.
+        count: usize,
+    },
+    /// A placeholder came from another writer, or lies outside what has
+    /// been written.
+    InvalidPlaceholder,
+    /// The input ended before the value being read.
+    Eof,
+    /// A VLQ was longer than its type allows, or its value overflowed it.
+    MalformedVlq,
+    /// Padding which should have been zero wasn't.
+    InvalidPadding {
+        /// The offset of the first non-zero padding byte.
+        offset: usize,
+    },
+    /// The underlying reader or writer failed.
+    #[cfg(feature="std")]
+    Io(io::ErrorKind),
+}
+
+#[cfg(feature="std")]
+impl From<io::Error> for CursorError {
+    fn from(e: io::Error) -> CursorError {
+        CursorError::Io(e.kind())
+    }
+}
+
+/// Space reserved for a value of type `T`, to be written later with
+/// `patch`.
+///
+/// Placeholders are patched through the writer that created them.
+#[must_use = "placeholders must be patched; the writer's finish fails otherwise"]
+#[derive(Debug)]
+pub struct Placeholder<T: IO> {
+    writer: usize,
+    offset: u64,
+    marker: PhantomData<T>,
+}
+
+impl<T: IO> Placeholder<T> {
+    fn new(writer: &Placeholders, offset: u64) -> Placeholder<T> {
+        Placeholder { writer: writer.id, offset, marker: PhantomData }
+    }
+
+    /// The offset of the reserved space.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+// Identifies writers, so a placeholder can't be patched through a writer
+// other than the one that handed it out.
+#[cfg(target_has_atomic="ptr")]
+static NEXT_WRITER: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(target_has_atomic="ptr")]
+fn next_writer() -> usize {
+    NEXT_WRITER.fetch_add(1, Ordering::Relaxed)
+}
+
+// Without compare-and-swap there's no counter to share, so all writers
+// get the same id.
+#[cfg(not(target_has_atomic="ptr"))]
+fn next_writer() -> usize {
+    0
+}
+
+// A writer's id, and the number of its placeholders not yet patched.
+#[derive(Debug)]
+struct Placeholders {
+    id: usize,
+    pending: usize,
+}
+
+impl Placeholders {
+    fn new() -> Placeholders {
+        Placeholders { id: next_writer(), pending: 0 }
+    }
+
+    fn add<T: IO>(&mut self, offset: u64) -> Placeholder<T> {
+        self.pending += 1;
+        Placeholder::new(self, offset)
+    }
+
+    // Checks `placeholder` came from this writer, and its value lies
+    // within the `end` bytes written.
+    fn check<T: IO>(&self, placeholder: &Placeholder<T>, end: u64) -> Result<(), CursorError> {
+        if placeholder.writer != self.id || placeholder.offset + size_of::<T>() as u64 > end {
+            Err(CursorError::InvalidPlaceholder)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn finish(&self) -> Result<(), CursorError> {
+        if self.pending == 0 {
+            Ok(())
+        } else {
+            Err(CursorError::UnfilledPlaceholders { count: self.pending })
+        }
+    }
+}
+
+#[cfg(feature="std")]
+impl Default for Placeholders {
+    fn default() -> Placeholders {
+        Placeholders::new()
+    }
+}
+
+// The number of bytes from `position` to the next multiple of
+// `alignment`.  Alignments of zero and one need no padding.
+fn padding_len(position: u64, alignment: usize) -> usize {
+    if alignment <= 1 {
+        return 0;
+    }
+    let remainder = (position % alignment as u64) as usize;
+    if remainder == 0 { 0 } else { alignment - remainder }
+}
+
+#[cfg(feature="std")]
+fn write_repeated<W: Write + ?Sized>(writer: &mut W, byte: u8, count: usize) -> io::Result<()> {
+    let block = [byte; 16];
+    let mut remaining = count;
+    while remaining > 0 {
+        let length = remaining.min(block.len());
+        writer.write_all(&block[..length])?;
+        remaining -= length;
+    }
+    Ok(())
+}
+
+/// Raw byte writes, common to `WriteCursor`, `VecWriter` and
+/// `SeekWriter`.
+pub trait CursorWrite {
+    /// Writes raw bytes at the current position, returning the number
+    /// written.
+    fn write_bytes(&mut self, data: &[u8]) -> Result<usize, CursorError>;
+}
+
+/// Writes values to a slice.
+pub struct WriteCursor<'a> {
+    sink: &'a mut [u8],
+    position: usize,
+    placeholders: Placeholders,
+}
+
+impl<'a> WriteCursor<'a> {
+    /// Creates a cursor writing to offset zero of `sink`.
+    pub fn new(sink: &'a mut [u8]) -> WriteCursor<'a> {
+        WriteCursor { sink, position: 0, placeholders: Placeholders::new() }
+    }
+
+    /// The number of bytes written so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The bytes written so far.
+    pub fn written(&self) -> &[u8] {
+        &self.sink[..self.position]
+    }
+
+    fn reserve(&self, length: usize) -> Result<(), CursorError> {
+        let required = self.position + length;
+        if self.sink.len() < required {
+            Err(CursorError::BufferTooSmall { required })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Writes an `IO` value at the current position.
+    ///
+    /// The return value is the number of bytes written.
+    pub fn write<T: IO + ?Sized>(&mut self, value: &mut T) -> Result<usize, CursorError> {
+        self.reserve(::core::mem::size_of_val(value))?;
+        let written = value.fio_write(&mut self.sink[self.position..]);
+        self.position += written;
+        Ok(written)
+    }
+
+    /// Writes a `VLQ` value at the current position.
+    ///
+    /// The return value is the number of bytes written.
+    #[cfg(feature="vlq")]
+    pub fn write_vlq<T: VLQ>(&mut self, value: &T) -> Result<usize, CursorError> {
+        let mut buffer = [0u8; MAX_VLQ_LEN];
+        let written = value.vlq_write(&mut buffer[..]);
+        self.write_bytes(&buffer[..written])
+    }
+
+    /// Writes raw bytes at the current position.
+    pub fn write_bytes(&mut self, data: &[u8]) -> Result<usize, CursorError> {
+        self.reserve(data.len())?;
+        self.sink[self.position..self.position + data.len()].copy_from_slice(data);
+        self.position += data.len();
+        Ok(data.len())
+    }
+
+    /// Writes zero bytes up to the next multiple of `alignment`,
+    /// returning the number written.
+    pub fn align_to(&mut self, alignment: usize) -> Result<usize, CursorError> {
+        self.pad(alignment, 0)
+    }
+
+    /// Writes `byte` up to the next multiple of `alignment`, returning the
+    /// number of bytes written.
+    pub fn pad(&mut self, alignment: usize, byte: u8) -> Result<usize, CursorError> {
+        let length = padding_len(self.position as u64, alignment);
+        self.reserve(length)?;
+        for b in &mut self.sink[self.position..self.position + length] {
+            *b = byte;
+        }
+        self.position += length;
+        Ok(length)
+    }
+
+    /// Reserves `size_of::<T>()` zero bytes at the current position.
+    pub fn placeholder<T: IO>(&mut self) -> Result<Placeholder<T>, CursorError> {
+        let length = size_of::<T>();
+        self.reserve(length)?;
+        let offset = self.position;
+        for byte in &mut self.sink[offset..offset + length] {
+            *byte = 0;
+        }
+        self.position += length;
+        Ok(self.placeholders.add(offset as u64))
+    }
+
+    /// Writes `value` into the space reserved by `placeholder`.
+    pub fn patch<T: IO>(&mut self, placeholder: Placeholder<T>, mut value: T) -> Result<(), CursorError> {
+        self.placeholders.check(&placeholder, self.position as u64)?;
+        value.fio_write(&mut self.sink[placeholder.offset as usize..]);
+        self.placeholders.pending -= 1;
+        Ok(())
+    }
+
+    /// Finishes writing, returning the number of bytes written, or an
+    /// error if any placeholders were not patched.
+    pub fn finish(self) -> Result<usize, CursorError> {
+        self.placeholders.finish()?;
+        Ok(self.position)
+    }
+}
+
+impl<'a> CursorWrite for WriteCursor<'a> {
+    fn write_bytes(&mut self, data: &[u8]) -> Result<usize, CursorError> {
+        WriteCursor::write_bytes(self, data)
+    }
+}
+
+/// Reads values from a slice.
+#[derive(Debug, Clone)]
+pub struct ReadCursor<'a> {
+    source: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ReadCursor<'a> {
+    /// Creates a cursor reading from offset zero of `source`.
+    pub fn new(source: &'a [u8]) -> ReadCursor<'a> {
+        ReadCursor { source, position: 0 }
+    }
+
+    /// The number of bytes read so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The input not yet read.
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.source[self.position..]
+    }
+
+    fn require(&self, length: usize) -> Result<(), CursorError> {
+        if self.source.len() - self.position < length {
+            Err(CursorError::Eof)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Reads an `IO` value at the current position.
+    ///
+    /// The return value is the number of bytes read.
+    pub fn read<T: IO + ?Sized>(&mut self, value: &mut T) -> Result<usize, CursorError> {
+        self.require(::core::mem::size_of_val(value))?;
+        let read = value.fio_read(&self.source[self.position..]);
+        self.position += read;
+        Ok(read)
+    }
+
+    /// Reads a `VLQ` value at the current position, which must end within
+    /// `size_of::<T>()` rounded up to whole 7-bit groups, and fit in a `T`.
+    ///
+    /// The return value is the number of bytes read.
+    #[cfg(feature="vlq")]
+    pub fn read_vlq<T: VLQ>(&mut self, value: &mut T) -> Result<usize, CursorError> {
+        let max = vlq_max::<T>();
+        let remaining = self.remaining();
+        match vlq_len(remaining, max) {
+            Some(length) if !vlq_fits::<T>(remaining, length) => Err(CursorError::MalformedVlq),
+            Some(_) => {
+                let read = value.vlq_read(remaining);
+                self.position += read;
+                Ok(read)
+            }
+            None if remaining.len() < max => Err(CursorError::Eof),
+            None => Err(CursorError::MalformedVlq),
+        }
+    }
+
+    /// Reads `length` raw bytes.
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], CursorError> {
+        self.require(length)?;
+        let data = &self.source[self.position..self.position + length];
+        self.position += length;
+        Ok(data)
+    }
+
+    /// Skips up to the next multiple of `alignment`, returning the number
+    /// of bytes skipped.  If `verify` is set the padding must be zero.
+    pub fn skip_padding(&mut self, alignment: usize, verify: bool) -> Result<usize, CursorError> {
+        let length = padding_len(self.position as u64, alignment);
+        self.require(length)?;
+        let padding = &self.source[self.position..self.position + length];
+        if verify {
+            if let Some(i) = padding.iter().position(|b| *b != 0) {
+                return Err(CursorError::InvalidPadding { offset: self.position + i });
+            }
+        }
+        self.position += length;
+        Ok(length)
+    }
+}
+
+/// Writes values by appending them to a `Vec<u8>`.
+#[cfg(feature="std")]
+#[derive(Debug, Default)]
+pub struct VecWriter {
+    buffer: Vec<u8>,
+    placeholders: Placeholders,
+}
+
+#[cfg(feature="std")]
+impl VecWriter {
+    /// Creates a writer with an empty buffer.
+    pub fn new() -> VecWriter {
+        VecWriter::default()
+    }
+
+    /// Creates a writer appending to `buffer`.
+    pub fn from_vec(buffer: Vec<u8>) -> VecWriter {
+        VecWriter { buffer, placeholders: Placeholders::new() }
+    }
+
+    /// The number of bytes in the buffer.
+    pub fn position(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// The bytes written so far.
+    pub fn written(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Appends an `IO` value, returning the number of bytes written.
+    pub fn write<T: IO + ?Sized>(&mut self, value: &mut T) -> usize {
+        let start = self.buffer.len();
+        self.buffer.resize(start + ::core::mem::size_of_val(value), 0);
+        value.fio_write(&mut self.buffer[start..])
+    }
+
+    /// Appends a `VLQ` value, returning the number of bytes written.
+    #[cfg(feature="vlq")]
+    pub fn write_vlq<T: VLQ>(&mut self, value: &T) -> usize {
+        let mut buffer = [0u8; MAX_VLQ_LEN];
+        let written = value.vlq_write(&mut buffer[..]);
+        self.buffer.extend_from_slice(&buffer[..written]);
+        written
+    }
+
+    /// Appends raw bytes.
+    pub fn write_bytes(&mut self, data: &[u8]) -> usize {
+        self.buffer.extend_from_slice(data);
+        data.len()
+    }
+
+    /// Appends zero bytes up to the next multiple of `alignment`,
+    /// returning the number written.
+    pub fn align_to(&mut self, alignment: usize) -> usize {
+        self.pad(alignment, 0)
+    }
+
+    /// Appends `byte` up to the next multiple of `alignment`, returning
+    /// the number of bytes written.
+    pub fn pad(&mut self, alignment: usize, byte: u8) -> usize {
+        let length = padding_len(self.buffer.len() as u64, alignment);
+        let end = self.buffer.len() + length;
+        self.buffer.resize(end, byte);
+        length
+    }
+
+    /// Appends `size_of::<T>()` zero bytes, to be patched later.
+    pub fn placeholder<T: IO>(&mut self) -> Placeholder<T> {
+        let offset = self.buffer.len();
+        self.buffer.resize(offset + size_of::<T>(), 0);
+        self.placeholders.add(offset as u64)
+    }
+
+    /// Writes `value` into the space reserved by `placeholder`.
+    pub fn patch<T: IO>(&mut self, placeholder: Placeholder<T>, mut value: T) -> Result<(), CursorError> {
+        self.placeholders.check(&placeholder, self.buffer.len() as u64)?;
+        value.fio_write(&mut self.buffer[placeholder.offset as usize..]);
+        self.placeholders.pending -= 1;
+        Ok(())
+    }
+
+    /// Finishes writing, returning the buffer, or an error if any
+    /// placeholders were not patched.
+    pub fn finish(self) -> Result<Vec<u8>, CursorError> {
+        self.placeholders.finish()?;
+        Ok(self.buffer)
+    }
+}
+
+#[cfg(feature="std")]
+impl CursorWrite for VecWriter {
+    fn write_bytes(&mut self, data: &[u8]) -> Result<usize, CursorError> {
+        Ok(VecWriter::write_bytes(self, data))
+    }
+}
+
+#[cfg(feature="std")]
+impl Write for VecWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes values to a seekable `std::io::Write`, such as a `File`.
+///
+/// Patching seeks back to the placeholder, writes the value, and seeks
+/// back to where writing left off.
+#[cfg(feature="std")]
+#[derive(Debug)]
+pub struct SeekWriter<W: Write + Seek> {
+    inner: W,
+    placeholders: Placeholders,
+}
+
+#[cfg(feature="std")]
+impl<W: Write + Seek> SeekWriter<W> {
+    /// Wraps `inner`, which is written from its current position.
+    pub fn new(inner: W) -> SeekWriter<W> {
+        SeekWriter { inner, placeholders: Placeholders::new() }
+    }
+
+    /// The underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Writes an `IO` value, returning the number of bytes written.
+    pub fn write<T: IO + ?Sized>(&mut self, value: &mut T) -> Result<usize, CursorError> {
+        let mut buffer = vec![0u8; ::core::mem::size_of_val(value)];
+        let written = value.fio_write(&mut buffer[..]);
+        self.inner.write_all(&buffer[..written])?;
+        Ok(written)
+    }
+
+    /// Writes a `VLQ` value, returning the number of bytes written.
+    #[cfg(feature="vlq")]
+    pub fn write_vlq<T: VLQ>(&mut self, value: &T) -> Result<usize, CursorError> {
+        let mut buffer = [0u8; MAX_VLQ_LEN];
+        let written = value.vlq_write(&mut buffer[..]);
+        self.inner.write_all(&buffer[..written])?;
+        Ok(written)
+    }
+
+    /// Writes raw bytes.
+    pub fn write_bytes(&mut self, data: &[u8]) -> Result<usize, CursorError> {
+        self.inner.write_all(data)?;
+        Ok(data.len())
+    }
+
+    /// Writes zero bytes up to the next multiple of `alignment` (relative
+    /// to the start of the underlying writer), returning the number
+    /// written.
+    pub fn align_to(&mut self, alignment: usize) -> Result<usize, CursorError> {
+        Ok(self.inner.align_to(alignment)?)
+    }
+
+    /// Writes `byte` up to the next multiple of `alignment`, returning the
+    /// number of bytes written.
+    pub fn pad(&mut self, alignment: usize, byte: u8) -> Result<usize, CursorError> {
+        Ok(self.inner.pad(alignment, byte)?)
+    }
+
+    /// Writes `size_of::<T>()` zero bytes, to be patched later.
+    pub fn placeholder<T: IO>(&mut self) -> Result<Placeholder<T>, CursorError> {
+        let offset = self.inner.stream_position()?;
+        write_repeated(&mut self.inner, 0, size_of::<T>())?;
+        Ok(self.placeholders.add(offset))
+    }
+
+    /// Writes `value` into the space reserved by `placeholder`.
+    pub fn patch<T: IO>(&mut self, placeholder: Placeholder<T>, mut value: T) -> Result<(), CursorError> {
+        let end = self.inner.stream_position()?;
+        self.placeholders.check(&placeholder, end)?;
+        let mut buffer = vec![0u8; size_of::<T>()];
+        value.fio_write(&mut buffer[..]);
+
+        self.inner.seek(SeekFrom::Start(placeholder.offset))?;
+        let result = self.inner.write_all(&buffer);
+        self.inner.seek(SeekFrom::Start(end))?;
+        result?;
+        self.placeholders.pending -= 1;
+        Ok(())
+    }
+
+    /// Finishes writing, returning the underlying writer, or an error if
+    /// any placeholders were not patched.
+    pub fn finish(self) -> Result<W, CursorError> {
+        self.placeholders.finish()?;
+        Ok(self.inner)
+    }
+}
+
+#[cfg(feature="std")]
+impl<W: Write + Seek> CursorWrite for SeekWriter<W> {
+    fn write_bytes(&mut self, data: &[u8]) -> Result<usize, CursorError> {
+        SeekWriter::write_bytes(self, data)
+    }
+}
+
+#[cfg(feature="std")]
+impl<W: Write + Seek> Write for SeekWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Alignment and padding for `std::io` writers.
+///
+/// Positions come from `Seek::stream_position`, so they are relative to
+/// the start of the underlying stream.
+#[cfg(feature="std")]
+pub trait WritePadding: Write {
+    /// Writes zero bytes up to the next multiple of `alignment`,
+    /// returning the number written.
+    fn align_to(&mut self, alignment: usize) -> io::Result<usize>
+    where Self: Seek {
+        self.pad(alignment, 0)
+    }
+
+    /// Writes `byte` up to the next multiple of `alignment`, returning the
+    /// number of bytes written.
+    fn pad(&mut self, alignment: usize, byte: u8) -> io::Result<usize>
+    where Self: Seek {
+        let length = padding_len(self.stream_position()?, alignment);
+        write_repeated(self, byte, length)?;
+        Ok(length)
+    }
+}
+
+#[cfg(feature="std")]
+impl<W: Write + ?Sized> WritePadding for W {}
+
+/// Padding for `std::io` readers.
+#[cfg(feature="std")]
+pub trait ReadPadding: Read {
+    /// Skips up to the next multiple of `alignment`, returning the number
+    /// of bytes skipped.  If `verify` is set the padding must be zero,
+    /// and an `InvalidData` error is returned otherwise.
+    fn skip_padding(&mut self, alignment: usize, verify: bool) -> io::Result<usize>
+    where Self: Seek {
+        let length = padding_len(self.stream_position()?, alignment);
+        let mut remaining = length;
+        let mut block = [0u8; 16];
+        while remaining > 0 {
+            let count = remaining.min(block.len());
+            self.read_exact(&mut block[..count])?;
+            if verify && block[..count].iter().any(|b| *b != 0) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "non-zero padding"));
+            }
+            remaining -= count;
+        }
+        Ok(length)
+    }
+}
+
+#[cfg(feature="std")]
+impl<R: Read + ?Sized> ReadPadding for R {}
+
+#[cfg(feature="io-u32")]
+#[test]
+fn test_cursor_placeholder() {
+    let mut sink = [0xFFu8; 16];
+    let mut cursor = WriteCursor::new(&mut sink[..]);
+    let length = cursor.placeholder::<u32>().unwrap();
+    cursor.write_bytes(b"abc").unwrap();
+    assert_eq!(length.offset(), 0);
+    cursor.patch(length, 3u32.to_le()).unwrap();
+    assert_eq!(cursor.finish(), Ok(7));
+    assert_eq!(&sink[..7], &[3, 0, 0, 0, b'a', b'b', b'c']);
+
+    let mut cursor = WriteCursor::new(&mut sink[..]);
+    let _ = cursor.placeholder::<u32>().unwrap();
+    assert_eq!(cursor.finish(), Err(CursorError::UnfilledPlaceholders { count: 1 }));
+
+    // A placeholder belongs to the writer that handed it out.
+    let mut other = [0u8; 8];
+    let mut cursor = WriteCursor::new(&mut sink[..]);
+    let mut foreign = WriteCursor::new(&mut other[..]);
+    let length = cursor.placeholder::<u32>().unwrap();
+    let _ = foreign.placeholder::<u32>().unwrap();
+    assert_eq!(foreign.patch(length, 1), Err(CursorError::InvalidPlaceholder));
+    assert_eq!(foreign.finish(), Err(CursorError::UnfilledPlaceholders { count: 1 }));
+    assert_eq!(cursor.finish(), Err(CursorError::UnfilledPlaceholders { count: 1 }));
+
+    let mut cursor = WriteCursor::new(&mut sink[..3]);
+    assert_eq!(cursor.placeholder::<u32>().map(|_| ()), Err(CursorError::BufferTooSmall { required: 4 }));
+    assert_eq!(cursor.write(&mut 1u32).map(|_| ()), Err(CursorError::BufferTooSmall { required: 4 }));
+}
+
+#[cfg(all(feature="io-u16", feature="io-u32", feature="std"))]
+#[test]
+fn test_cursor_writers() {
+    let mut writer = VecWriter::new();
+    let count = writer.placeholder::<u16>();
+    writer.write(&mut 7u32.to_be());
+    writer.patch(count, 1u16.to_be()).unwrap();
+    assert_eq!(writer.finish().unwrap(), vec![0, 1, 0, 0, 0, 7]);
+
+    let mut writer = VecWriter::new();
+    let _ = writer.placeholder::<u16>();
+    assert_eq!(writer.finish(), Err(CursorError::UnfilledPlaceholders { count: 1 }));
+
+    let mut writer = SeekWriter::new(io::Cursor::new(Vec::new()));
+    writer.write_bytes(b"hdr").unwrap();
+    let offset = writer.placeholder::<u32>().unwrap();
+    writer.write(&mut 9u16.to_le()).unwrap();
+    writer.patch(offset, 0xAABBCCDDu32.to_le()).unwrap();
+    writer.write_bytes(b"!").unwrap();
+    let inner = writer.finish().unwrap().into_inner();
+    assert_eq!(inner, vec![b'h', b'd', b'r', 0xDD, 0xCC, 0xBB, 0xAA, 9, 0, b'!']);
+}
+
+#[cfg(all(feature="io-u16", feature="vlq-32"))]
+#[test]
+fn test_cursor_alignment() {
+    let mut sink = [0xEEu8; 16];
+    let mut cursor = WriteCursor::new(&mut sink[..]);
+    cursor.write_vlq(&300u32).unwrap();
+    assert_eq!(cursor.align_to(4), Ok(2));
+    assert_eq!(cursor.align_to(4), Ok(0));
+    cursor.write(&mut 7u16.to_le()).unwrap();
+    assert_eq!(cursor.pad(8, 0xAA), Ok(2));
+    cursor.write_bytes(&[1]).unwrap();
+    assert_eq!(cursor.align_to(16), Ok(7));
+    assert_eq!(cursor.pad(32, 0), Err(CursorError::BufferTooSmall { required: 32 }));
+    assert_eq!(cursor.finish(), Ok(16));
+    assert_eq!(&sink[..10], &[0xAC, 0x02, 0, 0, 7, 0, 0xAA, 0xAA, 1, 0]);
+
+    let mut cursor = ReadCursor::new(&sink[..]);
+    let mut vlq: u32 = 0;
+    let mut value: u16 = 0;
+    assert_eq!(cursor.read_vlq(&mut vlq), Ok(2));
+    assert_eq!(cursor.skip_padding(4, true), Ok(2));
+    assert_eq!(cursor.read(&mut value), Ok(2));
+    assert_eq!((vlq, u16::from_le(value)), (300, 7));
+    let mut check = cursor.clone();
+    assert_eq!(check.skip_padding(8, true), Err(CursorError::InvalidPadding { offset: 6 }));
+    assert_eq!(cursor.skip_padding(8, false), Ok(2));
+    assert_eq!(cursor.read_bytes(1), Ok(&[1][..]));
+    assert_eq!(cursor.skip_padding(16, true), Ok(7));
+    assert_eq!(cursor.read(&mut value), Err(CursorError::Eof));
+    assert_eq!(ReadCursor::new(&[0x80, 0x80]).read_vlq(&mut vlq), Err(CursorError::Eof));
+    assert_eq!(ReadCursor::new(&[0xFF, 0xFF, 0xFF, 0xFF, 0x0F]).read_vlq(&mut vlq), Ok(5));
+    assert_eq!(vlq, 0xFFFFFFFF);
+    assert_eq!(ReadCursor::new(&[0xFF, 0xFF, 0xFF, 0xFF, 0x1F]).read_vlq(&mut vlq), Err(CursorError::MalformedVlq));
+}
+
+#[cfg(feature="std")]
+#[test]
+fn test_cursor_padding_ext() {
+    let mut writer = VecWriter::new();
+    writer.write_bytes(b"abc");
+    assert_eq!(writer.align_to(4), 1);
+    assert_eq!(writer.pad(2, 0xFF), 0);
+    writer.write_bytes(b"d");
+    assert_eq!(writer.pad(4, 0xFF), 3);
+    let buffer = writer.finish().unwrap();
+    assert_eq!(buffer, b"abc\0d\xFF\xFF\xFF".to_vec());
+
+    let mut stream = io::Cursor::new(Vec::new());
+    stream.write_all(b"abcde").unwrap();
+    assert_eq!(stream.align_to(8).unwrap(), 3);
+    assert_eq!(stream.pad(4, 1).unwrap(), 0);
+    stream.write_all(b"f").unwrap();
+    assert_eq!(stream.pad(4, 1).unwrap(), 3);
+    assert_eq!(stream.get_ref(), &b"abcde\0\0\0f\x01\x01\x01".to_vec());
+
+    stream.set_position(5);
+    assert_eq!(stream.skip_padding(8, true).unwrap(), 3);
+    stream.set_position(9);
+    assert_eq!(stream.skip_padding(4, false).unwrap(), 3);
+    stream.set_position(9);
+    assert_eq!(stream.skip_padding(4, true).unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,471 @@
+//! Packed (COMP-3) and zoned decimal fields, as found in mainframe and
+//! banking records.
+//!
+//! A `Field` describes the encoding and digit count (and, for COBOL
+//! pictures such as `S9(7)V99`, the implied decimal places).  A packed
+//! field holds two digits per byte, the last nibble being the sign, and
+//! is `digits / 2 + 1` bytes wide; an even digit count leaves a zero
+//! nibble at the front.  A zoned field holds a digit per byte, in the
+//! low nibble, with the zone in the high nibble (0xF for EBCDIC, 0x3 for
+//! ASCII).  The last byte's zone is the sign.
+//!
+//! The sign nibble is 0xC for positive, 0xD for negative, and 0xF for
+//! unsigned.  Signed fields write C or D and accept C, D or F; unsigned
+//! fields write F (or, zoned, the zone) and refuse C and D.  Zoned
+//! fields also accept their zone as positive, and a signed zoned field
+//! with a zone other than 0xF (an ASCII field, say) writes it for
+//! positive values, so they stay plain digits; negative values are still
+//! D.  Anything else is `InvalidSign`.
+//!
+//! Values are `i128` (enough for 38 digits), with `i64` and `Decimal`
+//! wrappers.  Reads and writes work at offset zero and return the number
+//! of bytes used, which is always the field's width.
+
+use core::fmt;
+
+/// Errors produced reading and writing decimal fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecimalError {
+    /// The sink is too small.
+    BufferTooSmall {
+        /// The number of bytes required.
+        required: usize,
+    },
+    /// The source is shorter than the field.
+    Eof,
+    /// A digit nibble isn't 0 to 9 (or a zone or pad nibble is wrong).
+    InvalidDigit {
+        /// The offset of the byte.
+        offset: usize,
+    },
+    /// The sign nibble isn't valid for the field.
+    InvalidSign {
+        /// The offset of the byte.
+        offset: usize,
+    },
+    /// The value has more digits than the field.
+    Overflow {
+        /// The number of digits in the field.
+        digits: usize,
+    },
+    /// The value doesn't fit the type it's being read into.
+    OutOfRange,
+    /// A negative value was written to an unsigned field.
+    Negative,
+    /// The value has more decimal places than the field (and they aren't
+    /// zeros).
+    Inexact,
+}
+
+/// A decimal number: `mantissa * 10^-scale`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+impl Decimal {
+    /// Creates a decimal, so `Decimal::new(-12345, 2)` is -123.45.
+    pub fn new(mantissa: i128, scale: u32) -> Decimal {
+        Decimal { mantissa, scale }
+    }
+
+    /// The unscaled value.
+    pub fn mantissa(&self) -> i128 {
+        self.mantissa
+    }
+
+    /// The number of decimal places.
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// Changes the scale, if that can be done exactly (and within range).
+    pub fn rescale(&self, scale: u32) -> Option<Decimal> {
+        let mantissa = if scale >= self.scale {
+            self.mantissa.checked_mul(10i128.checked_pow(scale - self.scale)?)?
+        } else {
+            let divisor = match 10i128.checked_pow(self.scale - scale) {
+                Some(divisor) => divisor,
+                // Only zero survives dividing by something this large.
+                None => return if self.mantissa == 0 { Some(Decimal::new(0, scale)) } else { None },
+            };
+            if self.mantissa % divisor != 0 {
+                return None;
+            }
+            self.mantissa / divisor
+        };
+        Some(Decimal::new(mantissa, scale))
+    }
+
+    /// Converts the value to an `f64` (approximately).
+    pub fn to_f64(&self) -> f64 {
+        // `powi` needs std, so build the divisor up and divide once.
+        let mut divisor = 1.0;
+        for _ in 0..self.scale {
+            divisor *= 10.0;
+        }
+        self.mantissa as f64 / divisor
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let magnitude = self.mantissa.unsigned_abs();
+        if self.scale == 0 {
+            return write!(f, "{}{}", sign, magnitude);
+        }
+        let (whole, fraction) = match 10u128.checked_pow(self.scale) {
+            Some(divisor) => (magnitude / divisor, magnitude % divisor),
+            None => (0, magnitude),
+        };
+        write!(f, "{}{}.{:0width$}", sign, whole, fraction, width = self.scale as usize)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Packed,
+    Zoned,
+}
+
+/// A packed or zoned decimal field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    encoding: Encoding,
+    digits: usize,
+    unsigned: bool,
+    zone: u8,
+    scale: u32,
+}
+
+impl Field {
+    /// A signed packed decimal (COMP-3) field of `digits` digits.
+    pub fn packed(digits: usize) -> Field {
+        Field {
+            encoding: Encoding::Packed,
+            digits,
+            unsigned: false,
+            zone: 0xF,
+            scale: 0,
+        }
+    }
+
+    /// A signed, EBCDIC zoned decimal field of `digits` digits.
+    pub fn zoned(digits: usize) -> Field {
+        Field {
+            encoding: Encoding::Zoned,
+            ..Field::packed(digits)
+        }
+    }
+
+    /// Makes the field unsigned.
+    pub fn unsigned(mut self, unsigned: bool) -> Field {
+        self.unsigned = unsigned;
+        self
+    }
+
+    /// Sets the zone nibble of a zoned field (0xF for EBCDIC, 0x3 for
+    /// ASCII).
+    pub fn zone(mut self, zone: u8) -> Field {
+        self.zone = zone & 0xF;
+        self
+    }
+
+    /// Sets the number of implied decimal places, used by `read_decimal`
+    /// and `write_decimal`.
+    pub fn scale(mut self, scale: u32) -> Field {
+        self.scale = scale;
+        self
+    }
+
+    /// The number of digits.
+    pub fn digits(&self) -> usize {
+        self.digits
+    }
+
+    /// The width of the field in bytes.
+    pub fn width(&self) -> usize {
+        match self.encoding {
+            Encoding::Packed => self.digits / 2 + 1,
+            Encoding::Zoned => self.digits,
+        }
+    }
+
+    // Whether a packed field has an even number of digits, and so a pad
+    // nibble at the front.
+    #[allow(clippy::manual_is_multiple_of)]
+    fn even(&self) -> bool {
+        self.digits % 2 == 0
+    }
+
+    // Whether a sign nibble is negative, or None if it isn't valid.
+    fn sign(&self, nibble: u8) -> Option<bool> {
+        match nibble {
+            0xC if !self.unsigned => Some(false),
+            0xD if !self.unsigned => Some(true),
+            0xF => Some(false),
+            _ if self.encoding == Encoding::Zoned && nibble == self.zone => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Reads the field at offset zero of `source`.
+    ///
+    /// Returns the value, and the number of bytes read.
+    pub fn read(&self, source: &[u8]) -> Result<(i128, usize), DecimalError> {
+        let width = self.width();
+        if source.len() < width {
+            return Err(DecimalError::Eof);
+        }
+        let field = &source[..width];
+        let mut value = 0i128;
+        let mut push = |digit: u8, offset: usize| -> Result<(), DecimalError> {
+            if digit > 9 {
+                return Err(DecimalError::InvalidDigit { offset });
+            }
+            value = value.checked_mul(10)
+                .and_then(|v| v.checked_add(digit as i128))
+                .ok_or(DecimalError::OutOfRange)?;
+            Ok(())
+        };
+
+        let sign = match self.encoding {
+            Encoding::Packed => {
+                // The pad nibble, when the digit count is even, must be zero.
+                if self.even() && field[0] >> 4 != 0 {
+                    return Err(DecimalError::InvalidDigit { offset: 0 });
+                }
+                for (offset, b) in field.iter().enumerate() {
+                    if offset > 0 || !self.even() {
+                        push(b >> 4, offset)?;
+                    }
+                    if offset < width - 1 {
+                        push(b & 0xF, offset)?;
+                    }
+                }
+                field[width - 1] & 0xF
+            }
+            Encoding::Zoned => {
+                for (offset, b) in field.iter().enumerate() {
+                    if offset < width - 1 && b >> 4 != self.zone {
+                        return Err(DecimalError::InvalidDigit { offset });
+                    }
+                    push(b & 0xF, offset)?;
+                }
+                match field.last() {
+                    Some(b) => b >> 4,
+                    None => return Ok((0, 0)),
+                }
+            }
+        };
+        match self.sign(sign) {
+            Some(true) => Ok((-value, width)),
+            Some(false) => Ok((value, width)),
+            None => Err(DecimalError::InvalidSign { offset: width - 1 }),
+        }
+    }
+
+    /// Writes `value` to offset zero of `sink`.
+    ///
+    /// The return value is the number of bytes written.
+    pub fn write(&self, value: i128, sink: &mut [u8]) -> Result<usize, DecimalError> {
+        if value < 0 && self.unsigned {
+            return Err(DecimalError::Negative);
+        }
+        let width = self.width();
+        if sink.len() < width {
+            return Err(DecimalError::BufferTooSmall { required: width });
+        }
+
+        // The digits, least significant first.
+        let mut magnitude = value.unsigned_abs();
+        let mut digit = || {
+            let d = (magnitude % 10) as u8;
+            magnitude /= 10;
+            d
+        };
+        let zoned = self.encoding == Encoding::Zoned;
+        let sign = match (self.unsigned, value < 0) {
+            (false, false) if zoned && self.zone != 0xF => self.zone,
+            (false, false) => 0xC,
+            (false, true) => 0xD,
+            (true, _) => if zoned { self.zone } else { 0xF },
+        };
+
+        let mut field = [0u8; 64];
+        let field = match field.get_mut(..width) {
+            Some(field) => field,
+            None => return self.write_slow(value, sink),
+        };
+        match self.encoding {
+            Encoding::Packed => {
+                for (i, b) in field.iter_mut().rev().enumerate() {
+                    let low = if i == 0 { sign } else { digit() };
+                    *b = (digit() << 4) | low;
+                }
+            }
+            Encoding::Zoned => {
+                for (i, b) in field.iter_mut().rev().enumerate() {
+                    let zone = if i == 0 { sign } else { self.zone };
+                    *b = (zone << 4) | digit();
+                }
+            }
+        }
+        // A digit count which is even leaves a pad nibble, which must
+        // stay zero, so any digits left over are an overflow.
+        let padded = self.encoding == Encoding::Packed && self.even() && field[0] >> 4 != 0;
+        if magnitude != 0 || padded {
+            return Err(DecimalError::Overflow { digits: self.digits });
+        }
+        sink[..width].copy_from_slice(field);
+        Ok(width)
+    }
+
+    // Fields wider than 64 bytes hold more digits than an i128, so only
+    // leading zeros differ; write the value into the tail and zero-fill.
+    fn write_slow(&self, value: i128, sink: &mut [u8]) -> Result<usize, DecimalError> {
+        let width = self.width();
+        let tail = Field { digits: if self.encoding == Encoding::Packed { 63 * 2 } else { 64 }, ..*self };
+        let tail_width = tail.width();
+        tail.write(value, &mut sink[width - tail_width..width])?;
+        let fill = match self.encoding {
+            Encoding::Packed => 0,
+            Encoding::Zoned => self.zone << 4,
+        };
+        for b in sink[..width - tail_width].iter_mut() {
+            *b = fill;
+        }
+        Ok(width)
+    }
+
+    /// Reads the field as an `i64`.
+    pub fn read_i64(&self, source: &[u8]) -> Result<(i64, usize), DecimalError> {
+        let (value, read) = self.read(source)?;
+        if value < i64::MIN as i128 || value > i64::MAX as i128 {
+            return Err(DecimalError::OutOfRange);
+        }
+        Ok((value as i64, read))
+    }
+
+    /// Writes an `i64` to the field.
+    pub fn write_i64(&self, value: i64, sink: &mut [u8]) -> Result<usize, DecimalError> {
+        self.write(value as i128, sink)
+    }
+
+    /// Reads the field as a `Decimal` with the field's scale.
+    pub fn read_decimal(&self, source: &[u8]) -> Result<(Decimal, usize), DecimalError> {
+        let (value, read) = self.read(source)?;
+        Ok((Decimal::new(value, self.scale), read))
+    }
+
+    /// Writes a `Decimal`, rescaled to the field's scale, to the field.
+    pub fn write_decimal(&self, value: &Decimal, sink: &mut [u8]) -> Result<usize, DecimalError> {
+        let value = match value.rescale(self.scale) {
+            Some(value) => value,
+            // Growing the scale only fails by running out of range.
+            None if self.scale > value.scale() => return Err(DecimalError::Overflow { digits: self.digits }),
+            None => return Err(DecimalError::Inexact),
+        };
+        self.write(value.mantissa(), sink)
+    }
+}
+
+#[test]
+fn test_decimal_packed() {
+    let mut data = [0xFFu8; 4];
+    let field = Field::packed(5);
+    assert_eq!(field.width(), 3);
+    assert_eq!(field.write(-12345, &mut data[..]), Ok(3));
+    assert_eq!(&data[..3], &[0x12, 0x34, 0x5D]);
+    assert_eq!(field.read(&data[..]), Ok((-12345, 3)));
+    assert_eq!(field.read(&[0x00, 0x04, 0x2F]), Ok((42, 3)));
+
+    // An even digit count has a pad nibble.
+    let field = Field::packed(4);
+    assert_eq!(field.write_i64(1234, &mut data[..]), Ok(3));
+    assert_eq!(&data[..3], &[0x01, 0x23, 0x4C]);
+    assert_eq!(field.read_i64(&data[..]), Ok((1234, 3)));
+    assert_eq!(field.write(12345, &mut data[..]), Err(DecimalError::Overflow { digits: 4 }));
+
+    let field = Field::packed(3).unsigned(true);
+    assert_eq!(field.write(7, &mut data[..]), Ok(2));
+    assert_eq!(&data[..2], &[0x00, 0x7F]);
+    assert_eq!(field.write(-7, &mut data[..]), Err(DecimalError::Negative));
+}
+
+#[test]
+fn test_decimal_zoned() {
+    let mut data = [0u8; 4];
+    let field = Field::zoned(4);
+    assert_eq!(field.write(-123, &mut data[..]), Ok(4));
+    assert_eq!(&data[..], &[0xF0, 0xF1, 0xF2, 0xD3]);
+    assert_eq!(field.read(&data[..]), Ok((-123, 4)));
+    assert_eq!(field.read(&[0xF0, 0xF1, 0xF2, 0xF3]), Ok((123, 4)));
+
+    let field = Field::zoned(4).zone(0x3).unsigned(true);
+    assert_eq!(field.write(905, &mut data[..]), Ok(4));
+    assert_eq!(&data[..], b"0905");
+    assert_eq!(field.read(b"0042"), Ok((42, 4)));
+    assert_eq!(field.read(b"0x42"), Err(DecimalError::InvalidDigit { offset: 1 }));
+
+    // Signed ASCII fields keep positive values as digits.
+    let field = Field::zoned(4).zone(0x3);
+    assert_eq!(field.read(b"0042"), Ok((42, 4)));
+    assert_eq!(field.write(42, &mut data[..]), Ok(4));
+    assert_eq!(&data[..], b"0042");
+    assert_eq!(field.write(-42, &mut data[..]), Ok(4));
+    assert_eq!(&data[..], &[0x30, 0x30, 0x34, 0xD2]);
+    assert_eq!(field.read(&data[..]), Ok((-42, 4)));
+}
+
+#[test]
+fn test_decimal_errors() {
+    let field = Field::packed(3);
+    assert_eq!(field.read(&[0x12, 0x3A]), Err(DecimalError::InvalidSign { offset: 1 }));
+    assert_eq!(field.read(&[0x1A, 0x3C]), Err(DecimalError::InvalidDigit { offset: 0 }));
+    assert_eq!(field.read(&[0x12]), Err(DecimalError::Eof));
+    assert_eq!(field.unsigned(true).read(&[0x12, 0x3D]), Err(DecimalError::InvalidSign { offset: 1 }));
+    assert_eq!(field.unsigned(true).read(&[0x12, 0x3C]), Err(DecimalError::InvalidSign { offset: 1 }));
+    assert_eq!(Field::packed(2).read(&[0x10, 0x2C]), Err(DecimalError::InvalidDigit { offset: 0 }));
+    assert_eq!(field.write(1, &mut [0u8; 1][..]), Err(DecimalError::BufferTooSmall { required: 2 }));
+
+    // 19 nines don't fit an i64; 39 don't fit an i128.
+    let mut data = [0x99u8; 20];
+    data[9] = 0x9C;
+    assert_eq!(Field::packed(19).read_i64(&data[..]), Err(DecimalError::OutOfRange));
+    data[9] = 0x99;
+    data[19] = 0x9C;
+    assert_eq!(Field::packed(39).read(&data[..]), Err(DecimalError::OutOfRange));
+
+    // Wide fields are zero filled.
+    let mut data = [0xFFu8; 70];
+    assert_eq!(Field::zoned(70).write(-5, &mut data[..]), Ok(70));
+    assert_eq!(Field::zoned(70).read(&data[..]), Ok((-5, 70)));
+}
+
+#[test]
+fn test_decimal_scaled() {
+    let mut data = [0u8; 5];
+    let field = Field::packed(9).scale(2);
+    assert_eq!(field.write_decimal(&Decimal::new(-12345, 2), &mut data[..]), Ok(5));
+    assert_eq!(&data[..], &[0x00, 0x00, 0x12, 0x34, 0x5D]);
+    assert_eq!(field.read_decimal(&data[..]), Ok((Decimal::new(-12345, 2), 5)));
+    assert_eq!(field.write_decimal(&Decimal::new(7, 0), &mut data[..]), Ok(5));
+    assert_eq!(field.read(&data[..]), Ok((700, 5)));
+    assert_eq!(field.write_decimal(&Decimal::new(1230, 3), &mut data[..]), Ok(5));
+    assert_eq!(field.write_decimal(&Decimal::new(1234, 3), &mut data[..]), Err(DecimalError::Inexact));
+
+    assert_eq!(Decimal::new(-12345, 2).to_f64(), -123.45);
+    assert_eq!(Decimal::new(5, 1).rescale(3), Some(Decimal::new(500, 3)));
+}
+
+#[cfg(feature="std")]
+#[test]
+fn test_decimal_display() {
+    assert_eq!(Decimal::new(-12345, 2).to_string(), "-123.45");
+    assert_eq!(Decimal::new(5, 3).to_string(), "0.005");
+    assert_eq!(Decimal::new(42, 0).to_string(), "42");
+    assert_eq!(Decimal::new(1, 40).to_string(), "0.0000000000000000000000000000000000000001");
+}
@@ -0,0 +1,923 @@
+//! ASN.1 DER (X.690 Distinguished Encoding Rules) reading and writing.
+//!
+//! Every value is a TLV: an identifier (`Tag`), a definite length in the
+//! short or long form, and the contents.  `Reader` reads TLVs from a
+//! slice, with typed helpers for the common universal types, and
+//! `Writer` writes them, filling in the lengths of SEQUENCEs and SETs
+//! once their contents are written.
+//!
+//! Decoding is strict: anything BER allows but DER does not (indefinite
+//! or non-minimal lengths, non-minimal tags and INTEGERs, BOOLEANs other
+//! than `0x00` and `0xFF`, non-zero BIT STRING padding, unsorted SETs) is
+//! an error.
+//!
+//! OBJECT IDENTIFIER subidentifiers are base 128 with a continuation bit,
+//! like `VLQ`, but with the most significant group first.
+
+use core::str;
+
+use IO;
+
+/// Errors produced while reading or writing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DerError {
+    /// The output buffer is too small.
+    BufferTooSmall {
+        /// The number of bytes the output buffer requires.
+        required: usize,
+    },
+    /// The input ended partway through a TLV.
+    Eof,
+    /// A tag number was encoded in more bytes than needed, or overflowed
+    /// a `u32`.
+    InvalidTag,
+    /// A length was indefinite, or encoded in more bytes than needed.
+    InvalidLength,
+    /// A length was too large (over `u32::MAX`).
+    LengthOverflow,
+    /// A TLV had a different tag from the one expected.
+    UnexpectedTag {
+        /// The tag expected.
+        expected: Tag,
+        /// The tag found.
+        found: Tag,
+    },
+    /// A BOOLEAN was not a single `0x00` or `0xFF` byte.
+    InvalidBoolean,
+    /// An INTEGER was empty or not minimally encoded.
+    InvalidInteger,
+    /// An INTEGER didn't fit the requested type.
+    IntegerOverflow,
+    /// A BIT STRING had an invalid unused bit count, or non-zero unused
+    /// bits.
+    InvalidBitString,
+    /// A NULL had contents.
+    InvalidNull,
+    /// An OBJECT IDENTIFIER was empty, not minimally encoded, truncated,
+    /// or had too few (or too large) arcs.
+    InvalidOid,
+    /// A UTF8String was not valid UTF-8.
+    InvalidUtf8,
+    /// The elements of a SET were not in ascending order.
+    UnsortedSet,
+    /// Data was left over after the last expected TLV.
+    TrailingData,
+}
+
+/// The class of a tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    /// Types defined by X.680.
+    Universal,
+    /// Types defined by an application.
+    Application,
+    /// Types whose meaning depends on their context.
+    ContextSpecific,
+    /// Privately defined types.
+    Private,
+}
+
+/// An identifier: class, primitive or constructed, and tag number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tag {
+    /// The tag's class.
+    pub class: Class,
+    /// Whether the contents are themselves TLVs.
+    pub constructed: bool,
+    /// The tag number.
+    pub number: u32,
+}
+
+impl Tag {
+    /// BOOLEAN.
+    pub const BOOLEAN: Tag = Tag::universal(1, false);
+    /// INTEGER.
+    pub const INTEGER: Tag = Tag::universal(2, false);
+    /// BIT STRING.
+    pub const BIT_STRING: Tag = Tag::universal(3, false);
+    /// OCTET STRING.
+    pub const OCTET_STRING: Tag = Tag::universal(4, false);
+    /// NULL.
+    pub const NULL: Tag = Tag::universal(5, false);
+    /// OBJECT IDENTIFIER.
+    pub const OID: Tag = Tag::universal(6, false);
+    /// UTF8String.
+    pub const UTF8_STRING: Tag = Tag::universal(12, false);
+    /// SEQUENCE and SEQUENCE OF.
+    pub const SEQUENCE: Tag = Tag::universal(16, true);
+    /// SET and SET OF.
+    pub const SET: Tag = Tag::universal(17, true);
+
+    const fn universal(number: u32, constructed: bool) -> Tag {
+        Tag { class: Class::Universal, constructed, number }
+    }
+
+    /// A context-specific tag (`[number]` in ASN.1 notation).
+    pub fn context(number: u32, constructed: bool) -> Tag {
+        Tag { class: Class::ContextSpecific, constructed, number }
+    }
+
+    fn class_bits(&self) -> u8 {
+        match self.class {
+            Class::Universal => 0x00,
+            Class::Application => 0x40,
+            Class::ContextSpecific => 0x80,
+            Class::Private => 0xC0,
+        }
+    }
+}
+
+// The longest length written: a long form with four length bytes.
+const MAX_LENGTH_LEN: usize = 5;
+
+fn check(sink: &[u8], required: usize) -> Result<(), DerError> {
+    if sink.len() < required {
+        Err(DerError::BufferTooSmall { required })
+    } else {
+        Ok(())
+    }
+}
+
+// The number of base 128 groups needed for `value`.
+fn base128_len(value: u64) -> usize {
+    let mut length = 1;
+    while length < 10 && value >> (7 * length) != 0 {
+        length += 1;
+    }
+    length
+}
+
+// Writes `value` in base 128, most significant group first.
+fn write_base128(value: u64, sink: &mut [u8]) -> Result<usize, DerError> {
+    let length = base128_len(value);
+    check(sink, length)?;
+    for (i, byte) in sink[..length].iter_mut().enumerate() {
+        let shift = 7 * (length - 1 - i);
+        let more = if i + 1 < length { 0x80 } else { 0 };
+        *byte = ((value >> shift) & 0x7F) as u8 | more;
+    }
+    Ok(length)
+}
+
+// Reads a minimally encoded base 128 value, returning it and the number
+// of bytes read.  `None` means the value was non-minimal, truncated or
+// overflowed.
+fn read_base128(source: &[u8]) -> Option<(u64, usize)> {
+    if source.first() == Some(&0x80) {
+        return None;
+    }
+    let mut value: u64 = 0;
+    for (i, byte) in source.iter().enumerate() {
+        if value >> 57 != 0 {
+            return None;
+        }
+        value = (value << 7) | (byte & 0x7F) as u64;
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+/// Writes an identifier to offset zero of `sink`, returning the number of
+/// bytes written.
+pub fn write_tag(tag: Tag, sink: &mut [u8]) -> Result<usize, DerError> {
+    let first = tag.class_bits() | if tag.constructed { 0x20 } else { 0 };
+    check(sink, 1)?;
+    if tag.number < 31 {
+        sink[0] = first | tag.number as u8;
+        return Ok(1);
+    }
+    sink[0] = first | 0x1F;
+    Ok(1 + write_base128(tag.number as u64, &mut sink[1..])?)
+}
+
+/// Reads an identifier from offset zero of `source`, returning it and the
+/// number of bytes read.
+pub fn read_tag(source: &[u8]) -> Result<(Tag, usize), DerError> {
+    let first = *source.first().ok_or(DerError::Eof)?;
+    let class = match first >> 6 {
+        0 => Class::Universal,
+        1 => Class::Application,
+        2 => Class::ContextSpecific,
+        _ => Class::Private,
+    };
+    let constructed = first & 0x20 != 0;
+    if first & 0x1F != 0x1F {
+        let number = (first & 0x1F) as u32;
+        return Ok((Tag { class, constructed, number }, 1));
+    }
+
+    let rest = &source[1..];
+    if rest.is_empty() || rest.iter().all(|b| b & 0x80 != 0) {
+        return Err(DerError::Eof);
+    }
+    let (number, length) = read_base128(rest).ok_or(DerError::InvalidTag)?;
+    // Numbers below 31 must use the low-tag-number form.
+    if number < 31 || number > u32::MAX as u64 {
+        return Err(DerError::InvalidTag);
+    }
+    Ok((Tag { class, constructed, number: number as u32 }, 1 + length))
+}
+
+/// Writes a definite length, in the short form if it is below 128, and
+/// the shortest long form otherwise.
+pub fn write_length(length: usize, sink: &mut [u8]) -> Result<usize, DerError> {
+    if length < 128 {
+        check(sink, 1)?;
+        sink[0] = length as u8;
+        return Ok(1);
+    }
+    if length as u64 > u32::MAX as u64 {
+        return Err(DerError::LengthOverflow);
+    }
+    let mut bytes = [0u8; 4];
+    (length as u32).to_be().fio_write(&mut bytes[..]);
+    let skip = bytes.iter().take_while(|b| **b == 0).count();
+    let count = 4 - skip;
+    check(sink, 1 + count)?;
+    sink[0] = 0x80 | count as u8;
+    sink[1..1 + count].copy_from_slice(&bytes[skip..]);
+    Ok(1 + count)
+}
+
+/// Reads a definite length, returning it and the number of bytes read.
+pub fn read_length(source: &[u8]) -> Result<(usize, usize), DerError> {
+    let first = *source.first().ok_or(DerError::Eof)?;
+    if first < 0x80 {
+        return Ok((first as usize, 1));
+    }
+    let count = (first & 0x7F) as usize;
+    if count == 0 {
+        // Indefinite lengths are BER only.
+        return Err(DerError::InvalidLength);
+    }
+    if count > 4 {
+        return Err(DerError::LengthOverflow);
+    }
+    if source.len() < 1 + count {
+        return Err(DerError::Eof);
+    }
+    let mut bytes = [0u8; 4];
+    bytes[4 - count..].copy_from_slice(&source[1..1 + count]);
+    let mut length: u32 = 0;
+    length.fio_read(&bytes[..]);
+    let length = u32::from_be(length) as usize;
+    if source[1] == 0 || length < 128 {
+        return Err(DerError::InvalidLength);
+    }
+    Ok((length, 1 + count))
+}
+
+/// A single TLV.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tlv<'a> {
+    /// The identifier.
+    pub tag: Tag,
+    /// The contents.
+    pub value: &'a [u8],
+}
+
+/// Reads a TLV from offset zero of `source`, returning it and the number
+/// of bytes read.
+pub fn read_tlv(source: &[u8]) -> Result<(Tlv<'_>, usize), DerError> {
+    let (tag, tag_len) = read_tag(source)?;
+    let (length, length_len) = read_length(&source[tag_len..])?;
+    let start = tag_len + length_len;
+    if source.len() - start < length {
+        return Err(DerError::Eof);
+    }
+    let value = &source[start..start + length];
+    Ok((Tlv { tag, value }, start + length))
+}
+
+// ----------------------------------------------------------------------
+// Values
+// ----------------------------------------------------------------------
+
+/// A BIT STRING: its bytes, and the number of unused bits in the last
+/// byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitString<'a> {
+    /// The number of unused (zero) low bits in the last byte, 0 to 7.
+    pub unused_bits: u8,
+    /// The bits, most significant first.
+    pub bytes: &'a [u8],
+}
+
+impl<'a> BitString<'a> {
+    /// The number of bits.
+    pub fn len(&self) -> usize {
+        self.bytes.len() * 8 - self.unused_bits as usize
+    }
+
+    /// Whether there are no bits.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+/// The contents of an OBJECT IDENTIFIER, which have been validated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Oid<'a>(&'a [u8]);
+
+impl<'a> Oid<'a> {
+    /// Validates encoded OBJECT IDENTIFIER contents.
+    pub fn new(contents: &'a [u8]) -> Result<Oid<'a>, DerError> {
+        if contents.is_empty() {
+            return Err(DerError::InvalidOid);
+        }
+        let mut read = 0;
+        while read < contents.len() {
+            read += read_base128(&contents[read..]).ok_or(DerError::InvalidOid)?.1;
+        }
+        Ok(Oid(contents))
+    }
+
+    /// The encoded contents.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.0
+    }
+
+    /// An iterator over the arcs (the first two are packed together in
+    /// the encoding).
+    pub fn arcs(&self) -> Arcs<'a> {
+        Arcs { contents: self.0, started: false, second: None }
+    }
+}
+
+/// An iterator over the arcs of an `Oid`.
+#[derive(Debug, Clone)]
+pub struct Arcs<'a> {
+    contents: &'a [u8],
+    started: bool,
+    // The second arc, once the first subidentifier has been split.
+    second: Option<u64>,
+}
+
+impl<'a> Iterator for Arcs<'a> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if let Some(second) = self.second.take() {
+            return Some(second);
+        }
+        let (value, length) = read_base128(self.contents)?;
+        self.contents = &self.contents[length..];
+        if self.started {
+            return Some(value);
+        }
+        self.started = true;
+        let arc = if value < 80 { value / 40 } else { 2 };
+        self.second = Some(value - arc * 40);
+        Some(arc)
+    }
+}
+
+// ----------------------------------------------------------------------
+// Reading
+// ----------------------------------------------------------------------
+
+/// Reads TLVs from a slice, in order.
+///
+/// If a method fails, the reader doesn't move.
+#[derive(Debug, Clone)]
+pub struct Reader<'a> {
+    source: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Creates a reader over `source`.
+    pub fn new(source: &'a [u8]) -> Reader<'a> {
+        Reader { source }
+    }
+
+    /// Whether all of the input has been read.
+    pub fn is_empty(&self) -> bool {
+        self.source.is_empty()
+    }
+
+    /// The input not yet read.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.source
+    }
+
+    /// Checks that all of the input has been read.
+    pub fn finish(&self) -> Result<(), DerError> {
+        if self.source.is_empty() {
+            Ok(())
+        } else {
+            Err(DerError::TrailingData)
+        }
+    }
+
+    /// The tag of the next TLV, without reading it.
+    pub fn peek_tag(&self) -> Result<Tag, DerError> {
+        read_tag(self.source).map(|(tag, _)| tag)
+    }
+
+    /// Reads the next TLV, whatever its tag.
+    pub fn read(&mut self) -> Result<Tlv<'a>, DerError> {
+        let (tlv, length) = read_tlv(self.source)?;
+        self.source = &self.source[length..];
+        Ok(tlv)
+    }
+
+    /// Reads the next TLV, which must have the tag `expected`, and
+    /// returns its contents.
+    pub fn expect(&mut self, expected: Tag) -> Result<&'a [u8], DerError> {
+        let (tlv, length) = read_tlv(self.source)?;
+        if tlv.tag != expected {
+            return Err(DerError::UnexpectedTag { expected, found: tlv.tag });
+        }
+        self.source = &self.source[length..];
+        Ok(tlv.value)
+    }
+
+    // Reads a TLV with the tag `expected`, converting its contents with
+    // `f`, and only moving on if that succeeds.
+    fn value<T, F>(&mut self, expected: Tag, f: F) -> Result<T, DerError>
+    where F: FnOnce(&'a [u8]) -> Result<T, DerError> {
+        let mut next = self.clone();
+        let value = f(next.expect(expected)?)?;
+        *self = next;
+        Ok(value)
+    }
+
+    /// Reads a BOOLEAN.
+    pub fn boolean(&mut self) -> Result<bool, DerError> {
+        self.value(Tag::BOOLEAN, |contents| match contents {
+            [0x00] => Ok(false),
+            [0xFF] => Ok(true),
+            _ => Err(DerError::InvalidBoolean),
+        })
+    }
+
+    /// Reads an INTEGER of any size, returning its big endian two's
+    /// complement contents.
+    pub fn integer_bytes(&mut self) -> Result<&'a [u8], DerError> {
+        self.value(Tag::INTEGER, integer_contents)
+    }
+
+    /// Reads an INTEGER which fits in an `i64`.
+    pub fn integer_i64(&mut self) -> Result<i64, DerError> {
+        self.value(Tag::INTEGER, |contents| {
+            let contents = integer_contents(contents)?;
+            if contents.len() > 8 {
+                return Err(DerError::IntegerOverflow);
+            }
+            let fill = if contents[0] & 0x80 != 0 { 0xFF } else { 0x00 };
+            let mut bytes = [fill; 8];
+            bytes[8 - contents.len()..].copy_from_slice(contents);
+            let mut value: i64 = 0;
+            value.fio_read(&bytes[..]);
+            Ok(i64::from_be(value))
+        })
+    }
+
+    /// Reads a non-negative INTEGER which fits in a `u64`.
+    pub fn integer_u64(&mut self) -> Result<u64, DerError> {
+        self.value(Tag::INTEGER, |contents| {
+            let contents = integer_contents(contents)?;
+            if contents[0] & 0x80 != 0 {
+                return Err(DerError::IntegerOverflow);
+            }
+            // A leading zero byte is allowed, to keep the value positive.
+            let contents = if contents[0] == 0 && contents.len() > 1 { &contents[1..] } else { contents };
+            if contents.len() > 8 {
+                return Err(DerError::IntegerOverflow);
+            }
+            let mut bytes = [0u8; 8];
+            bytes[8 - contents.len()..].copy_from_slice(contents);
+            let mut value: u64 = 0;
+            value.fio_read(&bytes[..]);
+            Ok(u64::from_be(value))
+        })
+    }
+
+    /// Reads a BIT STRING.
+    pub fn bit_string(&mut self) -> Result<BitString<'a>, DerError> {
+        self.value(Tag::BIT_STRING, |contents| {
+            let (&unused_bits, bytes) = contents.split_first().ok_or(DerError::InvalidBitString)?;
+            let padding = (1u16 << unused_bits.min(8)) - 1;
+            let valid = match bytes.last() {
+                None => unused_bits == 0,
+                Some(&last) => unused_bits < 8 && (last as u16) & padding == 0,
+            };
+            if !valid {
+                return Err(DerError::InvalidBitString);
+            }
+            Ok(BitString { unused_bits, bytes })
+        })
+    }
+
+    /// Reads an OCTET STRING.
+    pub fn octet_string(&mut self) -> Result<&'a [u8], DerError> {
+        self.expect(Tag::OCTET_STRING)
+    }
+
+    /// Reads a NULL.
+    pub fn null(&mut self) -> Result<(), DerError> {
+        self.value(Tag::NULL, |contents| {
+            if contents.is_empty() {
+                Ok(())
+            } else {
+                Err(DerError::InvalidNull)
+            }
+        })
+    }
+
+    /// Reads an OBJECT IDENTIFIER.
+    pub fn oid(&mut self) -> Result<Oid<'a>, DerError> {
+        self.value(Tag::OID, Oid::new)
+    }
+
+    /// Reads a UTF8String.
+    pub fn utf8_string(&mut self) -> Result<&'a str, DerError> {
+        self.value(Tag::UTF8_STRING, |contents| {
+            str::from_utf8(contents).map_err(|_| DerError::InvalidUtf8)
+        })
+    }
+
+    /// Reads a SEQUENCE, returning a reader over its contents.
+    pub fn sequence(&mut self) -> Result<Reader<'a>, DerError> {
+        self.expect(Tag::SEQUENCE).map(Reader::new)
+    }
+
+    /// Reads a SET, returning a reader over its contents.  The elements
+    /// must be in ascending order of their encodings.
+    pub fn set(&mut self) -> Result<Reader<'a>, DerError> {
+        self.value(Tag::SET, |contents| {
+            let mut previous: &[u8] = &[];
+            let mut rest = contents;
+            while !rest.is_empty() {
+                let length = read_tlv(rest)?.1;
+                if rest[..length] < *previous {
+                    return Err(DerError::UnsortedSet);
+                }
+                previous = &rest[..length];
+                rest = &rest[length..];
+            }
+            Ok(Reader::new(contents))
+        })
+    }
+}
+
+// Checks INTEGER contents are non-empty and minimal.
+fn integer_contents(contents: &[u8]) -> Result<&[u8], DerError> {
+    match contents {
+        [] => Err(DerError::InvalidInteger),
+        [0x00, next, ..] if next & 0x80 == 0 => Err(DerError::InvalidInteger),
+        [0xFF, next, ..] if next & 0x80 != 0 => Err(DerError::InvalidInteger),
+        _ => Ok(contents),
+    }
+}
+
+// ----------------------------------------------------------------------
+// Writing
+// ----------------------------------------------------------------------
+
+/// Writes TLVs to a byte buffer.
+///
+/// Each method writes a single TLV and returns the number of bytes
+/// written.  If a method fails, nothing it wrote is kept.
+pub struct Writer<'a> {
+    sink: &'a mut [u8],
+    position: usize,
+}
+
+impl<'a> Writer<'a> {
+    /// Creates a writer writing to offset zero of `sink`.
+    pub fn new(sink: &'a mut [u8]) -> Writer<'a> {
+        Writer { sink, position: 0 }
+    }
+
+    /// The number of bytes written so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Finishes writing, returning the number of bytes written.
+    pub fn finish(self) -> usize {
+        self.position
+    }
+
+    // Runs `f` as a single TLV, rolling back on failure.
+    fn item<F>(&mut self, f: F) -> Result<usize, DerError>
+    where F: FnOnce(&mut Writer<'a>) -> Result<(), DerError> {
+        let start = self.position;
+        match f(self) {
+            Ok(()) => Ok(self.position - start),
+            Err(e) => {
+                self.position = start;
+                Err(e)
+            }
+        }
+    }
+
+    fn raw(&mut self, data: &[u8]) -> Result<(), DerError> {
+        let required = self.position + data.len();
+        check(self.sink, required)?;
+        self.sink[self.position..required].copy_from_slice(data);
+        self.position = required;
+        Ok(())
+    }
+
+    fn raw_tag(&mut self, tag: Tag) -> Result<(), DerError> {
+        let mut buffer = [0u8; 6];
+        let length = write_tag(tag, &mut buffer[..])?;
+        self.raw(&buffer[..length])
+    }
+
+    fn raw_length(&mut self, length: usize) -> Result<(), DerError> {
+        let mut buffer = [0u8; MAX_LENGTH_LEN];
+        let written = write_length(length, &mut buffer[..])?;
+        self.raw(&buffer[..written])
+    }
+
+    /// Writes a TLV with the given tag and contents.
+    pub fn tlv(&mut self, tag: Tag, contents: &[u8]) -> Result<usize, DerError> {
+        self.item(|w| {
+            w.raw_tag(tag)?;
+            w.raw_length(contents.len())?;
+            w.raw(contents)
+        })
+    }
+
+    /// Writes a constructed TLV whose contents are written by `f`.
+    pub fn constructed<F>(&mut self, tag: Tag, f: F) -> Result<usize, DerError>
+    where F: FnOnce(&mut Writer) -> Result<(), DerError> {
+        self.item(|w| {
+            w.raw_tag(tag)?;
+            check(w.sink, w.position + MAX_LENGTH_LEN)?;
+            let start = w.position + MAX_LENGTH_LEN;
+            let length = {
+                let mut inner = Writer::new(&mut w.sink[start..]);
+                f(&mut inner).map_err(|e| match e {
+                    DerError::BufferTooSmall { required } => DerError::BufferTooSmall { required: start + required },
+                    e => e,
+                })?;
+                inner.finish()
+            };
+            w.raw_length(length)?;
+            let to = w.position;
+            w.sink.copy_within(start..start + length, to);
+            w.position += length;
+            Ok(())
+        })
+    }
+
+    /// Writes a BOOLEAN.
+    pub fn boolean(&mut self, value: bool) -> Result<usize, DerError> {
+        self.tlv(Tag::BOOLEAN, &[if value { 0xFF } else { 0x00 }])
+    }
+
+    /// Writes an INTEGER from its big endian two's complement bytes,
+    /// which must be minimal.
+    pub fn integer_bytes(&mut self, value: &[u8]) -> Result<usize, DerError> {
+        integer_contents(value)?;
+        self.tlv(Tag::INTEGER, value)
+    }
+
+    /// Writes an INTEGER.
+    pub fn integer_i64(&mut self, value: i64) -> Result<usize, DerError> {
+        let mut bytes = [0u8; 8];
+        value.to_be().fio_write(&mut bytes[..]);
+        let mut skip = 0;
+        while skip < 7 && integer_contents(&bytes[skip..]).is_err() {
+            skip += 1;
+        }
+        self.tlv(Tag::INTEGER, &bytes[skip..])
+    }
+
+    /// Writes a non-negative INTEGER.
+    pub fn integer_u64(&mut self, value: u64) -> Result<usize, DerError> {
+        let mut bytes = [0u8; 9];
+        value.to_be().fio_write(&mut bytes[1..]);
+        let mut skip = 0;
+        while skip < 8 && integer_contents(&bytes[skip..]).is_err() {
+            skip += 1;
+        }
+        self.tlv(Tag::INTEGER, &bytes[skip..])
+    }
+
+    /// Writes a BIT STRING.  The unused bits of the last byte must be
+    /// zero.
+    pub fn bit_string(&mut self, value: BitString) -> Result<usize, DerError> {
+        let padding = (1u16 << value.unused_bits.min(8)) - 1;
+        let valid = match value.bytes.last() {
+            None => value.unused_bits == 0,
+            Some(&last) => value.unused_bits < 8 && (last as u16) & padding == 0,
+        };
+        if !valid {
+            return Err(DerError::InvalidBitString);
+        }
+        self.item(|w| {
+            w.raw_tag(Tag::BIT_STRING)?;
+            w.raw_length(1 + value.bytes.len())?;
+            w.raw(&[value.unused_bits])?;
+            w.raw(value.bytes)
+        })
+    }
+
+    /// Writes an OCTET STRING.
+    pub fn octet_string(&mut self, value: &[u8]) -> Result<usize, DerError> {
+        self.tlv(Tag::OCTET_STRING, value)
+    }
+
+    /// Writes a NULL.
+    pub fn null(&mut self) -> Result<usize, DerError> {
+        self.tlv(Tag::NULL, &[])
+    }
+
+    /// Writes an OBJECT IDENTIFIER from its arcs.  There must be at least
+    /// two; the first must be 0, 1 or 2, and the second below 40 unless
+    /// the first is 2.
+    pub fn oid(&mut self, arcs: &[u64]) -> Result<usize, DerError> {
+        if arcs.len() < 2 || arcs[0] > 2 || (arcs[0] < 2 && arcs[1] >= 40) || arcs[1] > u64::MAX - 80 {
+            return Err(DerError::InvalidOid);
+        }
+        let first = arcs[0] * 40 + arcs[1];
+        let length = base128_len(first) + arcs[2..].iter().map(|arc| base128_len(*arc)).sum::<usize>();
+        self.item(|w| {
+            w.raw_tag(Tag::OID)?;
+            w.raw_length(length)?;
+            check(w.sink, w.position + length)?;
+            w.position += write_base128(first, &mut w.sink[w.position..])?;
+            for arc in &arcs[2..] {
+                w.position += write_base128(*arc, &mut w.sink[w.position..])?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Writes a UTF8String.
+    pub fn utf8_string(&mut self, value: &str) -> Result<usize, DerError> {
+        self.tlv(Tag::UTF8_STRING, value.as_bytes())
+    }
+
+    /// Writes a SEQUENCE whose contents are written by `f`.
+    pub fn sequence<F>(&mut self, f: F) -> Result<usize, DerError>
+    where F: FnOnce(&mut Writer) -> Result<(), DerError> {
+        self.constructed(Tag::SEQUENCE, f)
+    }
+
+    /// Writes a SET whose contents are written by `f`.  The elements are
+    /// then sorted into ascending order of their encodings, as DER
+    /// requires.
+    pub fn set<F>(&mut self, f: F) -> Result<usize, DerError>
+    where F: FnOnce(&mut Writer) -> Result<(), DerError> {
+        self.item(|w| {
+            let start = w.position;
+            w.constructed(Tag::SET, f)?;
+            let contents = w.position - read_tlv(&w.sink[start..w.position])?.0.value.len();
+            sort_elements(&mut w.sink[contents..w.position])
+        })
+    }
+}
+
+// Insertion sorts the TLVs in `contents` by their encodings, moving each
+// into place with a rotation.
+fn sort_elements(contents: &mut [u8]) -> Result<(), DerError> {
+    let mut sorted = 0;
+    while sorted < contents.len() {
+        let length = read_tlv(&contents[sorted..])?.1;
+        let mut at = 0;
+        while at < sorted {
+            let other = read_tlv(&contents[at..])?.1;
+            if contents[at..at + other] > contents[sorted..sorted + length] {
+                break;
+            }
+            at += other;
+        }
+        contents[at..sorted + length].rotate_right(length);
+        sorted += length;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_der_tag_length() {
+    let mut sink = [0u8; 8];
+    let tag = Tag::context(200, true);
+    assert_eq!(write_tag(tag, &mut sink[..]), Ok(3));
+    assert_eq!(&sink[..3], &[0xBF, 0x81, 0x48]);
+    assert_eq!(read_tag(&sink[..3]), Ok((tag, 3)));
+    assert_eq!(read_tag(&[0x30]), Ok((Tag::SEQUENCE, 1)));
+    assert_eq!(read_tag(&[0x1F, 0x80, 0x01]), Err(DerError::InvalidTag));
+    assert_eq!(read_tag(&[0x1F, 0x05]), Err(DerError::InvalidTag));
+    assert_eq!(read_tag(&[0x1F, 0x81]), Err(DerError::Eof));
+
+    let cases: [(usize, &[u8]); 4] = [(0, &[0x00]), (127, &[0x7F]), (128, &[0x81, 0x80]), (256, &[0x82, 0x01, 0x00])];
+    for &(length, expected) in cases.iter() {
+        let n = write_length(length, &mut sink[..]).unwrap();
+        assert_eq!(&sink[..n], expected);
+        assert_eq!(read_length(expected), Ok((length, n)));
+    }
+    assert_eq!(read_length(&[0x80]), Err(DerError::InvalidLength));
+    assert_eq!(read_length(&[0x81, 0x7F]), Err(DerError::InvalidLength));
+    assert_eq!(read_length(&[0x82, 0x00, 0x80]), Err(DerError::InvalidLength));
+    assert_eq!(read_length(&[0x85, 1, 0, 0, 0, 0]), Err(DerError::LengthOverflow));
+    assert_eq!(read_tlv(&[0x04, 0x03, 0x01]), Err(DerError::Eof));
+}
+
+#[test]
+fn test_der_values() {
+    let mut sink = [0u8; 128];
+    let mut w = Writer::new(&mut sink[..]);
+    let written = w.sequence(|w| {
+        w.integer_i64(0)?;
+        w.integer_i64(128)?;
+        w.integer_i64(-128)?;
+        w.integer_i64(-129)?;
+        w.integer_u64(u64::MAX)?;
+        w.boolean(true)?;
+        w.null()?;
+        w.oid(&[1, 2, 840, 113549])?;
+        w.bit_string(BitString { unused_bits: 4, bytes: &[0xA0] })?;
+        w.octet_string(&[1, 2, 3])?;
+        w.utf8_string("ok")?;
+        Ok(())
+    });
+    let n = w.finish();
+    assert_eq!(written, Ok(n));
+    assert_eq!(
+        &sink[..n],
+        &[
+            0x30, 0x33, 0x02, 0x01, 0x00, 0x02, 0x02, 0x00, 0x80, 0x02, 0x01, 0x80, 0x02, 0x02, 0xFF, 0x7F, 0x02, 0x09,
+            0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, 0x01, 0xFF, 0x05, 0x00, 0x06, 0x06, 0x2A, 0x86,
+            0x48, 0x86, 0xF7, 0x0D, 0x03, 0x02, 0x04, 0xA0, 0x04, 0x03, 0x01, 0x02, 0x03, 0x0C, 0x02, b'o', b'k',
+        ][..]
+    );
+
+    let mut outer = Reader::new(&sink[..n]);
+    let mut r = outer.sequence().unwrap();
+    assert_eq!(outer.finish(), Ok(()));
+    assert_eq!(r.integer_i64(), Ok(0));
+    assert_eq!(r.integer_u64(), Ok(128));
+    assert_eq!(r.integer_u64(), Err(DerError::IntegerOverflow));
+    assert_eq!(r.integer_i64(), Ok(-128));
+    assert_eq!(r.integer_i64(), Ok(-129));
+    assert_eq!(r.integer_i64(), Err(DerError::IntegerOverflow));
+    assert_eq!(r.integer_u64(), Ok(u64::MAX));
+    assert_eq!(r.null(), Err(DerError::UnexpectedTag { expected: Tag::NULL, found: Tag::BOOLEAN }));
+    assert_eq!(r.boolean(), Ok(true));
+    r.null().unwrap();
+    let oid = r.oid().unwrap();
+    assert!(oid.arcs().eq([1, 2, 840, 113549].iter().cloned()));
+    let bits = r.bit_string().unwrap();
+    assert_eq!((bits.len(), bits.bytes), (4, &[0xA0][..]));
+    assert_eq!(r.octet_string(), Ok(&[1, 2, 3][..]));
+    assert_eq!(r.utf8_string(), Ok("ok"));
+    assert_eq!(r.finish(), Ok(()));
+    assert!(Oid::new(&[0x82, 0x37]).unwrap().arcs().eq([2, 231].iter().cloned()));
+}
+
+#[test]
+fn test_der_strict() {
+    assert_eq!(Reader::new(&[0x01, 0x01, 0x01]).boolean(), Err(DerError::InvalidBoolean));
+    assert_eq!(Reader::new(&[0x02, 0x00]).integer_i64(), Err(DerError::InvalidInteger));
+    assert_eq!(Reader::new(&[0x02, 0x02, 0x00, 0x7F]).integer_i64(), Err(DerError::InvalidInteger));
+    assert_eq!(Reader::new(&[0x02, 0x02, 0xFF, 0x80]).integer_bytes(), Err(DerError::InvalidInteger));
+    assert_eq!(Reader::new(&[0x03, 0x02, 0x04, 0xA8]).bit_string(), Err(DerError::InvalidBitString));
+    assert_eq!(Reader::new(&[0x03, 0x01, 0x01]).bit_string(), Err(DerError::InvalidBitString));
+    assert_eq!(Reader::new(&[0x05, 0x01, 0x00]).null(), Err(DerError::InvalidNull));
+    assert_eq!(Reader::new(&[0x06, 0x02, 0x80, 0x01]).oid(), Err(DerError::InvalidOid));
+    assert_eq!(Reader::new(&[0x06, 0x01, 0x81]).oid(), Err(DerError::InvalidOid));
+    assert_eq!(Reader::new(&[0x0C, 0x01, 0xFF]).utf8_string(), Err(DerError::InvalidUtf8));
+    assert_eq!(Reader::new(&[0x31, 0x06, 0x02, 0x01, 0x02, 0x02, 0x01, 0x01]).set().map(|_| ()), Err(DerError::UnsortedSet));
+    assert_eq!(Reader::new(&[0x05, 0x00, 0x05]).finish(), Err(DerError::TrailingData));
+
+    // Failed reads leave the reader where it was.
+    let mut r = Reader::new(&[0x01, 0x01, 0x01]);
+    assert!(r.boolean().is_err());
+    assert_eq!(r.remaining().len(), 3);
+
+    let mut sink = [0u8; 4];
+    let mut w = Writer::new(&mut sink[..]);
+    assert_eq!(w.oid(&[3, 1]), Err(DerError::InvalidOid));
+    assert_eq!(w.integer_bytes(&[0x00, 0x01]), Err(DerError::InvalidInteger));
+    assert_eq!(w.octet_string(&[1, 2, 3]), Err(DerError::BufferTooSmall { required: 5 }));
+    assert_eq!(w.position(), 0);
+}
+
+#[test]
+fn test_der_set() {
+    let mut sink = [0u8; 32];
+    let mut w = Writer::new(&mut sink[..]);
+    let written = w.set(|w| {
+        w.integer_i64(300)?;
+        w.integer_i64(2)?;
+        w.boolean(false)?;
+        w.integer_i64(1)?;
+        Ok(())
+    });
+    assert_eq!(written, Ok(15));
+    assert_eq!(
+        &sink[..15],
+        &[0x31, 0x0D, 0x01, 0x01, 0x00, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02, 0x02, 0x02, 0x01, 0x2C][..]
+    );
+    let mut r = Reader::new(&sink[..15]).set().unwrap();
+    assert_eq!(r.boolean(), Ok(false));
+    assert_eq!(r.integer_i64(), Ok(1));
+}
@@ -0,0 +1,169 @@
+//! `embedded-io` integration, for `no_std` targets.
+//!
+//! `FioRead` and `FioWrite` extend `embedded_io::Read` and `Write` (and
+//! so UARTs, SPI flash and the like, through their HAL's `embedded-io`
+//! implementations) with `IO` and `VLQ` reads and writes.  Values are
+//! staged in a stack buffer the size of the value and go through its
+//! `fio_read` and `fio_write`, as the std `fio_read` and `fio_write` do,
+//! but errors are handed back rather than panicking.
+//!
+//! Writes fail with the writer's own error type.  Reads fail with
+//! `ReadError`, which wraps the reader's error type.
+
+use embedded_io::{Read, ReadExactError, Write};
+
+use IO;
+use with_scratch;
+
+#[cfg(feature="vlq")]
+use VLQ;
+
+#[cfg(feature="vlq")]
+use {vlq_fits, vlq_max, MAX_VLQ_LEN};
+
+/// Errors produced by `FioRead`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadError<E> {
+    /// The reader ended before the value being read.
+    Eof,
+    /// A VLQ was longer than its type allows, or its value overflowed it.
+    MalformedVlq,
+    /// The underlying reader failed.
+    Other(E),
+}
+
+impl<E> From<ReadExactError<E>> for ReadError<E> {
+    fn from(e: ReadExactError<E>) -> ReadError<E> {
+        match e {
+            ReadExactError::UnexpectedEof => ReadError::Eof,
+            ReadExactError::Other(e) => ReadError::Other(e),
+        }
+    }
+}
+
+/// Reads fricgan values from an `embedded_io::Read`.
+pub trait FioRead: Read {
+    /// Reads an `IO` value, returning the number of bytes read.
+    fn read_fio<T: IO>(&mut self, value: &mut T) -> Result<usize, ReadError<Self::Error>> {
+        with_scratch::<T, _, _>(|buffer| {
+            self.read_exact(buffer)?;
+            Ok(value.fio_read(buffer))
+        })
+    }
+
+    /// Reads a `VLQ` value, which must end within `size_of::<V>()`
+    /// rounded up to whole 7-bit groups.  Bytes are read one at a time,
+    /// so nothing past the end of the VLQ is consumed.
+    ///
+    /// The return value is the number of bytes read.
+    #[cfg(feature="vlq")]
+    fn read_vlq<V: VLQ>(&mut self, value: &mut V) -> Result<usize, ReadError<Self::Error>> {
+        let max = vlq_max::<V>();
+        let mut buffer = [0u8; MAX_VLQ_LEN];
+        for length in 0..max {
+            self.read_exact(&mut buffer[length..length + 1])?;
+            if (buffer[length] & 128) == 0 {
+                if !vlq_fits::<V>(&buffer[..], length + 1) {
+                    return Err(ReadError::MalformedVlq);
+                }
+                return Ok(value.vlq_read(&buffer[..length + 1]));
+            }
+        }
+        Err(ReadError::MalformedVlq)
+    }
+}
+
+impl<R: Read + ?Sized> FioRead for R {}
+
+/// Writes fricgan values to an `embedded_io::Write`.
+pub trait FioWrite: Write {
+    /// Writes an `IO` value, returning the number of bytes written.
+    fn write_fio<T: IO>(&mut self, value: &mut T) -> Result<usize, Self::Error> {
+        with_scratch::<T, _, _>(|buffer| {
+            let written = value.fio_write(buffer);
+            self.write_all(&buffer[..written])?;
+            Ok(written)
+        })
+    }
+
+    /// Writes a `VLQ` value, returning the number of bytes written.
+    #[cfg(feature="vlq")]
+    fn write_vlq<V: VLQ>(&mut self, value: &V) -> Result<usize, Self::Error> {
+        let mut buffer = [0u8; MAX_VLQ_LEN];
+        let written = value.vlq_write(&mut buffer[..]);
+        self.write_all(&buffer[..written])?;
+        Ok(written)
+    }
+}
+
+impl<W: Write + ?Sized> FioWrite for W {}
+
+#[cfg(all(feature="io-u32", feature="vlq-32"))]
+#[test]
+fn test_embedded_roundtrip() {
+    let mut data = [0u8; 8];
+    {
+        let mut sink = &mut data[..];
+        assert_eq!(sink.write_fio(&mut 0x01020304u32.to_be()), Ok(4));
+        assert_eq!(sink.write_vlq(&300u32), Ok(2));
+        assert_eq!(sink.len(), 2);
+    }
+    assert_eq!(&data[..6], &[0x01, 0x02, 0x03, 0x04, 0xAC, 0x02]);
+
+    let mut source = &data[..6];
+    let mut value = 0u32;
+    let mut vlq = 0u32;
+    assert_eq!(source.read_fio(&mut value), Ok(4));
+    assert_eq!(u32::from_be(value), 0x01020304);
+    assert_eq!(source.read_vlq(&mut vlq), Ok(2));
+    assert_eq!(vlq, 300);
+    assert_eq!(source.read_fio(&mut value), Err(ReadError::Eof));
+}
+
+#[cfg(all(feature="io-u32", feature="vlq-32"))]
+#[test]
+fn test_embedded_errors() {
+    use embedded_io::SliceWriteError;
+
+    let mut data = [0u8; 3];
+    let mut sink = &mut data[..];
+    assert_eq!(sink.write_fio(&mut 1u32), Err(SliceWriteError::Full));
+
+    let mut vlq = 0u32;
+    let mut source = &[0xFFu8, 0xFF, 0xFF, 0xFF, 0xFF, 0x01][..];
+    assert_eq!(source.read_vlq(&mut vlq), Err(ReadError::MalformedVlq));
+    assert_eq!(source, &[0x01]);
+
+    // Five bytes, but with bits above the 32 a `u32` holds.
+    let mut source = &[0xFFu8, 0xFF, 0xFF, 0xFF, 0x1F][..];
+    assert_eq!(source.read_vlq(&mut vlq), Err(ReadError::MalformedVlq));
+
+    let mut source = &[0xFFu8][..];
+    assert_eq!(source.read_vlq(&mut vlq), Err(ReadError::Eof));
+}
+
+#[test]
+fn test_embedded_through_io() {
+    // Stored little endian, written big endian: only its IO impl knows.
+    struct Big(u16);
+
+    impl IO for Big {
+        fn fio_write(&mut self, sink: &mut [u8]) -> usize {
+            sink[..2].copy_from_slice(&self.0.to_be_bytes());
+            2
+        }
+
+        fn fio_read(&mut self, source: &[u8]) -> usize {
+            self.0 = u16::from_be_bytes([source[0], source[1]]);
+            2
+        }
+    }
+
+    let mut data = [0u8; 2];
+    assert_eq!((&mut data[..]).write_fio(&mut Big(0x0102)), Ok(2));
+    assert_eq!(data, [0x01, 0x02]);
+
+    let mut value = Big(0);
+    assert_eq!((&data[..]).read_fio(&mut value), Ok(2));
+    assert_eq!(value.0, 0x0102);
+}
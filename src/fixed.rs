@@ -0,0 +1,325 @@
+//! Fixed point (Q format) numbers.
+//!
+//! `Fixed<I, FRAC>` is an integer `I` holding a value scaled by
+//! `2^FRAC`, so Q15 is `Fixed<i16, 15>` and Q16.16 `Fixed<i32, 16>`.  Its
+//! `IO` impl is that of `I`, so a fixed point field reads and writes just
+//! as the raw integer would (in native byte order).
+//!
+//! Conversion from `f32` and `f64` takes a `Rounding` mode, and
+//! saturates: out of range values (and infinities) clamp to the limits
+//! of `I`, and NaN becomes zero.  Arithmetic saturates the same way, with
+//! multiplication and division rounding to nearest (or as given).
+//!
+//! Any 8 to 64 bit integer may be used, with `FRAC` up to its width.
+
+use core::cmp::Ordering;
+
+use IO;
+
+/// How to round a value that falls between two representable values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// To the nearest value, ties to even.
+    Nearest,
+    /// Toward zero (truncation).
+    TowardZero,
+    /// Toward negative infinity.
+    Floor,
+    /// Toward positive infinity.
+    Ceiling,
+}
+
+impl Rounding {
+    // Whether a magnitude with a nonzero remainder rounds up (away from
+    // zero).  `half` orders the remainder against one half.
+    fn rounds_up(self, negative: bool, odd: bool, half: Ordering) -> bool {
+        match self {
+            Rounding::Nearest => half == Ordering::Greater || (half == Ordering::Equal && odd),
+            Rounding::TowardZero => false,
+            Rounding::Floor => negative,
+            Rounding::Ceiling => !negative,
+        }
+    }
+}
+
+mod repr {
+    /// The integers a `Fixed` may be built on.
+    pub trait Repr: Copy {
+        /// The width of the integer, in bits.
+        const BITS: u32;
+        /// The smallest value, widened.
+        const MIN: i128;
+        /// The largest value, widened.
+        const MAX: i128;
+
+        /// Widens the value.
+        fn widen(self) -> i128;
+
+        /// Narrows a value known to be in range.
+        fn narrow(value: i128) -> Self;
+    }
+
+    macro_rules! repr {
+        ($($t:ident)*) => {$(
+            impl Repr for $t {
+                const BITS: u32 = $t::BITS;
+                const MIN: i128 = $t::MIN as i128;
+                const MAX: i128 = $t::MAX as i128;
+
+                fn widen(self) -> i128 {
+                    self as i128
+                }
+
+                fn narrow(value: i128) -> $t {
+                    value as $t
+                }
+            }
+        )*};
+    }
+
+    repr!(i8 u8 i16 u16 i32 u32 i64 u64);
+}
+
+use self::repr::Repr;
+
+/// A fixed point number: an `I` scaled by `2^FRAC`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct Fixed<I, const FRAC: u32>(I);
+
+/// Signed Q7 (`i8`, 7 fractional bits).
+pub type Q7 = Fixed<i8, 7>;
+
+/// Signed Q15 (`i16`, 15 fractional bits).
+pub type Q15 = Fixed<i16, 15>;
+
+/// Signed Q31 (`i32`, 31 fractional bits).
+pub type Q31 = Fixed<i32, 31>;
+
+impl<I: Repr, const FRAC: u32> Fixed<I, FRAC> {
+    const VALID: () = assert!(FRAC <= I::BITS, "FRAC exceeds the width of the integer");
+
+    /// Creates a value from its raw (scaled) integer.
+    pub fn from_bits(bits: I) -> Fixed<I, FRAC> {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::VALID;
+        Fixed(bits)
+    }
+
+    /// The raw (scaled) integer.
+    pub fn to_bits(self) -> I {
+        self.0
+    }
+
+    /// The smallest value.
+    pub fn min_value() -> Fixed<I, FRAC> {
+        Fixed::from_bits(I::narrow(I::MIN))
+    }
+
+    /// The largest value.
+    pub fn max_value() -> Fixed<I, FRAC> {
+        Fixed::from_bits(I::narrow(I::MAX))
+    }
+
+    // Clamps a signed magnitude to the range of `I`.
+    fn saturate(negative: bool, magnitude: u128) -> Fixed<I, FRAC> {
+        let value = if negative {
+            if magnitude >= I::MIN.unsigned_abs() { I::MIN } else { -(magnitude as i128) }
+        } else if magnitude >= I::MAX as u128 {
+            I::MAX
+        } else {
+            magnitude as i128
+        };
+        Fixed::from_bits(I::narrow(value))
+    }
+
+    fn clamp(value: i128) -> Fixed<I, FRAC> {
+        Fixed::saturate(value < 0, value.unsigned_abs())
+    }
+
+    /// Converts an `f64`, rounding as given and saturating.
+    pub fn from_f64(value: f64, rounding: Rounding) -> Fixed<I, FRAC> {
+        if value.is_nan() {
+            return Fixed::from_bits(I::narrow(0));
+        }
+        // Scaling by a power of two is exact (or overflows to infinity,
+        // which saturates below).
+        let scaled = value * (1u128 << FRAC) as f64;
+        let negative = scaled < 0.0;
+        let magnitude = if negative { -scaled } else { scaled };
+        if magnitude >= u128::MAX as f64 {
+            return Fixed::saturate(negative, u128::MAX);
+        }
+        // The truncation is exact, as is the remainder: past 2^53 every
+        // f64 is an integer.
+        let whole = magnitude as u128;
+        let rest = magnitude - whole as f64;
+        let up = rest != 0.0 && rounding.rounds_up(
+            negative,
+            whole & 1 == 1,
+            rest.partial_cmp(&0.5).unwrap(),
+        );
+        Fixed::saturate(negative, whole + up as u128)
+    }
+
+    /// Converts an `f32`, rounding as given and saturating.
+    pub fn from_f32(value: f32, rounding: Rounding) -> Fixed<I, FRAC> {
+        Fixed::from_f64(value as f64, rounding)
+    }
+
+    /// Converts the value to an `f64` (exactly, for integers up to 53
+    /// bits).
+    pub fn to_f64(self) -> f64 {
+        self.0.widen() as f64 / (1u128 << FRAC) as f64
+    }
+
+    /// Converts the value to an `f32` (rounding to nearest).
+    pub fn to_f32(self) -> f32 {
+        // One rounding, in the conversion; the scaling is exact.
+        self.0.widen() as f32 / (1u128 << FRAC) as f32
+    }
+
+    /// Adds, saturating at the limits of `I`.
+    pub fn saturating_add(self, other: Fixed<I, FRAC>) -> Fixed<I, FRAC> {
+        Fixed::clamp(self.0.widen() + other.0.widen())
+    }
+
+    /// Subtracts, saturating at the limits of `I`.
+    pub fn saturating_sub(self, other: Fixed<I, FRAC>) -> Fixed<I, FRAC> {
+        Fixed::clamp(self.0.widen() - other.0.widen())
+    }
+
+    /// Negates, saturating at the limits of `I` (so unsigned values
+    /// become zero).
+    pub fn saturating_neg(self) -> Fixed<I, FRAC> {
+        Fixed::clamp(-self.0.widen())
+    }
+
+    /// Multiplies, rounding to nearest and saturating.
+    pub fn saturating_mul(self, other: Fixed<I, FRAC>) -> Fixed<I, FRAC> {
+        self.saturating_mul_round(other, Rounding::Nearest)
+    }
+
+    /// Multiplies, rounding as given and saturating.
+    pub fn saturating_mul_round(self, other: Fixed<I, FRAC>, rounding: Rounding) -> Fixed<I, FRAC> {
+        let (a, b) = (self.0.widen(), other.0.widen());
+        let product = a.unsigned_abs() * b.unsigned_abs();
+        Fixed::divide((a < 0) != (b < 0), product, 1u128 << FRAC, rounding)
+    }
+
+    /// Divides, rounding to nearest and saturating.  Division by zero
+    /// saturates in the direction of the dividend (and gives zero for
+    /// zero).
+    pub fn saturating_div(self, other: Fixed<I, FRAC>) -> Fixed<I, FRAC> {
+        self.saturating_div_round(other, Rounding::Nearest)
+    }
+
+    /// Divides, rounding as given and saturating.
+    pub fn saturating_div_round(self, other: Fixed<I, FRAC>, rounding: Rounding) -> Fixed<I, FRAC> {
+        let (a, b) = (self.0.widen(), other.0.widen());
+        if b == 0 {
+            return match a.cmp(&0) {
+                Ordering::Less => Fixed::min_value(),
+                Ordering::Equal => Fixed::from_bits(I::narrow(0)),
+                Ordering::Greater => Fixed::max_value(),
+            };
+        }
+        // At most 64 bits shifted by at most 64, so this can't overflow.
+        let dividend = a.unsigned_abs() << FRAC;
+        Fixed::divide((a < 0) != (b < 0), dividend, b.unsigned_abs(), rounding)
+    }
+
+    // Divides magnitudes, rounding and saturating the signed result.
+    fn divide(negative: bool, n: u128, d: u128, rounding: Rounding) -> Fixed<I, FRAC> {
+        let (quotient, rest) = (n / d, n % d);
+        let up = rest != 0 && rounding.rounds_up(
+            negative,
+            quotient & 1 == 1,
+            rest.cmp(&(d - rest)),
+        );
+        Fixed::saturate(negative, quotient + up as u128)
+    }
+}
+
+impl<I: Repr + IO, const FRAC: u32> IO for Fixed<I, FRAC> {
+    fn fio_read(&mut self, source: &[u8]) -> usize {
+        self.0.fio_read(source)
+    }
+
+    fn fio_write(&mut self, sink: &mut [u8]) -> usize {
+        self.0.fio_write(sink)
+    }
+}
+
+#[test]
+fn test_fixed_convert() {
+    assert_eq!(Q15::from_f64(0.5, Rounding::Nearest).to_bits(), 0x4000);
+    assert_eq!(Q15::from_f64(-1.0, Rounding::Nearest).to_bits(), -0x8000);
+    assert_eq!(Q15::from_bits(0x4000).to_f64(), 0.5);
+    assert_eq!(Fixed::<i32, 16>::from_f32(-1.25, Rounding::Nearest).to_bits(), -0x14000);
+    assert_eq!(Fixed::<u16, 8>::from_bits(0x0180).to_f32(), 1.5);
+
+    // Saturation.
+    assert_eq!(Q15::from_f64(1.0, Rounding::Nearest), Q15::max_value());
+    assert_eq!(Q15::from_f64(-7.0, Rounding::Nearest), Q15::min_value());
+    assert_eq!(Q15::from_f64(f64::INFINITY, Rounding::Nearest), Q15::max_value());
+    assert_eq!(Q15::from_f64(f64::NAN, Rounding::Nearest).to_bits(), 0);
+    assert_eq!(Fixed::<u8, 4>::from_f64(-1.0, Rounding::Nearest).to_bits(), 0);
+    assert_eq!(Fixed::<u64, 64>::from_f64(0.5, Rounding::Nearest).to_bits(), 1 << 63);
+    assert_eq!(Fixed::<i64, 0>::from_f64(1e300, Rounding::Nearest).to_bits(), i64::MAX);
+}
+
+#[test]
+fn test_fixed_rounding() {
+    type Q4 = Fixed<i8, 4>;
+    // 1/32 is halfway between 0 and 1/16.
+    let cases = [
+        (0.03125, [0, 0, 0, 1]),
+        (0.09375, [2, 1, 1, 2]),
+        (-0.03125, [0, 0, -1, 0]),
+        (-0.04, [-1, 0, -1, 0]),
+    ];
+    let modes = [Rounding::Nearest, Rounding::TowardZero, Rounding::Floor, Rounding::Ceiling];
+    for &(value, expected) in cases.iter() {
+        for (mode, bits) in modes.iter().zip(expected.iter()) {
+            assert_eq!(Q4::from_f64(value, *mode).to_bits(), *bits, "{} {:?}", value, mode);
+        }
+    }
+}
+
+#[test]
+fn test_fixed_arithmetic() {
+    let half = Q15::from_f64(0.5, Rounding::Nearest);
+    let quarter = Q15::from_f64(0.25, Rounding::Nearest);
+    assert_eq!(half.saturating_mul(half), quarter);
+    assert_eq!(quarter.saturating_div(half), half);
+    assert_eq!(half.saturating_add(half), Q15::max_value());
+    assert_eq!(half.saturating_neg().saturating_sub(half), Q15::min_value());
+    assert_eq!(Q15::min_value().saturating_neg(), Q15::max_value());
+    assert_eq!(Q15::min_value().saturating_mul(Q15::min_value()), Q15::max_value());
+    assert_eq!(half.saturating_div(quarter), Q15::max_value());
+    assert_eq!(half.saturating_neg().saturating_div(Q15::from_bits(0)), Q15::min_value());
+
+    // Rounding of products: 3/16 * 1/2 is 3/32.
+    type Q4 = Fixed<i8, 4>;
+    let (a, b) = (Q4::from_bits(3), Q4::from_bits(8));
+    assert_eq!(a.saturating_mul(b).to_bits(), 2);
+    assert_eq!(a.saturating_mul_round(b, Rounding::TowardZero).to_bits(), 1);
+    assert_eq!(a.saturating_neg().saturating_mul_round(b, Rounding::Floor).to_bits(), -2);
+
+    let big = Fixed::<u64, 64>::from_bits(u64::MAX);
+    assert_eq!(big.saturating_mul(big).to_bits(), u64::MAX - 1);
+    assert_eq!(big.saturating_div(big), Fixed::max_value());
+}
+
+#[cfg(feature="io-i32")]
+#[test]
+fn test_fixed_io() {
+    let mut data = [0u8; 4];
+    let mut value = Fixed::<i32, 16>::from_f64(3.75, Rounding::Nearest);
+    let mut copy = Fixed::<i32, 16>::default();
+    assert_eq!(value.fio_write(&mut data[..]), 4);
+    assert_eq!(i32::from_ne_bytes(data), 0x3C000);
+    assert_eq!(copy.fio_read(&data[..]), 4);
+    assert_eq!(copy.to_f64(), 3.75);
+}
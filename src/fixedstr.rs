@@ -0,0 +1,236 @@
+//! Fixed width, padded string fields.
+//!
+//! Record formats (tar headers, dBASE files, mainframe card images) store
+//! strings in fields of a fixed size, padded out with spaces or NULs.
+//! `FixedStr<N>` holds the value of an `N` byte field; `read` and `write`
+//! always take exactly `N` bytes, stripping the padding on read and
+//! adding it back on write.
+//!
+//! `FixedStr` isn't `IO`: its memory holds more than the field (the
+//! length and padding settings), so the helpers which size buffers with
+//! `size_of` would read or write the wrong number of bytes.
+//!
+//! Values are left aligned and padded with spaces by default; `align`
+//! and `pad` change this.  A value longer than the field is refused by
+//! `set` with `Overflow`, so `write` never has to truncate.
+//!
+//! Trimming removes every pad byte on the padding side, so a value which
+//! itself ends (or, right aligned, begins) with the pad byte won't come
+//! back intact.  That's the nature of these formats.
+
+use core::str::{from_utf8, Utf8Error};
+
+/// Which side of the field the value sits on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    /// The value comes first, followed by padding.
+    Left,
+    /// The padding comes first, followed by the value.
+    Right,
+}
+
+/// Errors produced by `FixedStr`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixedStrError {
+    /// The sink is too small to hold the field.
+    BufferTooSmall {
+        /// The number of bytes the sink requires.
+        required: usize,
+    },
+    /// The input ended before the field did.
+    Eof,
+    /// The value is wider than the field.
+    Overflow {
+        /// The length of the value.
+        length: usize,
+        /// The width of the field.
+        width: usize,
+    },
+}
+
+/// The value of an `N` byte padded field.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedStr<const N: usize> {
+    value: [u8; N],
+    len: usize,
+    align: Align,
+    pad: u8,
+}
+
+// Only the value counts; the bytes past it are leftovers.
+impl<const N: usize> PartialEq for FixedStr<N> {
+    fn eq(&self, other: &FixedStr<N>) -> bool {
+        self.as_bytes() == other.as_bytes() && self.align == other.align && self.pad == other.pad
+    }
+}
+
+impl<const N: usize> Eq for FixedStr<N> {}
+
+impl<const N: usize> Default for FixedStr<N> {
+    fn default() -> FixedStr<N> {
+        FixedStr::new()
+    }
+}
+
+impl<const N: usize> FixedStr<N> {
+    /// Creates an empty, left aligned, space padded field.
+    pub fn new() -> FixedStr<N> {
+        FixedStr {
+            value: [0u8; N],
+            len: 0,
+            align: Align::Left,
+            pad: b' ',
+        }
+    }
+
+    /// Sets the alignment.
+    pub fn align(mut self, align: Align) -> FixedStr<N> {
+        self.align = align;
+        self
+    }
+
+    /// Sets the pad byte (such as `b' '` or `0`).
+    pub fn pad(mut self, pad: u8) -> FixedStr<N> {
+        self.pad = pad;
+        self
+    }
+
+    /// The width of the field, `N`.
+    pub fn width(&self) -> usize {
+        N
+    }
+
+    /// The length of the value.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the value is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The value, without padding.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.value[..self.len]
+    }
+
+    /// The value, without padding, as a string.
+    pub fn as_str(&self) -> Result<&str, Utf8Error> {
+        from_utf8(self.as_bytes())
+    }
+
+    /// Replaces the value.  The value is unchanged on failure.
+    pub fn set(&mut self, value: &str) -> Result<(), FixedStrError> {
+        self.set_bytes(value.as_bytes())
+    }
+
+    /// Replaces the value with raw bytes (for fields in a legacy
+    /// encoding).  The value is unchanged on failure.
+    pub fn set_bytes(&mut self, value: &[u8]) -> Result<(), FixedStrError> {
+        if value.len() > N {
+            return Err(FixedStrError::Overflow { length: value.len(), width: N });
+        }
+        self.value[..value.len()].copy_from_slice(value);
+        self.len = value.len();
+        Ok(())
+    }
+
+    /// Writes the padded field to offset zero of `sink`.
+    ///
+    /// The return value is the number of bytes written, always `N`.
+    pub fn write(&self, sink: &mut [u8]) -> Result<usize, FixedStrError> {
+        if sink.len() < N {
+            return Err(FixedStrError::BufferTooSmall { required: N });
+        }
+        let field = &mut sink[..N];
+        for b in field.iter_mut() {
+            *b = self.pad;
+        }
+        let start = match self.align {
+            Align::Left => 0,
+            Align::Right => N - self.len,
+        };
+        field[start..start + self.len].copy_from_slice(self.as_bytes());
+        Ok(N)
+    }
+
+    /// Reads the field at offset zero of `source`, stripping the padding.
+    ///
+    /// The return value is the number of bytes read, always `N`.
+    pub fn read(&mut self, source: &[u8]) -> Result<usize, FixedStrError> {
+        if source.len() < N {
+            return Err(FixedStrError::Eof);
+        }
+        self.value.copy_from_slice(&source[..N]);
+        let pad = self.pad;
+        match self.align {
+            Align::Left => {
+                self.len = self.value.iter().rposition(|b| *b != pad).map_or(0, |i| i + 1);
+            }
+            Align::Right => {
+                let start = self.value.iter().position(|b| *b != pad).unwrap_or(N);
+                self.value.copy_within(start.., 0);
+                self.len = N - start;
+            }
+        }
+        Ok(N)
+    }
+}
+
+#[test]
+fn test_fixedstr_left() {
+    let mut data = [0xFFu8; 10];
+    let mut name = FixedStr::<8>::new();
+    assert_eq!(name.set("fricgan"), Ok(()));
+    assert_eq!(name.write(&mut data[..]), Ok(8));
+    assert_eq!(&data[..], b"fricgan \xFF\xFF");
+
+    let mut copy = FixedStr::<8>::new();
+    assert_eq!(copy.read(&data[..]), Ok(8));
+    assert_eq!(copy.as_str(), Ok("fricgan"));
+    assert_eq!(copy, name);
+
+    // An all padding field is empty.
+    assert_eq!(copy.read(b"        "), Ok(8));
+    assert!(copy.is_empty());
+}
+
+#[test]
+fn test_fixedstr_right() {
+    let mut data = [0xFFu8; 6];
+    let mut size = FixedStr::<6>::new().align(Align::Right).pad(b'0');
+    assert_eq!(size.set("1234"), Ok(()));
+    assert_eq!(size.write(&mut data[..]), Ok(6));
+    assert_eq!(&data[..], b"001234");
+
+    let mut copy = FixedStr::<6>::new().align(Align::Right).pad(b'0');
+    assert_eq!(copy.read(b"000042"), Ok(6));
+    assert_eq!(copy.as_bytes(), b"42");
+    assert_eq!(copy.len(), 2);
+}
+
+#[test]
+fn test_fixedstr_nul() {
+    let mut data = [0xFFu8; 8];
+    let mut field = FixedStr::<8>::new().pad(0);
+    assert_eq!(field.set_bytes(b"ustar"), Ok(()));
+    assert_eq!(field.write(&mut data[..]), Ok(8));
+    assert_eq!(&data[..], b"ustar\0\0\0");
+    assert_eq!(field.read(b"root\0\0\0\0"), Ok(8));
+    assert_eq!(field.as_bytes(), b"root");
+}
+
+#[test]
+fn test_fixedstr_overflow() {
+    let mut field = FixedStr::<4>::new();
+    assert_eq!(field.set("abcd"), Ok(()));
+    assert_eq!(field.set("abcde"), Err(FixedStrError::Overflow { length: 5, width: 4 }));
+    assert_eq!(field.as_str(), Ok("abcd"));
+    assert_eq!(field.width(), 4);
+
+    let mut data = [0u8; 3];
+    assert_eq!(field.write(&mut data[..]), Err(FixedStrError::BufferTooSmall { required: 4 }));
+    assert_eq!(field.read(b"abc"), Err(FixedStrError::Eof));
+    assert_eq!(field.as_str(), Ok("abcd"));
+}
@@ -0,0 +1,464 @@
+//! Length-delimited framing.
+//!
+//! A frame is a length prefix, the payload, and (optionally) a trailing
+//! CRC-32 of the payload.  The length counts the payload only; the
+//! checksum, where present, is not included in it.
+//!
+//! `FrameWriter` writes frames into a byte buffer, and `FrameReader`
+//! decodes them.  Decoding is incremental: if the input does not yet hold
+//! a whole frame the reader says so (rather than failing), so partial
+//! input can be buffered and retried once more bytes arrive.
+
+use IO;
+use VLQ;
+use {vlq_encoded_len, vlq_fits, vlq_len};
+use checksum::crc32;
+
+/// The encoding used for the length prefix of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthPrefix {
+    /// Two byte little endian length.
+    U16Le,
+    /// Two byte big endian length.
+    U16Be,
+    /// Four byte little endian length.
+    U32Le,
+    /// Four byte big endian length.
+    U32Be,
+    /// `u32` VLQ encoded length (one to five bytes).
+    Vlq,
+}
+
+impl LengthPrefix {
+    /// The largest payload length the prefix is able to represent.
+    pub fn max_length(&self) -> usize {
+        match *self {
+            LengthPrefix::U16Le | LengthPrefix::U16Be => 0xFFFF,
+            _ => 0xFFFFFFFF,
+        }
+    }
+
+    /// The number of bytes the prefix occupies for a given length.
+    pub fn encoded_len(&self, length: usize) -> usize {
+        match *self {
+            LengthPrefix::U16Le | LengthPrefix::U16Be => 2,
+            LengthPrefix::U32Le | LengthPrefix::U32Be => 4,
+            LengthPrefix::Vlq => vlq_encoded_len(length as u64),
+        }
+    }
+
+    // The checksum follows the byte order of the prefix.  VLQ is
+    // little endian in spirit (least significant group first), so the
+    // checksum follows suit.
+    fn big_endian(&self) -> bool {
+        matches!(*self, LengthPrefix::U16Be | LengthPrefix::U32Be)
+    }
+
+    fn write(&self, length: usize, sink: &mut [u8]) -> usize {
+        match *self {
+            LengthPrefix::U16Le => (length as u16).to_le().fio_write(sink),
+            LengthPrefix::U16Be => (length as u16).to_be().fio_write(sink),
+            LengthPrefix::U32Le => (length as u32).to_le().fio_write(sink),
+            LengthPrefix::U32Be => (length as u32).to_be().fio_write(sink),
+            LengthPrefix::Vlq => (length as u32).vlq_write(sink),
+        }
+    }
+
+    // Returns `Ok(None)` where the prefix is incomplete.
+    fn read(&self, source: &[u8]) -> Result<Option<(usize, usize)>, FrameError> {
+        match *self {
+            LengthPrefix::U16Le | LengthPrefix::U16Be => {
+                if source.len() < 2 {
+                    return Ok(None);
+                }
+                let mut length: u16 = 0;
+                let read = length.fio_read(source);
+                let length = if self.big_endian() {
+                    u16::from_be(length)
+                } else {
+                    u16::from_le(length)
+                };
+                Ok(Some((length as usize, read)))
+            }
+            LengthPrefix::U32Le | LengthPrefix::U32Be => {
+                if source.len() < 4 {
+                    return Ok(None);
+                }
+                let mut length: u32 = 0;
+                let read = length.fio_read(source);
+                let length = if self.big_endian() {
+                    u32::from_be(length)
+                } else {
+                    u32::from_le(length)
+                };
+                Ok(Some((length as usize, read)))
+            }
+            LengthPrefix::Vlq => {
+                // The final byte of the VLQ is the first without the
+                // continuation bit; it has to be within five bytes, and
+                // the value has to fit in 32 bits.
+                match vlq_len(source, 5) {
+                    Some(read) if !vlq_fits::<u32>(source, read) => Err(FrameError::MalformedLength),
+                    Some(_) => {
+                        let mut length: u32 = 0;
+                        let read = length.vlq_read(source);
+                        Ok(Some((length as usize, read)))
+                    }
+                    None if source.len() < 5 => Ok(None),
+                    None => Err(FrameError::MalformedLength),
+                }
+            }
+        }
+    }
+}
+
+/// Errors produced while writing or reading frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// The payload length exceeds the configured maximum frame size.
+    TooLarge {
+        /// The length of the payload.
+        length: usize,
+        /// The configured maximum.
+        max: usize,
+    },
+    /// The sink is too small to hold the frame.
+    BufferTooSmall {
+        /// The number of bytes the frame requires.
+        required: usize,
+    },
+    /// The trailing checksum did not match the payload.
+    ChecksumMismatch {
+        /// The checksum carried by the frame.
+        expected: u32,
+        /// The checksum computed over the payload.
+        actual: u32,
+    },
+    /// The length prefix could not be decoded, or overflowed 32 bits.
+    MalformedLength,
+}
+
+/// Settings shared by `FrameWriter` and `FrameReader`; both sides of a
+/// connection need to agree on these.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameConfig {
+    prefix: LengthPrefix,
+    max_frame_size: usize,
+    crc32: bool,
+}
+
+impl FrameConfig {
+    /// Creates a configuration using `prefix`, with the maximum frame
+    /// size set to the largest length `prefix` can hold and no checksum.
+    pub fn new(prefix: LengthPrefix) -> FrameConfig {
+        FrameConfig {
+            prefix,
+            max_frame_size: prefix.max_length(),
+            crc32: false,
+        }
+    }
+
+    /// Sets the maximum payload size.  This is clamped to what the
+    /// length prefix can represent.
+    pub fn max_frame_size(mut self, max: usize) -> FrameConfig {
+        self.max_frame_size = if max < self.prefix.max_length() {
+            max
+        } else {
+            self.prefix.max_length()
+        };
+        self
+    }
+
+    /// Enables (or disables) the trailing CRC-32.
+    pub fn crc32(mut self, enabled: bool) -> FrameConfig {
+        self.crc32 = enabled;
+        self
+    }
+
+    /// The length prefix in use.
+    pub fn prefix(&self) -> LengthPrefix {
+        self.prefix
+    }
+
+    /// The total number of bytes a frame carrying `length` bytes of
+    /// payload occupies.
+    pub fn frame_len(&self, length: usize) -> usize {
+        self.prefix.encoded_len(length) + length + self.trailer_len()
+    }
+
+    fn trailer_len(&self) -> usize {
+        if self.crc32 { 4 } else { 0 }
+    }
+
+    fn check_length(&self, length: usize) -> Result<(), FrameError> {
+        if length > self.max_frame_size {
+            Err(FrameError::TooLarge {
+                length,
+                max: self.max_frame_size,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Writes payloads out as frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameWriter {
+    config: FrameConfig,
+}
+
+impl FrameWriter {
+    /// Creates a writer from `config`.
+    pub fn new(config: FrameConfig) -> FrameWriter {
+        FrameWriter { config }
+    }
+
+    /// The configuration of the writer.
+    pub fn config(&self) -> &FrameConfig {
+        &self.config
+    }
+
+    /// Writes `payload` to offset zero of `sink` as a frame.
+    ///
+    /// The return value is the number of bytes written.
+    pub fn write(&self, payload: &[u8], sink: &mut [u8]) -> Result<usize, FrameError> {
+        self.config.check_length(payload.len())?;
+        let required = self.config.frame_len(payload.len());
+        if sink.len() < required {
+            return Err(FrameError::BufferTooSmall { required });
+        }
+
+        let mut written = self.config.prefix.write(payload.len(), sink);
+        sink[written..written + payload.len()].copy_from_slice(payload);
+        written += payload.len();
+
+        if self.config.crc32 {
+            let crc = crc32(payload);
+            let mut crc = if self.config.prefix.big_endian() {
+                crc.to_be()
+            } else {
+                crc.to_le()
+            };
+            written += crc.fio_write(&mut sink[written..]);
+        }
+
+        Ok(written)
+    }
+
+    /// Appends `payload` to `sink` as a frame, returning the number of
+    /// bytes appended.
+    #[cfg(feature="std")]
+    pub fn write_to_vec(&self, payload: &[u8], sink: &mut Vec<u8>) -> Result<usize, FrameError> {
+        self.config.check_length(payload.len())?;
+        let start = sink.len();
+        sink.resize(start + self.config.frame_len(payload.len()), 0);
+        self.write(payload, &mut sink[start..])
+    }
+}
+
+/// Decodes frames.
+///
+/// `decode` works on a caller supplied buffer and does not allocate.  With
+/// `std` enabled the reader can also buffer input itself, via `push` and
+/// `next_frame`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameReader {
+    config: FrameConfig,
+    #[cfg(feature="std")]
+    buffer: Vec<u8>,
+}
+
+impl FrameReader {
+    /// Creates a reader from `config`.
+    pub fn new(config: FrameConfig) -> FrameReader {
+        FrameReader {
+            config,
+            #[cfg(feature="std")]
+            buffer: Vec::new(),
+        }
+    }
+
+    /// The configuration of the reader.
+    pub fn config(&self) -> &FrameConfig {
+        &self.config
+    }
+
+    /// Decodes the frame at offset zero of `source`.
+    ///
+    /// Returns the payload and the total number of bytes the frame used,
+    /// or `Ok(None)` if `source` does not yet contain the whole frame.
+    /// Oversized frames are rejected as soon as the length is known.
+    pub fn decode<'a>(&self, source: &'a [u8]) -> Result<Option<(&'a [u8], usize)>, FrameError> {
+        let (length, offset) = match self.config.prefix.read(source)? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        self.config.check_length(length)?;
+
+        // A length near `u32::MAX` overflows a 32-bit `usize` here.
+        let too_large = FrameError::TooLarge { length, max: self.config.max_frame_size };
+        let end = offset.checked_add(length).ok_or(too_large)?;
+        let total = end.checked_add(self.config.trailer_len()).ok_or(too_large)?;
+        if source.len() < total {
+            return Ok(None);
+        }
+
+        let payload = &source[offset..end];
+        if self.config.crc32 {
+            let mut expected: u32 = 0;
+            expected.fio_read(&source[end..]);
+            let expected = if self.config.prefix.big_endian() {
+                u32::from_be(expected)
+            } else {
+                u32::from_le(expected)
+            };
+            let actual = crc32(payload);
+            if expected != actual {
+                return Err(FrameError::ChecksumMismatch {
+                    expected,
+                    actual,
+                });
+            }
+        }
+
+        Ok(Some((payload, total)))
+    }
+
+    /// Appends received bytes to the internal buffer.
+    #[cfg(feature="std")]
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Number of bytes buffered but not yet returned as frames.
+    #[cfg(feature="std")]
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Removes the next complete frame from the internal buffer, returning
+    /// its payload, or `Ok(None)` if more input is required.
+    ///
+    /// On error the buffer is left untouched; the stream is unlikely to be
+    /// recoverable, so callers will normally drop the connection.
+    #[cfg(feature="std")]
+    pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>, FrameError> {
+        let (payload, used) = match self.decode(&self.buffer)? {
+            Some((payload, used)) => (payload.to_vec(), used),
+            None => return Ok(None),
+        };
+        self.buffer.drain(..used);
+        Ok(Some(payload))
+    }
+}
+
+#[test]
+fn test_framing_fixed() {
+    let payload = b"hello";
+    let mut data = [0u8; 16];
+
+    let config = FrameConfig::new(LengthPrefix::U16Be);
+    let writer = FrameWriter::new(config);
+    assert_eq!(writer.write(payload, &mut data[..]), Ok(7));
+    assert_eq!(&data[..7], b"\x00\x05hello");
+
+    let reader = FrameReader::new(config);
+    assert_eq!(reader.decode(&data[..7]), Ok(Some((&payload[..], 7))));
+
+    let config = FrameConfig::new(LengthPrefix::U32Le);
+    let writer = FrameWriter::new(config);
+    assert_eq!(writer.write(payload, &mut data[..]), Ok(9));
+    assert_eq!(&data[..9], b"\x05\x00\x00\x00hello");
+
+    let reader = FrameReader::new(config);
+    assert_eq!(reader.decode(&data[..9]), Ok(Some((&payload[..], 9))));
+}
+
+#[test]
+fn test_framing_partial() {
+    let payload = [0xAAu8; 200];
+    let mut data = [0u8; 256];
+
+    let config = FrameConfig::new(LengthPrefix::Vlq).crc32(true);
+    let writer = FrameWriter::new(config);
+    let reader = FrameReader::new(config);
+    let written = writer.write(&payload[..], &mut data[..]).unwrap();
+    assert_eq!(written, 2 + 200 + 4);
+
+    // Every truncation of the frame is incomplete rather than an error.
+    for i in 0..written {
+        assert_eq!(reader.decode(&data[..i]), Ok(None));
+    }
+    assert_eq!(reader.decode(&data[..written]), Ok(Some((&payload[..], written))));
+
+    // Corrupt the payload.
+    data[10] = 0;
+    match reader.decode(&data[..written]) {
+        Err(FrameError::ChecksumMismatch { .. }) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn test_framing_limits() {
+    let payload = [0u8; 32];
+    let mut data = [0u8; 64];
+
+    let config = FrameConfig::new(LengthPrefix::U16Le).max_frame_size(16);
+    let writer = FrameWriter::new(config);
+    assert_eq!(
+        writer.write(&payload[..], &mut data[..]),
+        Err(FrameError::TooLarge { length: 32, max: 16 })
+    );
+    assert_eq!(
+        writer.write(&payload[..16], &mut data[..8]),
+        Err(FrameError::BufferTooSmall { required: 18 })
+    );
+
+    // The reader rejects the frame from the header alone.
+    let reader = FrameReader::new(config);
+    assert_eq!(
+        reader.decode(&[32, 0]),
+        Err(FrameError::TooLarge { length: 32, max: 16 })
+    );
+
+    let reader = FrameReader::new(FrameConfig::new(LengthPrefix::Vlq));
+    assert_eq!(reader.decode(&[0xFF; 4]), Ok(None));
+    assert_eq!(reader.decode(&[0xFF; 5]), Err(FrameError::MalformedLength));
+    assert_eq!(reader.decode(&[0x80, 0x80, 0x80, 0x80, 0x10]), Err(FrameError::MalformedLength));
+
+    // The largest 32-bit length, plus its header, doesn't fit a 32-bit
+    // `usize`.
+    let reader = FrameReader::new(FrameConfig::new(LengthPrefix::U32Le));
+    if cfg!(target_pointer_width="32") {
+        assert_eq!(reader.decode(&[0xFF; 4]), Err(FrameError::TooLarge { length: 0xFFFFFFFF, max: 0xFFFFFFFF }));
+    } else {
+        assert_eq!(reader.decode(&[0xFF; 4]), Ok(None));
+    }
+}
+
+#[cfg(feature="std")]
+#[test]
+fn test_framing_stream() {
+    let config = FrameConfig::new(LengthPrefix::U32Be).crc32(true);
+    let writer = FrameWriter::new(config);
+    let mut reader = FrameReader::new(config);
+
+    let mut stream = Vec::new();
+    writer.write_to_vec(b"first", &mut stream).unwrap();
+    writer.write_to_vec(b"", &mut stream).unwrap();
+    writer.write_to_vec(b"third", &mut stream).unwrap();
+
+    // Feed the stream a few bytes at a time.
+    let mut frames = Vec::new();
+    for chunk in stream.chunks(3) {
+        reader.push(chunk);
+        while let Some(frame) = reader.next_frame().unwrap() {
+            frames.push(frame);
+        }
+    }
+
+    assert_eq!(frames, vec![b"first".to_vec(), vec![], b"third".to_vec()]);
+    assert_eq!(reader.buffered(), 0);
+}
@@ -0,0 +1,356 @@
+//! Half precision (`F16`, IEEE 754 binary16) and bfloat16 (`Bf16`) floats.
+//!
+//! Both types are stored as their bits.  Converting from `f32` rounds to
+//! nearest, ties to even; values too large become infinity, values too
+//! small become (signed) zero or, for `F16`, subnormals.  NaNs stay NaN,
+//! keeping as much of the payload as fits (and coming back quiet).
+//! Converting to `f32` is always exact.
+//!
+//! The `IO` impls copy the bits in native byte order, as the other `IO`
+//! impls do; `to_le_bytes` and friends give an explicit order.  Tensors
+//! are better served by `encode_f16` and `decode_f16` (and the `bf16`
+//! equivalents), which convert whole slices of `f32` to and from bytes
+//! in the given order.
+
+use IO;
+
+/// The byte order used by the slice conversions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// Least significant byte first.
+    Little,
+    /// Most significant byte first.
+    Big,
+}
+
+/// Errors produced by the slice conversions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HalfError {
+    /// The sink is too small.
+    BufferTooSmall {
+        /// The number of bytes required.
+        required: usize,
+    },
+    /// The source is shorter than the values being read.
+    Eof,
+}
+
+macro_rules! half_type {
+    ($name:ident) => {
+        impl $name {
+            /// Creates a value from its bits.
+            pub fn from_bits(bits: u16) -> $name {
+                $name(bits)
+            }
+
+            /// The bits of the value.
+            pub fn to_bits(self) -> u16 {
+                self.0
+            }
+
+            /// Creates a value from its bytes in little endian order.
+            pub fn from_le_bytes(bytes: [u8; 2]) -> $name {
+                $name(u16::from_le_bytes(bytes))
+            }
+
+            /// Creates a value from its bytes in big endian order.
+            pub fn from_be_bytes(bytes: [u8; 2]) -> $name {
+                $name(u16::from_be_bytes(bytes))
+            }
+
+            /// The bytes of the value in little endian order.
+            pub fn to_le_bytes(self) -> [u8; 2] {
+                self.0.to_le_bytes()
+            }
+
+            /// The bytes of the value in big endian order.
+            pub fn to_be_bytes(self) -> [u8; 2] {
+                self.0.to_be_bytes()
+            }
+
+            /// Converts the value to an `f64` (exactly).
+            pub fn to_f64(self) -> f64 {
+                self.to_f32() as f64
+            }
+
+            /// Returns true if the value is a NaN.
+            pub fn is_nan(self) -> bool {
+                self.to_f32().is_nan()
+            }
+        }
+
+        impl From<f32> for $name {
+            fn from(value: f32) -> $name {
+                $name::from_f32(value)
+            }
+        }
+
+        impl From<$name> for f32 {
+            fn from(value: $name) -> f32 {
+                value.to_f32()
+            }
+        }
+
+        impl From<$name> for f64 {
+            fn from(value: $name) -> f64 {
+                value.to_f64()
+            }
+        }
+
+        impl IO for $name {
+            fn fio_read(&mut self, source: &[u8]) -> usize {
+                let mut bytes = [0u8; 2];
+                let read = bytes[..].fio_read(source);
+                self.0 = u16::from_ne_bytes(bytes);
+                read
+            }
+
+            fn fio_write(&mut self, sink: &mut [u8]) -> usize {
+                self.0.to_ne_bytes()[..].fio_write(sink)
+            }
+        }
+    };
+}
+
+macro_rules! slice_conversions {
+    ($name:ident, $encode:ident, $decode:ident, $what:expr) => {
+        #[doc = concat!("Encodes `values` as ", $what, " to offset zero of `sink`, in `order`.")]
+        ///
+        /// The return value is the number of bytes written.
+        pub fn $encode(values: &[f32], sink: &mut [u8], order: ByteOrder) -> Result<usize, HalfError> {
+            let required = values.len() * 2;
+            if sink.len() < required {
+                return Err(HalfError::BufferTooSmall { required });
+            }
+            // The order is matched outside the loops to keep them tight.
+            let chunks = sink[..required].chunks_exact_mut(2).zip(values.iter());
+            match order {
+                ByteOrder::Little => for (b, v) in chunks {
+                    b.copy_from_slice(&$name::from_f32(*v).to_le_bytes());
+                },
+                ByteOrder::Big => for (b, v) in chunks {
+                    b.copy_from_slice(&$name::from_f32(*v).to_be_bytes());
+                },
+            }
+            Ok(required)
+        }
+
+        #[doc = concat!("Decodes ", $what, " in `order` from offset zero of `source`, filling `values`.")]
+        ///
+        /// The return value is the number of bytes read.
+        pub fn $decode(source: &[u8], values: &mut [f32], order: ByteOrder) -> Result<usize, HalfError> {
+            let required = values.len() * 2;
+            if source.len() < required {
+                return Err(HalfError::Eof);
+            }
+            let chunks = source[..required].chunks_exact(2).zip(values.iter_mut());
+            match order {
+                ByteOrder::Little => for (b, v) in chunks {
+                    *v = $name::from_le_bytes([b[0], b[1]]).to_f32();
+                },
+                ByteOrder::Big => for (b, v) in chunks {
+                    *v = $name::from_be_bytes([b[0], b[1]]).to_f32();
+                },
+            }
+            Ok(required)
+        }
+    };
+}
+
+// ----------------------------------------------------------------------
+// binary16
+// ----------------------------------------------------------------------
+
+/// An IEEE 754 half precision float: 1 sign bit, 5 exponent bits and 10
+/// mantissa bits.
+#[cfg(feature="io-f16")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct F16(u16);
+
+#[cfg(feature="io-f16")]
+half_type!(F16);
+
+#[cfg(feature="io-f16")]
+impl F16 {
+    /// Converts an `f32`, rounding to nearest (ties to even).
+    pub fn from_f32(value: f32) -> F16 {
+        let bits = value.to_bits();
+        let sign = ((bits >> 16) & 0x8000) as u16;
+        let exponent = ((bits >> 23) & 0xFF) as i32;
+        let mantissa = bits & 0x7F_FFFF;
+
+        if exponent == 0xFF {
+            return F16(match mantissa {
+                0 => sign | 0x7C00,
+                _ => sign | 0x7E00 | (mantissa >> 13) as u16,
+            });
+        }
+
+        let exponent = exponent - 127 + 15;
+        if exponent >= 0x1F {
+            return F16(sign | 0x7C00);
+        }
+        if exponent <= 0 {
+            // Subnormal (or zero).  Anything below half the smallest
+            // subnormal rounds to zero.
+            if exponent < -10 {
+                return F16(sign);
+            }
+            let significand = mantissa | 0x80_0000;
+            let shift = (14 - exponent) as u32;
+            let half = significand >> shift;
+            let rest = significand & ((1 << shift) - 1);
+            let halfway = 1 << (shift - 1);
+            let round = rest > halfway || (rest == halfway && half & 1 == 1);
+            // Rounding up may carry into the exponent, which is correct.
+            return F16(sign | (half + round as u32) as u16);
+        }
+
+        let half = ((exponent as u32) << 10) | (mantissa >> 13);
+        let rest = mantissa & 0x1FFF;
+        let round = rest > 0x1000 || (rest == 0x1000 && half & 1 == 1);
+        // As above; the largest finite value rounds up to infinity.
+        F16(sign | (half + round as u32) as u16)
+    }
+
+    /// Converts the value to an `f32` (exactly).
+    pub fn to_f32(self) -> f32 {
+        let sign = ((self.0 & 0x8000) as u32) << 16;
+        let exponent = ((self.0 >> 10) & 0x1F) as u32;
+        let mantissa = (self.0 & 0x3FF) as u32;
+
+        let bits = match exponent {
+            0 if mantissa == 0 => sign,
+            0 => {
+                // Subnormal; normalise it.
+                let shift = mantissa.leading_zeros() - 21;
+                sign | ((113 - shift) << 23) | (((mantissa << shift) & 0x3FF) << 13)
+            }
+            0x1F => sign | 0x7F80_0000 | (mantissa << 13),
+            _ => sign | ((exponent + 112) << 23) | (mantissa << 13),
+        };
+        f32::from_bits(bits)
+    }
+}
+
+#[cfg(feature="io-f16")]
+slice_conversions!(F16, encode_f16, decode_f16, "half precision floats");
+
+// ----------------------------------------------------------------------
+// bfloat16
+// ----------------------------------------------------------------------
+
+/// A bfloat16: the top half of an `f32`, with 1 sign bit, 8 exponent
+/// bits and 7 mantissa bits.
+#[cfg(feature="io-bf16")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct Bf16(u16);
+
+#[cfg(feature="io-bf16")]
+half_type!(Bf16);
+
+#[cfg(feature="io-bf16")]
+impl Bf16 {
+    /// Converts an `f32`, rounding to nearest (ties to even).
+    pub fn from_f32(value: f32) -> Bf16 {
+        let bits = value.to_bits();
+        if value.is_nan() {
+            return Bf16((bits >> 16) as u16 | 0x0040);
+        }
+        // Overflow carries into the exponent, giving infinity.
+        let round = 0x7FFF + ((bits >> 16) & 1);
+        Bf16((bits.wrapping_add(round) >> 16) as u16)
+    }
+
+    /// Converts the value to an `f32` (exactly).
+    pub fn to_f32(self) -> f32 {
+        f32::from_bits((self.0 as u32) << 16)
+    }
+}
+
+#[cfg(feature="io-bf16")]
+slice_conversions!(Bf16, encode_bf16, decode_bf16, "bfloat16s");
+
+#[cfg(feature="io-f16")]
+#[test]
+fn test_half_f16() {
+    for &(value, bits) in [
+        (0.0f32, 0x0000u16), (-0.0, 0x8000), (1.0, 0x3C00), (-2.0, 0xC000),
+        (65504.0, 0x7BFF), (6.1035156e-5, 0x0400), (5.9604645e-8, 0x0001),
+        (f32::INFINITY, 0x7C00), (f32::NEG_INFINITY, 0xFC00),
+    ].iter() {
+        assert_eq!(F16::from_f32(value).to_bits(), bits, "{}", value);
+        assert_eq!(F16::from_bits(bits).to_f32(), value);
+    }
+    assert!(F16::from_f32(f32::NAN).is_nan());
+    assert!(F16::from_bits(0x7C01).to_f32().is_nan());
+
+    // Every f16 survives a round trip through f32.
+    for bits in 0..=0xFFFFu16 {
+        let value = F16::from_bits(bits);
+        if !value.is_nan() {
+            assert_eq!(F16::from_f32(value.to_f32()), value);
+        }
+    }
+}
+
+#[cfg(feature="io-f16")]
+#[test]
+fn test_half_f16_rounding() {
+    // 1 + 2^-11 is halfway between 1 and the next f16; ties go to even.
+    assert_eq!(F16::from_f32(1.0 + 0.00048828125).to_bits(), 0x3C00);
+    assert_eq!(F16::from_f32(1.0 + 3.0 * 0.00048828125).to_bits(), 0x3C02);
+    assert_eq!(F16::from_f32(1.0 + 0.0004885).to_bits(), 0x3C01);
+    // Overflow, and the largest value which still rounds down.
+    assert_eq!(F16::from_f32(65520.0).to_bits(), 0x7C00);
+    assert_eq!(F16::from_f32(65519.0).to_bits(), 0x7BFF);
+    // Subnormals: half the smallest rounds to zero, just over rounds up.
+    assert_eq!(F16::from_f32(2.9802322e-8).to_bits(), 0x0000);
+    assert_eq!(F16::from_f32(2.9802326e-8).to_bits(), 0x0001);
+    assert_eq!(F16::from_f32(-1e-10).to_bits(), 0x8000);
+    // The largest subnormal rounds up into the normals.
+    assert_eq!(F16::from_f32(6.1032e-5).to_bits(), 0x0400);
+}
+
+#[cfg(feature="io-bf16")]
+#[test]
+fn test_half_bf16() {
+    assert_eq!(Bf16::from_f32(1.0).to_bits(), 0x3F80);
+    assert_eq!(Bf16::from_f32(-0.0).to_bits(), 0x8000);
+    assert_eq!(Bf16::from_f32(f32::from_bits(0x7F7F_7FFF)).to_bits(), 0x7F7F);
+    assert_eq!(Bf16::from_f32(f32::MAX).to_bits(), 0x7F80);
+    assert_eq!(Bf16::from_bits(0x4049).to_f32(), 3.140625);
+    // Ties to even.
+    assert_eq!(Bf16::from_f32(f32::from_bits(0x3F80_8000)).to_bits(), 0x3F80);
+    assert_eq!(Bf16::from_f32(f32::from_bits(0x3F81_8000)).to_bits(), 0x3F82);
+    // NaNs whose payload is in the low bits stay NaN.
+    assert!(Bf16::from_f32(f32::from_bits(0x7F80_0001)).is_nan());
+}
+
+#[cfg(all(feature="io-f16", feature="io-bf16"))]
+#[test]
+fn test_half_slices() {
+    let values = [1.0f32, -2.0, 0.5];
+    let mut data = [0u8; 8];
+    assert_eq!(encode_f16(&values[..], &mut data[..], ByteOrder::Little), Ok(6));
+    assert_eq!(&data[..6], &[0x00, 0x3C, 0x00, 0xC0, 0x00, 0x38]);
+    assert_eq!(encode_bf16(&values[..], &mut data[..], ByteOrder::Big), Ok(6));
+    assert_eq!(&data[..6], &[0x3F, 0x80, 0xC0, 0x00, 0x3F, 0x00]);
+
+    let mut decoded = [0f32; 3];
+    assert_eq!(decode_bf16(&data[..6], &mut decoded[..], ByteOrder::Big), Ok(6));
+    assert_eq!(decoded, values);
+    assert_eq!(decode_bf16(&data[..5], &mut decoded[..], ByteOrder::Big), Err(HalfError::Eof));
+    assert_eq!(
+        encode_f16(&values[..], &mut data[..4], ByteOrder::Little),
+        Err(HalfError::BufferTooSmall { required: 6 })
+    );
+
+    let mut value = F16::from_f32(1.5);
+    let mut copy = F16::default();
+    assert_eq!(value.fio_write(&mut data[..]), 2);
+    assert_eq!(copy.fio_read(&data[..]), 2);
+    assert_eq!(copy.to_f32(), 1.5);
+}
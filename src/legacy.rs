@@ -0,0 +1,449 @@
+//! Legacy (pre-IEEE and extended) floating point formats, converted to
+//! and from `f64`.
+//!
+//! * IBM System/360 hexadecimal floats (`IbmSingle`, `IbmDouble`), as
+//!   in SEG-Y, stored big endian.  The exponent is base 16, so the
+//!   single format keeps between 21 and 24 significant bits depending on
+//!   the value, and the double format up to 56 (more than an `f64`).
+//! * VAX `F`, `D` and `G` floats, stored in the VAX's order: 16-bit
+//!   little endian words, most significant word first.  `F` and `D`
+//!   share an 8-bit exponent, so `D` has more precision than an `f64`
+//!   but far less range; `G` matches an `f64` bit for bit, save for the
+//!   smallest values.
+//! * x87 80-bit extended precision (`X87`), stored little endian, with a
+//!   64-bit significand.  Every `f64` fits exactly.
+//!
+//! Reads round to nearest (ties to even) where the format holds more
+//! precision than an `f64`, and overflow to infinity (or underflow to
+//! zero) where it has more range.  Writes round to nearest too.  IBM and
+//! VAX have no infinities, NaNs or subnormals: writing a value too large
+//! is `Overflow`, a value too small becomes zero, infinities and NaNs are
+//! `NotFinite`, and -0.0 becomes 0.0 for VAX.  A VAX reserved operand
+//! (negative zero) is `Reserved` on read.
+
+/// A floating point format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// IBM single precision (4 bytes).
+    IbmSingle,
+    /// IBM double precision (8 bytes).
+    IbmDouble,
+    /// VAX F_floating (4 bytes).
+    VaxF,
+    /// VAX D_floating (8 bytes).
+    VaxD,
+    /// VAX G_floating (8 bytes).
+    VaxG,
+    /// x87 extended precision (10 bytes).
+    X87,
+}
+
+/// Errors produced reading and writing legacy floats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegacyFloatError {
+    /// The sink is too small.
+    BufferTooSmall {
+        /// The number of bytes required.
+        required: usize,
+    },
+    /// The source is shorter than the value.
+    Eof,
+    /// A VAX reserved operand.
+    Reserved,
+    /// An infinity or NaN, which the format can't hold.
+    NotFinite,
+    /// The value is too large for the format.
+    Overflow,
+}
+
+// ----------------------------------------------------------------------
+// Shared helpers
+// ----------------------------------------------------------------------
+
+// Splits a finite, nonzero f64 into its sign, significand and exponent,
+// so that the magnitude is `significand * 2^exponent`.
+fn split(value: f64) -> (bool, u64, i32) {
+    let bits = value.to_bits();
+    let exponent = ((bits >> 52) & 0x7FF) as i32;
+    let mantissa = bits & 0xF_FFFF_FFFF_FFFF;
+    let (significand, exponent) = match exponent {
+        0 => (mantissa, -1074),
+        _ => (mantissa | (1 << 52), exponent - 1075),
+    };
+    (bits >> 63 == 1, significand, exponent)
+}
+
+// Rounds a nonzero `significand * 2^exponent` to exactly `bits`
+// significant bits (to nearest, ties to even).
+fn round(significand: u64, exponent: i32, bits: u32) -> (u64, i32) {
+    let length = 64 - significand.leading_zeros();
+    if length <= bits {
+        let shift = bits - length;
+        return (significand << shift, exponent - shift as i32);
+    }
+    let drop = length - bits;
+    let kept = significand >> drop;
+    let rest = significand & ((1 << drop) - 1);
+    let half = 1 << (drop - 1);
+    let kept = kept + (rest > half || (rest == half && kept & 1 == 1)) as u64;
+    if kept >> bits != 0 {
+        (kept >> 1, exponent + drop as i32 + 1)
+    } else {
+        (kept, exponent + drop as i32)
+    }
+}
+
+// Builds the f64 nearest `significand * 2^exponent`, rounding once (even
+// where the result is subnormal) and overflowing to infinity.
+fn compose(negative: bool, significand: u64, exponent: i32) -> f64 {
+    let sign = (negative as u64) << 63;
+    if significand == 0 {
+        return f64::from_bits(sign);
+    }
+    let shift = significand.leading_zeros();
+    let significand = (significand << shift) as u128;
+    // The value is in [2^top, 2^(top + 1)).
+    let top = exponent - shift as i32 + 63;
+    if top > 1023 {
+        return f64::from_bits(sign | 0x7FF0_0000_0000_0000);
+    }
+
+    // Keep 53 bits, or fewer for subnormals.
+    let drop = if top >= -1022 { 11 } else { 11 + (-1022 - top) as u32 };
+    if drop > 64 {
+        // Below half the smallest subnormal.
+        return f64::from_bits(sign);
+    }
+    let kept = significand >> drop;
+    let rest = significand & ((1 << drop) - 1);
+    let half = 1 << (drop - 1);
+    let kept = (kept + (rest > half || (rest == half && kept & 1 == 1)) as u128) as u64;
+
+    if top < -1022 {
+        // A carry into bit 52 gives the smallest normal, as it should.
+        return f64::from_bits(sign | kept);
+    }
+    let (kept, top) = if kept >> 53 != 0 { (kept >> 1, top + 1) } else { (kept, top) };
+    if top > 1023 {
+        return f64::from_bits(sign | 0x7FF0_0000_0000_0000);
+    }
+    f64::from_bits(sign | (((top + 1023) as u64) << 52) | (kept & 0xF_FFFF_FFFF_FFFF))
+}
+
+// ----------------------------------------------------------------------
+// IBM
+// ----------------------------------------------------------------------
+
+// The value is `0.fraction * 16^(exponent - 64)`, normalised so the
+// first hex digit of the fraction is nonzero.
+fn ibm_decode(bits: u64, width: usize) -> f64 {
+    let fraction_bits = (width * 8 - 8) as u32;
+    let negative = bits >> (width * 8 - 1) == 1;
+    let exponent = ((bits >> fraction_bits) & 0x7F) as i32;
+    let fraction = bits & ((1 << fraction_bits) - 1);
+    compose(negative, fraction, 4 * (exponent - 64) - fraction_bits as i32)
+}
+
+fn ibm_encode(value: f64, width: usize) -> Result<u64, LegacyFloatError> {
+    let fraction_bits = (width * 8 - 8) as u32;
+    let sign = ((value.is_sign_negative()) as u64) << (width * 8 - 1);
+    if value == 0.0 {
+        return Ok(sign);
+    }
+    let (_, significand, exponent) = split(value);
+
+    // The hex exponent leaves room for between 0 and 3 fewer bits than
+    // the fraction has, so round (once) to what's left.
+    let top = exponent + (64 - significand.leading_zeros()) as i32;
+    let mut hex = (top + 3).div_euclid(4);
+    let spare = (4 * hex - top) as u32;
+    let (significand, exponent) = round(significand, exponent, fraction_bits - spare);
+    let mut fraction = significand << (exponent + fraction_bits as i32 - 4 * hex);
+    if fraction >> fraction_bits != 0 {
+        // Rounding carried into a new hex digit.
+        fraction >>= 4;
+        hex += 1;
+    }
+
+    match hex + 64 {
+        e if e > 127 => Err(LegacyFloatError::Overflow),
+        e if e < 0 => Ok(sign),
+        e => Ok(sign | ((e as u64) << fraction_bits) | fraction),
+    }
+}
+
+// ----------------------------------------------------------------------
+// VAX
+// ----------------------------------------------------------------------
+
+// (significand bits including the hidden bit, exponent bits, bias)
+fn vax_layout(format: Format) -> (u32, u32, i32) {
+    match format {
+        Format::VaxF => (24, 8, 128),
+        Format::VaxD => (56, 8, 128),
+        _ => (53, 11, 1024),
+    }
+}
+
+// The value is `0.1fraction * 2^(exponent - bias)`.
+fn vax_decode(bits: u64, format: Format, width: usize) -> Result<f64, LegacyFloatError> {
+    let (precision, exponent_bits, bias) = vax_layout(format);
+    let negative = bits >> (width * 8 - 1) == 1;
+    let exponent = ((bits >> (precision - 1)) & ((1 << exponent_bits) - 1)) as i32;
+    if exponent == 0 {
+        // Zero, whatever the fraction, unless the sign is set.
+        return if negative { Err(LegacyFloatError::Reserved) } else { Ok(0.0) };
+    }
+    let significand = (bits & ((1 << (precision - 1)) - 1)) | (1 << (precision - 1));
+    Ok(compose(negative, significand, exponent - bias - precision as i32))
+}
+
+fn vax_encode(value: f64, format: Format, width: usize) -> Result<u64, LegacyFloatError> {
+    let (precision, exponent_bits, bias) = vax_layout(format);
+    if value == 0.0 {
+        return Ok(0);
+    }
+    let (negative, significand, exponent) = split(value);
+    let (significand, exponent) = round(significand, exponent, precision);
+    let exponent = exponent + precision as i32 + bias;
+    if exponent >= 1 << exponent_bits {
+        return Err(LegacyFloatError::Overflow);
+    }
+    if exponent < 1 {
+        return Ok(0);
+    }
+    Ok(((negative as u64) << (width * 8 - 1))
+        | ((exponent as u64) << (precision - 1))
+        | (significand & ((1 << (precision - 1)) - 1)))
+}
+
+// VAX values are little endian 16-bit words, the most significant first.
+fn vax_bits(source: &[u8]) -> u64 {
+    source.chunks_exact(2).fold(0, |bits, w| (bits << 16) | u16::from_le_bytes([w[0], w[1]]) as u64)
+}
+
+fn vax_bytes(bits: u64, sink: &mut [u8]) {
+    let words = sink.len() / 2;
+    for (i, w) in sink.chunks_exact_mut(2).enumerate() {
+        w.copy_from_slice(&((bits >> (16 * (words - 1 - i))) as u16).to_le_bytes());
+    }
+}
+
+// ----------------------------------------------------------------------
+// x87
+// ----------------------------------------------------------------------
+
+fn x87_decode(source: &[u8]) -> f64 {
+    let mut significand = [0u8; 8];
+    significand.copy_from_slice(&source[..8]);
+    let significand = u64::from_le_bytes(significand);
+    let top = u16::from_le_bytes([source[8], source[9]]);
+    let negative = top >> 15 == 1;
+    let exponent = (top & 0x7FFF) as i32;
+
+    match exponent {
+        0x7FFF => {
+            let sign = (negative as u64) << 63;
+            if significand << 1 == 0 {
+                f64::from_bits(sign | 0x7FF0_0000_0000_0000)
+            } else {
+                // Keep what fits of the payload; the result is quiet.
+                let payload = (significand >> 11) & 0xF_FFFF_FFFF_FFFF;
+                f64::from_bits(sign | 0x7FF8_0000_0000_0000 | payload)
+            }
+        }
+        // Denormals share the smallest exponent.
+        0 => compose(negative, significand, 1 - 16383 - 63),
+        _ => compose(negative, significand, exponent - 16383 - 63),
+    }
+}
+
+fn x87_encode(value: f64, sink: &mut [u8]) {
+    let sign = (value.is_sign_negative() as u16) << 15;
+    let (top, significand) = if value.is_nan() {
+        let payload = value.to_bits() & 0xF_FFFF_FFFF_FFFF;
+        (sign | 0x7FFF, 0xC000_0000_0000_0000 | (payload << 11))
+    } else if value.is_infinite() {
+        (sign | 0x7FFF, 0x8000_0000_0000_0000)
+    } else if value == 0.0 {
+        (sign, 0)
+    } else {
+        // Every f64, subnormals included, is a normal x87 value.
+        let (_, significand, exponent) = split(value);
+        let (significand, exponent) = round(significand, exponent, 64);
+        (sign | (exponent + 63 + 16383) as u16, significand)
+    };
+    sink[..8].copy_from_slice(&significand.to_le_bytes());
+    sink[8..10].copy_from_slice(&top.to_le_bytes());
+}
+
+// ----------------------------------------------------------------------
+// Format
+// ----------------------------------------------------------------------
+
+impl Format {
+    /// The width of a value in bytes.
+    pub fn width(&self) -> usize {
+        match *self {
+            Format::IbmSingle | Format::VaxF => 4,
+            Format::IbmDouble | Format::VaxD | Format::VaxG => 8,
+            Format::X87 => 10,
+        }
+    }
+
+    /// Reads a value at offset zero of `source`.
+    ///
+    /// Returns the value, and the number of bytes read.
+    pub fn read(&self, source: &[u8]) -> Result<(f64, usize), LegacyFloatError> {
+        let width = self.width();
+        if source.len() < width {
+            return Err(LegacyFloatError::Eof);
+        }
+        let source = &source[..width];
+        let value = match *self {
+            Format::IbmSingle | Format::IbmDouble => {
+                let bits = source.iter().fold(0u64, |bits, b| (bits << 8) | *b as u64);
+                ibm_decode(bits, width)
+            }
+            Format::VaxF | Format::VaxD | Format::VaxG => vax_decode(vax_bits(source), *self, width)?,
+            Format::X87 => x87_decode(source),
+        };
+        Ok((value, width))
+    }
+
+    /// Writes `value` to offset zero of `sink`.
+    ///
+    /// The return value is the number of bytes written.
+    pub fn write(&self, value: f64, sink: &mut [u8]) -> Result<usize, LegacyFloatError> {
+        let width = self.width();
+        if sink.len() < width {
+            return Err(LegacyFloatError::BufferTooSmall { required: width });
+        }
+        let sink = &mut sink[..width];
+        if *self == Format::X87 {
+            x87_encode(value, sink);
+            return Ok(width);
+        }
+        if !value.is_finite() {
+            return Err(LegacyFloatError::NotFinite);
+        }
+        match *self {
+            Format::IbmSingle | Format::IbmDouble => {
+                let bits = ibm_encode(value, width)?;
+                sink.copy_from_slice(&bits.to_be_bytes()[8 - width..]);
+            }
+            _ => vax_bytes(vax_encode(value, *self, width)?, sink),
+        }
+        Ok(width)
+    }
+}
+
+#[test]
+fn test_legacy_ibm() {
+    let mut data = [0u8; 8];
+    let single = Format::IbmSingle;
+    assert_eq!(single.write(-118.625, &mut data[..]), Ok(4));
+    assert_eq!(&data[..4], &[0xC2, 0x76, 0xA0, 0x00]);
+    assert_eq!(single.read(&data[..]), Ok((-118.625, 4)));
+    assert_eq!(single.write(0.1, &mut data[..]), Ok(4));
+    assert_eq!(&data[..4], &[0x40, 0x19, 0x99, 0x9A]);
+    assert_eq!(single.read(&data[..]), Ok((0x19999A as f64 / 2f64.powi(24), 4)));
+    assert_eq!(single.write(1.0, &mut data[..]), Ok(4));
+    assert_eq!(&data[..4], &[0x41, 0x10, 0x00, 0x00]);
+    // Rounding carries into a new hex digit: 0xFFFFFF8 rounds up to 16^7.
+    assert_eq!(single.write(268435448.0, &mut data[..]), Ok(4));
+    assert_eq!(&data[..4], &[0x48, 0x10, 0x00, 0x00]);
+
+    // The double format holds 56 bits, and an f64 53, so 0.1 survives.
+    let double = Format::IbmDouble;
+    assert_eq!(double.write(0.1, &mut data[..]), Ok(8));
+    assert_eq!(data, [0x40, 0x19, 0x99, 0x99, 0x99, 0x99, 0x99, 0x9A]);
+    assert_eq!(double.read(&data[..]), Ok((0.1, 8)));
+    // Reads of the extra bits round to nearest.
+    assert_eq!(double.read(&[0x41, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]), Ok((16.0, 8)));
+    assert_eq!(double.read(&[0x41, 0x80, 0, 0, 0, 0, 0, 0x04]), Ok((8.0, 8)));
+    assert_eq!(double.read(&[0x41, 0x80, 0, 0, 0, 0, 0, 0x0C]), Ok((8.0 + 2f64.powi(-48), 8)));
+}
+
+#[test]
+fn test_legacy_ibm_limits() {
+    let mut data = [0u8; 4];
+    let single = Format::IbmSingle;
+    let max = (1.0 - 2f64.powi(-24)) * 2f64.powi(252);
+    assert_eq!(single.write(max, &mut data[..]), Ok(4));
+    assert_eq!(&data[..], &[0x7F, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(single.write(7.3e75, &mut data[..]), Err(LegacyFloatError::Overflow));
+    assert_eq!(single.write(1e-80, &mut data[..]), Ok(4));
+    assert_eq!(data, [0, 0, 0, 0]);
+    assert_eq!(single.write(-0.0, &mut data[..]), Ok(4));
+    assert_eq!(data, [0x80, 0, 0, 0]);
+    assert_eq!(single.write(f64::NAN, &mut data[..]), Err(LegacyFloatError::NotFinite));
+    assert_eq!(single.write(1.0, &mut data[..3]), Err(LegacyFloatError::BufferTooSmall { required: 4 }));
+    assert_eq!(single.read(&data[..3]), Err(LegacyFloatError::Eof));
+    // The smallest normalised value, and an unnormalised one.
+    assert_eq!(single.read(&[0x00, 0x10, 0x00, 0x00]), Ok((16f64.powi(-65), 4)));
+    assert_eq!(single.read(&[0x41, 0x00, 0x00, 0x01]), Ok((2f64.powi(-20), 4)));
+}
+
+#[test]
+fn test_legacy_vax() {
+    let mut data = [0u8; 8];
+    assert_eq!(Format::VaxF.write(1.0, &mut data[..]), Ok(4));
+    assert_eq!(&data[..4], &[0x80, 0x40, 0x00, 0x00]);
+    assert_eq!(Format::VaxF.write(-0.75, &mut data[..]), Ok(4));
+    assert_eq!(&data[..4], &[0x40, 0xC0, 0x00, 0x00]);
+    assert_eq!(Format::VaxF.read(&data[..]), Ok((-0.75, 4)));
+    assert_eq!(Format::VaxD.write(1.0, &mut data[..]), Ok(8));
+    assert_eq!(data, [0x80, 0x40, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(Format::VaxG.write(1.0, &mut data[..]), Ok(8));
+    assert_eq!(data, [0x10, 0x40, 0, 0, 0, 0, 0, 0]);
+
+    for &value in [0.1, -123.456, 1e30, 3.0e-39].iter() {
+        for format in [Format::VaxD, Format::VaxG].iter() {
+            assert_eq!(format.write(value, &mut data[..]), Ok(8));
+            assert_eq!(format.read(&data[..]), Ok((value, 8)), "{:?} {}", format, value);
+        }
+    }
+    assert_eq!(Format::VaxF.write(0.1, &mut data[..]), Ok(4));
+    assert_eq!(Format::VaxF.read(&data[..]), Ok((0.1f32 as f64, 4)));
+
+    // F and D share their range; G has the range of an f64.
+    assert_eq!(Format::VaxD.write(1e39, &mut data[..]), Err(LegacyFloatError::Overflow));
+    assert_eq!(Format::VaxG.write(1e300, &mut data[..]), Ok(8));
+    assert_eq!(Format::VaxG.write(f64::MAX, &mut data[..]), Err(LegacyFloatError::Overflow));
+    assert_eq!(Format::VaxD.write(1e-40, &mut data[..]), Ok(8));
+    assert_eq!(data, [0; 8]);
+    assert_eq!(Format::VaxF.write(-0.0, &mut data[..]), Ok(4));
+    assert_eq!(&data[..4], &[0; 4]);
+    assert_eq!(Format::VaxF.write(f64::INFINITY, &mut data[..]), Err(LegacyFloatError::NotFinite));
+    assert_eq!(Format::VaxF.read(&[0x00, 0x80, 0x00, 0x00]), Err(LegacyFloatError::Reserved));
+    assert_eq!(Format::VaxF.read(&[0x00, 0x00, 0x12, 0x34]), Ok((0.0, 4)));
+}
+
+#[test]
+fn test_legacy_x87() {
+    let mut data = [0u8; 10];
+    let x87 = Format::X87;
+    assert_eq!(x87.write(1.0, &mut data[..]), Ok(10));
+    assert_eq!(data, [0, 0, 0, 0, 0, 0, 0, 0x80, 0xFF, 0x3F]);
+    assert_eq!(x87.write(-2.5, &mut data[..]), Ok(10));
+    assert_eq!(data, [0, 0, 0, 0, 0, 0, 0, 0xA0, 0x00, 0xC0]);
+
+    for &value in [0.1, f64::MAX, f64::MIN_POSITIVE, 5e-324, -0.0, f64::NEG_INFINITY].iter() {
+        assert_eq!(x87.write(value, &mut data[..]), Ok(10));
+        let (read, _) = x87.read(&data[..]).unwrap();
+        assert_eq!(read.to_bits(), value.to_bits(), "{}", value);
+    }
+    assert_eq!(x87.write(f64::NAN, &mut data[..]), Ok(10));
+    assert!(x87.read(&data[..]).unwrap().0.is_nan());
+
+    // Out of f64 range, and the 64-bit significand rounding to 53 bits.
+    assert_eq!(x87.read(&[0, 0, 0, 0, 0, 0, 0, 0x80, 0xFF, 0x7F]), Ok((f64::INFINITY, 10)));
+    assert_eq!(x87.read(&[0, 0, 0, 0, 0, 0, 0, 0x80, 0x00, 0x00]), Ok((0.0, 10)));
+    assert_eq!(x87.read(&[0x00, 0x04, 0, 0, 0, 0, 0, 0x80, 0xFF, 0x3F]), Ok((1.0, 10)));
+    assert_eq!(x87.read(&[0x00, 0x0C, 0, 0, 0, 0, 0, 0x80, 0xFF, 0x3F]), Ok((1.0 + 2f64.powi(-51), 10)));
+    // Halfway between the two smallest f64 subnormals, ties to even.
+    let half = 16383 - 1074;
+    let top = (half as u16).to_le_bytes();
+    assert_eq!(x87.read(&[0, 0, 0, 0, 0, 0, 0, 0xC0, top[0], top[1]]), Ok((1e-323, 10)));
+}
@@ -30,6 +30,7 @@ extern crate tempdir;
         feature="io-f64",
         feature="io-i64",
         feature="io-u64",
+        feature="std",
     )
 )]
 use core::mem::size_of;
@@ -46,6 +47,7 @@ use core::mem::size_of;
         feature="io-f64",
         feature="io-i64",
         feature="io-u64",
+        feature="std",
     )
 )]
 use core::slice::from_raw_parts_mut;
@@ -63,9 +65,41 @@ use std::io::Write as StandardWrite;
 #[cfg(feature="num-traits")]
 extern crate num_traits;
 
-#[cfg(feature="num-traits")]
+#[cfg(any(feature="io-string", feature="vlq-string", feature="heapless", feature="arrayvec"))]
 use num_traits::{cast::{FromPrimitive, ToPrimitive}, sign::Unsigned};
 
+#[cfg(feature="serde")]
+extern crate serde;
+
+#[cfg(feature="tokio")]
+extern crate tokio;
+
+#[cfg(feature="tokio-codec")]
+extern crate tokio_util;
+
+#[cfg(any(feature="bytes", feature="tokio-codec"))]
+extern crate bytes;
+
+#[cfg(feature="bytes")]
+#[macro_use]
+extern crate alloc;
+
+#[cfg(feature="futures-io")]
+extern crate futures_io;
+
+#[cfg(feature="embedded-io")]
+extern crate embedded_io;
+
+#[cfg(feature="heapless")]
+extern crate heapless;
+
+#[cfg(feature="arrayvec")]
+extern crate arrayvec;
+
+#[cfg(all(test, feature="serde"))]
+#[macro_use]
+extern crate serde_derive;
+
 // ----------------------------------------------------------------------
 // IO (and IO implementations)
 // ----------------------------------------------------------------------
@@ -77,6 +111,12 @@ use num_traits::{cast::{FromPrimitive, ToPrimitive}, sign::Unsigned};
 ///
 /// The prefix `fio` is for fricgan-input-output, and is used to
 /// prevent name collisions.
+///
+/// A value is written and read as exactly `size_of_val` bytes (for a
+/// `[u8]`, its length).  Readers over streams and buffers size what they
+/// take from the input by this before `fio_read` is called, so a type
+/// whose encoding is a different size (such as `fixedstr::FixedStr`)
+/// can't be `IO`.
 pub trait IO {
     /// Writes bytes to a byte buffer.
     /// `self` is mutable because certain types need to step the
@@ -104,9 +144,7 @@ impl IO for [u8] {
         #[cfg(feature="safety-checks")]
         assert!(self.len() <= sink.len());
 
-        for i in 0..self.len() {
-            sink[i] = self[i];
-        }
+        sink[..self.len()].copy_from_slice(self);
 
         self.len()
     }
@@ -115,9 +153,8 @@ impl IO for [u8] {
         #[cfg(feature="safety-checks")]
         assert!(self.len() <= source.len());
 
-        for i in 0..self.len() {
-            self[i] = source[i];
-        }
+        let length = self.len();
+        self.copy_from_slice(&source[..length]);
 
         self.len()
     }
@@ -642,6 +679,18 @@ fn test_io_f64() {
     assert_eq!(test, test2);
 }
 
+// Calls `f` with zeroed scratch space the size of a `T`, for staging an
+// `IO` value without an allocator.  Only the scratch space is viewed as
+// bytes; the value itself is left to its `IO` impl.
+#[cfg(any(feature="embedded-io", feature="checksum"))]
+pub(crate) fn with_scratch<T, R, F: FnOnce(&mut [u8]) -> R>(f: F) -> R {
+    let mut buffer = ::core::mem::MaybeUninit::<T>::zeroed();
+    let bytes = unsafe {
+        ::core::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, ::core::mem::size_of::<T>())
+    };
+    f(bytes)
+}
+
 // ----------------------------------------------------------------------
 // Standard integration
 // ----------------------------------------------------------------------
@@ -670,7 +719,7 @@ pub fn fio_write<T: IO + Sized, W: StandardWrite>(object: &mut T, sink: &mut W)
         )
     };
     
-    if sink.write_all(&mut obj[..]).is_ok() {
+    if sink.write_all(&obj[..]).is_ok() {
         size_of::<T>()
     } else {
         0
@@ -787,10 +836,10 @@ impl VLQ for u32 {
             let b: u8 = source[i];
             *self += ((b & 127) as Self) << bits;
             bits += 7;
+            i += 1;
             if (b & 128) == 0 {
                 break;
             }
-            i += 1;
         }
         i
     }
@@ -801,12 +850,12 @@ impl VLQ for u32 {
         while remainder >= 128 {
             let b: u8 = remainder as u8 | 128;
             sink[i] = b;
-            remainder = remainder >> 7;
+            remainder >>= 7;
             i += 1;
         }
         let b: u8 = remainder as u8;
         sink[i] = b;
-        i
+        i + 1
     }
 }
 
@@ -816,14 +865,14 @@ impl VLQ for u64 {
         *self = 0;
         let mut bits: usize = 0;
         let mut i: usize = 0;
-        while bits != 71 {
+        while bits != 70 {
             let b: u8 = source[i];
             *self += ((b & 127) as Self) << bits;
             bits += 7;
+            i += 1;
             if (b & 128) == 0 {
                 break;
             }
-            i += 1;
         }
         i
     }
@@ -834,13 +883,185 @@ impl VLQ for u64 {
         while remainder >= 128 {
             let b: u8 = remainder as u8 | 128;
             sink[i] = b;
-            remainder = remainder >> 7;
+            remainder >>= 7;
             i += 1;
         }
         let b: u8 = remainder as u8;
         sink[i] = b;
-        i
+        i + 1
+    }
+}
+
+// The longest VLQ there is an implementation for (a `u64`).
+#[cfg(all(feature="vlq", any(
+    feature="cursor", feature="bytes", feature="embedded-io", feature="tokio", feature="futures-io",
+    feature="heapless", feature="arrayvec",
+)))]
+pub(crate) const MAX_VLQ_LEN: usize = 10;
+
+// Returns the most bytes the VLQ of a `V` can take: its width in 7-bit
+// groups, rounded up.
+#[cfg(all(feature="vlq", any(
+    feature="cursor", feature="bytes", feature="embedded-io", feature="tokio", feature="futures-io",
+    feature="heapless", feature="arrayvec",
+)))]
+pub(crate) fn vlq_max<V>() -> usize {
+    let max = (::core::mem::size_of::<V>() * 8).div_ceil(7);
+    if max < MAX_VLQ_LEN { max } else { MAX_VLQ_LEN }
+}
+
+// Returns the length of the VLQ at offset zero of `source`, provided it
+// terminates within `max` bytes.  `vlq_read` does not check bounds, so
+// anything reading untrusted input checks this first.
+#[cfg(all(feature="vlq", any(
+    feature="framing", feature="protobuf", feature="serde", feature="tlv", all(feature="utf", feature="std"),
+    feature="cursor", feature="bytes", feature="tokio", feature="futures-io", feature="heapless",
+    feature="arrayvec",
+)))]
+pub(crate) fn vlq_len(source: &[u8], max: usize) -> Option<usize> {
+    source.iter().take(max).position(|b| (b & 128) == 0).map(|i| i + 1)
+}
+
+// Returns whether the VLQ of `length` bytes at offset zero of `source`
+// fits in a `V`.  `vlq_read` drops whatever the last byte carries above
+// the width of `V`; readers of untrusted input reject it instead.
+#[cfg(all(feature="vlq", any(
+    feature="framing", feature="tlv", all(feature="utf", feature="std"), feature="cursor",
+    feature="bytes", feature="embedded-io", feature="tokio", feature="futures-io",
+    feature="heapless", feature="arrayvec",
+)))]
+pub(crate) fn vlq_fits<V>(source: &[u8], length: usize) -> bool {
+    let bits = ::core::mem::size_of::<V>() * 8;
+    let shift = 7 * (length - 1);
+    shift + 7 <= bits || (shift < bits && (source[length - 1] >> (bits - shift)) == 0)
+}
+
+// The most bytes a length prefix takes: a `u128`, or the longest VLQ.
+#[cfg(any(
+    feature="heapless", feature="arrayvec",
+    all(any(feature="io-string", feature="vlq-string"), any(feature="bytes", feature="tokio", feature="futures-io")),
+))]
+pub(crate) const MAX_PREFIX_LEN: usize = 16;
+
+// Why a length prefix couldn't be read.
+#[cfg(any(
+    feature="heapless", feature="arrayvec",
+    all(any(feature="io-string", feature="vlq-string"), any(feature="bytes", feature="tokio", feature="futures-io")),
+))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PrefixError {
+    // The source ended within the prefix.
+    Eof,
+    // A VLQ prefix was longer than its type allows, or overflowed it.
+    #[cfg(feature="vlq")]
+    MalformedVlq,
+    // The length doesn't fit a `usize`.
+    Overflow,
+}
+
+// Reads a length prefixed as an `IO` value `V` at offset zero of
+// `source`, returning the length and the size of the prefix.  Shared by
+// the string and vector readers of `bounded`, `buf` and `asyncio`.
+#[cfg(any(
+    feature="heapless", feature="arrayvec",
+    all(feature="io-string", any(feature="bytes", feature="tokio", feature="futures-io")),
+))]
+pub(crate) fn read_fio_prefix<V>(source: &[u8]) -> Result<(usize, usize), PrefixError>
+where V: ToPrimitive + FromPrimitive + Unsigned + IO {
+    if source.len() < ::core::mem::size_of::<V>() {
+        return Err(PrefixError::Eof);
+    }
+    let mut length = V::from_usize(0).unwrap();
+    let read = length.fio_read(source);
+    let length = length.to_usize().ok_or(PrefixError::Overflow)?;
+    Ok((length, read))
+}
+
+// Writes `length` as an `IO` value `V` to `buffer`, returning the size of
+// the prefix, or None if `V` can't hold it.
+#[cfg(any(
+    feature="heapless", feature="arrayvec",
+    all(feature="io-string", any(feature="bytes", feature="tokio", feature="futures-io")),
+))]
+pub(crate) fn write_fio_prefix<V>(length: usize, buffer: &mut [u8; MAX_PREFIX_LEN]) -> Option<usize>
+where V: ToPrimitive + FromPrimitive + Unsigned + IO {
+    let mut length = V::from_usize(length)?;
+    if ::core::mem::size_of::<V>() > MAX_PREFIX_LEN {
+        return None;
+    }
+    Some(length.fio_write(&mut buffer[..]))
+}
+
+// Reads a length prefixed as a `VLQ` value `V` at offset zero of
+// `source`, returning the length and the size of the prefix.  A VLQ
+// which runs past `vlq_max::<V>()` bytes, or overflows `V`, is malformed.
+#[cfg(all(feature="vlq", any(
+    feature="heapless", feature="arrayvec",
+    all(feature="vlq-string", any(feature="bytes", feature="tokio", feature="futures-io")),
+)))]
+pub(crate) fn read_vlq_prefix<V>(source: &[u8]) -> Result<(usize, usize), PrefixError>
+where V: ToPrimitive + FromPrimitive + Unsigned + VLQ {
+    let max = vlq_max::<V>();
+    let read = match vlq_len(source, max) {
+        Some(read) => read,
+        None if source.len() < max => return Err(PrefixError::Eof),
+        None => return Err(PrefixError::MalformedVlq),
+    };
+    if !vlq_fits::<V>(source, read) {
+        return Err(PrefixError::MalformedVlq);
     }
+    let mut length = V::from_usize(0).unwrap();
+    length.vlq_read(&source[..read]);
+    let length = length.to_usize().ok_or(PrefixError::Overflow)?;
+    Ok((length, read))
+}
+
+// Writes `length` as a `VLQ` value `V` to `buffer`, returning the size of
+// the prefix, or None if `V` can't hold it.
+#[cfg(all(feature="vlq", any(
+    feature="heapless", feature="arrayvec",
+    all(feature="vlq-string", any(feature="bytes", feature="tokio", feature="futures-io")),
+)))]
+pub(crate) fn write_vlq_prefix<V>(length: usize, buffer: &mut [u8; MAX_PREFIX_LEN]) -> Option<usize>
+where V: ToPrimitive + FromPrimitive + Unsigned + VLQ {
+    let length = V::from_usize(length)?;
+    Some(length.vlq_write(&mut buffer[..]))
+}
+
+// Returns the number of bytes `value` takes as a VLQ.
+#[cfg(any(feature="framing", feature="protobuf", feature="tlv", feature="utf"))]
+pub(crate) fn vlq_encoded_len(value: u64) -> usize {
+    let mut length = 1;
+    let mut remainder = value >> 7;
+    while remainder != 0 {
+        remainder >>= 7;
+        length += 1;
+    }
+    length
+}
+
+#[cfg(feature="vlq-32")]
+#[test]
+fn test_vlq_32() {
+    let mut data: [u8; 8] = [0; 8];
+    let mut test: u32 = 0;
+
+    // single byte
+    assert_eq!(127u32.vlq_write(&mut data[..]), 1);
+    assert_eq!(data[0], 0x7F);
+    assert_eq!(test.vlq_read(&data[..]), 1);
+    assert_eq!(test, 127);
+
+    // two bytes
+    assert_eq!(300u32.vlq_write(&mut data[..]), 2);
+    assert_eq!(&data[..2], &[0xAC, 0x02]);
+    assert_eq!(test.vlq_read(&data[..]), 2);
+    assert_eq!(test, 300);
+
+    // maximum
+    assert_eq!(0xFFFFFFFFu32.vlq_write(&mut data[..]), 5);
+    assert_eq!(test.vlq_read(&data[..]), 5);
+    assert_eq!(test, 0xFFFFFFFF);
 }
 
 // ----------------------------------------------------------------------
@@ -969,30 +1190,25 @@ impl VLQString for String {
     fn vlq_string_read<V>(&mut self, source: &[u8]) -> usize
     where V: ToPrimitive + FromPrimitive + Unsigned + VLQ {
         let mut length: V = V::from_usize(0).unwrap();
-        let mut read = length.vlq_read(source);
-        let length_usize : usize = read;
+        let offset = length.vlq_read(source);
+        let length_usize : usize = V::to_usize(&length).unwrap();
         self.reserve_exact(length_usize);
-        read += unsafe {
+        offset + unsafe {
             let vv: &mut Vec<u8> = self.as_mut_vec();
             vv.set_len(length_usize);
             let v: &mut [u8] = vv.as_mut_slice();
-            v.fio_read(&source[length_usize..])
-        };
-        read
+            v.fio_read(&source[offset..])
+        }
     }
 
     fn vlq_string_write<V>(&mut self, sink: &mut [u8]) -> usize
     where V: ToPrimitive + FromPrimitive + Unsigned + VLQ {
         let length: V = V::from_usize(self.len()).unwrap();
-        let mut written = length.vlq_write(sink);
-        let length_usize : usize = written;
-        written += unsafe {
-            let vv: &mut Vec<u8> = self.as_mut_vec();
-            vv.set_len(length_usize);
-            let v: &mut [u8] = vv.as_mut_slice();
-            v.fio_write(&mut sink[length_usize..])
-        };
-        written
+        let offset = length.vlq_write(sink);
+        offset + unsafe {
+            let v: &mut [u8] = self.as_mut_vec().as_mut_slice();
+            v.fio_write(&mut sink[offset..])
+        }
     }
 }
 
@@ -1020,10 +1236,10 @@ fn test_vlq_string() {
     // but the point remains here that the only test variable should
     // be the random string above)
     let mut u : u32 = 0;
-    u.vlq_read(&v[..]);
 
     // o is for offset.
-    let o = usize::from_u32(0).unwrap();
+    let o = u.vlq_read(&v[..]);
+    assert_eq!(u as usize, a.len());
 
     assert_eq!(l, a.len() + o);
 
@@ -1039,4 +1255,178 @@ fn test_vlq_string() {
     }
 
     println!("a: {}\nb: {}", a.as_str(), b.as_str());
-}
\ No newline at end of file
+}
+
+// ----------------------------------------------------------------------
+// C strings
+// ----------------------------------------------------------------------
+
+#[cfg(feature="cstring")]
+pub mod cstring;
+
+// ----------------------------------------------------------------------
+// Fixed width padded strings
+// ----------------------------------------------------------------------
+
+#[cfg(feature="fixedstr")]
+pub mod fixedstr;
+
+// ----------------------------------------------------------------------
+// UTF-16 and UTF-32
+// ----------------------------------------------------------------------
+
+#[cfg(feature="utf")]
+pub mod utf;
+
+// ----------------------------------------------------------------------
+// Single-byte character sets
+// ----------------------------------------------------------------------
+
+#[cfg(feature="charset")]
+pub mod charset;
+
+// ----------------------------------------------------------------------
+// Bounded strings and vectors (heapless, arrayvec)
+// ----------------------------------------------------------------------
+
+#[cfg(any(feature="heapless", feature="arrayvec"))]
+pub mod bounded;
+
+// ----------------------------------------------------------------------
+// Cursors
+// ----------------------------------------------------------------------
+
+#[cfg(feature="cursor")]
+pub mod cursor;
+
+// ----------------------------------------------------------------------
+// Async (tokio and futures)
+// ----------------------------------------------------------------------
+
+#[cfg(any(feature="tokio", feature="futures-io"))]
+pub mod asyncio;
+
+// ----------------------------------------------------------------------
+// bytes
+// ----------------------------------------------------------------------
+
+#[cfg(feature="bytes")]
+pub mod buf;
+
+// ----------------------------------------------------------------------
+// embedded-io
+// ----------------------------------------------------------------------
+
+#[cfg(feature="embedded-io")]
+pub mod embedded;
+
+// ----------------------------------------------------------------------
+// Checksums
+// ----------------------------------------------------------------------
+
+#[cfg(feature="checksum")]
+pub mod checksum;
+
+// ----------------------------------------------------------------------
+// Framing
+// ----------------------------------------------------------------------
+
+#[cfg(feature="framing")]
+pub mod framing;
+
+// ----------------------------------------------------------------------
+// Byte stuffing
+// ----------------------------------------------------------------------
+
+#[cfg(feature="cobs")]
+pub mod cobs;
+
+#[cfg(feature="slip")]
+pub mod slip;
+
+// ----------------------------------------------------------------------
+// Protocol Buffers
+// ----------------------------------------------------------------------
+
+#[cfg(feature="protobuf")]
+pub mod protobuf;
+
+// ----------------------------------------------------------------------
+// MessagePack
+// ----------------------------------------------------------------------
+
+#[cfg(feature="msgpack")]
+pub mod msgpack;
+
+// ----------------------------------------------------------------------
+// CBOR
+// ----------------------------------------------------------------------
+
+#[cfg(feature="cbor")]
+pub mod cbor;
+
+// ----------------------------------------------------------------------
+// ASN.1 DER
+// ----------------------------------------------------------------------
+
+#[cfg(feature="der")]
+pub mod der;
+
+// ----------------------------------------------------------------------
+// TLV
+// ----------------------------------------------------------------------
+
+#[cfg(feature="tlv")]
+pub mod tlv;
+
+// ----------------------------------------------------------------------
+// RIFF/IFF chunks
+// ----------------------------------------------------------------------
+
+#[cfg(feature="chunk")]
+pub mod chunk;
+
+// ----------------------------------------------------------------------
+// serde
+// ----------------------------------------------------------------------
+
+#[cfg(feature="serde")]
+pub mod serialize;
+
+#[cfg(feature="serde")]
+pub use serialize::{from_slice, to_vec, to_writer};
+
+// ----------------------------------------------------------------------
+// Half precision and bfloat16
+// ----------------------------------------------------------------------
+
+#[cfg(any(feature="io-f16", feature="io-bf16"))]
+pub mod half;
+
+// ----------------------------------------------------------------------
+// Fixed point
+// ----------------------------------------------------------------------
+
+#[cfg(feature="fixed")]
+pub mod fixed;
+
+// ----------------------------------------------------------------------
+// Packed and zoned decimal
+// ----------------------------------------------------------------------
+
+#[cfg(feature="decimal")]
+pub mod decimal;
+
+// ----------------------------------------------------------------------
+// Legacy floating point (IBM, VAX, x87)
+// ----------------------------------------------------------------------
+
+#[cfg(feature="legacy-float")]
+pub mod legacy;
+
+// ----------------------------------------------------------------------
+// Canonical floats and raw float bits
+// ----------------------------------------------------------------------
+
+#[cfg(feature="canonical-float")]
+pub mod canonical;
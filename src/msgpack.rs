@@ -0,0 +1,820 @@
+//! MessagePack encoding and decoding.
+//!
+//! The slice based functions (`write_*` and `read_token`) need no
+//! allocation and work without std; each call handles a single item (a
+//! scalar, or the header of an array, map, string, binary or extension).
+//! Multi-byte values are big endian, written through `IO`.
+//!
+//! With `std` enabled, `Value` holds any MessagePack value, and can be
+//! encoded to or decoded from slices, `std::io::Write` and
+//! `std::io::Read`.  Extension type -1 (timestamps) is decoded as
+//! `Timestamp`.
+
+use core::str;
+
+#[cfg(feature="std")]
+use std::io;
+
+use IO;
+
+/// Errors produced while encoding or decoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MsgpackError {
+    /// The output buffer is too small.
+    BufferTooSmall {
+        /// The number of bytes the output buffer requires.
+        required: usize,
+    },
+    /// The input ended partway through an item.
+    Eof,
+    /// The marker byte is not used by MessagePack (`0xC1`).
+    InvalidMarker(u8),
+    /// A string was not valid UTF-8.
+    InvalidUtf8,
+    /// A length was too large to be represented (over `u32::MAX`).
+    LengthOverflow,
+    /// A timestamp extension had an invalid length or nanosecond value.
+    InvalidTimestamp,
+    /// Arrays and maps were nested too deeply to decode.
+    DepthLimitExceeded,
+    /// The underlying reader or writer failed.
+    #[cfg(feature="std")]
+    Io(io::ErrorKind),
+}
+
+#[cfg(feature="std")]
+impl From<io::Error> for MsgpackError {
+    fn from(e: io::Error) -> MsgpackError {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => MsgpackError::Eof,
+            kind => MsgpackError::Io(kind),
+        }
+    }
+}
+
+/// The extension type used for timestamps.
+pub const TIMESTAMP_EXT: i8 = -1;
+
+// Nesting limit when decoding `Value`s, so hostile input can't exhaust
+// the stack.
+#[cfg(feature="std")]
+const MAX_DEPTH: usize = 512;
+
+// ----------------------------------------------------------------------
+// Headers
+// ----------------------------------------------------------------------
+
+// The decoded form of a marker and the bytes following it (up to, but
+// not including, any payload).
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Header {
+    Nil,
+    Bool(bool),
+    Uint(u64),
+    Int(i64),
+    F32(f32),
+    F64(f64),
+    Str(usize),
+    Bin(usize),
+    Array(u32),
+    Map(u32),
+    Ext(i8, usize),
+}
+
+// The number of bytes following `marker` that belong to the header.
+fn header_len(marker: u8) -> Result<usize, MsgpackError> {
+    Ok(match marker {
+        0xC1 => return Err(MsgpackError::InvalidMarker(marker)),
+        0xC4 | 0xCC | 0xD0 | 0xD9 => 1,
+        0xD4..=0xD8 => 1,
+        0xC5 | 0xC7 | 0xCD | 0xD1 | 0xDA | 0xDC | 0xDE => 2,
+        0xC8 => 3,
+        0xC6 | 0xCA | 0xCE | 0xD2 | 0xDB | 0xDD | 0xDF => 4,
+        0xC9 => 5,
+        0xCB | 0xCF | 0xD3 => 8,
+        _ => 0,
+    })
+}
+
+fn be_u16(source: &[u8]) -> u16 {
+    let mut value: u16 = 0;
+    value.fio_read(source);
+    u16::from_be(value)
+}
+
+fn be_u32(source: &[u8]) -> u32 {
+    let mut value: u32 = 0;
+    value.fio_read(source);
+    u32::from_be(value)
+}
+
+fn be_u64(source: &[u8]) -> u64 {
+    let mut value: u64 = 0;
+    value.fio_read(source);
+    u64::from_be(value)
+}
+
+// Parses a header; `extra` holds exactly `header_len(marker)` bytes.
+fn parse_header(marker: u8, extra: &[u8]) -> Header {
+    match marker {
+        0x00..=0x7F => Header::Uint(marker as u64),
+        0x80..=0x8F => Header::Map((marker & 0x0F) as u32),
+        0x90..=0x9F => Header::Array((marker & 0x0F) as u32),
+        0xA0..=0xBF => Header::Str((marker & 0x1F) as usize),
+        0xC0 => Header::Nil,
+        0xC2 => Header::Bool(false),
+        0xC3 => Header::Bool(true),
+        0xC4 | 0xD9 => {
+            let length = extra[0] as usize;
+            if marker == 0xC4 { Header::Bin(length) } else { Header::Str(length) }
+        }
+        0xC5 | 0xDA => {
+            let length = be_u16(extra) as usize;
+            if marker == 0xC5 { Header::Bin(length) } else { Header::Str(length) }
+        }
+        0xC6 | 0xDB => {
+            let length = be_u32(extra) as usize;
+            if marker == 0xC6 { Header::Bin(length) } else { Header::Str(length) }
+        }
+        0xC7 => Header::Ext(extra[1] as i8, extra[0] as usize),
+        0xC8 => Header::Ext(extra[2] as i8, be_u16(extra) as usize),
+        0xC9 => Header::Ext(extra[4] as i8, be_u32(extra) as usize),
+        0xCA => Header::F32(f32::from_bits(be_u32(extra))),
+        0xCB => Header::F64(f64::from_bits(be_u64(extra))),
+        0xCC => Header::Uint(extra[0] as u64),
+        0xCD => Header::Uint(be_u16(extra) as u64),
+        0xCE => Header::Uint(be_u32(extra) as u64),
+        0xCF => Header::Uint(be_u64(extra)),
+        0xD0 => Header::Int(extra[0] as i8 as i64),
+        0xD1 => Header::Int(be_u16(extra) as i16 as i64),
+        0xD2 => Header::Int(be_u32(extra) as i32 as i64),
+        0xD3 => Header::Int(be_u64(extra) as i64),
+        0xD4 => Header::Ext(extra[0] as i8, 1),
+        0xD5 => Header::Ext(extra[0] as i8, 2),
+        0xD6 => Header::Ext(extra[0] as i8, 4),
+        0xD7 => Header::Ext(extra[0] as i8, 8),
+        0xD8 => Header::Ext(extra[0] as i8, 16),
+        0xDC => Header::Array(be_u16(extra) as u32),
+        0xDD => Header::Array(be_u32(extra)),
+        0xDE => Header::Map(be_u16(extra) as u32),
+        0xDF => Header::Map(be_u32(extra)),
+        // 0xE0 to 0xFF; 0xC1 is rejected by header_len.
+        _ => Header::Int(marker as i8 as i64),
+    }
+}
+
+// ----------------------------------------------------------------------
+// Slice encoding
+// ----------------------------------------------------------------------
+
+fn check(sink: &[u8], required: usize) -> Result<(), MsgpackError> {
+    if sink.len() < required {
+        Err(MsgpackError::BufferTooSmall { required })
+    } else {
+        Ok(())
+    }
+}
+
+// Writes a marker followed by a big endian value of the given width.
+fn write_marked(sink: &mut [u8], marker: u8, value: u64, width: usize) -> Result<usize, MsgpackError> {
+    check(sink, 1 + width)?;
+    sink[0] = marker;
+    let body = &mut sink[1..];
+    match width {
+        0 => 0,
+        1 => {
+            body[0] = value as u8;
+            1
+        }
+        2 => (value as u16).to_be().fio_write(body),
+        4 => (value as u32).to_be().fio_write(body),
+        _ => value.to_be().fio_write(body),
+    };
+    Ok(1 + width)
+}
+
+// Writes a length using the smallest of three markers (for 8, 16 and 32
+// bit lengths).  A zero `marker8` means there is no 8-bit form.
+fn write_length(sink: &mut [u8], length: usize, markers: [u8; 3]) -> Result<usize, MsgpackError> {
+    if length <= 0xFF && markers[0] != 0 {
+        write_marked(sink, markers[0], length as u64, 1)
+    } else if length <= 0xFFFF {
+        write_marked(sink, markers[1], length as u64, 2)
+    } else if length <= 0xFFFFFFFF {
+        write_marked(sink, markers[2], length as u64, 4)
+    } else {
+        Err(MsgpackError::LengthOverflow)
+    }
+}
+
+fn write_payload(sink: &mut [u8], offset: usize, payload: &[u8]) -> Result<usize, MsgpackError> {
+    let required = offset + payload.len();
+    check(sink, required)?;
+    sink[offset..required].copy_from_slice(payload);
+    Ok(required)
+}
+
+/// Writes nil to offset zero of `sink`.
+///
+/// Like all of the `write_` functions, the return value is the number of
+/// bytes written.
+pub fn write_nil(sink: &mut [u8]) -> Result<usize, MsgpackError> {
+    write_marked(sink, 0xC0, 0, 0)
+}
+
+/// Writes a boolean.
+pub fn write_bool(sink: &mut [u8], value: bool) -> Result<usize, MsgpackError> {
+    write_marked(sink, if value { 0xC3 } else { 0xC2 }, 0, 0)
+}
+
+/// Writes an unsigned integer, in the smallest form that holds it.
+pub fn write_uint(sink: &mut [u8], value: u64) -> Result<usize, MsgpackError> {
+    if value <= 0x7F {
+        write_marked(sink, value as u8, 0, 0)
+    } else if value <= 0xFF {
+        write_marked(sink, 0xCC, value, 1)
+    } else if value <= 0xFFFF {
+        write_marked(sink, 0xCD, value, 2)
+    } else if value <= 0xFFFFFFFF {
+        write_marked(sink, 0xCE, value, 4)
+    } else {
+        write_marked(sink, 0xCF, value, 8)
+    }
+}
+
+/// Writes a signed integer, in the smallest form that holds it
+/// (non-negative values use the unsigned forms).
+pub fn write_int(sink: &mut [u8], value: i64) -> Result<usize, MsgpackError> {
+    if value >= 0 {
+        write_uint(sink, value as u64)
+    } else if value >= -32 {
+        write_marked(sink, value as u8, 0, 0)
+    } else if value >= i8::MIN as i64 {
+        write_marked(sink, 0xD0, value as u64, 1)
+    } else if value >= i16::MIN as i64 {
+        write_marked(sink, 0xD1, value as u64, 2)
+    } else if value >= i32::MIN as i64 {
+        write_marked(sink, 0xD2, value as u64, 4)
+    } else {
+        write_marked(sink, 0xD3, value as u64, 8)
+    }
+}
+
+/// Writes a 32-bit float.
+pub fn write_f32(sink: &mut [u8], value: f32) -> Result<usize, MsgpackError> {
+    write_marked(sink, 0xCA, value.to_bits() as u64, 4)
+}
+
+/// Writes a 64-bit float.
+pub fn write_f64(sink: &mut [u8], value: f64) -> Result<usize, MsgpackError> {
+    write_marked(sink, 0xCB, value.to_bits(), 8)
+}
+
+/// Writes a string header for a string of `length` bytes; the string's
+/// bytes follow it.
+pub fn write_str_len(sink: &mut [u8], length: usize) -> Result<usize, MsgpackError> {
+    if length <= 31 {
+        write_marked(sink, 0xA0 | length as u8, 0, 0)
+    } else {
+        write_length(sink, length, [0xD9, 0xDA, 0xDB])
+    }
+}
+
+/// Writes a string (header and bytes).
+pub fn write_str(sink: &mut [u8], value: &str) -> Result<usize, MsgpackError> {
+    let offset = write_str_len(sink, value.len())?;
+    write_payload(sink, offset, value.as_bytes())
+}
+
+/// Writes a binary header for `length` bytes of data; the data follows
+/// it.
+pub fn write_bin_len(sink: &mut [u8], length: usize) -> Result<usize, MsgpackError> {
+    write_length(sink, length, [0xC4, 0xC5, 0xC6])
+}
+
+/// Writes binary data (header and bytes).
+pub fn write_bin(sink: &mut [u8], value: &[u8]) -> Result<usize, MsgpackError> {
+    let offset = write_bin_len(sink, value.len())?;
+    write_payload(sink, offset, value)
+}
+
+/// Writes an array header; the `length` elements follow it.
+pub fn write_array_len(sink: &mut [u8], length: usize) -> Result<usize, MsgpackError> {
+    if length <= 15 {
+        write_marked(sink, 0x90 | length as u8, 0, 0)
+    } else {
+        write_length(sink, length, [0, 0xDC, 0xDD])
+    }
+}
+
+/// Writes a map header; the `length` key and value pairs follow it.
+pub fn write_map_len(sink: &mut [u8], length: usize) -> Result<usize, MsgpackError> {
+    if length <= 15 {
+        write_marked(sink, 0x80 | length as u8, 0, 0)
+    } else {
+        write_length(sink, length, [0, 0xDE, 0xDF])
+    }
+}
+
+/// Writes an extension header for `length` bytes of data of type
+/// `ext_type`; the data follows it.
+pub fn write_ext_len(sink: &mut [u8], ext_type: i8, length: usize) -> Result<usize, MsgpackError> {
+    let written = match length {
+        1 => write_marked(sink, 0xD4, 0, 0)?,
+        2 => write_marked(sink, 0xD5, 0, 0)?,
+        4 => write_marked(sink, 0xD6, 0, 0)?,
+        8 => write_marked(sink, 0xD7, 0, 0)?,
+        16 => write_marked(sink, 0xD8, 0, 0)?,
+        _ => write_length(sink, length, [0xC7, 0xC8, 0xC9])?,
+    };
+    check(sink, written + 1)?;
+    sink[written] = ext_type as u8;
+    Ok(written + 1)
+}
+
+/// Writes an extension (header and data).
+pub fn write_ext(sink: &mut [u8], ext_type: i8, data: &[u8]) -> Result<usize, MsgpackError> {
+    let offset = write_ext_len(sink, ext_type, data.len())?;
+    write_payload(sink, offset, data)
+}
+
+/// A point in time, stored as the timestamp extension (type -1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp {
+    /// Seconds since the Unix epoch.
+    pub seconds: i64,
+    /// Nanoseconds within the second (below 1,000,000,000).
+    pub nanoseconds: u32,
+}
+
+impl Timestamp {
+    /// Decodes the data of a timestamp extension.
+    pub fn from_ext(data: &[u8]) -> Result<Timestamp, MsgpackError> {
+        let (seconds, nanoseconds) = match data.len() {
+            4 => (be_u32(data) as i64, 0),
+            8 => {
+                let value = be_u64(data);
+                ((value & 0x3FFFFFFFF) as i64, (value >> 34) as u32)
+            }
+            12 => (be_u64(&data[4..]) as i64, be_u32(data)),
+            _ => return Err(MsgpackError::InvalidTimestamp),
+        };
+        if nanoseconds >= 1_000_000_000 {
+            return Err(MsgpackError::InvalidTimestamp);
+        }
+        Ok(Timestamp { seconds, nanoseconds })
+    }
+
+    // Encodes the extension data in the smallest of the three forms,
+    // returning the buffer and the length used.
+    fn to_ext(self) -> Result<([u8; 12], usize), MsgpackError> {
+        if self.nanoseconds >= 1_000_000_000 {
+            return Err(MsgpackError::InvalidTimestamp);
+        }
+        let mut data = [0u8; 12];
+        let length = if self.seconds >> 34 == 0 {
+            let value = ((self.nanoseconds as u64) << 34) | self.seconds as u64;
+            if value >> 32 == 0 {
+                (value as u32).to_be().fio_write(&mut data[..])
+            } else {
+                value.to_be().fio_write(&mut data[..])
+            }
+        } else {
+            self.nanoseconds.to_be().fio_write(&mut data[..]);
+            4 + (self.seconds as u64).to_be().fio_write(&mut data[4..])
+        };
+        Ok((data, length))
+    }
+}
+
+/// Writes a timestamp, in the smallest of the three timestamp forms.
+pub fn write_timestamp(sink: &mut [u8], value: Timestamp) -> Result<usize, MsgpackError> {
+    let (data, length) = value.to_ext()?;
+    write_ext(sink, TIMESTAMP_EXT, &data[..length])
+}
+
+// ----------------------------------------------------------------------
+// Slice decoding
+// ----------------------------------------------------------------------
+
+/// A single item read from a slice.  Strings, binary and extension data
+/// are borrowed from the slice.  Arrays and maps are just their headers;
+/// the elements (or key and value pairs) follow.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Token<'a> {
+    /// nil.
+    Nil,
+    /// A boolean.
+    Bool(bool),
+    /// A positive fixint or an unsigned integer (`uint 8` to `uint 64`).
+    Uint(u64),
+    /// A negative fixint or a signed integer (`int 8` to `int 64`).
+    Int(i64),
+    /// A 32-bit float.
+    F32(f32),
+    /// A 64-bit float.
+    F64(f64),
+    /// A string.
+    Str(&'a str),
+    /// Binary data.
+    Bin(&'a [u8]),
+    /// An array header, holding the number of elements.
+    Array(u32),
+    /// A map header, holding the number of key and value pairs.
+    Map(u32),
+    /// An extension, holding the type and data.
+    Ext(i8, &'a [u8]),
+}
+
+/// Reads a single item from offset zero of `source`, returning the item
+/// and the number of bytes read.
+pub fn read_token(source: &[u8]) -> Result<(Token<'_>, usize), MsgpackError> {
+    let marker = *source.first().ok_or(MsgpackError::Eof)?;
+    let extra = header_len(marker)?;
+    if source.len() < 1 + extra {
+        return Err(MsgpackError::Eof);
+    }
+    let header = parse_header(marker, &source[1..1 + extra]);
+    let offset = 1 + extra;
+
+    let payload = |length: usize| {
+        if source.len() - offset < length {
+            Err(MsgpackError::Eof)
+        } else {
+            Ok(&source[offset..offset + length])
+        }
+    };
+
+    Ok(match header {
+        Header::Nil => (Token::Nil, offset),
+        Header::Bool(value) => (Token::Bool(value), offset),
+        Header::Uint(value) => (Token::Uint(value), offset),
+        Header::Int(value) => (Token::Int(value), offset),
+        Header::F32(value) => (Token::F32(value), offset),
+        Header::F64(value) => (Token::F64(value), offset),
+        Header::Array(length) => (Token::Array(length), offset),
+        Header::Map(length) => (Token::Map(length), offset),
+        Header::Str(length) => {
+            let value = str::from_utf8(payload(length)?).map_err(|_| MsgpackError::InvalidUtf8)?;
+            (Token::Str(value), offset + length)
+        }
+        Header::Bin(length) => (Token::Bin(payload(length)?), offset + length),
+        Header::Ext(ext_type, length) => (Token::Ext(ext_type, payload(length)?), offset + length),
+    })
+}
+
+// ----------------------------------------------------------------------
+// Value
+// ----------------------------------------------------------------------
+
+/// Any MessagePack value.
+///
+/// Integers read from unsigned markers (and positive fixints) are `Uint`,
+/// and those read from signed markers (and negative fixints) are `Int`.
+/// When written, both use the smallest form that holds the value.
+#[cfg(feature="std")]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// nil.
+    Nil,
+    /// A boolean.
+    Bool(bool),
+    /// An unsigned integer.
+    Uint(u64),
+    /// A signed integer.
+    Int(i64),
+    /// A 32-bit float.
+    F32(f32),
+    /// A 64-bit float.
+    F64(f64),
+    /// A string.
+    String(String),
+    /// Binary data.
+    Binary(Vec<u8>),
+    /// An array.
+    Array(Vec<Value>),
+    /// A map, as key and value pairs in encoded order.
+    Map(Vec<(Value, Value)>),
+    /// A timestamp (extension type -1).
+    Timestamp(Timestamp),
+    /// Any other extension, holding the type and data.
+    Ext(i8, Vec<u8>),
+}
+
+#[cfg(feature="std")]
+impl Value {
+    /// Decodes a value from offset zero of `source`, returning the value
+    /// and the number of bytes read.
+    pub fn from_slice(source: &[u8]) -> Result<(Value, usize), MsgpackError> {
+        let mut offset = 0;
+        let value = Value::from_slice_at(source, &mut offset, 0)?;
+        Ok((value, offset))
+    }
+
+    fn from_slice_at(source: &[u8], offset: &mut usize, depth: usize) -> Result<Value, MsgpackError> {
+        if depth > MAX_DEPTH {
+            return Err(MsgpackError::DepthLimitExceeded);
+        }
+        let (token, read) = read_token(&source[*offset..])?;
+        *offset += read;
+        Ok(match token {
+            Token::Nil => Value::Nil,
+            Token::Bool(value) => Value::Bool(value),
+            Token::Uint(value) => Value::Uint(value),
+            Token::Int(value) => Value::Int(value),
+            Token::F32(value) => Value::F32(value),
+            Token::F64(value) => Value::F64(value),
+            Token::Str(value) => Value::String(value.to_owned()),
+            Token::Bin(value) => Value::Binary(value.to_vec()),
+            Token::Ext(TIMESTAMP_EXT, data) => Value::Timestamp(Timestamp::from_ext(data)?),
+            Token::Ext(ext_type, data) => Value::Ext(ext_type, data.to_vec()),
+            Token::Array(length) => {
+                let mut values = Vec::with_capacity(capacity(length));
+                for _ in 0..length {
+                    values.push(Value::from_slice_at(source, offset, depth + 1)?);
+                }
+                Value::Array(values)
+            }
+            Token::Map(length) => {
+                let mut pairs = Vec::with_capacity(capacity(length));
+                for _ in 0..length {
+                    let key = Value::from_slice_at(source, offset, depth + 1)?;
+                    let value = Value::from_slice_at(source, offset, depth + 1)?;
+                    pairs.push((key, value));
+                }
+                Value::Map(pairs)
+            }
+        })
+    }
+
+    /// Decodes a value from `reader`.
+    pub fn read_from<R: io::Read>(reader: &mut R) -> Result<Value, MsgpackError> {
+        Value::read_from_at(reader, 0)
+    }
+
+    fn read_from_at<R: io::Read>(reader: &mut R, depth: usize) -> Result<Value, MsgpackError> {
+        if depth > MAX_DEPTH {
+            return Err(MsgpackError::DepthLimitExceeded);
+        }
+        let mut buffer = [0u8; 9];
+        reader.read_exact(&mut buffer[..1])?;
+        let marker = buffer[0];
+        let extra = header_len(marker)?;
+        reader.read_exact(&mut buffer[1..1 + extra])?;
+
+        Ok(match parse_header(marker, &buffer[1..1 + extra]) {
+            Header::Nil => Value::Nil,
+            Header::Bool(value) => Value::Bool(value),
+            Header::Uint(value) => Value::Uint(value),
+            Header::Int(value) => Value::Int(value),
+            Header::F32(value) => Value::F32(value),
+            Header::F64(value) => Value::F64(value),
+            Header::Str(length) => {
+                let data = read_payload(reader, length)?;
+                Value::String(String::from_utf8(data).map_err(|_| MsgpackError::InvalidUtf8)?)
+            }
+            Header::Bin(length) => Value::Binary(read_payload(reader, length)?),
+            Header::Ext(TIMESTAMP_EXT, length) => {
+                Value::Timestamp(Timestamp::from_ext(&read_payload(reader, length)?)?)
+            }
+            Header::Ext(ext_type, length) => Value::Ext(ext_type, read_payload(reader, length)?),
+            Header::Array(length) => {
+                let mut values = Vec::with_capacity(capacity(length));
+                for _ in 0..length {
+                    values.push(Value::read_from_at(reader, depth + 1)?);
+                }
+                Value::Array(values)
+            }
+            Header::Map(length) => {
+                let mut pairs = Vec::with_capacity(capacity(length));
+                for _ in 0..length {
+                    let key = Value::read_from_at(reader, depth + 1)?;
+                    let value = Value::read_from_at(reader, depth + 1)?;
+                    pairs.push((key, value));
+                }
+                Value::Map(pairs)
+            }
+        })
+    }
+
+    /// Encodes the value to offset zero of `sink`, returning the number of
+    /// bytes written.
+    pub fn write(&self, sink: &mut [u8]) -> Result<usize, MsgpackError> {
+        let mut offset = 0;
+        self.encode(&mut |data: &[u8]| {
+            let written = write_payload(sink, offset, data)?;
+            offset = written;
+            Ok(())
+        })?;
+        Ok(offset)
+    }
+
+    /// Encodes the value to `writer`.
+    pub fn write_to<W: io::Write>(&self, writer: &mut W) -> Result<(), MsgpackError> {
+        self.encode(&mut |data: &[u8]| writer.write_all(data).map_err(MsgpackError::from))
+    }
+
+    /// Encodes the value to a new `Vec<u8>`.
+    pub fn to_vec(&self) -> Result<Vec<u8>, MsgpackError> {
+        let mut sink = Vec::new();
+        self.write_to(&mut sink)?;
+        Ok(sink)
+    }
+
+    // Encodes headers into a scratch buffer, passing each piece of the
+    // output to `out`.
+    fn encode<F>(&self, out: &mut F) -> Result<(), MsgpackError>
+    where F: FnMut(&[u8]) -> Result<(), MsgpackError> {
+        let mut header = [0u8; 10];
+        let length = match *self {
+            Value::Nil => write_nil(&mut header)?,
+            Value::Bool(value) => write_bool(&mut header, value)?,
+            Value::Uint(value) => write_uint(&mut header, value)?,
+            Value::Int(value) => write_int(&mut header, value)?,
+            Value::F32(value) => write_f32(&mut header, value)?,
+            Value::F64(value) => write_f64(&mut header, value)?,
+            Value::String(ref value) => write_str_len(&mut header, value.len())?,
+            Value::Binary(ref value) => write_bin_len(&mut header, value.len())?,
+            Value::Timestamp(value) => write_ext_len(&mut header, TIMESTAMP_EXT, value.to_ext()?.1)?,
+            Value::Ext(ext_type, ref data) => write_ext_len(&mut header, ext_type, data.len())?,
+            Value::Array(ref values) => write_array_len(&mut header, values.len())?,
+            Value::Map(ref pairs) => write_map_len(&mut header, pairs.len())?,
+        };
+        out(&header[..length])?;
+
+        match *self {
+            Value::String(ref value) => out(value.as_bytes()),
+            Value::Binary(ref value) => out(value),
+            Value::Timestamp(value) => {
+                let (data, length) = value.to_ext()?;
+                out(&data[..length])
+            }
+            Value::Ext(_, ref data) => out(data),
+            Value::Array(ref values) => {
+                for value in values {
+                    value.encode(out)?;
+                }
+                Ok(())
+            }
+            Value::Map(ref pairs) => {
+                for (key, value) in pairs {
+                    key.encode(out)?;
+                    value.encode(out)?;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// Don't trust lengths from the input for preallocation.
+#[cfg(feature="std")]
+fn capacity(length: u32) -> usize {
+    ::std::cmp::min(length as usize, 4096)
+}
+
+#[cfg(feature="std")]
+fn read_payload<R: io::Read>(reader: &mut R, length: usize) -> Result<Vec<u8>, MsgpackError> {
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    reader.take(length as u64).read_to_end(&mut data)?;
+    if data.len() != length {
+        return Err(MsgpackError::Eof);
+    }
+    Ok(data)
+}
+
+#[test]
+fn test_msgpack_scalars() {
+    type Write = dyn Fn(&mut [u8]) -> Result<usize, MsgpackError>;
+
+    let mut sink = [0u8; 16];
+    let cases: [(&Write, &[u8]); 20] = [
+        (&|s| write_nil(s), &[0xC0]),
+        (&|s| write_bool(s, true), &[0xC3]),
+        (&|s| write_bool(s, false), &[0xC2]),
+        (&|s| write_uint(s, 0x7F), &[0x7F]),
+        (&|s| write_uint(s, 0x80), &[0xCC, 0x80]),
+        (&|s| write_uint(s, 0x100), &[0xCD, 0x01, 0x00]),
+        (&|s| write_uint(s, 0x10000), &[0xCE, 0x00, 0x01, 0x00, 0x00]),
+        (&|s| write_uint(s, 0x100000000), &[0xCF, 0, 0, 0, 1, 0, 0, 0, 0]),
+        (&|s| write_int(s, 5), &[0x05]),
+        (&|s| write_int(s, -1), &[0xFF]),
+        (&|s| write_int(s, -32), &[0xE0]),
+        (&|s| write_int(s, -33), &[0xD0, 0xDF]),
+        (&|s| write_int(s, -129), &[0xD1, 0xFF, 0x7F]),
+        (&|s| write_int(s, -32769), &[0xD2, 0xFF, 0xFF, 0x7F, 0xFF]),
+        (&|s| write_int(s, i64::MIN), &[0xD3, 0x80, 0, 0, 0, 0, 0, 0, 0]),
+        (&|s| write_f32(s, 1.0), &[0xCA, 0x3F, 0x80, 0x00, 0x00]),
+        (&|s| write_f64(s, 1.0), &[0xCB, 0x3F, 0xF0, 0, 0, 0, 0, 0, 0]),
+        (&|s| write_str(s, "abc"), &[0xA3, b'a', b'b', b'c']),
+        (&|s| write_bin(s, &[1, 2]), &[0xC4, 0x02, 0x01, 0x02]),
+        (&|s| write_ext(s, 5, &[1, 2, 3, 4]), &[0xD6, 0x05, 1, 2, 3, 4]),
+    ];
+
+    for &(write, expected) in cases.iter() {
+        let n = write(&mut sink[..]).unwrap();
+        assert_eq!(&sink[..n], expected);
+        let (_, read) = read_token(&sink[..n]).unwrap();
+        assert_eq!(read, n);
+    }
+
+    assert_eq!(read_token(&[0xD0, 0xDF]), Ok((Token::Int(-33), 2)));
+    assert_eq!(read_token(&[0xCD, 0x01, 0x00]), Ok((Token::Uint(256), 3)));
+    assert_eq!(read_token(&[0xA3, b'a', b'b', b'c', 0xC0]), Ok((Token::Str("abc"), 4)));
+    assert_eq!(read_token(&[0xDC, 0x00, 0x10]), Ok((Token::Array(16), 3)));
+    assert_eq!(read_token(&[0xC7, 0x03, 0x7F, 1, 2, 3]), Ok((Token::Ext(0x7F, &[1, 2, 3]), 6)));
+
+    assert_eq!(read_token(&[0xC1]), Err(MsgpackError::InvalidMarker(0xC1)));
+    assert_eq!(read_token(&[0xCD, 0x01]), Err(MsgpackError::Eof));
+    assert_eq!(read_token(&[0xA3, b'a']), Err(MsgpackError::Eof));
+    assert_eq!(read_token(&[0xA1, 0xFF]), Err(MsgpackError::InvalidUtf8));
+    assert_eq!(write_uint(&mut sink[..2], 0x100), Err(MsgpackError::BufferTooSmall { required: 3 }));
+}
+
+#[test]
+fn test_msgpack_lengths() {
+    let mut sink = [0u8; 8];
+    assert_eq!(write_str_len(&mut sink[..], 31), Ok(1));
+    assert_eq!(sink[0], 0xBF);
+    assert_eq!(write_str_len(&mut sink[..], 32), Ok(2));
+    assert_eq!(&sink[..2], &[0xD9, 0x20]);
+    assert_eq!(write_bin_len(&mut sink[..], 0x100), Ok(3));
+    assert_eq!(&sink[..3], &[0xC5, 0x01, 0x00]);
+    assert_eq!(write_array_len(&mut sink[..], 15), Ok(1));
+    assert_eq!(sink[0], 0x9F);
+    assert_eq!(write_array_len(&mut sink[..], 16), Ok(3));
+    assert_eq!(&sink[..3], &[0xDC, 0x00, 0x10]);
+    assert_eq!(write_map_len(&mut sink[..], 0x10000), Ok(5));
+    assert_eq!(&sink[..5], &[0xDF, 0x00, 0x01, 0x00, 0x00]);
+    assert_eq!(write_ext_len(&mut sink[..], -2, 3), Ok(3));
+    assert_eq!(&sink[..3], &[0xC7, 0x03, 0xFE]);
+    assert_eq!(write_ext_len(&mut sink[..], 1, 16), Ok(2));
+    assert_eq!(&sink[..2], &[0xD8, 0x01]);
+}
+
+#[test]
+fn test_msgpack_timestamp() {
+    let mut sink = [0u8; 16];
+    let cases = [
+        (Timestamp { seconds: 1, nanoseconds: 0 }, 6),
+        (Timestamp { seconds: 1, nanoseconds: 5 }, 10),
+        (Timestamp { seconds: 1 << 34, nanoseconds: 0 }, 15),
+        (Timestamp { seconds: -1, nanoseconds: 999_999_999 }, 15),
+    ];
+    for &(timestamp, length) in cases.iter() {
+        assert_eq!(write_timestamp(&mut sink[..], timestamp), Ok(length));
+        match read_token(&sink[..length]) {
+            Ok((Token::Ext(TIMESTAMP_EXT, data), n)) => {
+                assert_eq!(n, length);
+                assert_eq!(Timestamp::from_ext(data), Ok(timestamp));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+    // The 96-bit form uses ext 8.
+    assert_eq!(&sink[..3], &[0xC7, 0x0C, 0xFF]);
+    assert_eq!(write_timestamp(&mut sink[..], Timestamp { seconds: 1, nanoseconds: 0 }), Ok(6));
+    assert_eq!(&sink[..6], &[0xD6, 0xFF, 0x00, 0x00, 0x00, 0x01]);
+
+    assert_eq!(Timestamp::from_ext(&[0, 0, 0]), Err(MsgpackError::InvalidTimestamp));
+    assert_eq!(
+        write_timestamp(&mut sink[..], Timestamp { seconds: 0, nanoseconds: 1_000_000_000 }),
+        Err(MsgpackError::InvalidTimestamp)
+    );
+}
+
+#[cfg(feature="std")]
+#[test]
+fn test_msgpack_value() {
+    let value = Value::Map(vec![
+        (Value::String("name".to_owned()), Value::String("fricgan".to_owned())),
+        (Value::Uint(1), Value::Array(vec![Value::Nil, Value::Bool(true), Value::Int(-200), Value::F64(0.5)])),
+        (Value::Binary(vec![0; 300]), Value::Ext(7, vec![1, 2, 3])),
+        (Value::Timestamp(Timestamp { seconds: 1_500_000_000, nanoseconds: 1 }), Value::F32(-1.5)),
+    ]);
+
+    let encoded = value.to_vec().unwrap();
+    assert_eq!(encoded[0], 0x84);
+    assert_eq!(Value::from_slice(&encoded[..]), Ok((value.clone(), encoded.len())));
+    assert_eq!(Value::read_from(&mut &encoded[..]), Ok(value.clone()));
+
+    let mut sink = [0u8; 512];
+    assert_eq!(value.write(&mut sink[..]), Ok(encoded.len()));
+    assert_eq!(&sink[..encoded.len()], &encoded[..]);
+    assert!(matches!(value.write(&mut sink[..16]), Err(MsgpackError::BufferTooSmall { .. })));
+
+    // Signed markers decode as Int, even when positive.
+    assert_eq!(Value::from_slice(&[0xD0, 0x05]), Ok((Value::Int(5), 2)));
+
+    for length in 1..encoded.len() {
+        assert_eq!(Value::from_slice(&encoded[..length]).map(|_| ()), Err(MsgpackError::Eof));
+        assert_eq!(Value::read_from(&mut &encoded[..length]), Err(MsgpackError::Eof));
+    }
+
+    let nested = vec![0x91u8; 1000];
+    assert_eq!(Value::from_slice(&nested[..]), Err(MsgpackError::DepthLimitExceeded));
+    assert_eq!(Value::read_from(&mut &nested[..]), Err(MsgpackError::DepthLimitExceeded));
+}
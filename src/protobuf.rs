@@ -0,0 +1,920 @@
+//! Protocol Buffers wire format primitives.
+//!
+//! This is not a code generator, just the pieces needed to encode or
+//! decode messages by hand: varints (protobuf varints are the same as
+//! fricgan's `VLQ`), tags, ZigZag encoding, fixed width fields,
+//! length-delimited fields and packed repeated fields.
+//!
+//! `MessageWriter` writes fields into a byte buffer.  `fields` iterates
+//! over the fields of an encoded message, in order; fields the caller
+//! does not recognise are simply skipped over by ignoring them.  Nothing
+//! here allocates or requires std.
+
+use core::str;
+
+use IO;
+use VLQ;
+use {vlq_encoded_len, vlq_len};
+
+/// The largest valid field number (2^29 - 1).
+pub const MAX_FIELD_NUMBER: u32 = (1 << 29) - 1;
+
+/// The encoding of a field's value, stored in the low three bits of the
+/// tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireType {
+    /// `int32`, `int64`, `uint32`, `uint64`, `sint32`, `sint64`, `bool`
+    /// and `enum`.
+    Varint = 0,
+    /// `fixed64`, `sfixed64` and `double`.
+    Fixed64 = 1,
+    /// `string`, `bytes`, embedded messages and packed repeated fields.
+    LengthDelimited = 2,
+    /// Start of a (deprecated) group.
+    StartGroup = 3,
+    /// End of a (deprecated) group.
+    EndGroup = 4,
+    /// `fixed32`, `sfixed32` and `float`.
+    Fixed32 = 5,
+}
+
+impl WireType {
+    /// Converts the low three bits of a tag to a `WireType`.
+    pub fn from_u8(value: u8) -> Result<WireType, ProtobufError> {
+        match value {
+            0 => Ok(WireType::Varint),
+            1 => Ok(WireType::Fixed64),
+            2 => Ok(WireType::LengthDelimited),
+            3 => Ok(WireType::StartGroup),
+            4 => Ok(WireType::EndGroup),
+            5 => Ok(WireType::Fixed32),
+            other => Err(ProtobufError::InvalidWireType(other)),
+        }
+    }
+}
+
+/// Errors produced while encoding or decoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtobufError {
+    /// The output buffer is too small.
+    BufferTooSmall {
+        /// The number of bytes the output buffer requires.
+        required: usize,
+    },
+    /// The input ended partway through a field.
+    Eof,
+    /// A varint was longer than ten bytes, or did not fit in 64 bits.
+    InvalidVarint,
+    /// A tag held an unknown wire type.
+    InvalidWireType(u8),
+    /// A field number was zero or larger than `MAX_FIELD_NUMBER`.
+    InvalidFieldNumber(u64),
+    /// An end group tag had no matching start group tag.
+    UnexpectedEndGroup,
+    /// A field was read as the wrong type.
+    WireTypeMismatch {
+        /// The wire type the accessor requires.
+        expected: WireType,
+        /// The wire type of the field.
+        found: WireType,
+    },
+    /// A packed fixed width field's length was not a multiple of the
+    /// element size.
+    InvalidPackedLength,
+    /// A string field was not valid UTF-8.
+    InvalidUtf8,
+}
+
+// ----------------------------------------------------------------------
+// Primitives
+// ----------------------------------------------------------------------
+
+/// ZigZag encodes a `sint32` value.
+pub fn encode_zigzag32(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+/// Decodes a ZigZag encoded `sint32` value.
+pub fn decode_zigzag32(value: u32) -> i32 {
+    ((value >> 1) as i32) ^ -((value & 1) as i32)
+}
+
+/// ZigZag encodes a `sint64` value.
+pub fn encode_zigzag64(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Decodes a ZigZag encoded `sint64` value.
+pub fn decode_zigzag64(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+/// The number of bytes `value` occupies as a varint.
+pub fn varint_len(value: u64) -> usize {
+    vlq_encoded_len(value)
+}
+
+/// Writes `value` as a varint to offset zero of `sink`.
+///
+/// The return value is the number of bytes written.
+pub fn write_varint(value: u64, sink: &mut [u8]) -> Result<usize, ProtobufError> {
+    let required = varint_len(value);
+    if sink.len() < required {
+        return Err(ProtobufError::BufferTooSmall { required });
+    }
+    Ok(value.vlq_write(sink))
+}
+
+/// Reads a varint from offset zero of `source`, returning the value and
+/// the number of bytes read.
+pub fn read_varint(source: &[u8]) -> Result<(u64, usize), ProtobufError> {
+    match vlq_len(source, 10) {
+        // The tenth byte only has room for the top bit of a u64.
+        Some(10) if source[9] > 1 => Err(ProtobufError::InvalidVarint),
+        Some(length) => {
+            let mut value: u64 = 0;
+            value.vlq_read(source);
+            Ok((value, length))
+        }
+        None if source.len() < 10 => Err(ProtobufError::Eof),
+        None => Err(ProtobufError::InvalidVarint),
+    }
+}
+
+/// Writes a tag (field number and wire type) to offset zero of `sink`.
+///
+/// The return value is the number of bytes written.
+pub fn write_tag(field: u32, wire_type: WireType, sink: &mut [u8]) -> Result<usize, ProtobufError> {
+    if field == 0 || field > MAX_FIELD_NUMBER {
+        return Err(ProtobufError::InvalidFieldNumber(field as u64));
+    }
+    write_varint(((field as u64) << 3) | wire_type as u64, sink)
+}
+
+/// Reads a tag from offset zero of `source`, returning the field number,
+/// the wire type, and the number of bytes read.
+pub fn read_tag(source: &[u8]) -> Result<(u32, WireType, usize), ProtobufError> {
+    let (tag, read) = read_varint(source)?;
+    let wire_type = WireType::from_u8((tag & 7) as u8)?;
+    let field = tag >> 3;
+    if field == 0 || field > MAX_FIELD_NUMBER as u64 {
+        return Err(ProtobufError::InvalidFieldNumber(field));
+    }
+    Ok((field as u32, wire_type, read))
+}
+
+fn read_fixed32(source: &[u8]) -> Result<u32, ProtobufError> {
+    if source.len() < 4 {
+        return Err(ProtobufError::Eof);
+    }
+    let mut value: u32 = 0;
+    value.fio_read(source);
+    Ok(u32::from_le(value))
+}
+
+fn read_fixed64(source: &[u8]) -> Result<u64, ProtobufError> {
+    if source.len() < 8 {
+        return Err(ProtobufError::Eof);
+    }
+    let mut value: u64 = 0;
+    value.fio_read(source);
+    Ok(u64::from_le(value))
+}
+
+// ----------------------------------------------------------------------
+// Writing
+// ----------------------------------------------------------------------
+
+// The largest length prefix an embedded message may need.
+const MAX_LENGTH_PREFIX: usize = 5;
+
+/// Writes the fields of a message to a byte buffer.
+///
+/// Each method writes a single field (tag and value), and returns the
+/// number of bytes written.
+pub struct MessageWriter<'a> {
+    sink: &'a mut [u8],
+    position: usize,
+}
+
+impl<'a> MessageWriter<'a> {
+    /// Creates a writer writing to offset zero of `sink`.
+    pub fn new(sink: &'a mut [u8]) -> MessageWriter<'a> {
+        MessageWriter { sink, position: 0 }
+    }
+
+    /// The number of bytes written so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Finishes the message, returning its length.
+    pub fn finish(self) -> usize {
+        self.position
+    }
+
+    fn reserve(&self, length: usize) -> Result<(), ProtobufError> {
+        let required = self.position + length;
+        if self.sink.len() < required {
+            Err(ProtobufError::BufferTooSmall { required })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn raw_varint(&mut self, value: u64) -> Result<usize, ProtobufError> {
+        let written = write_varint(value, &mut self.sink[self.position..])?;
+        self.position += written;
+        Ok(written)
+    }
+
+    fn raw_fixed<T: IO>(&mut self, mut value: T, length: usize) -> Result<usize, ProtobufError> {
+        self.reserve(length)?;
+        let written = value.fio_write(&mut self.sink[self.position..]);
+        self.position += written;
+        Ok(written)
+    }
+
+    // Writes `f` as a single field, rolling back on failure.
+    fn field<F>(&mut self, f: F) -> Result<usize, ProtobufError>
+    where F: FnOnce(&mut MessageWriter<'a>) -> Result<(), ProtobufError> {
+        let start = self.position;
+        match f(self) {
+            Ok(()) => Ok(self.position - start),
+            Err(e) => {
+                self.position = start;
+                Err(e)
+            }
+        }
+    }
+
+    /// Writes a tag on its own (for instance, ahead of hand-written
+    /// values).
+    pub fn tag(&mut self, field: u32, wire_type: WireType) -> Result<usize, ProtobufError> {
+        let written = write_tag(field, wire_type, &mut self.sink[self.position..])?;
+        self.position += written;
+        Ok(written)
+    }
+
+    /// Writes a `uint64` field.
+    pub fn uint64(&mut self, field: u32, value: u64) -> Result<usize, ProtobufError> {
+        self.field(|w| {
+            w.tag(field, WireType::Varint)?;
+            w.raw_varint(value).map(|_| ())
+        })
+    }
+
+    /// Writes a `uint32` field.
+    pub fn uint32(&mut self, field: u32, value: u32) -> Result<usize, ProtobufError> {
+        self.uint64(field, value as u64)
+    }
+
+    /// Writes an `int64` field.
+    pub fn int64(&mut self, field: u32, value: i64) -> Result<usize, ProtobufError> {
+        self.uint64(field, value as u64)
+    }
+
+    /// Writes an `int32` field.  Negative values are sign extended, and
+    /// so always take ten bytes; prefer `sint32` for those.
+    pub fn int32(&mut self, field: u32, value: i32) -> Result<usize, ProtobufError> {
+        self.uint64(field, value as i64 as u64)
+    }
+
+    /// Writes a ZigZag encoded `sint32` field.
+    pub fn sint32(&mut self, field: u32, value: i32) -> Result<usize, ProtobufError> {
+        self.uint64(field, encode_zigzag32(value) as u64)
+    }
+
+    /// Writes a ZigZag encoded `sint64` field.
+    pub fn sint64(&mut self, field: u32, value: i64) -> Result<usize, ProtobufError> {
+        self.uint64(field, encode_zigzag64(value))
+    }
+
+    /// Writes a `bool` field.
+    pub fn bool(&mut self, field: u32, value: bool) -> Result<usize, ProtobufError> {
+        self.uint64(field, value as u64)
+    }
+
+    /// Writes a `fixed32` field.
+    pub fn fixed32(&mut self, field: u32, value: u32) -> Result<usize, ProtobufError> {
+        self.field(|w| {
+            w.tag(field, WireType::Fixed32)?;
+            w.raw_fixed(value.to_le(), 4).map(|_| ())
+        })
+    }
+
+    /// Writes a `fixed64` field.
+    pub fn fixed64(&mut self, field: u32, value: u64) -> Result<usize, ProtobufError> {
+        self.field(|w| {
+            w.tag(field, WireType::Fixed64)?;
+            w.raw_fixed(value.to_le(), 8).map(|_| ())
+        })
+    }
+
+    /// Writes an `sfixed32` field.
+    pub fn sfixed32(&mut self, field: u32, value: i32) -> Result<usize, ProtobufError> {
+        self.fixed32(field, value as u32)
+    }
+
+    /// Writes an `sfixed64` field.
+    pub fn sfixed64(&mut self, field: u32, value: i64) -> Result<usize, ProtobufError> {
+        self.fixed64(field, value as u64)
+    }
+
+    /// Writes a `float` field.
+    pub fn float(&mut self, field: u32, value: f32) -> Result<usize, ProtobufError> {
+        self.fixed32(field, value.to_bits())
+    }
+
+    /// Writes a `double` field.
+    pub fn double(&mut self, field: u32, value: f64) -> Result<usize, ProtobufError> {
+        self.fixed64(field, value.to_bits())
+    }
+
+    /// Writes a `bytes` field.
+    pub fn bytes(&mut self, field: u32, value: &[u8]) -> Result<usize, ProtobufError> {
+        self.field(|w| {
+            w.tag(field, WireType::LengthDelimited)?;
+            w.raw_varint(value.len() as u64)?;
+            w.reserve(value.len())?;
+            w.sink[w.position..w.position + value.len()].copy_from_slice(value);
+            w.position += value.len();
+            Ok(())
+        })
+    }
+
+    /// Writes a `string` field.
+    pub fn string(&mut self, field: u32, value: &str) -> Result<usize, ProtobufError> {
+        self.bytes(field, value.as_bytes())
+    }
+
+    /// Writes an embedded message field, whose fields are written by `f`.
+    ///
+    /// The length isn't known until `f` has finished, so the message is
+    /// written after room for the largest possible length and moved into
+    /// place afterwards; the sink needs those few bytes of slack.
+    pub fn message<F>(&mut self, field: u32, f: F) -> Result<usize, ProtobufError>
+    where F: FnOnce(&mut MessageWriter) -> Result<(), ProtobufError> {
+        self.field(|w| {
+            w.tag(field, WireType::LengthDelimited)?;
+            w.reserve(MAX_LENGTH_PREFIX)?;
+            let start = w.position + MAX_LENGTH_PREFIX;
+            let length = {
+                let mut inner = MessageWriter::new(&mut w.sink[start..]);
+                f(&mut inner)?;
+                inner.finish()
+            };
+            w.raw_varint(length as u64)?;
+            let to = w.position;
+            w.sink.copy_within(start..start + length, to);
+            w.position += length;
+            Ok(())
+        })
+    }
+
+    /// Writes a packed repeated field of varints (`int32`, `int64`,
+    /// `uint32`, `uint64`, `bool` or `enum`; `sint32` and `sint64` values
+    /// should be ZigZag encoded first).
+    pub fn packed_varints<I>(&mut self, field: u32, values: I) -> Result<usize, ProtobufError>
+    where I: IntoIterator<Item = u64> + Clone {
+        let length: usize = values.clone().into_iter().map(varint_len).sum();
+        self.field(|w| {
+            w.tag(field, WireType::LengthDelimited)?;
+            w.raw_varint(length as u64)?;
+            w.reserve(length)?;
+            for value in values {
+                w.raw_varint(value)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Writes a packed repeated field of `fixed32` values (or `sfixed32`
+    /// and `float` values, as bits).
+    pub fn packed_fixed32<I>(&mut self, field: u32, values: I) -> Result<usize, ProtobufError>
+    where I: IntoIterator<Item = u32> + Clone {
+        let count = values.clone().into_iter().count();
+        self.field(|w| {
+            w.tag(field, WireType::LengthDelimited)?;
+            w.raw_varint(count as u64 * 4)?;
+            w.reserve(count * 4)?;
+            for value in values {
+                w.raw_fixed(value.to_le(), 4)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Writes a packed repeated field of `fixed64` values (or `sfixed64`
+    /// and `double` values, as bits).
+    pub fn packed_fixed64<I>(&mut self, field: u32, values: I) -> Result<usize, ProtobufError>
+    where I: IntoIterator<Item = u64> + Clone {
+        let count = values.clone().into_iter().count();
+        self.field(|w| {
+            w.tag(field, WireType::LengthDelimited)?;
+            w.raw_varint(count as u64 * 8)?;
+            w.reserve(count * 8)?;
+            for value in values {
+                w.raw_fixed(value.to_le(), 8)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+// ----------------------------------------------------------------------
+// Reading
+// ----------------------------------------------------------------------
+
+/// The value of a field, as found on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldValue<'a> {
+    /// A varint.
+    Varint(u64),
+    /// Eight little endian bytes.
+    Fixed64(u64),
+    /// A length-delimited value.
+    LengthDelimited(&'a [u8]),
+    /// The encoded contents of a group.
+    Group(&'a [u8]),
+    /// Four little endian bytes.
+    Fixed32(u32),
+}
+
+/// A single field of a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field<'a> {
+    /// The field number.
+    pub number: u32,
+    /// The field's value.
+    pub value: FieldValue<'a>,
+}
+
+impl<'a> Field<'a> {
+    /// The field's wire type.
+    pub fn wire_type(&self) -> WireType {
+        match self.value {
+            FieldValue::Varint(_) => WireType::Varint,
+            FieldValue::Fixed64(_) => WireType::Fixed64,
+            FieldValue::LengthDelimited(_) => WireType::LengthDelimited,
+            FieldValue::Group(_) => WireType::StartGroup,
+            FieldValue::Fixed32(_) => WireType::Fixed32,
+        }
+    }
+
+    fn mismatch(&self, expected: WireType) -> ProtobufError {
+        ProtobufError::WireTypeMismatch {
+            expected,
+            found: self.wire_type(),
+        }
+    }
+
+    /// The value of a varint field (`uint64`, or any varint type before
+    /// conversion).
+    pub fn as_uint64(&self) -> Result<u64, ProtobufError> {
+        match self.value {
+            FieldValue::Varint(value) => Ok(value),
+            _ => Err(self.mismatch(WireType::Varint)),
+        }
+    }
+
+    /// The value of a `uint32` field.  As in protobuf itself, larger values
+    /// are truncated.
+    pub fn as_uint32(&self) -> Result<u32, ProtobufError> {
+        self.as_uint64().map(|v| v as u32)
+    }
+
+    /// The value of an `int64` field.
+    pub fn as_int64(&self) -> Result<i64, ProtobufError> {
+        self.as_uint64().map(|v| v as i64)
+    }
+
+    /// The value of an `int32` field.
+    pub fn as_int32(&self) -> Result<i32, ProtobufError> {
+        self.as_uint64().map(|v| v as i32)
+    }
+
+    /// The value of a `sint32` field.
+    pub fn as_sint32(&self) -> Result<i32, ProtobufError> {
+        self.as_uint64().map(|v| decode_zigzag32(v as u32))
+    }
+
+    /// The value of a `sint64` field.
+    pub fn as_sint64(&self) -> Result<i64, ProtobufError> {
+        self.as_uint64().map(decode_zigzag64)
+    }
+
+    /// The value of a `bool` field.
+    pub fn as_bool(&self) -> Result<bool, ProtobufError> {
+        self.as_uint64().map(|v| v != 0)
+    }
+
+    /// The value of a `fixed32` field.
+    pub fn as_fixed32(&self) -> Result<u32, ProtobufError> {
+        match self.value {
+            FieldValue::Fixed32(value) => Ok(value),
+            _ => Err(self.mismatch(WireType::Fixed32)),
+        }
+    }
+
+    /// The value of a `fixed64` field.
+    pub fn as_fixed64(&self) -> Result<u64, ProtobufError> {
+        match self.value {
+            FieldValue::Fixed64(value) => Ok(value),
+            _ => Err(self.mismatch(WireType::Fixed64)),
+        }
+    }
+
+    /// The value of an `sfixed32` field.
+    pub fn as_sfixed32(&self) -> Result<i32, ProtobufError> {
+        self.as_fixed32().map(|v| v as i32)
+    }
+
+    /// The value of an `sfixed64` field.
+    pub fn as_sfixed64(&self) -> Result<i64, ProtobufError> {
+        self.as_fixed64().map(|v| v as i64)
+    }
+
+    /// The value of a `float` field.
+    pub fn as_float(&self) -> Result<f32, ProtobufError> {
+        self.as_fixed32().map(f32::from_bits)
+    }
+
+    /// The value of a `double` field.
+    pub fn as_double(&self) -> Result<f64, ProtobufError> {
+        self.as_fixed64().map(f64::from_bits)
+    }
+
+    /// The value of a `bytes` (or any length-delimited) field.
+    pub fn as_bytes(&self) -> Result<&'a [u8], ProtobufError> {
+        match self.value {
+            FieldValue::LengthDelimited(value) => Ok(value),
+            _ => Err(self.mismatch(WireType::LengthDelimited)),
+        }
+    }
+
+    /// The value of a `string` field.
+    pub fn as_str(&self) -> Result<&'a str, ProtobufError> {
+        str::from_utf8(self.as_bytes()?).map_err(|_| ProtobufError::InvalidUtf8)
+    }
+
+    /// The fields of an embedded message (or group).
+    pub fn as_message(&self) -> Result<Fields<'a>, ProtobufError> {
+        match self.value {
+            FieldValue::LengthDelimited(value) | FieldValue::Group(value) => Ok(fields(value)),
+            _ => Err(self.mismatch(WireType::LengthDelimited)),
+        }
+    }
+
+    /// The values of a packed repeated varint field.
+    pub fn packed_varints(&self) -> Result<PackedVarints<'a>, ProtobufError> {
+        Ok(PackedVarints { source: self.as_bytes()? })
+    }
+
+    /// The values of a packed repeated `fixed32` (`sfixed32` or `float`)
+    /// field.
+    pub fn packed_fixed32(&self) -> Result<PackedFixed32<'a>, ProtobufError> {
+        let source = self.as_bytes()?;
+        if source.len() % 4 != 0 {
+            return Err(ProtobufError::InvalidPackedLength);
+        }
+        Ok(PackedFixed32 { source })
+    }
+
+    /// The values of a packed repeated `fixed64` (`sfixed64` or `double`)
+    /// field.
+    pub fn packed_fixed64(&self) -> Result<PackedFixed64<'a>, ProtobufError> {
+        let source = self.as_bytes()?;
+        if source.len() % 8 != 0 {
+            return Err(ProtobufError::InvalidPackedLength);
+        }
+        Ok(PackedFixed64 { source })
+    }
+}
+
+/// Iterates over the fields of an encoded message.
+pub fn fields(message: &[u8]) -> Fields<'_> {
+    Fields {
+        source: message,
+        failed: false,
+    }
+}
+
+/// An iterator over the fields of an encoded message.  Iteration stops
+/// after the first error.
+#[derive(Debug, Clone)]
+pub struct Fields<'a> {
+    source: &'a [u8],
+    failed: bool,
+}
+
+impl<'a> Fields<'a> {
+    /// The input not yet consumed.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.source
+    }
+
+    fn next_field(&mut self) -> Result<Field<'a>, ProtobufError> {
+        let source = self.source;
+        let (number, wire_type, mut read) = read_tag(source)?;
+        let value = match wire_type {
+            WireType::Varint => {
+                let (value, length) = read_varint(&source[read..])?;
+                read += length;
+                FieldValue::Varint(value)
+            }
+            WireType::Fixed64 => {
+                let value = read_fixed64(&source[read..])?;
+                read += 8;
+                FieldValue::Fixed64(value)
+            }
+            WireType::Fixed32 => {
+                let value = read_fixed32(&source[read..])?;
+                read += 4;
+                FieldValue::Fixed32(value)
+            }
+            WireType::LengthDelimited => {
+                let (length, prefix) = read_varint(&source[read..])?;
+                read += prefix;
+                if ((source.len() - read) as u64) < length {
+                    return Err(ProtobufError::Eof);
+                }
+                let value = &source[read..read + length as usize];
+                read += length as usize;
+                FieldValue::LengthDelimited(value)
+            }
+            WireType::StartGroup => {
+                let start = read;
+                let end = skip_group(source, &mut read, number)?;
+                FieldValue::Group(&source[start..end])
+            }
+            WireType::EndGroup => return Err(ProtobufError::UnexpectedEndGroup),
+        };
+        self.source = &source[read..];
+        Ok(Field { number, value })
+    }
+}
+
+// Skips past a group's contents and its end tag, returning the offset
+// at which the contents end.  Nested groups are counted rather than
+// recursed into.
+fn skip_group(source: &[u8], read: &mut usize, number: u32) -> Result<usize, ProtobufError> {
+    let mut depth = 1;
+    loop {
+        let tag_start = *read;
+        let (field, wire_type, length) = read_tag(&source[*read..])?;
+        *read += length;
+        match wire_type {
+            WireType::Varint => *read += read_varint(&source[*read..])?.1,
+            WireType::Fixed64 => {
+                read_fixed64(&source[*read..])?;
+                *read += 8;
+            }
+            WireType::Fixed32 => {
+                read_fixed32(&source[*read..])?;
+                *read += 4;
+            }
+            WireType::LengthDelimited => {
+                let (length, prefix) = read_varint(&source[*read..])?;
+                *read += prefix;
+                if ((source.len() - *read) as u64) < length {
+                    return Err(ProtobufError::Eof);
+                }
+                *read += length as usize;
+            }
+            WireType::StartGroup => depth += 1,
+            WireType::EndGroup => {
+                depth -= 1;
+                if depth == 0 {
+                    if field != number {
+                        return Err(ProtobufError::UnexpectedEndGroup);
+                    }
+                    return Ok(tag_start);
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<Field<'a>, ProtobufError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.source.is_empty() {
+            return None;
+        }
+        let result = self.next_field();
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
+
+/// An iterator over the values of a packed repeated varint field.
+#[derive(Debug, Clone)]
+pub struct PackedVarints<'a> {
+    source: &'a [u8],
+}
+
+impl<'a> Iterator for PackedVarints<'a> {
+    type Item = Result<u64, ProtobufError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.source.is_empty() {
+            return None;
+        }
+        match read_varint(self.source) {
+            Ok((value, length)) => {
+                self.source = &self.source[length..];
+                Some(Ok(value))
+            }
+            Err(e) => {
+                self.source = &[];
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// An iterator over the values of a packed repeated `fixed32` field.
+#[derive(Debug, Clone)]
+pub struct PackedFixed32<'a> {
+    source: &'a [u8],
+}
+
+impl<'a> Iterator for PackedFixed32<'a> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        let value = read_fixed32(self.source).ok()?;
+        self.source = &self.source[4..];
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.source.len() / 4, Some(self.source.len() / 4))
+    }
+}
+
+/// An iterator over the values of a packed repeated `fixed64` field.
+#[derive(Debug, Clone)]
+pub struct PackedFixed64<'a> {
+    source: &'a [u8],
+}
+
+impl<'a> Iterator for PackedFixed64<'a> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        let value = read_fixed64(self.source).ok()?;
+        self.source = &self.source[8..];
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.source.len() / 8, Some(self.source.len() / 8))
+    }
+}
+
+#[test]
+fn test_protobuf_primitives() {
+    assert_eq!(encode_zigzag32(0), 0);
+    assert_eq!(encode_zigzag32(-1), 1);
+    assert_eq!(encode_zigzag32(1), 2);
+    assert_eq!(encode_zigzag32(i32::MIN), 0xFFFFFFFF);
+    assert_eq!(decode_zigzag32(0xFFFFFFFE), i32::MAX);
+    assert_eq!(encode_zigzag64(-2), 3);
+    assert_eq!(decode_zigzag64(0xFFFFFFFFFFFFFFFF), i64::MIN);
+
+    let mut data = [0u8; 10];
+    assert_eq!(write_varint(u64::MAX, &mut data[..]), Ok(10));
+    assert_eq!(read_varint(&data[..]), Ok((u64::MAX, 10)));
+    data[9] = 2;
+    assert_eq!(read_varint(&data[..]), Err(ProtobufError::InvalidVarint));
+    assert_eq!(read_varint(&data[..3]), Err(ProtobufError::Eof));
+    assert_eq!(write_varint(300, &mut data[..1]), Err(ProtobufError::BufferTooSmall { required: 2 }));
+
+    assert_eq!(write_tag(0, WireType::Varint, &mut data[..]), Err(ProtobufError::InvalidFieldNumber(0)));
+    assert_eq!(read_tag(&[0x02]), Err(ProtobufError::InvalidFieldNumber(0)));
+    assert_eq!(read_tag(&[0x0E]), Err(ProtobufError::InvalidWireType(6)));
+}
+
+#[test]
+fn test_protobuf_encode() {
+    let mut data = [0u8; 64];
+    let length = {
+        let mut writer = MessageWriter::new(&mut data[..]);
+        assert_eq!(writer.uint32(1, 150), Ok(3));
+        assert_eq!(writer.string(2, "testing"), Ok(9));
+        assert_eq!(writer.message(3, |m| m.uint32(1, 150).map(|_| ())), Ok(5));
+        assert_eq!(writer.packed_varints(4, [3u64, 270, 86942].iter().cloned()), Ok(8));
+        assert_eq!(writer.sint32(5, -2), Ok(2));
+        assert_eq!(writer.fixed32(6, 1), Ok(5));
+        writer.finish()
+    };
+
+    let expected: &[u8] = &[
+        0x08, 0x96, 0x01,
+        0x12, 0x07, b't', b'e', b's', b't', b'i', b'n', b'g',
+        0x1A, 0x03, 0x08, 0x96, 0x01,
+        0x22, 0x06, 0x03, 0x8E, 0x02, 0x9E, 0xA7, 0x05,
+        0x28, 0x03,
+        0x35, 0x01, 0x00, 0x00, 0x00,
+    ];
+    assert_eq!(&data[..length], expected);
+
+    // A failed write leaves the writer where it was.
+    let mut data = [0u8; 4];
+    let mut writer = MessageWriter::new(&mut data[..]);
+    assert_eq!(writer.fixed64(1, 0), Err(ProtobufError::BufferTooSmall { required: 9 }));
+    assert_eq!(writer.position(), 0);
+}
+
+#[test]
+fn test_protobuf_decode() {
+    let mut data = [0u8; 128];
+    let length = {
+        let mut writer = MessageWriter::new(&mut data[..]);
+        writer.int32(1, -1).unwrap();
+        writer.double(2, 2.5).unwrap();
+        writer.bytes(99, b"unknown").unwrap();
+        writer.message(3, |m| {
+            m.string(1, "inner")?;
+            m.bool(2, true)?;
+            Ok(())
+        }).unwrap();
+        writer.packed_fixed32(4, [1u32, 2, 3].iter().cloned()).unwrap();
+        writer.packed_fixed64(5, [(-1.5f64).to_bits()].iter().cloned()).unwrap();
+        writer.sint64(6, i64::MIN).unwrap();
+        writer.finish()
+    };
+
+    let mut seen = 0;
+    for field in fields(&data[..length]) {
+        let field = field.unwrap();
+        match field.number {
+            1 => assert_eq!(field.as_int32(), Ok(-1)),
+            2 => assert_eq!(field.as_double(), Ok(2.5)),
+            3 => {
+                let mut inner = field.as_message().unwrap();
+                assert_eq!(inner.next().unwrap().unwrap().as_str(), Ok("inner"));
+                assert_eq!(inner.next().unwrap().unwrap().as_bool(), Ok(true));
+                assert!(inner.next().is_none());
+            }
+            4 => assert!(field.packed_fixed32().unwrap().eq([1u32, 2, 3].iter().cloned())),
+            5 => assert!(field.packed_fixed64().unwrap().map(f64::from_bits).eq([-1.5f64].iter().cloned())),
+            6 => {
+                assert_eq!(field.as_sint64(), Ok(i64::MIN));
+                assert_eq!(
+                    field.as_fixed32(),
+                    Err(ProtobufError::WireTypeMismatch {
+                        expected: WireType::Fixed32,
+                        found: WireType::Varint,
+                    })
+                );
+            }
+            _ => continue,
+        }
+        seen += 1;
+    }
+    assert_eq!(seen, 6);
+
+    // Truncated input.
+    let mut iter = fields(&data[..length - 1]);
+    assert!(iter.by_ref().any(|f| f == Err(ProtobufError::Eof)));
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn test_protobuf_groups() {
+    // 1: 150, group 2 { 1: 1, group 3 { } }, 4: 5
+    let data: &[u8] = &[
+        0x08, 0x96, 0x01,
+        0x13, 0x08, 0x01, 0x1B, 0x1C, 0x14,
+        0x20, 0x05,
+    ];
+    let mut iter = fields(data);
+    assert_eq!(iter.next().unwrap().unwrap().as_uint64(), Ok(150));
+    let group = iter.next().unwrap().unwrap();
+    assert_eq!(group.number, 2);
+    assert_eq!(group.value, FieldValue::Group(&[0x08, 0x01, 0x1B, 0x1C]));
+    assert_eq!(iter.next().unwrap().unwrap().as_uint64(), Ok(5));
+    assert!(iter.next().is_none());
+
+    // Mismatched end.
+    let mut iter = fields(&[0x13, 0x1C]);
+    assert_eq!(iter.next(), Some(Err(ProtobufError::UnexpectedEndGroup)));
+}
+
+#[test]
+fn test_protobuf_packed_varints() {
+    let mut data = [0u8; 32];
+    let values = [1i32, -1, 1000, i32::MIN];
+    let length = {
+        let mut writer = MessageWriter::new(&mut data[..]);
+        writer.packed_varints(7, values.iter().map(|v| encode_zigzag32(*v) as u64)).unwrap();
+        writer.finish()
+    };
+    let field = fields(&data[..length]).next().unwrap().unwrap();
+    let decoded = field.packed_varints().unwrap().map(|v| decode_zigzag32(v.unwrap() as u32));
+    assert!(decoded.eq(values.iter().cloned()));
+}
@@ -0,0 +1,974 @@
+//! serde support.
+//!
+//! `to_vec`, `to_writer` and `from_slice` map the serde data model onto
+//! fricgan's primitives, giving `#[derive(Serialize, Deserialize)]` types a
+//! compact binary form.  The format is not self describing; both sides
+//! need the same types and the same `Config`.
+//!
+//! - `bool` is a single byte (`0` or `1`), as are `u8` and `i8`.
+//! - Other integers (and `char`, and enum variant indices) are either
+//!   fixed width little endian, through `IO`, or VLQ encoded, through
+//!   `VLQ` (with signed values ZigZag encoded first).
+//! - Floats are their little endian IEEE 754 bits.
+//! - Strings and byte arrays are a length followed by the bytes.
+//!   Sequences and maps are a length followed by their elements.  Lengths
+//!   are either a little endian `u32` or a VLQ.
+//! - `Option` is a byte (`0` for `None`, `1` for `Some`), followed by the
+//!   value where present.
+//! - Structs and tuples are their fields in order, with no names or
+//!   lengths.  Enums are the variant index followed by the variant's
+//!   fields.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::mem::size_of;
+
+use serde::de::{self, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+
+use IO;
+use VLQ;
+use vlq_len;
+
+/// How integers (or lengths) are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntEncoding {
+    /// Fixed width, little endian.
+    Fixed,
+    /// VLQ, with signed values ZigZag encoded.
+    Vlq,
+}
+
+/// Encoding settings.  The default encodes both integers and lengths as
+/// VLQs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    integers: IntEncoding,
+    lengths: IntEncoding,
+}
+
+impl Config {
+    /// Creates the default configuration.
+    pub fn new() -> Config {
+        Config {
+            integers: IntEncoding::Vlq,
+            lengths: IntEncoding::Vlq,
+        }
+    }
+
+    /// Sets the encoding used for integers wider than a byte, `char`, and
+    /// enum variant indices.
+    pub fn integers(mut self, encoding: IntEncoding) -> Config {
+        self.integers = encoding;
+        self
+    }
+
+    /// Sets the encoding used for string, byte array, sequence and map
+    /// lengths.  Fixed width lengths are `u32`.
+    pub fn lengths(mut self, encoding: IntEncoding) -> Config {
+        self.lengths = encoding;
+        self
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config::new()
+    }
+}
+
+/// Errors produced while serializing or deserializing.
+#[derive(Debug)]
+pub enum Error {
+    /// A custom error from a `Serialize` or `Deserialize` implementation.
+    Message(String),
+    /// The underlying writer failed.
+    Io(io::Error),
+    /// The input ended early.
+    Eof,
+    /// Input remained after the value was deserialized.
+    TrailingBytes,
+    /// A `bool` was neither `0` nor `1`.
+    InvalidBool(u8),
+    /// An `Option` tag was neither `0` nor `1`.
+    InvalidOption(u8),
+    /// A `char` was not a valid Unicode scalar value.
+    InvalidChar(u32),
+    /// A string was not valid UTF-8.
+    InvalidUtf8,
+    /// A VLQ was longer than ten bytes.
+    InvalidVlq,
+    /// An integer did not fit in the type being deserialized.
+    IntegerOverflow,
+    /// A length was too large for a fixed width length prefix.
+    LengthOverflow,
+    /// A sequence or map was serialized without a known length.
+    UnknownLength,
+    /// `deserialize_any` (and `deserialize_ignored_any`) are not
+    /// supported, as the format is not self describing.
+    AnyNotSupported,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Message(ref message) => f.write_str(message),
+            Error::Io(ref e) => write!(f, "io error: {}", e),
+            Error::Eof => f.write_str("unexpected end of input"),
+            Error::TrailingBytes => f.write_str("trailing bytes after value"),
+            Error::InvalidBool(b) => write!(f, "invalid bool: {}", b),
+            Error::InvalidOption(b) => write!(f, "invalid option tag: {}", b),
+            Error::InvalidChar(c) => write!(f, "invalid char: {:#x}", c),
+            Error::InvalidUtf8 => f.write_str("invalid UTF-8"),
+            Error::InvalidVlq => f.write_str("invalid VLQ"),
+            Error::IntegerOverflow => f.write_str("integer out of range"),
+            Error::LengthOverflow => f.write_str("length out of range"),
+            Error::UnknownLength => f.write_str("sequences and maps must have a known length"),
+            Error::AnyNotSupported => f.write_str("deserialize_any is not supported"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error::Message(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error::Message(msg.to_string())
+    }
+}
+
+/// Result type for serialization and deserialization.
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+// ----------------------------------------------------------------------
+// Serialization
+// ----------------------------------------------------------------------
+
+/// Serializes `value` to a `Vec<u8>` using the default `Config`.
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    to_vec_with_config(value, Config::new())
+}
+
+/// Serializes `value` to a `Vec<u8>`.
+pub fn to_vec_with_config<T: Serialize + ?Sized>(value: &T, config: Config) -> Result<Vec<u8>> {
+    let mut sink = Vec::new();
+    to_writer_with_config(&mut sink, value, config)?;
+    Ok(sink)
+}
+
+/// Serializes `value` to `writer` using the default `Config`.
+pub fn to_writer<W: io::Write, T: Serialize + ?Sized>(writer: W, value: &T) -> Result<()> {
+    to_writer_with_config(writer, value, Config::new())
+}
+
+/// Serializes `value` to `writer`.
+pub fn to_writer_with_config<W, T>(writer: W, value: &T, config: Config) -> Result<()>
+where W: io::Write, T: Serialize + ?Sized {
+    let mut serializer = Serializer::new(writer, config);
+    value.serialize(&mut serializer)
+}
+
+/// A serde `Serializer` writing to a `std::io::Write`.
+pub struct Serializer<W: io::Write> {
+    writer: W,
+    config: Config,
+}
+
+impl<W: io::Write> Serializer<W> {
+    /// Creates a serializer writing to `writer`.
+    pub fn new(writer: W, config: Config) -> Serializer<W> {
+        Serializer { writer, config }
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_bytes(&mut self, data: &[u8]) -> Result<()> {
+        self.writer.write_all(data).map_err(Error::Io)
+    }
+
+    fn write_fixed<T: IO>(&mut self, mut value: T) -> Result<()> {
+        let mut buffer = [0u8; 8];
+        let written = value.fio_write(&mut buffer[..]);
+        self.write_bytes(&buffer[..written])
+    }
+
+    fn write_vlq(&mut self, value: u64) -> Result<()> {
+        let mut buffer = [0u8; 10];
+        let written = value.vlq_write(&mut buffer[..]);
+        self.write_bytes(&buffer[..written])
+    }
+
+    fn write_length(&mut self, length: usize) -> Result<()> {
+        match self.config.lengths {
+            IntEncoding::Fixed => {
+                if length > u32::MAX as usize {
+                    return Err(Error::LengthOverflow);
+                }
+                self.write_fixed((length as u32).to_le())
+            }
+            IntEncoding::Vlq => self.write_vlq(length as u64),
+        }
+    }
+}
+
+macro_rules! serialize_unsigned {
+    ($name:ident, $t:ty) => {
+        fn $name(self, v: $t) -> Result<()> {
+            match self.config.integers {
+                IntEncoding::Fixed => self.write_fixed(v.to_le()),
+                IntEncoding::Vlq => self.write_vlq(v as u64),
+            }
+        }
+    };
+}
+
+macro_rules! serialize_signed {
+    ($name:ident, $t:ty) => {
+        fn $name(self, v: $t) -> Result<()> {
+            match self.config.integers {
+                IntEncoding::Fixed => self.write_fixed(v.to_le()),
+                IntEncoding::Vlq => self.write_vlq(zigzag(v as i64)),
+            }
+        }
+    };
+}
+
+impl<W: io::Write> ser::Serializer for &mut Serializer<W> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.write_fixed(v as u8)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.write_fixed(v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.write_fixed(v)
+    }
+
+    serialize_unsigned!(serialize_u16, u16);
+    serialize_unsigned!(serialize_u32, u32);
+    serialize_unsigned!(serialize_u64, u64);
+    serialize_signed!(serialize_i16, i16);
+    serialize_signed!(serialize_i32, i32);
+    serialize_signed!(serialize_i64, i64);
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.write_fixed(f32::from_bits(v.to_bits().to_le()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.write_fixed(f64::from_bits(v.to_bits().to_le()))
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.serialize_u32(v as u32)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.serialize_bytes(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.write_length(v.len())?;
+        self.write_bytes(v)
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        self.write_fixed(0u8)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<()> {
+        self.write_fixed(1u8)?;
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, index: u32, _variant: &'static str) -> Result<()> {
+        self.serialize_u32(index)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.serialize_u32(index)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self> {
+        let len = len.ok_or(Error::UnknownLength)?;
+        self.write_length(len)?;
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self> {
+        self.serialize_u32(index)?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self> {
+        let len = len.ok_or(Error::UnknownLength)?;
+        self.write_length(len)?;
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self> {
+        self.serialize_u32(index)?;
+        Ok(self)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+impl<W: io::Write> ser::SerializeSeq for &mut Serializer<W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: io::Write> ser::SerializeTuple for &mut Serializer<W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: io::Write> ser::SerializeTupleStruct for &mut Serializer<W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: io::Write> ser::SerializeTupleVariant for &mut Serializer<W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: io::Write> ser::SerializeMap for &mut Serializer<W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: io::Write> ser::SerializeStruct for &mut Serializer<W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, _key: &'static str, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: io::Write> ser::SerializeStructVariant for &mut Serializer<W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, _key: &'static str, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+// ----------------------------------------------------------------------
+// Deserialization
+// ----------------------------------------------------------------------
+
+/// Deserializes a `T` from `source` using the default `Config`.  All of
+/// `source` must be used.
+pub fn from_slice<'de, T: de::Deserialize<'de>>(source: &'de [u8]) -> Result<T> {
+    from_slice_with_config(source, Config::new())
+}
+
+/// Deserializes a `T` from `source`.  All of `source` must be used.
+pub fn from_slice_with_config<'de, T: de::Deserialize<'de>>(source: &'de [u8], config: Config) -> Result<T> {
+    let mut deserializer = Deserializer::new(source, config);
+    let value = T::deserialize(&mut deserializer)?;
+    if deserializer.remaining().is_empty() {
+        Ok(value)
+    } else {
+        Err(Error::TrailingBytes)
+    }
+}
+
+/// A serde `Deserializer` reading from a byte slice.  Strings and byte
+/// arrays are borrowed from the slice where the target type allows it.
+pub struct Deserializer<'de> {
+    input: &'de [u8],
+    config: Config,
+}
+
+impl<'de> Deserializer<'de> {
+    /// Creates a deserializer reading from `input`.
+    pub fn new(input: &'de [u8], config: Config) -> Deserializer<'de> {
+        Deserializer { input, config }
+    }
+
+    /// The input not yet consumed.
+    pub fn remaining(&self) -> &'de [u8] {
+        self.input
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'de [u8]> {
+        if self.input.len() < length {
+            return Err(Error::Eof);
+        }
+        let (taken, rest) = self.input.split_at(length);
+        self.input = rest;
+        Ok(taken)
+    }
+
+    fn read_fixed<T: IO + Default>(&mut self) -> Result<T> {
+        let source = self.take(size_of::<T>())?;
+        let mut value = T::default();
+        value.fio_read(source);
+        Ok(value)
+    }
+
+    fn read_vlq(&mut self) -> Result<u64> {
+        match vlq_len(self.input, 10) {
+            // Only the lowest bit of a tenth byte fits in a u64.
+            Some(10) if self.input[9] > 1 => Err(Error::InvalidVlq),
+            Some(length) => {
+                let mut value: u64 = 0;
+                value.vlq_read(self.input);
+                self.input = &self.input[length..];
+                Ok(value)
+            }
+            None if self.input.len() < 10 => Err(Error::Eof),
+            None => Err(Error::InvalidVlq),
+        }
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        self.read_fixed::<u8>()
+    }
+
+    fn read_unsigned(&mut self, max: u64) -> Result<u64> {
+        let value = self.read_vlq()?;
+        if value > max {
+            Err(Error::IntegerOverflow)
+        } else {
+            Ok(value)
+        }
+    }
+
+    fn read_signed(&mut self, min: i64, max: i64) -> Result<i64> {
+        let value = unzigzag(self.read_vlq()?);
+        if value < min || value > max {
+            Err(Error::IntegerOverflow)
+        } else {
+            Ok(value)
+        }
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        match self.config.integers {
+            IntEncoding::Fixed => Ok(u32::from_le(self.read_fixed()?)),
+            IntEncoding::Vlq => Ok(self.read_unsigned(u32::MAX as u64)? as u32),
+        }
+    }
+
+    fn read_length(&mut self) -> Result<usize> {
+        let length = match self.config.lengths {
+            IntEncoding::Fixed => u32::from_le(self.read_fixed()?) as u64,
+            IntEncoding::Vlq => self.read_vlq()?,
+        };
+        if length > usize::MAX as u64 {
+            Err(Error::LengthOverflow)
+        } else {
+            Ok(length as usize)
+        }
+    }
+
+    fn read_bytes(&mut self) -> Result<&'de [u8]> {
+        let length = self.read_length()?;
+        self.take(length)
+    }
+
+    fn read_str(&mut self) -> Result<&'de str> {
+        let bytes = self.read_bytes()?;
+        ::std::str::from_utf8(bytes).map_err(|_| Error::InvalidUtf8)
+    }
+}
+
+macro_rules! deserialize_unsigned {
+    ($name:ident, $t:ty, $visit:ident) => {
+        fn $name<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+            let value = match self.config.integers {
+                IntEncoding::Fixed => <$t>::from_le(self.read_fixed()?),
+                IntEncoding::Vlq => self.read_unsigned(<$t>::MAX as u64)? as $t,
+            };
+            visitor.$visit(value)
+        }
+    };
+}
+
+macro_rules! deserialize_signed {
+    ($name:ident, $t:ty, $visit:ident) => {
+        fn $name<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+            let value = match self.config.integers {
+                IntEncoding::Fixed => <$t>::from_le(self.read_fixed()?),
+                IntEncoding::Vlq => self.read_signed(<$t>::MIN as i64, <$t>::MAX as i64)? as $t,
+            };
+            visitor.$visit(value)
+        }
+    };
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(Error::AnyNotSupported)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.read_u8()? {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            other => Err(Error::InvalidBool(other)),
+        }
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u8(self.read_u8()?)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i8(self.read_fixed::<i8>()?)
+    }
+
+    deserialize_unsigned!(deserialize_u16, u16, visit_u16);
+    deserialize_unsigned!(deserialize_u32, u32, visit_u32);
+    deserialize_unsigned!(deserialize_u64, u64, visit_u64);
+    deserialize_signed!(deserialize_i16, i16, visit_i16);
+    deserialize_signed!(deserialize_i32, i32, visit_i32);
+    deserialize_signed!(deserialize_i64, i64, visit_i64);
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let value: f32 = self.read_fixed()?;
+        visitor.visit_f32(f32::from_bits(u32::from_le(value.to_bits())))
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let value: f64 = self.read_fixed()?;
+        visitor.visit_f64(f64::from_bits(u64::from_le(value.to_bits())))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let value = self.read_u32()?;
+        match ::std::char::from_u32(value) {
+            Some(c) => visitor.visit_char(c),
+            None => Err(Error::InvalidChar(value)),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_str(self.read_str()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_bytes(self.read_bytes()?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.read_u8()? {
+            0 => visitor.visit_none(),
+            1 => visitor.visit_some(self),
+            other => Err(Error::InvalidOption(other)),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let remaining = self.read_length()?;
+        visitor.visit_seq(Access { de: self, remaining })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(Access { de: self, remaining: len })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let remaining = self.read_length()?;
+        visitor.visit_map(Access { de: self, remaining })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_u32(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(Error::AnyNotSupported)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+// Sequence, tuple and map elements.
+struct Access<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    remaining: usize,
+}
+
+impl<'a, 'de> de::SeqAccess<'de> for Access<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        // Don't let a corrupt length trigger a huge allocation.
+        Some(::std::cmp::min(self.remaining, self.de.input.len()))
+    }
+}
+
+impl<'a, 'de> de::MapAccess<'de> for Access<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(::std::cmp::min(self.remaining, self.de.input.len()))
+    }
+}
+
+impl<'de> de::EnumAccess<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self)> {
+        let index = self.read_u32()?;
+        let value = seed.deserialize(index.into_deserializer())?;
+        Ok((value, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_tuple(self, len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_tuple(self, fields.len(), visitor)
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum TestShape {
+    Empty,
+    Circle(f32),
+    Point(i16, i16),
+    Rect { width: u32, height: u32 },
+}
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct TestRecord<'a> {
+    id: u64,
+    delta: i32,
+    flag: bool,
+    initial: char,
+    name: String,
+    borrowed: &'a str,
+    scores: Vec<u16>,
+    lookup: ::std::collections::BTreeMap<String, i8>,
+    parent: Option<u32>,
+    shapes: Vec<TestShape>,
+    ratio: f64,
+    pair: (u8, i64),
+}
+
+#[cfg(test)]
+fn test_record() -> TestRecord<'static> {
+    let mut lookup = ::std::collections::BTreeMap::new();
+    lookup.insert("a".to_owned(), -1);
+    lookup.insert("b".to_owned(), 100);
+    TestRecord {
+        id: 0x0123456789ABCDEF,
+        delta: -300,
+        flag: true,
+        initial: 'é',
+        name: "fricgan".to_owned(),
+        borrowed: "borrowed",
+        scores: vec![1, 300, 65535],
+        lookup,
+        parent: None,
+        shapes: vec![
+            TestShape::Empty,
+            TestShape::Circle(1.5),
+            TestShape::Point(-1, 1),
+            TestShape::Rect { width: 3, height: 4 },
+        ],
+        ratio: -0.25,
+        pair: (7, i64::MIN),
+    }
+}
+
+#[test]
+fn test_serialize_round_trip() {
+    let record = test_record();
+    let fixed = Config::new()
+        .integers(IntEncoding::Fixed)
+        .lengths(IntEncoding::Fixed);
+
+    for config in [Config::new(), fixed].iter() {
+        let data = to_vec_with_config(&record, *config).unwrap();
+        let back: TestRecord = from_slice_with_config(&data, *config).unwrap();
+        assert_eq!(back, record);
+
+        let mut written = Vec::new();
+        to_writer_with_config(&mut written, &record, *config).unwrap();
+        assert_eq!(written, data);
+    }
+
+    // VLQ integers are smaller.
+    let vlq = to_vec(&record).unwrap();
+    let fixed = to_vec_with_config(&record, fixed).unwrap();
+    assert!(vlq.len() < fixed.len());
+}
+
+#[test]
+fn test_serialize_layout() {
+    assert_eq!(to_vec(&300u32).unwrap(), vec![0xAC, 0x02]);
+    assert_eq!(to_vec(&-1i32).unwrap(), vec![0x01]);
+    assert_eq!(to_vec(&"hi").unwrap(), vec![0x02, b'h', b'i']);
+    assert_eq!(to_vec(&Some(true)).unwrap(), vec![0x01, 0x01]);
+    assert_eq!(to_vec(&TestShape::Point(1, -2)).unwrap(), vec![0x02, 0x02, 0x03]);
+
+    let fixed = Config::new()
+        .integers(IntEncoding::Fixed)
+        .lengths(IntEncoding::Fixed);
+    assert_eq!(to_vec_with_config(&300u32, fixed).unwrap(), vec![0x2C, 0x01, 0x00, 0x00]);
+    assert_eq!(
+        to_vec_with_config(&vec![1u8, 2], fixed).unwrap(),
+        vec![0x02, 0x00, 0x00, 0x00, 0x01, 0x02]
+    );
+    assert_eq!(to_vec_with_config(&1.0f32, fixed).unwrap(), vec![0x00, 0x00, 0x80, 0x3F]);
+}
+
+#[test]
+fn test_serialize_errors() {
+    match from_slice::<u32>(&[0x80]) {
+        Err(Error::Eof) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+    match from_slice::<u32>(&[0x80, 0x80, 0x80, 0x80, 0x10]) {
+        Err(Error::IntegerOverflow) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+    match from_slice::<u64>(&[0xFF; 11]) {
+        Err(Error::InvalidVlq) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+    match from_slice::<u64>(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x02]) {
+        Err(Error::InvalidVlq) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(from_slice::<u64>(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]).unwrap(), u64::MAX);
+    match from_slice::<bool>(&[2]) {
+        Err(Error::InvalidBool(2)) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+    match from_slice::<String>(&[0x01, 0xFF]) {
+        Err(Error::InvalidUtf8) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+    match from_slice::<u8>(&[1, 2]) {
+        Err(Error::TrailingBytes) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+    match from_slice::<Vec<u64>>(&[0xFF, 0xFF, 0xFF, 0xFF, 0x0F]) {
+        Err(Error::Eof) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+}
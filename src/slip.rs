@@ -0,0 +1,317 @@
+//! Serial Line Internet Protocol (SLIP, RFC 1055) framing.
+//!
+//! Packets are terminated by `END`, and any `END` or `ESC` bytes within
+//! the packet are replaced by two byte escape sequences.  Unlike COBS the
+//! overhead depends on the data (at worst every byte doubles).
+//!
+//! `encode` writes the terminating `END`; senders often also write an
+//! `END` before each packet to flush out line noise, which decoders here
+//! ignore.  `SlipDecoder` decodes a byte stream one byte at a time.
+
+/// Marks the end of a packet.
+pub const END: u8 = 0xC0;
+
+/// Begins an escape sequence.
+pub const ESC: u8 = 0xDB;
+
+/// `ESC ESC_END` is an escaped `END` byte.
+pub const ESC_END: u8 = 0xDC;
+
+/// `ESC ESC_ESC` is an escaped `ESC` byte.
+pub const ESC_ESC: u8 = 0xDD;
+
+/// Errors produced while encoding or decoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlipError {
+    /// The output buffer is too small.
+    BufferTooSmall {
+        /// The number of bytes the output buffer requires.
+        required: usize,
+    },
+    /// `ESC` was followed by something other than `ESC_END` or `ESC_ESC`.
+    InvalidEscape(u8),
+    /// The data ended with an incomplete escape sequence.
+    Truncated,
+    /// An `END` byte was found partway through the data.
+    UnexpectedEnd,
+}
+
+/// The number of bytes `source` encodes to, including the final `END`.
+pub fn encoded_len(source: &[u8]) -> usize {
+    source.iter().filter(|b| **b == END || **b == ESC).count() + source.len() + 1
+}
+
+/// The largest possible encoded size for `length` bytes of input,
+/// including the final `END`.
+pub fn max_encoded_len(length: usize) -> usize {
+    length * 2 + 1
+}
+
+// Encodes `buf[start..start + length]` to the front of `buf`.  Provided
+// the encoded size fits before the end of the buffer, and the input is
+// at the end of the buffer, the output never overtakes the input.
+fn encode_within(buf: &mut [u8], start: usize, length: usize) -> usize {
+    let mut out = 0;
+    for read in start..start + length {
+        let byte = buf[read];
+        match byte {
+            END => {
+                buf[out] = ESC;
+                buf[out + 1] = ESC_END;
+                out += 2;
+            }
+            ESC => {
+                buf[out] = ESC;
+                buf[out + 1] = ESC_ESC;
+                out += 2;
+            }
+            _ => {
+                buf[out] = byte;
+                out += 1;
+            }
+        }
+    }
+    buf[out] = END;
+    out + 1
+}
+
+// Decodes `buf[..length]` in place.
+fn decode_within(buf: &mut [u8], length: usize) -> Result<usize, SlipError> {
+    // Leading and trailing END bytes are framing, not data.
+    let mut read = 0;
+    while read < length && buf[read] == END {
+        read += 1;
+    }
+    let mut end = length;
+    while end > read && buf[end - 1] == END {
+        end -= 1;
+    }
+
+    let mut out = 0;
+    while read < end {
+        let byte = match buf[read] {
+            END => return Err(SlipError::UnexpectedEnd),
+            ESC => {
+                read += 1;
+                if read == end {
+                    return Err(SlipError::Truncated);
+                }
+                match buf[read] {
+                    ESC_END => END,
+                    ESC_ESC => ESC,
+                    other => return Err(SlipError::InvalidEscape(other)),
+                }
+            }
+            byte => byte,
+        };
+        buf[out] = byte;
+        out += 1;
+        read += 1;
+    }
+    Ok(out)
+}
+
+/// SLIP encodes `source` to offset zero of `sink`, followed by `END`.
+///
+/// The return value is the number of bytes written.
+pub fn encode(source: &[u8], sink: &mut [u8]) -> Result<usize, SlipError> {
+    let required = encoded_len(source);
+    if sink.len() < required {
+        return Err(SlipError::BufferTooSmall { required });
+    }
+    let start = required - source.len();
+    sink[start..required].copy_from_slice(source);
+    Ok(encode_within(sink, start, source.len()))
+}
+
+/// SLIP encodes the first `length` bytes of `buf` in place, followed by
+/// `END`.
+///
+/// The return value is the encoded length.  If `length` is past the end
+/// of `buf`, the error asks for room for the worst case.
+pub fn encode_in_place(buf: &mut [u8], length: usize) -> Result<usize, SlipError> {
+    if length > buf.len() {
+        return Err(SlipError::BufferTooSmall { required: max_encoded_len(length) });
+    }
+    let required = encoded_len(&buf[..length]);
+    if buf.len() < required {
+        return Err(SlipError::BufferTooSmall { required });
+    }
+    let start = required - length;
+    buf.copy_within(0..length, start);
+    Ok(encode_within(buf, start, length))
+}
+
+/// Decodes a single SLIP encoded packet from `source` to offset zero of
+/// `sink`.  Leading and trailing `END` bytes are ignored.
+///
+/// `sink` must be at least `source.len()` bytes.  The return value is the
+/// number of bytes written.
+pub fn decode(source: &[u8], sink: &mut [u8]) -> Result<usize, SlipError> {
+    if sink.len() < source.len() {
+        return Err(SlipError::BufferTooSmall { required: source.len() });
+    }
+    sink[..source.len()].copy_from_slice(source);
+    decode_within(sink, source.len())
+}
+
+/// Decodes a single SLIP encoded packet in place, returning the decoded
+/// length.  Leading and trailing `END` bytes are ignored.
+pub fn decode_in_place(buf: &mut [u8]) -> Result<usize, SlipError> {
+    let length = buf.len();
+    decode_within(buf, length)
+}
+
+/// Decodes a stream of SLIP packets, one byte at a time, into a caller
+/// supplied buffer.
+///
+/// Empty packets are skipped.  After an error the rest of the packet is
+/// discarded, and decoding resumes after the next `END`.
+pub struct SlipDecoder<'a> {
+    buffer: &'a mut [u8],
+    length: usize,
+    escaped: bool,
+    discarding: bool,
+    complete: bool,
+}
+
+impl<'a> SlipDecoder<'a> {
+    /// Creates a decoder writing packets into `buffer`.
+    pub fn new(buffer: &'a mut [u8]) -> SlipDecoder<'a> {
+        SlipDecoder {
+            buffer,
+            length: 0,
+            escaped: false,
+            discarding: false,
+            complete: false,
+        }
+    }
+
+    /// Discards any partially decoded packet.
+    pub fn reset(&mut self) {
+        self.length = 0;
+        self.escaped = false;
+        self.discarding = false;
+        self.complete = false;
+    }
+
+    /// Feeds a single byte to the decoder.
+    ///
+    /// Returns the decoded packet once its `END` is received, and
+    /// `Ok(None)` otherwise.
+    pub fn feed(&mut self, byte: u8) -> Result<Option<&[u8]>, SlipError> {
+        if self.complete {
+            self.reset();
+        }
+
+        if byte == END {
+            if self.escaped && !self.discarding {
+                self.reset();
+                return Err(SlipError::Truncated);
+            }
+            if self.discarding || self.length == 0 {
+                self.reset();
+                return Ok(None);
+            }
+            self.complete = true;
+            return Ok(Some(&self.buffer[..self.length]));
+        }
+
+        if self.discarding {
+            return Ok(None);
+        }
+
+        let byte = if self.escaped {
+            self.escaped = false;
+            match byte {
+                ESC_END => END,
+                ESC_ESC => ESC,
+                other => {
+                    self.discarding = true;
+                    return Err(SlipError::InvalidEscape(other));
+                }
+            }
+        } else if byte == ESC {
+            self.escaped = true;
+            return Ok(None);
+        } else {
+            byte
+        };
+
+        if self.length == self.buffer.len() {
+            self.discarding = true;
+            return Err(SlipError::BufferTooSmall { required: self.length + 1 });
+        }
+        self.buffer[self.length] = byte;
+        self.length += 1;
+        Ok(None)
+    }
+}
+
+#[test]
+fn test_slip_encode_decode() {
+    let data = [0x01, END, 0x02, ESC, 0x03];
+    let encoded = [0x01, ESC, ESC_END, 0x02, ESC, ESC_ESC, 0x03, END];
+    assert_eq!(encoded_len(&data[..]), encoded.len());
+
+    let mut sink = [0u8; 16];
+    assert_eq!(encode(&data[..], &mut sink[..]), Ok(8));
+    assert_eq!(&sink[..8], &encoded[..]);
+
+    assert_eq!(decode(&encoded[..], &mut sink[..]), Ok(5));
+    assert_eq!(&sink[..5], &data[..]);
+
+    // In place, with an exactly sized buffer.
+    let mut buf = [0u8; 8];
+    buf[..5].copy_from_slice(&data[..]);
+    assert_eq!(encode_in_place(&mut buf[..], 5), Ok(8));
+    assert_eq!(buf, encoded);
+    assert_eq!(decode_in_place(&mut buf[..]), Ok(5));
+    assert_eq!(&buf[..5], &data[..]);
+
+    // Worst case.
+    let mut buf = [END; 9];
+    assert_eq!(encode_in_place(&mut buf[..], 4), Ok(max_encoded_len(4)));
+    assert_eq!(buf, [ESC, ESC_END, ESC, ESC_END, ESC, ESC_END, ESC, ESC_END, END]);
+}
+
+#[test]
+fn test_slip_errors() {
+    let mut sink = [0u8; 16];
+    assert_eq!(
+        encode(&[END, END][..], &mut sink[..4]),
+        Err(SlipError::BufferTooSmall { required: 5 })
+    );
+    assert_eq!(encode_in_place(&mut sink[..4], 5), Err(SlipError::BufferTooSmall { required: 11 }));
+    assert_eq!(decode(&[0x01, ESC, 0x02, END][..], &mut sink[..]), Err(SlipError::InvalidEscape(0x02)));
+    assert_eq!(decode(&[0x01, ESC][..], &mut sink[..]), Err(SlipError::Truncated));
+    assert_eq!(decode(&[0x01, END, 0x02][..], &mut sink[..]), Err(SlipError::UnexpectedEnd));
+    assert_eq!(decode(&[END, 0x01, END][..], &mut sink[..]), Ok(1));
+}
+
+#[test]
+fn test_slip_decoder() {
+    let stream = [END, 0x01, ESC, ESC_END, END, END, 0x02, ESC, 0x00, 0x03, END, ESC, ESC_ESC, END];
+    let mut buffer = [0u8; 4];
+    let mut decoder = SlipDecoder::new(&mut buffer[..]);
+
+    let mut packets = 0;
+    let mut errors = 0;
+    for byte in stream.iter() {
+        match decoder.feed(*byte) {
+            Ok(Some(packet)) => {
+                match packets {
+                    0 => assert_eq!(packet, &[0x01, END][..]),
+                    _ => assert_eq!(packet, &[ESC][..]),
+                }
+                packets += 1;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                assert_eq!(e, SlipError::InvalidEscape(0x00));
+                errors += 1;
+            }
+        }
+    }
+    assert_eq!((packets, errors), (2, 1));
+}
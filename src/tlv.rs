@@ -0,0 +1,445 @@
+//! Generic type-length-value records.
+//!
+//! A record is a tag, a length, and `length` bytes of value.  The tag and
+//! length are each encoded as configured by `TlvConfig`: a `u8`, a `u16`
+//! or `u32` in either byte order, or a `u32` VLQ.
+//!
+//! `records` iterates over the records in a slice (a record's value can
+//! itself hold records, and be iterated the same way), and `TlvWriter`
+//! writes them, filling in the lengths of nested records once their
+//! contents are written.
+
+use IO;
+use VLQ;
+use {vlq_encoded_len, vlq_fits, vlq_len};
+
+/// The encoding of a tag or a length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// A single byte.
+    U8,
+    /// Two byte little endian.
+    U16Le,
+    /// Two byte big endian.
+    U16Be,
+    /// Four byte little endian.
+    U32Le,
+    /// Four byte big endian.
+    U32Be,
+    /// `u32` VLQ (one to five bytes).
+    Vlq,
+}
+
+// The most bytes any encoding occupies.
+const MAX_ENCODED_LEN: usize = 5;
+
+impl Encoding {
+    /// The largest value the encoding is able to represent.
+    pub fn max_value(&self) -> u32 {
+        match *self {
+            Encoding::U8 => 0xFF,
+            Encoding::U16Le | Encoding::U16Be => 0xFFFF,
+            _ => 0xFFFFFFFF,
+        }
+    }
+
+    /// The number of bytes the encoding occupies for a given value.
+    pub fn encoded_len(&self, value: u32) -> usize {
+        match *self {
+            Encoding::U8 => 1,
+            Encoding::U16Le | Encoding::U16Be => 2,
+            Encoding::U32Le | Encoding::U32Be => 4,
+            Encoding::Vlq => vlq_encoded_len(value as u64),
+        }
+    }
+
+    // `sink` must hold `encoded_len(value)` bytes.
+    fn write(&self, value: u32, sink: &mut [u8]) -> usize {
+        match *self {
+            Encoding::U8 => {
+                sink[0] = value as u8;
+                1
+            }
+            Encoding::U16Le => (value as u16).to_le().fio_write(sink),
+            Encoding::U16Be => (value as u16).to_be().fio_write(sink),
+            Encoding::U32Le => value.to_le().fio_write(sink),
+            Encoding::U32Be => value.to_be().fio_write(sink),
+            Encoding::Vlq => value.vlq_write(sink),
+        }
+    }
+
+    fn read(&self, source: &[u8]) -> Result<(u32, usize), TlvError> {
+        let width = match *self {
+            Encoding::U8 => 1,
+            Encoding::U16Le | Encoding::U16Be => 2,
+            Encoding::U32Le | Encoding::U32Be => 4,
+            Encoding::Vlq => {
+                // The final byte of the VLQ is the first without the
+                // continuation bit; it has to be within five bytes, and
+                // the value has to fit in 32 bits.
+                return match vlq_len(source, MAX_ENCODED_LEN) {
+                    Some(read) if !vlq_fits::<u32>(source, read) => Err(TlvError::MalformedVlq),
+                    Some(_) => {
+                        let mut value: u32 = 0;
+                        let read = value.vlq_read(source);
+                        Ok((value, read))
+                    }
+                    None if source.len() < MAX_ENCODED_LEN => Err(TlvError::Truncated),
+                    None => Err(TlvError::MalformedVlq),
+                };
+            }
+        };
+        if source.len() < width {
+            return Err(TlvError::Truncated);
+        }
+        let value = match *self {
+            Encoding::U8 => source[0] as u32,
+            Encoding::U16Le | Encoding::U16Be => {
+                let mut value: u16 = 0;
+                value.fio_read(source);
+                if *self == Encoding::U16Be { u16::from_be(value) as u32 } else { u16::from_le(value) as u32 }
+            }
+            _ => {
+                let mut value: u32 = 0;
+                value.fio_read(source);
+                if *self == Encoding::U32Be { u32::from_be(value) } else { u32::from_le(value) }
+            }
+        };
+        Ok((value, width))
+    }
+}
+
+/// Errors produced while writing or reading records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlvError {
+    /// The sink is too small to hold the record.
+    BufferTooSmall {
+        /// The number of bytes the sink requires.
+        required: usize,
+    },
+    /// The input ended partway through a tag or length.
+    Truncated,
+    /// A record's length runs past the end of the input.
+    Overrun {
+        /// The length of the record's value.
+        length: usize,
+        /// The number of bytes left in the input.
+        available: usize,
+    },
+    /// A VLQ tag or length was longer than five bytes, or overflowed 32
+    /// bits.
+    MalformedVlq,
+    /// The tag can't be represented by the tag encoding.
+    TagTooLarge {
+        /// The tag.
+        tag: u32,
+        /// The largest tag the encoding represents.
+        max: u32,
+    },
+    /// The length can't be represented by the length encoding.
+    LengthTooLarge {
+        /// The length of the value.
+        length: usize,
+        /// The largest length the encoding represents.
+        max: u32,
+    },
+}
+
+/// The tag and length encodings of a record format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TlvConfig {
+    tag: Encoding,
+    length: Encoding,
+}
+
+impl TlvConfig {
+    /// Creates a configuration with the given tag and length encodings.
+    pub fn new(tag: Encoding, length: Encoding) -> TlvConfig {
+        TlvConfig { tag, length }
+    }
+
+    /// The tag encoding.
+    pub fn tag_encoding(&self) -> Encoding {
+        self.tag
+    }
+
+    /// The length encoding.
+    pub fn length_encoding(&self) -> Encoding {
+        self.length
+    }
+
+    /// The total encoded size of a record with the given tag and value
+    /// length.
+    pub fn record_len(&self, tag: u32, length: usize) -> usize {
+        self.tag.encoded_len(tag) + self.length.encoded_len(length as u32) + length
+    }
+
+    fn check_tag(&self, tag: u32) -> Result<(), TlvError> {
+        let max = self.tag.max_value();
+        if tag > max {
+            return Err(TlvError::TagTooLarge { tag, max });
+        }
+        Ok(())
+    }
+
+    fn check_length(&self, length: usize) -> Result<(), TlvError> {
+        let max = self.length.max_value();
+        if length > max as usize {
+            return Err(TlvError::LengthTooLarge { length, max });
+        }
+        Ok(())
+    }
+}
+
+/// A single record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record<'a> {
+    /// The record's tag.
+    pub tag: u32,
+    /// The record's value.
+    pub value: &'a [u8],
+}
+
+/// Reads a single record from offset zero of `source`, returning it and
+/// the number of bytes read.
+pub fn read_record(config: TlvConfig, source: &[u8]) -> Result<(Record<'_>, usize), TlvError> {
+    let (tag, tag_len) = config.tag.read(source)?;
+    let (length, length_len) = config.length.read(&source[tag_len..])?;
+    let start = tag_len + length_len;
+    let length = length as usize;
+    let available = source.len() - start;
+    if length > available {
+        return Err(TlvError::Overrun { length, available });
+    }
+    Ok((Record { tag, value: &source[start..start + length] }, start + length))
+}
+
+/// Returns an iterator over the records in `source`.
+pub fn records(config: TlvConfig, source: &[u8]) -> Records<'_> {
+    Records {
+        config,
+        source,
+        failed: false,
+    }
+}
+
+/// An iterator over the records in a slice.  Iteration stops after the
+/// first error.
+#[derive(Debug, Clone)]
+pub struct Records<'a> {
+    config: TlvConfig,
+    source: &'a [u8],
+    failed: bool,
+}
+
+impl<'a> Records<'a> {
+    /// The input not yet consumed.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.source
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<Record<'a>, TlvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.source.is_empty() {
+            return None;
+        }
+        match read_record(self.config, self.source) {
+            Ok((record, read)) => {
+                self.source = &self.source[read..];
+                Some(Ok(record))
+            }
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Writes records to a byte buffer.
+///
+/// Each method writes a single record and returns the number of bytes
+/// written.  If a method fails, nothing it wrote is kept.
+pub struct TlvWriter<'a> {
+    config: TlvConfig,
+    sink: &'a mut [u8],
+    position: usize,
+}
+
+impl<'a> TlvWriter<'a> {
+    /// Creates a writer writing to offset zero of `sink`.
+    pub fn new(config: TlvConfig, sink: &'a mut [u8]) -> TlvWriter<'a> {
+        TlvWriter { config, sink, position: 0 }
+    }
+
+    /// The number of bytes written so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Finishes writing, returning the number of bytes written.
+    pub fn finish(self) -> usize {
+        self.position
+    }
+
+    fn reserve(&self, length: usize) -> Result<(), TlvError> {
+        let required = self.position + length;
+        if self.sink.len() < required {
+            Err(TlvError::BufferTooSmall { required })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Writes a record with the given tag and value.
+    pub fn record(&mut self, tag: u32, value: &[u8]) -> Result<usize, TlvError> {
+        self.config.check_tag(tag)?;
+        self.config.check_length(value.len())?;
+        let length = self.config.record_len(tag, value.len());
+        self.reserve(length)?;
+
+        let start = self.position;
+        self.position += self.config.tag.write(tag, &mut self.sink[self.position..]);
+        self.position += self.config.length.write(value.len() as u32, &mut self.sink[self.position..]);
+        self.sink[self.position..self.position + value.len()].copy_from_slice(value);
+        self.position += value.len();
+        Ok(self.position - start)
+    }
+
+    /// Writes a record whose value is written by `f`, usually as nested
+    /// records.  The length is filled in afterwards.
+    ///
+    /// Fixed width lengths are written in place.  VLQ lengths have five
+    /// bytes reserved, and the value is moved down once its length is
+    /// known.
+    pub fn nested<F>(&mut self, tag: u32, f: F) -> Result<usize, TlvError>
+    where F: FnOnce(&mut TlvWriter) -> Result<(), TlvError> {
+        self.config.check_tag(tag)?;
+        let start = self.position;
+        let result = self.nested_inner(tag, f);
+        if result.is_err() {
+            self.position = start;
+        }
+        result.map(|()| self.position - start)
+    }
+
+    fn nested_inner<F>(&mut self, tag: u32, f: F) -> Result<(), TlvError>
+    where F: FnOnce(&mut TlvWriter) -> Result<(), TlvError> {
+        let tag_len = self.config.tag.encoded_len(tag);
+        let reserved = match self.config.length {
+            Encoding::Vlq => MAX_ENCODED_LEN,
+            encoding => encoding.encoded_len(0),
+        };
+        self.reserve(tag_len + reserved)?;
+        self.position += self.config.tag.write(tag, &mut self.sink[self.position..]);
+
+        let header = self.position;
+        let start = header + reserved;
+        let length = {
+            let mut inner = TlvWriter::new(self.config, &mut self.sink[start..]);
+            f(&mut inner).map_err(|e| match e {
+                TlvError::BufferTooSmall { required } => TlvError::BufferTooSmall { required: start + required },
+                e => e,
+            })?;
+            inner.finish()
+        };
+        self.config.check_length(length)?;
+
+        let written = self.config.length.write(length as u32, &mut self.sink[header..]);
+        if written != reserved {
+            self.sink.copy_within(start..start + length, header + written);
+        }
+        self.position = header + written + length;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_tlv_encodings() {
+    let encodings = [
+        (Encoding::U8, &[0x01, 0x02, 0xAA, 0xBB][..]),
+        (Encoding::U16Le, &[0x01, 0x00, 0x02, 0x00, 0xAA, 0xBB][..]),
+        (Encoding::U16Be, &[0x00, 0x01, 0x00, 0x02, 0xAA, 0xBB][..]),
+        (Encoding::U32Le, &[0x01, 0, 0, 0, 0x02, 0, 0, 0, 0xAA, 0xBB][..]),
+        (Encoding::U32Be, &[0, 0, 0, 0x01, 0, 0, 0, 0x02, 0xAA, 0xBB][..]),
+        (Encoding::Vlq, &[0x01, 0x02, 0xAA, 0xBB][..]),
+    ];
+    let mut sink = [0u8; 16];
+    for &(encoding, expected) in encodings.iter() {
+        let config = TlvConfig::new(encoding, encoding);
+        let mut w = TlvWriter::new(config, &mut sink[..]);
+        assert_eq!(w.record(1, &[0xAA, 0xBB]), Ok(expected.len()));
+        assert_eq!(&sink[..expected.len()], expected);
+        assert_eq!(
+            read_record(config, expected),
+            Ok((Record { tag: 1, value: &[0xAA, 0xBB] }, expected.len()))
+        );
+    }
+
+    let config = TlvConfig::new(Encoding::Vlq, Encoding::U16Be);
+    let mut w = TlvWriter::new(config, &mut sink[..]);
+    assert_eq!(w.record(300, &[]), Ok(4));
+    assert_eq!(&sink[..4], &[0xAC, 0x02, 0x00, 0x00]);
+}
+
+#[test]
+fn test_tlv_records() {
+    let config = TlvConfig::new(Encoding::U8, Encoding::U8);
+    let source = [0x01, 0x01, 0xAA, 0x02, 0x00, 0x03, 0x02, 0xBB];
+    let mut iter = records(config, &source[..]);
+    assert_eq!(iter.next(), Some(Ok(Record { tag: 1, value: &[0xAA] })));
+    assert_eq!(iter.next(), Some(Ok(Record { tag: 2, value: &[] })));
+    assert_eq!(iter.next(), Some(Err(TlvError::Overrun { length: 2, available: 1 })));
+    assert_eq!(iter.next(), None);
+    assert_eq!(iter.remaining(), &[0x03, 0x02, 0xBB]);
+
+    let config = TlvConfig::new(Encoding::U16Le, Encoding::Vlq);
+    assert_eq!(read_record(config, &[0x01]), Err(TlvError::Truncated));
+    assert_eq!(read_record(config, &[0x01, 0x00, 0x80]), Err(TlvError::Truncated));
+    assert_eq!(read_record(config, &[0x01, 0x00, 0x80, 0x80, 0x80, 0x80, 0x80]), Err(TlvError::MalformedVlq));
+    assert_eq!(read_record(config, &[0x01, 0x00, 0x80, 0x80, 0x80, 0x80, 0x10]), Err(TlvError::MalformedVlq));
+}
+
+#[test]
+fn test_tlv_nested() {
+    let mut sink = [0u8; 256];
+    for &length in [Encoding::U16Be, Encoding::Vlq].iter() {
+        let config = TlvConfig::new(Encoding::U8, length);
+        let mut w = TlvWriter::new(config, &mut sink[..]);
+        let written = w.nested(1, |w| {
+            w.record(2, &[0xAA])?;
+            w.nested(3, |w| w.record(4, &[0u8; 200]).map(|_| ()))?;
+            Ok(())
+        });
+        let n = w.finish();
+        assert_eq!(written, Ok(n));
+
+        let mut outer = records(config, &sink[..n]);
+        let record = outer.next().unwrap().unwrap();
+        assert_eq!((record.tag, outer.next()), (1, None));
+        let mut inner = records(config, record.value);
+        assert_eq!(inner.next(), Some(Ok(Record { tag: 2, value: &[0xAA] })));
+        let record = inner.next().unwrap().unwrap();
+        assert_eq!((record.tag, inner.next()), (3, None));
+        let nested = read_record(config, record.value).unwrap().0;
+        assert_eq!((nested.tag, nested.value.len()), (4, 200));
+    }
+}
+
+#[test]
+fn test_tlv_errors() {
+    let config = TlvConfig::new(Encoding::U8, Encoding::U8);
+    let mut sink = [0u8; 8];
+    let mut w = TlvWriter::new(config, &mut sink[..]);
+    assert_eq!(w.record(256, &[]), Err(TlvError::TagTooLarge { tag: 256, max: 255 }));
+    assert_eq!(w.record(1, &[0u8; 300]), Err(TlvError::LengthTooLarge { length: 300, max: 255 }));
+    assert_eq!(w.record(1, &[0u8; 7]), Err(TlvError::BufferTooSmall { required: 9 }));
+    assert_eq!(
+        w.nested(1, |w| w.record(2, &[0u8; 5]).map(|_| ())),
+        Err(TlvError::BufferTooSmall { required: 9 })
+    );
+    assert_eq!(w.position(), 0);
+}
@@ -0,0 +1,454 @@
+//! UTF-16 and UTF-32 strings.
+//!
+//! `UtfConfig` describes how a string is stored: its `Encoding` (UTF-16
+//! or UTF-32, in either byte order), and how its length is given.  The
+//! length is either a prefix counting code units (a `u8`, a `u16` or a
+//! `u32` in the encoding's byte order, or a `u32` VLQ, as `FricganString`
+//! and `VLQString` allow) or a terminating NUL code unit.
+//!
+//! On writing, a byte order mark can be emitted ahead of the string; it
+//! counts as a code unit.  On reading, a leading byte order mark can be
+//! detected, in which case it is skipped and overrides the configured
+//! byte order for the rest of the string.
+//!
+//! Unpaired surrogates (and, in UTF-32, values which aren't Unicode
+//! scalar values) are errors, unless the configuration is lossy, in
+//! which case they decode as U+FFFD.  Reading into a `String` requires
+//! `std`; writing does not.
+
+#[cfg(feature="std")]
+use core::char;
+
+use IO;
+use VLQ;
+use vlq_encoded_len;
+
+#[cfg(feature="std")]
+use {vlq_fits, vlq_len};
+
+const BOM: u32 = 0xFEFF;
+
+/// A Unicode encoding form and byte order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-16, little endian.
+    Utf16Le,
+    /// UTF-16, big endian.
+    Utf16Be,
+    /// UTF-32, little endian.
+    Utf32Le,
+    /// UTF-32, big endian.
+    Utf32Be,
+}
+
+impl Encoding {
+    /// The number of bytes in a code unit.
+    pub fn unit_len(&self) -> usize {
+        match *self {
+            Encoding::Utf16Le | Encoding::Utf16Be => 2,
+            Encoding::Utf32Le | Encoding::Utf32Be => 4,
+        }
+    }
+
+    /// Whether the encoding is big endian.
+    pub fn is_big_endian(&self) -> bool {
+        matches!(*self, Encoding::Utf16Be | Encoding::Utf32Be)
+    }
+
+    /// The number of code units `value` encodes to (without a byte order
+    /// mark or terminator).
+    pub fn units(&self, value: &str) -> usize {
+        match *self {
+            Encoding::Utf16Le | Encoding::Utf16Be => value.encode_utf16().count(),
+            Encoding::Utf32Le | Encoding::Utf32Be => value.chars().count(),
+        }
+    }
+
+    // The same form with the other byte order.
+    #[cfg(feature="std")]
+    fn swapped(&self) -> Encoding {
+        match *self {
+            Encoding::Utf16Le => Encoding::Utf16Be,
+            Encoding::Utf16Be => Encoding::Utf16Le,
+            Encoding::Utf32Le => Encoding::Utf32Be,
+            Encoding::Utf32Be => Encoding::Utf32Le,
+        }
+    }
+
+    // `source` holds at least one unit.
+    #[cfg(feature="std")]
+    fn read_unit(&self, source: &[u8]) -> u32 {
+        match *self {
+            Encoding::Utf16Le => u16::from_le_bytes([source[0], source[1]]) as u32,
+            Encoding::Utf16Be => u16::from_be_bytes([source[0], source[1]]) as u32,
+            Encoding::Utf32Le | Encoding::Utf32Be => {
+                let mut unit: u32 = 0;
+                unit.fio_read(source);
+                if self.is_big_endian() { u32::from_be(unit) } else { u32::from_le(unit) }
+            }
+        }
+    }
+
+    // `sink` holds at least one unit.
+    fn write_unit(&self, unit: u32, sink: &mut [u8]) -> usize {
+        match *self {
+            Encoding::Utf16Le => (unit as u16).to_le().fio_write(sink),
+            Encoding::Utf16Be => (unit as u16).to_be().fio_write(sink),
+            Encoding::Utf32Le => unit.to_le().fio_write(sink),
+            Encoding::Utf32Be => unit.to_be().fio_write(sink),
+        }
+    }
+}
+
+/// How the length of a string is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Length {
+    /// A single byte count of code units.
+    U8,
+    /// A two byte count of code units, in the encoding's byte order.
+    U16,
+    /// A four byte count of code units, in the encoding's byte order.
+    U32,
+    /// A `u32` VLQ count of code units.
+    Vlq,
+    /// No prefix; the string ends with a NUL code unit.
+    Nul,
+}
+
+impl Length {
+    /// The largest number of code units the length can describe.
+    pub fn max_units(&self) -> usize {
+        match *self {
+            Length::U8 => 0xFF,
+            Length::U16 => 0xFFFF,
+            Length::U32 | Length::Vlq => 0xFFFFFFFF,
+            Length::Nul => usize::MAX,
+        }
+    }
+}
+
+/// Errors produced reading or writing UTF-16 and UTF-32 strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UtfError {
+    /// The sink is too small.
+    BufferTooSmall {
+        /// The number of bytes required.
+        required: usize,
+    },
+    /// The input ended before the string did.
+    Eof,
+    /// A VLQ length was longer than five bytes, or overflowed 32 bits.
+    MalformedVlq,
+    /// The string has more code units than the length can describe.
+    LengthOverflow {
+        /// The number of code units.
+        units: usize,
+        /// The largest number the length describes.
+        max: usize,
+    },
+    /// A NUL-terminated string contains a NUL.
+    InteriorNul {
+        /// The offset of the NUL in the string.
+        position: usize,
+    },
+    /// An unpaired surrogate was read.
+    InvalidSurrogate {
+        /// The offset of the code unit (from the start of the input).
+        offset: usize,
+    },
+    /// A UTF-32 code unit is not a Unicode scalar value.
+    InvalidCodePoint {
+        /// The offset of the code unit (from the start of the input).
+        offset: usize,
+    },
+}
+
+/// How a string is encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UtfConfig {
+    encoding: Encoding,
+    length: Length,
+    bom: bool,
+    lossy: bool,
+}
+
+impl UtfConfig {
+    /// Creates a configuration for `encoding` and `length`, without byte
+    /// order marks, and rejecting invalid input.
+    pub fn new(encoding: Encoding, length: Length) -> UtfConfig {
+        UtfConfig {
+            encoding,
+            length,
+            bom: false,
+            lossy: false,
+        }
+    }
+
+    /// Enables (or disables) byte order marks: emitting one on write and
+    /// detecting one on read.
+    pub fn bom(mut self, enabled: bool) -> UtfConfig {
+        self.bom = enabled;
+        self
+    }
+
+    /// Enables (or disables) lossy decoding, replacing invalid code units
+    /// with U+FFFD.
+    pub fn lossy(mut self, enabled: bool) -> UtfConfig {
+        self.lossy = enabled;
+        self
+    }
+
+    /// The encoding.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// The length encoding.
+    pub fn length(&self) -> Length {
+        self.length
+    }
+
+    /// The number of bytes `value` is written as.
+    pub fn encoded_len(&self, value: &str) -> usize {
+        let unit = self.encoding.unit_len();
+        let units = self.encoding.units(value) + if self.bom { 1 } else { 0 };
+        let prefix = match self.length {
+            Length::U8 => 1,
+            Length::U16 => 2,
+            Length::U32 => 4,
+            Length::Vlq => vlq_encoded_len(units as u64),
+            Length::Nul => unit,
+        };
+        prefix + units * unit
+    }
+
+    /// Writes `value` to offset zero of `sink`.
+    ///
+    /// The return value is the number of bytes written.
+    pub fn write(&self, value: &str, sink: &mut [u8]) -> Result<usize, UtfError> {
+        let units = self.encoding.units(value) + if self.bom { 1 } else { 0 };
+        let max = self.length.max_units();
+        if units > max {
+            return Err(UtfError::LengthOverflow { units, max });
+        }
+        if self.length == Length::Nul {
+            if let Some(position) = value.bytes().position(|b| b == 0) {
+                return Err(UtfError::InteriorNul { position });
+            }
+        }
+        let required = self.encoded_len(value);
+        if sink.len() < required {
+            return Err(UtfError::BufferTooSmall { required });
+        }
+
+        let big_endian = self.encoding.is_big_endian();
+        let mut written = match self.length {
+            Length::U8 => {
+                sink[0] = units as u8;
+                1
+            }
+            Length::U16 if big_endian => (units as u16).to_be().fio_write(sink),
+            Length::U16 => (units as u16).to_le().fio_write(sink),
+            Length::U32 if big_endian => (units as u32).to_be().fio_write(sink),
+            Length::U32 => (units as u32).to_le().fio_write(sink),
+            Length::Vlq => (units as u32).vlq_write(sink),
+            Length::Nul => 0,
+        };
+
+        if self.bom {
+            written += self.encoding.write_unit(BOM, &mut sink[written..]);
+        }
+        match self.encoding {
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                for unit in value.encode_utf16() {
+                    written += self.encoding.write_unit(unit as u32, &mut sink[written..]);
+                }
+            }
+            Encoding::Utf32Le | Encoding::Utf32Be => {
+                for c in value.chars() {
+                    written += self.encoding.write_unit(c as u32, &mut sink[written..]);
+                }
+            }
+        }
+        if self.length == Length::Nul {
+            written += self.encoding.write_unit(0, &mut sink[written..]);
+        }
+        Ok(written)
+    }
+
+    // Returns the offset of the first code unit and the number of units
+    // (not counting any terminator).
+    #[cfg(feature="std")]
+    fn read_length(&self, source: &[u8]) -> Result<(usize, usize), UtfError> {
+        let unit = self.encoding.unit_len();
+        let big_endian = self.encoding.is_big_endian();
+        let (units, offset) = match self.length {
+            Length::U8 => match source.first() {
+                Some(length) => (*length as usize, 1),
+                None => return Err(UtfError::Eof),
+            },
+            Length::U16 => {
+                if source.len() < 2 {
+                    return Err(UtfError::Eof);
+                }
+                let mut length: u16 = 0;
+                length.fio_read(source);
+                let length = if big_endian { u16::from_be(length) } else { u16::from_le(length) };
+                (length as usize, 2)
+            }
+            Length::U32 => {
+                if source.len() < 4 {
+                    return Err(UtfError::Eof);
+                }
+                let mut length: u32 = 0;
+                length.fio_read(source);
+                let length = if big_endian { u32::from_be(length) } else { u32::from_le(length) };
+                (length as usize, 4)
+            }
+            Length::Vlq => match vlq_len(source, 5) {
+                Some(read) if !vlq_fits::<u32>(source, read) => return Err(UtfError::MalformedVlq),
+                Some(_) => {
+                    let mut length: u32 = 0;
+                    let read = length.vlq_read(source);
+                    (length as usize, read)
+                }
+                None if source.len() < 5 => return Err(UtfError::Eof),
+                None => return Err(UtfError::MalformedVlq),
+            },
+            Length::Nul => {
+                // A NUL is zero in either byte order.
+                match source.chunks_exact(unit).position(|u| u.iter().all(|b| *b == 0)) {
+                    Some(units) => return Ok((0, units)),
+                    None => return Err(UtfError::Eof),
+                }
+            }
+        };
+        if (source.len() - offset) / unit < units {
+            return Err(UtfError::Eof);
+        }
+        Ok((offset, units))
+    }
+
+    /// Reads the string at offset zero of `source`.
+    ///
+    /// Returns the string and the number of bytes read.
+    #[cfg(feature="std")]
+    pub fn read(&self, source: &[u8]) -> Result<(String, usize), UtfError> {
+        let unit = self.encoding.unit_len();
+        let (offset, units) = self.read_length(source)?;
+        let end = offset + units * unit;
+        let read = if self.length == Length::Nul { end + unit } else { end };
+
+        let mut encoding = self.encoding;
+        let mut start = offset;
+        if self.bom && units > 0 {
+            if encoding.read_unit(&source[start..]) == BOM {
+                start += unit;
+            } else if encoding.swapped().read_unit(&source[start..]) == BOM {
+                encoding = encoding.swapped();
+                start += unit;
+            }
+        }
+
+        let mut value = String::with_capacity(end - start);
+        match encoding {
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let mut position = start;
+                let units = source[start..end].chunks_exact(2).map(|u| encoding.read_unit(u) as u16);
+                for c in char::decode_utf16(units) {
+                    match c {
+                        Ok(c) => {
+                            position += c.len_utf16() * 2;
+                            value.push(c);
+                        }
+                        Err(_) if self.lossy => {
+                            position += 2;
+                            value.push(char::REPLACEMENT_CHARACTER);
+                        }
+                        Err(_) => return Err(UtfError::InvalidSurrogate { offset: position }),
+                    }
+                }
+            }
+            Encoding::Utf32Le | Encoding::Utf32Be => {
+                for (i, u) in source[start..end].chunks_exact(4).enumerate() {
+                    match char::from_u32(encoding.read_unit(u)) {
+                        Some(c) => value.push(c),
+                        None if self.lossy => value.push(char::REPLACEMENT_CHARACTER),
+                        None => return Err(UtfError::InvalidCodePoint { offset: start + i * 4 }),
+                    }
+                }
+            }
+        }
+        Ok((value, read))
+    }
+}
+
+#[cfg(feature="std")]
+#[test]
+fn test_utf_utf16() {
+    let mut data = [0u8; 32];
+    let config = UtfConfig::new(Encoding::Utf16Le, Length::U16);
+    assert_eq!(config.write("h\u{e9}\u{1F600}", &mut data[..]), Ok(10));
+    assert_eq!(&data[..10], b"\x04\x00h\x00\xE9\x00\x3D\xD8\x00\xDE");
+    assert_eq!(config.read(&data[..]), Ok(("h\u{e9}\u{1F600}".to_owned(), 10)));
+
+    let config = UtfConfig::new(Encoding::Utf16Be, Length::Nul);
+    assert_eq!(config.write("abc", &mut data[..]), Ok(8));
+    assert_eq!(&data[..8], b"\x00a\x00b\x00c\x00\x00");
+    assert_eq!(config.read(&data[..]), Ok(("abc".to_owned(), 8)));
+    assert_eq!(config.read(&data[..6]), Err(UtfError::Eof));
+    assert_eq!(config.write("a\0b", &mut data[..]), Err(UtfError::InteriorNul { position: 1 }));
+}
+
+#[cfg(feature="std")]
+#[test]
+fn test_utf_bom() {
+    let mut data = [0u8; 32];
+    let config = UtfConfig::new(Encoding::Utf16Be, Length::Vlq).bom(true);
+    assert_eq!(config.write("hi", &mut data[..]), Ok(7));
+    assert_eq!(&data[..7], b"\x03\xFE\xFF\x00h\x00i");
+    assert_eq!(config.read(&data[..]), Ok(("hi".to_owned(), 7)));
+
+    // A little endian mark overrides the configured byte order.
+    let le = UtfConfig::new(Encoding::Utf16Le, Length::Vlq).bom(true);
+    assert_eq!(le.write("hi", &mut data[..]), Ok(7));
+    assert_eq!(&data[..3], b"\x03\xFF\xFE");
+    assert_eq!(config.read(&data[..]), Ok(("hi".to_owned(), 7)));
+
+    // Without detection the mark is just a character.
+    let plain = UtfConfig::new(Encoding::Utf16Le, Length::Vlq);
+    assert_eq!(plain.read(&data[..]), Ok(("\u{FEFF}hi".to_owned(), 7)));
+    assert_eq!(plain.read(&[0x80, 0x80, 0x80, 0x80, 0x10]), Err(UtfError::MalformedVlq));
+}
+
+#[cfg(feature="std")]
+#[test]
+fn test_utf_invalid() {
+    // An unpaired high surrogate followed by 'a'.
+    let data = b"\x02\x00\xD8\x61\x00";
+    let config = UtfConfig::new(Encoding::Utf16Le, Length::U8);
+    assert_eq!(config.read(&data[..]), Err(UtfError::InvalidSurrogate { offset: 1 }));
+    assert_eq!(config.lossy(true).read(&data[..]), Ok(("\u{FFFD}a".to_owned(), 5)));
+
+    let data = b"\x01\x00\x00\x11\x00";
+    let config = UtfConfig::new(Encoding::Utf32Le, Length::U8);
+    assert_eq!(config.read(&data[..]), Err(UtfError::InvalidCodePoint { offset: 1 }));
+    assert_eq!(config.lossy(true).read(&data[..]), Ok(("\u{FFFD}".to_owned(), 5)));
+
+    assert_eq!(config.read(&data[..4]), Err(UtfError::Eof));
+}
+
+#[test]
+fn test_utf_utf32() {
+    let mut data = [0u8; 16];
+    let config = UtfConfig::new(Encoding::Utf32Be, Length::U32).bom(true);
+    assert_eq!(config.encoded_len("\u{1F600}"), 12);
+    assert_eq!(config.write("\u{1F600}", &mut data[..]), Ok(12));
+    assert_eq!(&data[..12], b"\x00\x00\x00\x02\x00\x00\xFE\xFF\x00\x01\xF6\x00");
+    assert_eq!(config.write("ab", &mut data[..8]), Err(UtfError::BufferTooSmall { required: 16 }));
+
+    let config = UtfConfig::new(Encoding::Utf16Le, Length::U8);
+    let long = [b'x'; 256];
+    let long = ::core::str::from_utf8(&long[..]).unwrap();
+    assert_eq!(config.write(long, &mut data[..]), Err(UtfError::LengthOverflow { units: 256, max: 255 }));
+}